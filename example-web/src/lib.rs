@@ -0,0 +1,121 @@
+//! A message viewer for the browser. Built for `wasm32-unknown-unknown`, this module exports a handful of plain functions
+//! which `www/index.html` calls to render a message as a collapsible HTML tree. No bindings generator is needed since
+//! only bytes cross the boundary: the page copies the message into memory obtained from `alloc` and reads the rendered
+//! HTML from `output`.
+
+use nachricht::{Decoder, Sign, Value};
+use std::cell::RefCell;
+use std::fmt::Write;
+
+thread_local! {
+    /// The result of the last call to `render`
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Reserve `len` bytes for the page to write a message into.
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Release memory obtained from `alloc`.
+///
+/// # Safety
+///
+/// `ptr` and `len` need to be the result and the argument of a previous call to `alloc`.
+#[no_mangle]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Render the message in the given memory and return the length of the result, which can be found at `output`.
+///
+/// # Safety
+///
+/// `ptr` needs to point to `len` initialized bytes, e.g. obtained from `alloc`.
+#[no_mangle]
+pub unsafe extern "C" fn render(ptr: *const u8, len: usize) -> usize {
+    let html = to_html(std::slice::from_raw_parts(ptr, len));
+    OUTPUT.with(|output| {
+        *output.borrow_mut() = html;
+        output.borrow().len()
+    })
+}
+
+/// The location of the result of the last call to `render`. It remains valid until the next call.
+#[no_mangle]
+pub extern "C" fn output() -> *const u8 {
+    OUTPUT.with(|output| output.borrow().as_ptr())
+}
+
+/// Render a message as nested `<details>` elements, or the reason why it could not be decoded. Messages come from
+/// anywhere, hence they are decoded with `decode_untrusted` which also bounds the depth of the recursion below.
+pub fn to_html(buf: &[u8]) -> String {
+    let mut html = String::new();
+    match Decoder::decode_untrusted(buf) {
+        Ok(value) => write_value(&mut html, &value),
+        Err(e) => write!(html, "<p class=\"error\">{}</p>", escape(&e.to_string())).unwrap(),
+    }
+    html
+}
+
+fn write_value(html: &mut String, value: &Value) {
+    match value {
+        Value::Null => html.push_str("<span class=\"null\">null</span>"),
+        Value::Bool(v) => write!(html, "<span class=\"bool\">{}</span>", v).unwrap(),
+        Value::F32(v) => write!(html, "<span class=\"number\">{}</span>", v).unwrap(),
+        Value::F64(v) => write!(html, "<span class=\"number\">{}</span>", v).unwrap(),
+        Value::Int(Sign::Pos, v) => write!(html, "<span class=\"number\">{}</span>", v).unwrap(),
+        Value::Int(Sign::Neg, v) => write!(html, "<span class=\"number\">-{}</span>", v).unwrap(),
+        Value::Str(v) => write!(html, "<span class=\"string\">\"{}\"</span>", escape(v)).unwrap(),
+        Value::Symbol(v) => write!(html, "<span class=\"symbol\">#{}</span>", escape(v)).unwrap(),
+        Value::Bytes(v) => {
+            let hex = v.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+            write!(html, "<span class=\"bytes\">{} bytes: {}</span>", v.len(), hex).unwrap();
+        },
+        Value::Array(v) => {
+            open(html, "array", v.len());
+            for element in v {
+                html.push_str("<li>");
+                write_value(html, element);
+                html.push_str("</li>");
+            }
+            close(html);
+        },
+        Value::Map(v) => {
+            open(html, "map", v.len());
+            for (key, val) in v {
+                html.push_str("<li>");
+                write_value(html, key);
+                html.push_str(": ");
+                write_value(html, val);
+                html.push_str("</li>");
+            }
+            close(html);
+        },
+        Value::Record(v) => {
+            open(html, "record", v.len());
+            for (key, val) in v {
+                write!(html, "<li><span class=\"key\">{}</span>: ", escape(key)).unwrap();
+                write_value(html, val);
+                html.push_str("</li>");
+            }
+            close(html);
+        },
+    }
+}
+
+fn open(html: &mut String, kind: &str, len: usize) {
+    write!(html, "<details open><summary>{} of {}</summary><ul>", kind, len).unwrap();
+}
+
+fn close(html: &mut String) {
+    html.push_str("</ul></details>");
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,278 @@
+//! Implements `#[derive(Nachricht)]` for `nachricht_serde::Nachricht`. The derived implementation mirrors what
+//! `#[derive(Serialize)]` does to the names of structs, variants and fields, so the relevant `#[serde(...)]` attributes
+//! are honoured: `rename`, `rename_all`, `skip` and `skip_serializing`. Fields using `with` or `serialize_with` are
+//! not traversed since their serialized form is unknown. Attributes which change the shape of a type, like `flatten`,
+//! `tag` or `untagged`, are rejected.
+//!
+//! Structs and struct variants may carry `#[nachricht(version = 2)]`, which sets the version of their layout as with
+//! `Layouts::set_version`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, Lit, LitInt, Meta, NestedMeta, Result, Type};
+
+#[proc_macro_derive(Nachricht, attributes(nachricht))]
+pub fn derive_nachricht(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+/// The subset of serde attributes which influences layouts
+#[derive(Default)]
+struct Attrs {
+    rename: Option<String>,
+    rename_all: Option<RenameRule>,
+    skip: bool,
+    opaque: bool,
+}
+
+impl Attrs {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut result = Attrs::default();
+        for attr in attrs.iter().filter(|a| a.path.is_ident("serde")) {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                other => return Err(Error::new_spanned(other, "expected #[serde(...)]")),
+            };
+            for nested in list.nested {
+                let meta = match nested {
+                    NestedMeta::Meta(meta) => meta,
+                    NestedMeta::Lit(lit) => return Err(Error::new_spanned(lit, "unexpected literal")),
+                };
+                let name = meta.path().get_ident().map(|i| i.to_string()).unwrap_or_default();
+                match (name.as_str(), &meta) {
+                    ("rename", Meta::NameValue(nv)) => result.rename = Some(string(&nv.lit)?),
+                    ("rename", Meta::List(list)) => {
+                        for nested in list.nested.iter() {
+                            if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                                if nv.path.is_ident("serialize") {
+                                    result.rename = Some(string(&nv.lit)?);
+                                }
+                            }
+                        }
+                    },
+                    ("rename_all", Meta::NameValue(nv)) => result.rename_all = Some(RenameRule::parse(&nv.lit)?),
+                    ("rename_all", Meta::List(list)) => {
+                        for nested in list.nested.iter() {
+                            if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                                if nv.path.is_ident("serialize") {
+                                    result.rename_all = Some(RenameRule::parse(&nv.lit)?);
+                                }
+                            }
+                        }
+                    },
+                    ("skip", _) | ("skip_serializing", _) => result.skip = true,
+                    ("with", _) | ("serialize_with", _) => result.opaque = true,
+                    ("flatten", _) | ("tag", _) | ("content", _) | ("untagged", _) | ("transparent", _)
+                        | ("remote", _) | ("into", _) => {
+                        return Err(Error::new_spanned(meta, format!("#[serde({})] is not supported by #[derive(Nachricht)]", name)));
+                    },
+                    _ => {},
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// The version from `#[nachricht(version = ...)]`, if any
+fn version(attrs: &[Attribute]) -> Result<Option<LitInt>> {
+    let mut result = None;
+    for attr in attrs.iter().filter(|a| a.path.is_ident("nachricht")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            other => return Err(Error::new_spanned(other, "expected #[nachricht(...)]")),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("version") => match nv.lit {
+                    Lit::Int(version) => { version.base10_parse::<u64>()?; result = Some(version); },
+                    other => return Err(Error::new_spanned(other, "expected an integer literal")),
+                },
+                other => return Err(Error::new_spanned(other, "unknown nachricht attribute")),
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Sets the version of the layout, which must be one of named fields
+fn set_version(version: Option<LitInt>, fields: &Fields, name: &str, variant: TokenStream2) -> Result<TokenStream2> {
+    Ok(match (version, fields) {
+        (None, _) => quote! {},
+        (Some(version), Fields::Named(_)) => quote! { layouts.set_version(#name, #variant, #version)?; },
+        (Some(version), _) => return Err(Error::new_spanned(version, "only structs with named fields have versioned layouts")),
+    })
+}
+
+fn string(lit: &Lit) -> Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        other => Err(Error::new_spanned(other, "expected a string literal")),
+    }
+}
+
+/// The case conventions of `#[serde(rename_all = "...")]`
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(lit: &Lit) -> Result<Self> {
+        Ok(match string(lit)?.as_str() {
+            "lowercase"            => RenameRule::Lower,
+            "UPPERCASE"            => RenameRule::Upper,
+            "PascalCase"           => RenameRule::Pascal,
+            "camelCase"            => RenameRule::Camel,
+            "snake_case"           => RenameRule::Snake,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case"           => RenameRule::Kebab,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebab,
+            _ => return Err(Error::new_spanned(lit, "unknown rename rule")),
+        })
+    }
+
+    /// Variants are written in PascalCase
+    fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            RenameRule::Pascal => variant.to_owned(),
+            RenameRule::Lower => variant.to_ascii_lowercase(),
+            RenameRule::Upper => variant.to_ascii_uppercase(),
+            RenameRule::Camel => variant[..1].to_ascii_lowercase() + &variant[1..],
+            RenameRule::Snake => {
+                let mut snake = String::new();
+                for (i, c) in variant.char_indices() {
+                    if i > 0 && c.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(c.to_ascii_lowercase());
+                }
+                snake
+            },
+            RenameRule::ScreamingSnake => RenameRule::Snake.apply_to_variant(variant).to_ascii_uppercase(),
+            RenameRule::Kebab => RenameRule::Snake.apply_to_variant(variant).replace('_', "-"),
+            RenameRule::ScreamingKebab => RenameRule::ScreamingSnake.apply_to_variant(variant).replace('_', "-"),
+        }
+    }
+
+    /// Fields are written in snake_case
+    fn apply_to_field(self, field: &str) -> String {
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_owned(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for c in field.chars() {
+                    if c == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(c.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(c);
+                    }
+                }
+                pascal
+            },
+            RenameRule::Camel => {
+                let pascal = RenameRule::Pascal.apply_to_field(field);
+                pascal[..1].to_ascii_lowercase() + &pascal[1..]
+            },
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+/// The serialized field names and the types which need to be traversed
+fn fields(fields: &Fields, rule: Option<RenameRule>) -> Result<(Vec<String>, Vec<Type>)> {
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    for field in fields.iter() {
+        let attrs = Attrs::parse(&field.attrs)?;
+        if attrs.skip {
+            continue;
+        }
+        if let Some(ident) = &field.ident {
+            let ident = ident.to_string();
+            let ident = ident.trim_start_matches("r#");
+            names.push(attrs.rename.unwrap_or_else(|| rule.map(|r| r.apply_to_field(ident)).unwrap_or_else(|| ident.to_owned())));
+        }
+        if !attrs.opaque {
+            types.push(field.ty.clone());
+        }
+    }
+    Ok((names, types))
+}
+
+fn expand(mut input: DeriveInput) -> Result<TokenStream2> {
+    let attrs = Attrs::parse(&input.attrs)?;
+    let name = attrs.rename.unwrap_or_else(|| input.ident.to_string());
+    let mut types = Vec::new();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let (names, tys) = fields(&data.fields, attrs.rename_all)?;
+            types.extend(tys);
+            let version = set_version(version(&input.attrs)?, &data.fields, &name, quote! { ::core::option::Option::None })?;
+            match data.fields {
+                Fields::Named(_) => quote! {
+                    layouts.insert_layout(#name, ::core::option::Option::None, &[#(#names),*])?;
+                    #version
+                },
+                _ => quote! {},
+            }
+        },
+        Data::Enum(data) => {
+            let mut body = TokenStream2::new();
+            for variant in data.variants.iter() {
+                let variant_attrs = Attrs::parse(&variant.attrs)?;
+                if variant_attrs.skip {
+                    continue;
+                }
+                let ident = variant.ident.to_string();
+                let variant_name = variant_attrs.rename.unwrap_or_else(|| attrs.rename_all.map(|r| r.apply_to_variant(&ident)).unwrap_or(ident));
+                let (names, tys) = fields(&variant.fields, variant_attrs.rename_all)?;
+                types.extend(tys);
+                let version = set_version(version(&variant.attrs)?, &variant.fields, &name, quote! { ::core::option::Option::Some(#variant_name) })?;
+                body.extend(match variant.fields {
+                    Fields::Unit => quote! {},
+                    Fields::Unnamed(_) => quote! { layouts.insert_variant(#name, #variant_name); },
+                    Fields::Named(_) => quote! {
+                        layouts.insert_variant(#name, #variant_name);
+                        layouts.insert_layout(#name, ::core::option::Option::Some(#variant_name), &[#(#names),*])?;
+                        #version
+                    },
+                });
+            }
+            body
+        },
+        Data::Union(_) => return Err(Error::new(Span::call_site(), "#[derive(Nachricht)] does not support unions")),
+    };
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::nachricht_serde::Nachricht));
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::nachricht_serde::Nachricht for #ident #ty_generics #where_clause {
+            fn collect_layouts(layouts: &mut ::nachricht_serde::Layouts) -> ::nachricht_serde::Result<()> {
+                if !layouts.visit(::core::any::type_name::<Self>()) {
+                    return ::core::result::Result::Ok(());
+                }
+                #body
+                #( <#types as ::nachricht_serde::Nachricht>::collect_layouts(layouts)?; )*
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
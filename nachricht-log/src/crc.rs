@@ -0,0 +1,49 @@
+use nachricht::Digest;
+
+/// The lookup table of the reflected polynomial 0xedb88320, one entry per byte
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-32 of zlib, Ethernet and PNG as a four byte big endian checksum for `nachricht::FrameOptions`
+pub struct Crc32;
+
+impl Crc32 {
+
+    pub fn checksum(message: &[u8]) -> u32 {
+        !message.iter().fold(!0u32, |crc, b| TABLE[((crc ^ u32::from(*b)) & 0xff) as usize] ^ (crc >> 8))
+    }
+
+}
+
+impl Digest for Crc32 {
+    fn digest(&self, message: &[u8]) -> Vec<u8> {
+        Self::checksum(message).to_be_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Crc32;
+
+    #[test]
+    fn check() {
+        assert_eq!(0, Crc32::checksum(b""));
+        assert_eq!(0xcbf4_3926, Crc32::checksum(b"123456789"));
+    }
+
+}
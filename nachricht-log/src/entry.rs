@@ -0,0 +1,60 @@
+use nachricht::{Sign, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// A single entry of a log. On disk, it is a record with the fields `key`, `sequence`, `timestamp` and `value`, where
+/// removals lack the `value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry<'a> {
+    /// Numbers entries in the order in which they were appended, starting at 0
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub key: Cow<'a, str>,
+    /// The value of the key from now on, or `None` if the key was removed
+    pub value: Option<Value<'a>>,
+}
+
+impl<'a> Entry<'a> {
+
+    /// Copy all borrowed data so that the entry is no longer tied to the lifetime of its source.
+    pub fn into_owned(self) -> Entry<'static> {
+        Entry {
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+            key: Cow::Owned(self.key.into_owned()),
+            value: self.value.map(Value::into_owned),
+        }
+    }
+
+    pub(crate) fn into_value(self) -> Value<'a> {
+        let mut fields = BTreeMap::from([
+            (Cow::Borrowed("key"), Value::Str(self.key)),
+            (Cow::Borrowed("sequence"), Value::Int(Sign::Pos, self.sequence)),
+            (Cow::Borrowed("timestamp"), Value::Int(Sign::Pos, self.timestamp)),
+        ]);
+        if let Some(value) = self.value {
+            fields.insert(Cow::Borrowed("value"), value);
+        }
+        Value::Record(fields)
+    }
+
+    pub(crate) fn from_value(value: Value<'a>) -> Result<Self, &'static str> {
+        let mut fields = match value {
+            Value::Record(fields) => fields,
+            _ => return Err("entry needs to be a record"),
+        };
+        let int = |value: Option<Value>, missing| match value {
+            Some(Value::Int(Sign::Pos, v)) => Ok(v),
+            _ => Err(missing),
+        };
+        let sequence = int(fields.remove("sequence"), "sequence needs to be a positive integer")?;
+        let timestamp = int(fields.remove("timestamp"), "timestamp needs to be a positive integer")?;
+        let key = match fields.remove("key") {
+            Some(Value::Str(key)) => key,
+            _ => return Err("key needs to be a string"),
+        };
+        Ok(Entry { sequence, timestamp, key, value: fields.remove("value") })
+    }
+
+}
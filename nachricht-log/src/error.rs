@@ -0,0 +1,46 @@
+use nachricht::{EncodeError, FrameError};
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug)]
+pub enum LogError {
+    Io(std::io::Error),
+    Encode(EncodeError),
+    /// The frame at the given offset is damaged
+    Frame(u64, FrameError),
+    /// The message at the given offset is not an entry of a log
+    Malformed(u64, &'static str),
+}
+
+impl std::error::Error for LogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LogError::Io(e) => Some(e),
+            LogError::Encode(e) => Some(e),
+            LogError::Frame(_, e) => Some(e),
+            LogError::Malformed(_, _) => None,
+        }
+    }
+}
+
+impl Display for LogError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            LogError::Io(e) => write!(f, "IO error: {}", e),
+            LogError::Encode(e) => write!(f, "Entry could not be encoded: {}", e),
+            LogError::Frame(at, e) => write!(f, "Damaged entry at offset {}: {}", at, e),
+            LogError::Malformed(at, m) => write!(f, "Malformed entry at offset {}: {}", at, m),
+        }
+    }
+}
+
+impl From<std::io::Error> for LogError {
+    fn from(e: std::io::Error) -> LogError {
+        LogError::Io(e)
+    }
+}
+
+impl From<EncodeError> for LogError {
+    fn from(e: EncodeError) -> LogError {
+        LogError::Encode(e)
+    }
+}
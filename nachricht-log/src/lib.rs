@@ -0,0 +1,33 @@
+//! An append-only key-value log of `nachricht` values in a single file. Entries are framed with
+//! `nachricht::MessageWriter` and protected by a CRC-32 checksum, so a log can be read back with any frame reader which
+//! uses `Crc32`:
+//!
+//! ```
+//! use nachricht::*;
+//! use nachricht_log::Log;
+//! use std::borrow::Cow;
+//!
+//! let path = std::env::temp_dir().join(format!("nachricht-log-doc-{}", std::process::id()));
+//! let mut log = Log::open(&path).unwrap();
+//! log.append("jessica", &Value::Str(Cow::Borrowed("FelisCatus"))).unwrap();
+//! log.append("wantan", &Value::Str(Cow::Borrowed("LynxLynx"))).unwrap();
+//! log.remove("jessica").unwrap();
+//! assert_eq!(None, log.get("jessica").unwrap());
+//! log.compact().unwrap();
+//! drop(log);
+//!
+//! let log = Log::open(&path).unwrap();
+//! let entries = log.entries().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+//! assert_eq!(vec![("wantan", 1), ("jessica", 2)], entries.iter().map(|e| (e.key.as_ref(), e.sequence)).collect::<Vec<_>>());
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+
+mod crc;
+mod entry;
+mod error;
+mod log;
+
+pub use crc::*;
+pub use entry::*;
+pub use error::*;
+pub use crate::log::*;
@@ -0,0 +1,326 @@
+use nachricht::{DecodeError, FrameError, FrameOptions, MessageReader, MessageWriter, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crc::Crc32;
+use crate::entry::Entry;
+use crate::error::LogError;
+
+/// Every entry is a frame with a checksum
+const FRAMES: FrameOptions<'static> = FrameOptions { compressor: None, checksum: Some(&Crc32) };
+
+/// Where the latest entry of a key is stored
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    offset: u64,
+    len: usize,
+    sequence: u64,
+    removed: bool,
+}
+
+/// An append-only key-value log in a single file, see the crate documentation.
+#[derive(Debug)]
+pub struct Log {
+    path: PathBuf,
+    file: File,
+    /// The size of the file as far as it consists of complete entries
+    len: u64,
+    next_sequence: u64,
+    index: HashMap<String, Location>,
+}
+
+impl Log {
+
+    /// Open the log at the path and create it if it does not exist. An incomplete entry at the end of the file, which
+    /// is left behind if the process crashed while appending it, is truncated. Damaged entries anywhere else fail with
+    /// `LogError::Frame`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LogError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let mut log = Log { path, file, len: 0, next_sequence: 0, index: HashMap::new() };
+        let mut reader = MessageReader::with_options(FRAMES);
+        let mut offset = 0;
+        while let Some((entry, len)) = read_entry(&mut reader, &buf, offset)? {
+            log.track(&entry, offset as u64, len);
+            offset += len;
+        }
+        if offset < buf.len() {
+            log.file.set_len(offset as u64)?;
+        }
+        log.len = offset as u64;
+        Ok(log)
+    }
+
+    /// Set the key to the value. Returns the sequence number of the new entry.
+    pub fn append(&mut self, key: &str, value: &Value) -> Result<u64, LogError> {
+        self.write(Entry { sequence: self.next_sequence, timestamp: now(), key: Cow::Borrowed(key), value: Some(value.clone()) })
+    }
+
+    /// Remove the key. Returns the sequence number of the new entry, which is written even if the key does not exist.
+    pub fn remove(&mut self, key: &str) -> Result<u64, LogError> {
+        self.write(Entry { sequence: self.next_sequence, timestamp: now(), key: Cow::Borrowed(key), value: None })
+    }
+
+    /// The latest value of the key, unless it has been removed.
+    pub fn get(&self, key: &str) -> Result<Option<Value<'static>>, LogError> {
+        let location = match self.index.get(key) {
+            Some(location) if !location.removed => *location,
+            _ => return Ok(None),
+        };
+        let mut buf = vec![0; location.len];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(location.offset))?;
+        file.read_exact(&mut buf)?;
+        let mut reader = MessageReader::with_options(FRAMES);
+        match read_entry(&mut reader, &buf, 0) {
+            Ok(Some((entry, _))) => Ok(entry.value.map(Value::into_owned)),
+            Ok(None) => Err(LogError::Malformed(location.offset, "entry ends prematurely")),
+            Err(LogError::Frame(_, e)) => Err(LogError::Frame(location.offset, e)),
+            Err(LogError::Malformed(_, m)) => Err(LogError::Malformed(location.offset, m)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// All entries in the order in which they were appended, including removals. The file is read at once.
+    pub fn entries(&self) -> Result<Entries, LogError> {
+        let mut buf = Vec::new();
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(0))?;
+        file.take(self.len).read_to_end(&mut buf)?;
+        Ok(Entries { buf, offset: 0, reader: MessageReader::with_options(FRAMES) })
+    }
+
+    /// The keys which currently have a value, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> + '_ {
+        self.index.iter().filter(|(_, l)| !l.removed).map(|(k, _)| k.as_str())
+    }
+
+    /// The number of keys which currently have a value.
+    pub fn len(&self) -> usize {
+        self.keys().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The sequence number of the next entry.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Make sure that all entries appended so far have reached the disk.
+    pub fn sync(&mut self) -> Result<(), LogError> {
+        Ok(self.file.sync_data()?)
+    }
+
+    /// Rewrite the log with only the latest entry of every key which still has a value. The last entry is kept even if
+    /// it is a removal, so that sequence numbers continue where they left off. Entries keep their sequence numbers and
+    /// are copied verbatim into a new file which then replaces the log.
+    pub fn compact(&mut self) -> Result<(), LogError> {
+        let last = self.next_sequence.checked_sub(1);
+        let mut locations = self.index.values().filter(|l| !l.removed || Some(l.sequence) == last).copied().collect::<Vec<_>>();
+        locations.sort_by_key(|l| l.offset);
+        let mut buf = Vec::new();
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(0))?;
+        file.take(self.len).read_to_end(&mut buf)?;
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(".compact");
+        let mut compacted = File::create(&path)?;
+        for location in locations {
+            let start = location.offset as usize;
+            compacted.write_all(&buf[start..start + location.len])?;
+        }
+        compacted.sync_all()?;
+        fs::rename(&path, &self.path)?;
+        *self = Log::open(&self.path)?;
+        Ok(())
+    }
+
+    fn write(&mut self, entry: Entry) -> Result<u64, LogError> {
+        let sequence = entry.sequence;
+        let key = entry.key.to_string();
+        let removed = entry.value.is_none();
+        let mut writer = MessageWriter::with_options(Vec::new(), FRAMES);
+        let len = writer.write(&entry.into_value())?;
+        if let Err(e) = self.file.write_all(&writer.into_inner()) {
+            // do not leave an incomplete entry in front of the next one
+            self.file.set_len(self.len)?;
+            return Err(e.into());
+        }
+        self.index.insert(key, Location { offset: self.len, len, sequence, removed });
+        self.len += len as u64;
+        self.next_sequence = sequence + 1;
+        Ok(sequence)
+    }
+
+    fn track(&mut self, entry: &Entry, offset: u64, len: usize) {
+        let location = Location { offset, len, sequence: entry.sequence, removed: entry.value.is_none() };
+        self.index.insert(entry.key.to_string(), location);
+        self.next_sequence = entry.sequence + 1;
+    }
+
+}
+
+/// The entries of a log, see `Log::entries`. Iteration stops after the first error.
+pub struct Entries {
+    buf: Vec<u8>,
+    offset: usize,
+    reader: MessageReader<'static>,
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry<'static>, LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match read_entry(&mut self.reader, &self.buf, self.offset) {
+            Ok(Some((entry, len))) => {
+                self.offset += len;
+                Some(Ok(entry.into_owned()))
+            },
+            Ok(None) => None,
+            Err(e) => {
+                self.offset = self.buf.len();
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// Read the entry at the offset and return it with its size. Returns `None` at the end of the input or if the input
+/// ends within the entry.
+fn read_entry<'s>(reader: &'s mut MessageReader<'static>, buf: &'s [u8], offset: usize) -> Result<Option<(Entry<'s>, usize)>, LogError> {
+    if offset == buf.len() {
+        return Ok(None);
+    }
+    match reader.read(&buf[offset..]) {
+        Ok((value, len)) => Entry::from_value(value).map(|e| Some((e, len))).map_err(|m| LogError::Malformed(offset as u64, m)),
+        Err(FrameError::Decode(e)) if *e.inner() == DecodeError::Eof => Ok(None),
+        Err(e) => Err(LogError::Frame(offset as u64, e)),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Log;
+    use crate::error::LogError;
+    use nachricht::{FrameError, Sign, Value};
+    use std::borrow::Cow;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    /// A path in the temporary directory which is removed again when dropped
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("nachricht-log-{}-{}", name, std::process::id()));
+            let _ = fs::remove_file(&path);
+            TempPath(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn int(v: u64) -> Value<'static> {
+        Value::Int(Sign::Pos, v)
+    }
+
+    fn keys(log: &Log) -> Vec<(String, u64, bool)> {
+        log.entries().unwrap().map(|e| e.unwrap()).map(|e| (e.key.into_owned(), e.sequence, e.value.is_some())).collect()
+    }
+
+    #[test]
+    fn append() {
+        let path = TempPath::new("append");
+        let mut log = Log::open(&path.0).unwrap();
+        assert!(log.is_empty());
+        assert_eq!(0, log.append("jessica", &int(9)).unwrap());
+        assert_eq!(1, log.append("wantan", &Value::Str(Cow::Borrowed("LynxLynx"))).unwrap());
+        assert_eq!(2, log.append("jessica", &int(8)).unwrap());
+        assert_eq!(3, log.remove("wantan").unwrap());
+        assert_eq!(Some(int(8)), log.get("jessica").unwrap());
+        assert_eq!(None, log.get("wantan").unwrap());
+        assert_eq!(None, log.get("sphinx").unwrap());
+        assert_eq!(vec!["jessica"], log.keys().collect::<Vec<_>>());
+        log.sync().unwrap();
+        drop(log);
+        let mut log = Log::open(&path.0).unwrap();
+        assert_eq!(4, log.next_sequence());
+        assert_eq!(Some(int(8)), log.get("jessica").unwrap());
+        let expected = vec![("jessica".to_string(), 0, true), ("wantan".to_string(), 1, true), ("jessica".to_string(), 2, true), ("wantan".to_string(), 3, false)];
+        assert_eq!(expected, keys(&log));
+        let entry = log.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(Some(int(9)), entry.value);
+        assert!(entry.timestamp > 0);
+        assert_eq!(4, log.append("sphinx", &Value::Null).unwrap());
+        assert_eq!(Some(Value::Null), log.get("sphinx").unwrap());
+        assert_eq!(2, log.len());
+    }
+
+    #[test]
+    fn recover() {
+        let path = TempPath::new("recover");
+        let mut log = Log::open(&path.0).unwrap();
+        log.append("jessica", &int(9)).unwrap();
+        log.append("wantan", &int(7)).unwrap();
+        drop(log);
+        let complete = fs::metadata(&path.0).unwrap().len();
+        // a crash while appending leaves part of an entry behind
+        let mut file = OpenOptions::new().append(true).open(&path.0).unwrap();
+        file.write_all(&[0x5f, 0x02]).unwrap();
+        drop(file);
+        let mut log = Log::open(&path.0).unwrap();
+        assert_eq!(complete, fs::metadata(&path.0).unwrap().len());
+        assert_eq!(2, log.append("sphinx", &int(1)).unwrap());
+        assert_eq!(3, keys(&log).len());
+        drop(log);
+        // damage within the file is not repaired
+        let mut bytes = fs::read(&path.0).unwrap();
+        bytes[4] ^= 0xff;
+        fs::write(&path.0, &bytes).unwrap();
+        assert!(matches!(Log::open(&path.0), Err(LogError::Frame(0, FrameError::Checksum))));
+    }
+
+    #[test]
+    fn compact() {
+        let path = TempPath::new("compact");
+        let mut log = Log::open(&path.0).unwrap();
+        for lives in (1..=9).rev() {
+            log.append("jessica", &int(lives)).unwrap();
+        }
+        log.append("wantan", &int(7)).unwrap();
+        log.append("sphinx", &int(1)).unwrap();
+        log.remove("sphinx").unwrap();
+        let before = fs::metadata(&path.0).unwrap().len();
+        log.compact().unwrap();
+        assert!(fs::metadata(&path.0).unwrap().len() * 3 < before);
+        let expected = vec![("jessica".to_string(), 8, true), ("wantan".to_string(), 9, true), ("sphinx".to_string(), 11, false)];
+        assert_eq!(expected, keys(&log));
+        assert_eq!(Some(int(1)), log.get("jessica").unwrap());
+        assert_eq!(12, log.append("sphinx", &int(2)).unwrap());
+        log.compact().unwrap();
+        drop(log);
+        let log = Log::open(&path.0).unwrap();
+        assert_eq!(vec![("jessica".to_string(), 8, true), ("wantan".to_string(), 9, true), ("sphinx".to_string(), 12, true)], keys(&log));
+        assert_eq!(13, log.next_sequence());
+    }
+
+}
@@ -0,0 +1,82 @@
+//! Search the strings and symbols of encoded messages, e.g. to find the capture which contains a given identifier.
+//! Messages are first checked with `scan_symbols`, so only those which contain a match get decoded.
+
+use nachricht::*;
+
+/// A string or symbol which contains the pattern
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    /// The offset of the message within the searched buffer
+    pub offset: usize,
+    /// The path of the value within its message in the syntax of `redact`, or the empty string for the message itself
+    pub path: String,
+    pub value: Value<'a>,
+}
+
+/// Search all concatenated messages in the buffer for strings and symbols which contain the pattern. Record keys and
+/// map keys are not searched, but map entries are addressed by their keys. Fails on the first malformed message.
+pub fn grep<'a>(buf: &'a [u8], pattern: &str) -> Result<Vec<Match<'a>>, DecoderError> {
+    let mut matches = Vec::new();
+    let mut offset = 0;
+    while let Some(message) = buf.get(offset..).filter(|message| !message.is_empty()) {
+        let (tokens, c) = scan_symbols(message).map_err(|e| e.into_inner().at(offset))?;
+        if tokens.iter().any(|token| token.contains(pattern)) {
+            let (value, _) = Decoder::decode(message).map_err(|e| e.into_inner().at(offset))?;
+            search(value, pattern, |path, value| matches.push(Match { offset, path, value }));
+        }
+        offset += c;
+    }
+    Ok(matches)
+}
+
+/// Call `f` with the path and value of every match in document order
+fn search<'a>(value: Value<'a>, pattern: &str, mut f: impl FnMut(String, Value<'a>)) {
+    let mut stack = vec![(value, String::new())];
+    while let Some((value, path)) = stack.pop() {
+        let child = |segment: &str| match path.as_str() {
+            "" => segment.to_owned(),
+            parent => format!("{}.{}", parent, segment),
+        };
+        match value {
+            Value::Str(ref v) | Value::Symbol(ref v) if v.contains(pattern) => f(path, value),
+            Value::Array(v) => stack.extend(v.into_iter().enumerate().rev().map(|(i, element)| (element, child(&i.to_string())))),
+            Value::Map(v) => stack.extend(v.into_iter().rev().map(|(key, val)| {
+                let segment = match &key {
+                    Value::Str(k) | Value::Symbol(k) => k.to_string(),
+                    key => key.to_string(),
+                };
+                (val, child(&segment))
+            })),
+            Value::Record(v) => stack.extend(v.into_iter().rev().map(|(key, val)| (val, child(&key)))),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{grep, Match};
+    use nachricht::*;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn matches() {
+        let user = |id: &'static str| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("id"), Value::Str(Cow::Borrowed(id))),
+            (Cow::Borrowed("tags"), Value::Array(vec![Value::Symbol(Cow::Borrowed("admin")), Value::Str(Cow::Borrowed(id))])),
+        ]));
+        let mut buf = Vec::new();
+        Encoder::encode(&user("4711"), &mut buf).unwrap();
+        let second = buf.len();
+        Encoder::encode(&Value::Map(vec![(Value::Int(Sign::Pos, 1), user("0815")), (Value::Str(Cow::Borrowed("id")), Value::Str(Cow::Borrowed("id")))]), &mut buf).unwrap();
+        let found = |offset, path: &str, value: &'static str| Match { offset, path: path.to_string(), value: Value::Str(Cow::Borrowed(value)) };
+        assert_eq!(vec![found(0, "id", "4711"), found(0, "tags.1", "4711")], grep(&buf, "47").unwrap());
+        assert_eq!(vec![found(second, "1.id", "0815"), found(second, "1.tags.1", "0815")], grep(&buf, "081").unwrap());
+        // record keys are not searched
+        assert_eq!(vec![found(second, "id", "id")], grep(&buf, "id").unwrap());
+        assert_eq!(Vec::<Match>::new(), grep(&buf, "tags").unwrap());
+        assert_eq!(DecodeError::Eof, grep(&buf[..buf.len() - 1], "47").unwrap_err().into_inner());
+    }
+
+}
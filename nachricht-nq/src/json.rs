@@ -0,0 +1,27 @@
+//! A compact JSON representation of messages for consumption by other programs, see `nachricht::json` for how values
+//! are mapped to JSON.
+
+use nachricht::*;
+
+pub fn to_json(value: &Value) -> String {
+    value.to_json(JsonOptions::default()).expect("the default options never fail").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use nachricht::*;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn json() {
+        let value = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("bytes"), Value::Bytes(Cow::Borrowed(&[0, 1]))),
+            (Cow::Borrowed("list"), Value::Array(vec![Value::Int(Sign::Neg, 3), Value::F64(0.5), Value::F32(f32::NAN), Value::F32(0.1), Value::Null])),
+            (Cow::Borrowed("map"), Value::Map(vec![(Value::Int(Sign::Pos, 1), Value::Symbol(Cow::Borrowed("one")))])),
+            (Cow::Borrowed("text"), Value::Str(Cow::Borrowed("\"a\"\n\u{1}"))),
+        ]));
+        assert_eq!(r#"{"bytes":"AAE=","list":[-3,0.5,null,0.1,null],"map":[[1,"one"]],"text":"\"a\"\n\u0001"}"#, super::to_json(&value));
+    }
+
+}
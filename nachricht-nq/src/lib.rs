@@ -0,0 +1,62 @@
+//! The textual representation of nachricht messages and the other output formats of `nq`, for tools which want to
+//! parse or print messages without shelling out to the binary.
+//!
+//! ```
+//! use nachricht_nq::{parse, render, Format};
+//!
+//! let value = parse("(name: \"Jessica\", species: #FelisCatus)").unwrap();
+//! assert_eq!(r#"{"name":"Jessica","species":"FelisCatus"}"#, render(&value, Format::Json).unwrap());
+//! assert_eq!(value, parse(&render(&value, Format::Text).unwrap()).unwrap());
+//! ```
+
+mod grep;
+mod json;
+mod parser;
+
+use nachricht::{EncodeError, Encoder, TextOptions, Value};
+
+pub use grep::{grep, Match};
+pub use json::to_json;
+pub use parser::{parse, parse_relaxed};
+
+/// The formats in which `nq` prints messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The textual representation, which `parse` reads back
+    Text,
+    /// A compact JSON document, see `to_json`
+    Json,
+    /// The wire format as hexadecimal bytes separated by spaces
+    Hex,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "hex" => Ok(Format::Hex),
+            _ => Err(format!("unknown output format {}", s)),
+        }
+    }
+}
+
+/// Print the value in the given format. Only `Format::Hex` can fail, if the value cannot be encoded.
+pub fn render(value: &Value, format: Format) -> Result<String, EncodeError> {
+    render_with_options(value, format, TextOptions::default())
+}
+
+/// Like `render`, but deviates from the canonical textual representation as specified by the options.
+pub fn render_with_options(value: &Value, format: Format, options: TextOptions) -> Result<String, EncodeError> {
+    Ok(match format {
+        Format::Text => value.to_text(options),
+        Format::Json => to_json(value),
+        Format::Hex => {
+            let mut buf = Vec::new();
+            Encoder::encode(value, &mut buf)?;
+            buf.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+        },
+    })
+}
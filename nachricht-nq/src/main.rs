@@ -1,9 +1,9 @@
-mod parser;
-
 use nachricht::*;
+use nachricht_nq::{grep, render_with_options, to_json, Format};
 use std::io::{self, Read};
 use anyhow::{Context, Result};
 use structopt::StructOpt;
+use structopt::clap::Shell;
 use std::str::from_utf8;
 use std::path::PathBuf;
 use std::fs::File;
@@ -21,46 +21,267 @@ struct Opt {
     #[structopt(short, long)]
     text: bool,
 
+    /// Only accept the canonical textual representation, without the relaxations for hand-written text like
+    /// unquoted strings or missing commas
+    #[structopt(long)]
+    strict: bool,
+
     /// Open a nachricht encoded file in the standard editor
     #[structopt(short, long, parse(from_os_str))]
     file: Option<PathBuf>,
+
+    /// Replace all fields matching the path with null, e.g. `users.*.email` or `**.password`. May be given multiple
+    /// times
+    #[structopt(short, long, number_of_values = 1)]
+    redact: Vec<String>,
+
+    /// Replace redacted fields with the SHA-256 hash of their wire format instead of null
+    #[structopt(long, requires = "redact")]
+    hash: bool,
+
+    /// Sort the entries of all maps, so that the wire format is canonical and the output can be compared byte by byte,
+    /// e.g. to normalize fixtures before committing them. Implies `--encode` unless `--output` is given
+    #[structopt(short, long, conflicts_with_all = &["file", "stats", "verify"])]
+    canon: bool,
+
+    /// Read any number of concatenated messages and print statistics about their fields
+    #[structopt(short, long, conflicts_with_all = &["encode", "text", "file"])]
+    stats: bool,
+
+    /// Read any number of concatenated messages and print the path of every string or symbol which contains the
+    /// pattern, prefixed with the input and the offset of its message. Exits with an error if nothing matches
+    #[structopt(short, long, conflicts_with_all = &["encode", "text", "file", "redact", "stats", "verify", "canon", "array"])]
+    grep: Option<String>,
+
+    /// Check the message for structural validity and print a report including its symbol table. Exits with an error
+    /// if the message is invalid
+    #[structopt(short, long, conflicts_with_all = &["encode", "text", "file", "redact", "stats"])]
+    verify: bool,
+
+    /// Combine the messages of all inputs into one array instead of processing them one after another
+    #[structopt(short, long, conflicts_with_all = &["file", "stats", "verify"])]
+    array: bool,
+
+    /// Print decoded messages and verification reports in this format: `text` is the textual representation, `json` a
+    /// compact JSON document per line and `hex` the wire format as hexadecimal bytes
+    #[structopt(short, long, possible_values = &["text", "json", "hex"], conflicts_with_all = &["encode", "file", "stats"])]
+    output: Option<Format>,
+
+    /// Print byte strings of up to this many bytes in hexadecimal instead of base64
+    #[structopt(long, conflicts_with_all = &["encode", "stats"])]
+    hex_bytes: Option<usize>,
+
+    /// Print symbols which have been defined before as references to their index in the symbol table, e.g. `&3`
+    #[structopt(long, conflicts_with_all = &["encode", "stats"])]
+    references: bool,
+
+    /// Indent nested values by this many spaces instead of two
+    #[structopt(long, conflicts_with_all = &["encode", "stats"])]
+    indent: Option<usize>,
+
+    /// Pad the keys of records so that the values of all fields start in the same column
+    #[structopt(long, conflicts_with_all = &["encode", "stats"])]
+    align: bool,
+
+    /// Print the entries of maps sorted by their keys, like `--canon` orders them
+    #[structopt(long, conflicts_with_all = &["encode", "stats"])]
+    sort_maps: bool,
+
+    /// Print a completion script for the given shell and exit
+    #[structopt(long, possible_values = &Shell::variants(), case_insensitive = true)]
+    completions: Option<Shell>,
+
+    /// Read the input from these files instead of stdin. Each file contains one message, the outputs are concatenated
+    #[structopt(parse(from_os_str), conflicts_with = "file")]
+    inputs: Vec<PathBuf>,
+}
+
+struct Sha256;
+
+impl Digest for Sha256 {
+    fn digest(&self, message: &[u8]) -> Vec<u8> {
+        <sha2::Sha256 as sha2::Digest>::digest(message).to_vec()
+    }
 }
 
 fn main() -> Result<()> {
     let opt = Opt::from_args();
-    match opt.file {
-        Some(path) => file_mode(path),
+    if let Some(shell) = opt.completions {
+        Opt::clap().gen_completions_to("nq", shell, &mut io::stdout());
+        return Ok(());
+    }
+    if opt.stats {
+        return stats_mode(&opt.inputs);
+    }
+    if let Some(pattern) = &opt.grep {
+        return grep_mode(&opt.inputs, pattern, text_options(&opt));
+    }
+    if opt.verify {
+        return verify_mode(&opt.inputs, opt.output.unwrap_or(Format::Text));
+    }
+    match opt.file.clone() {
+        Some(path) => file_mode(path, opt.strict, text_options(&opt)),
         None => streaming_mode(opt),
     }
 }
 
-fn file_mode(path: PathBuf) -> Result<()> {
+fn file_mode(path: PathBuf, strict: bool, options: TextOptions) -> Result<()> {
     let mut buf = Vec::new();
     File::open(&path)?.read_to_end(&mut buf)?;
     let field = Decoder::decode(&buf)?.0;
-    let edited = edit::edit(format!("{}", &field))?;
-    let parsed = parser::parse(&edited)?;
+    let edited = edit::edit(field.to_text(options))?;
+    let parsed = parse(edited.as_bytes(), strict)?;
     Encoder::encode(&parsed, &mut File::create(&path)?)?;
     Ok(())
 }
 
 fn streaming_mode(opt: Opt) -> Result<()> {
-    let mut buffer = Vec::new();
-    io::stdin().read_to_end(&mut buffer).context("Failed to read stdin")?;
-    let value = if opt.text {
-        parse(&buffer)?
-    } else {
-        Decoder::decode(&buffer)?.0
-    };
-    if opt.encode {
-        Encoder::encode(&value, &mut io::stdout())?;
-    } else {
-        println!("{}", &value);
+    let inputs = read_inputs(&opt.inputs)?;
+    let mut values = Vec::new();
+    for (name, buffer) in &inputs {
+        let mut value = if opt.text {
+            parse(buffer, opt.strict)
+        } else {
+            Decoder::decode(buffer).map(|(value, _)| value).map_err(anyhow::Error::from)
+        }.with_context(|| format!("Failed to read {}", name))?;
+        if !opt.redact.is_empty() {
+            let paths = opt.redact.iter().map(String::as_str).collect::<Vec<_>>();
+            let redaction = if opt.hash { Redaction::Hash(&Sha256) } else { Redaction::Null };
+            redact(&mut value, &paths, &redaction)?;
+        }
+        if opt.canon {
+            canonicalize(&mut value)?;
+        }
+        if opt.array {
+            values.push(value);
+        } else {
+            output(&opt, &value)?;
+        }
+    }
+    if opt.array {
+        output(&opt, &Value::Array(values))?;
     }
     Ok(())
 }
 
-fn parse(buffer: &[u8]) -> Result<Value> {
-    let string = from_utf8(&buffer).context("input is not utf-8")?;
-    parser::parse(string)
+fn output(opt: &Opt, value: &Value) -> Result<()> {
+    if opt.encode || (opt.canon && opt.output.is_none()) {
+        Encoder::encode(value, &mut io::stdout())?;
+        return Ok(());
+    }
+    println!("{}", render_with_options(value, opt.output.unwrap_or(Format::Text), text_options(opt))?);
+    Ok(())
+}
+
+fn text_options(opt: &Opt) -> TextOptions {
+    TextOptions {
+        hex_bytes: opt.hex_bytes.unwrap_or_default(),
+        references: opt.references,
+        indent: opt.indent.unwrap_or(TextOptions::default().indent),
+        align: opt.align,
+        sort_maps: opt.sort_maps,
+    }
+}
+
+/// The name and contents of every input, which is stdin if no files are given
+fn read_inputs(paths: &[PathBuf]) -> Result<Vec<(String, Vec<u8>)>> {
+    if paths.is_empty() {
+        let mut buffer = Vec::new();
+        io::stdin().read_to_end(&mut buffer).context("Failed to read stdin")?;
+        return Ok(vec![("stdin".to_string(), buffer)]);
+    }
+    paths.iter().map(|path| {
+        let mut buffer = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut buffer)).with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok((path.display().to_string(), buffer))
+    }).collect()
+}
+
+fn stats_mode(paths: &[PathBuf]) -> Result<()> {
+    let mut stats = Statistics::default();
+    for (name, buffer) in read_inputs(paths)? {
+        let mut pos = 0;
+        while pos < buffer.len() {
+            let (value, c) = Decoder::decode(&buffer[pos..]).with_context(|| format!("Failed to decode message at {} in {}", pos, name))?;
+            stats.add(&value, c);
+            pos += c;
+        }
+    }
+    println!("{} messages, {:.1} bytes on average", stats.messages(), stats.average_size());
+    println!("{:<40} {:>8} {:>9} {:>10}  types", "path", "count", "frequency", "avg length");
+    for (path, path_stats) in stats.paths() {
+        let types = path_stats.types.iter().map(|(t, c)| format!("{}: {}", t, c)).collect::<Vec<_>>().join(", ");
+        println!("{:<40} {:>8} {:>9.3} {:>10.1}  {}", if path.is_empty() { "." } else { path }, path_stats.count,
+            stats.frequency(path).unwrap_or_default(), path_stats.average_length(), types);
+    }
+    Ok(())
+}
+
+fn grep_mode(paths: &[PathBuf], pattern: &str, options: TextOptions) -> Result<()> {
+    let mut found = false;
+    for (name, buffer) in read_inputs(paths)? {
+        for m in grep(&buffer, pattern).with_context(|| format!("Failed to search {}", name))? {
+            println!("{}:{}:{}: {}", name, m.offset, if m.path.is_empty() { "." } else { &m.path }, m.value.to_text(options));
+            found = true;
+        }
+    }
+    if !found {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn verify_mode(paths: &[PathBuf], format: Format) -> Result<()> {
+    for (name, buffer) in read_inputs(paths)? {
+        let report = verify(&buffer).with_context(|| format!("Invalid message in {}", name))?;
+        let mut decoder = Decoder::new(&buffer);
+        decoder.decode_next()?;
+        let symbols = decoder.symbols().as_slice();
+        if format == Format::Json {
+            println!("{}", verify_json(&name, &report, symbols));
+            continue;
+        }
+        if paths.len() > 1 {
+            println!("{}:", name);
+        }
+        println!("valid message of {} bytes", report.size);
+        println!("{} values, maximum depth {}", report.values, report.depth);
+        println!("{} symbols, {} records, {} redefinitions", report.symbols, report.records, report.redefinitions);
+        for (i, entry) in symbols.iter().enumerate() {
+            match entry {
+                Refable::Sym(symbol) => println!("{:>6}  Sym  {}", i, symbol),
+                Refable::Rec(keys) => println!("{:>6}  Rec  ({})", i, keys.join(", ")),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn verify_json(name: &str, report: &Report, symbols: &[Refable]) -> String {
+    let mut out = String::new();
+    out.push_str("{\"input\":");
+    out.push_str(&to_json(&Value::Str(name.into())));
+    out.push_str(&format!(",\"size\":{},\"values\":{},\"depth\":{},\"symbols\":{},\"records\":{},\"redefinitions\":{},\"table\":[",
+        report.size, report.values, report.depth, report.symbols, report.records, report.redefinitions));
+    for (i, entry) in symbols.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let value = match entry {
+            Refable::Sym(symbol) => Value::Symbol((*symbol).into()),
+            Refable::Rec(keys) => Value::Array(keys.iter().map(|key| Value::Symbol((*key).into())).collect()),
+        };
+        out.push_str(&to_json(&value));
+    }
+    out.push_str("]}");
+    out
+}
+
+fn parse(buffer: &[u8], strict: bool) -> Result<Value<'_>> {
+    let string = from_utf8(buffer).context("input is not utf-8")?;
+    if strict {
+        nachricht_nq::parse(string)
+    } else {
+        nachricht_nq::parse_relaxed(string)
+    }
 }
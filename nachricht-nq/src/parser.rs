@@ -1,20 +1,98 @@
 use nom::{
-    character::complete::digit1,
+    character::complete::{digit1, one_of},
     Finish,
     IResult,
-    combinator::{all_consuming, map, map_res, opt, recognize, value},
-    sequence::{terminated, tuple, delimited},
+    combinator::{all_consuming, cond, map, map_opt, map_res, not, opt, recognize, value, verify},
+    sequence::{delimited, preceded, terminated, tuple},
     branch::alt,
-    bytes::complete::{tag, take_while, escaped_transform, is_not},
-    multi::separated_list0,
+    bytes::complete::{tag, tag_no_case, take_until, take_while, take_while1, take_while_m_n, escaped_transform, is_not},
+    multi::{many0, many1, separated_list0},
 };
 use nachricht::*;
 use anyhow::{anyhow, Result};
 use base64::decode;
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+
+/// Parse the canonical textual representation, as printed by the `Display` implementation of `Value`.
+pub fn parse(i: &str) -> Result<Value<'_>> {
+    parse_with(i, false)
+}
+
+/// Parse a more forgiving syntax meant for text written by hand: the commas between elements may be omitted or
+/// repeated and strings which cannot be mistaken for anything else need no quotes, e.g. `(name: Jessica age: 3)`.
+/// Everything `parse` accepts is accepted here too, with the same result.
+pub fn parse_relaxed(i: &str) -> Result<Value<'_>> {
+    parse_with(i, true)
+}
+
+fn parse_with(i: &str, relaxed: bool) -> Result<Value<'_>> {
+    let node = all_consuming(terminated(|i| nch_value(i, relaxed), white))(i).finish().map_err(|e| anyhow!("{}", e))?.1;
+    node.resolve(&mut Interner::default())
+}
+
+/// A parsed value whose references have not been resolved yet. References count the symbols and record layouts in the
+/// order of the wire format, in which the fields of a record are sorted by key, so they can only be resolved once the
+/// whole value is known.
+enum Node {
+    Value(Value<'static>),
+    Ref(usize),
+    Array(Vec<Node>),
+    Map(Vec<(Node, Node)>),
+    Record(Vec<(String, Node)>),
+}
+
+/// The symbol table the `Encoder` would build, `None` marks a record layout
+#[derive(Default)]
+struct Interner {
+    entries: Vec<Option<String>>,
+    symbols: HashSet<String>,
+    layouts: HashSet<Vec<String>>,
+}
+
+impl Interner {
+
+    fn symbol(&mut self, symbol: &str) {
+        if self.symbols.insert(symbol.to_owned()) {
+            self.entries.push(Some(symbol.to_owned()));
+        }
+    }
+
+}
+
+impl Node {
+
+    fn resolve(self, interner: &mut Interner) -> Result<Value<'static>> {
+        Ok(match self {
+            Node::Value(Value::Symbol(symbol)) => {
+                interner.symbol(&symbol);
+                Value::Symbol(symbol)
+            },
+            Node::Value(value) => value,
+            Node::Ref(index) => match interner.entries.get(index) {
+                Some(Some(symbol)) => Value::Symbol(Cow::Owned(symbol.clone())),
+                Some(None) => return Err(anyhow!("&{} refers to a record layout instead of a symbol", index)),
+                None => return Err(anyhow!("&{} refers to a symbol which has not been defined yet", index)),
+            },
+            Node::Array(elements) => Value::Array(elements.into_iter().map(|e| e.resolve(interner)).collect::<Result<_>>()?),
+            Node::Map(entries) => Value::Map(entries.into_iter().map(|(k, v)| Ok((k.resolve(interner)?, v.resolve(interner)?))).collect::<Result<_>>()?),
+            Node::Record(fields) => {
+                let len = fields.len();
+                let fields = fields.into_iter().collect::<BTreeMap<_, _>>();
+                if fields.len() < len {
+                    return Err(anyhow!("a record contains the same key more than once"));
+                }
+                let keys = fields.keys().cloned().collect::<Vec<_>>();
+                if !interner.layouts.contains(&keys) {
+                    keys.iter().for_each(|key| interner.symbol(key));
+                    interner.entries.push(None);
+                    interner.layouts.insert(keys);
+                }
+                Value::Record(fields.into_iter().map(|(k, v)| Ok((Cow::Owned(k), v.resolve(interner)?))).collect::<Result<_>>()?)
+            },
+        })
+    }
 
-pub fn parse(i: &str) -> Result<Value> {
-    Ok(all_consuming(terminated(nch_value, white))(i).finish().map_err(|e| anyhow!("{}", e))?.1)
 }
 
 pub enum Keyword {
@@ -23,11 +101,16 @@ pub enum Keyword {
     False,
 }
 
-const WHITESPACE: &'static str = " \t\r\n";
-const B64_CHARS: &'static str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz01234567890+/";
+const WHITESPACE: &str = " \t\r\n";
+const B64_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz01234567890+/";
 
+/// Insignificant whitespace including comments, which span until the end of the line or between `/*` and `*/`
 fn white(i: &str) -> IResult<&str, &str> {
-    take_while(move |c| WHITESPACE.contains(c))(i)
+    recognize(many0(alt((
+        take_while1(move |c| WHITESPACE.contains(c)),
+        recognize(tuple((tag("//"), take_while(|c| c != '\n')))),
+        recognize(tuple((tag("/*"), take_until("*/"), tag("*/")))),
+    ))))(i)
 }
 
 fn keyword(i: &str) -> IResult<&str, Keyword> {
@@ -38,28 +121,62 @@ fn keyword(i: &str) -> IResult<&str, Keyword> {
     ))(i)
 }
 
+/// A bare symbol or key. A slash which starts a comment ends it.
 fn identifier(i: &str) -> IResult<&str, &str> {
-    is_not(" \\$,:\"'()#\n")(i)
+    recognize(many1(alt((
+        take_while1(|c| c != '/' && Value::is_identifier_char(c)),
+        terminated(tag("/"), not(alt((tag("/"), tag("*"))))),
+    ))))(i)
 }
 
+/// A decimal number with an optional exponent or one of the special values `inf` and `nan`
 fn float(i: &str) -> IResult<&str, &str> {
-    recognize(tuple((opt(tag("-")), opt(digit1), opt(tag(".")), opt(digit1))))(i)
+    recognize(tuple((opt(tag("-")), alt((
+        tag_no_case("inf"),
+        tag_no_case("nan"),
+        recognize(tuple((opt(digit1), opt(tag(".")), opt(digit1), opt(tuple((one_of("eE"), opt(one_of("+-")), digit1)))))),
+    )))))(i)
+}
+
+/// The bit pattern of a NaN other than the default one, e.g. `(0x7fc00001)` after `$nan`
+fn nan_bits(i: &str) -> IResult<&str, u64> {
+    map_res(delimited(tag_no_case("nan(0x"), take_while_m_n(1, 16, |c: char| c.is_ascii_hexdigit()), tag(")")), |hex| u64::from_str_radix(hex, 16))(i)
 }
 
 fn float32(i: &str) -> IResult<&str, f32> {
-    map_res(tuple((tag("$"), float)), |(_,n)| n.parse())(i)
+    alt((
+        verify(map_opt(preceded(tag("$"), nan_bits), |bits| u32::try_from(bits).ok().map(f32::from_bits)), |v| v.is_nan()),
+        map_res(tuple((tag("$"), float)), |(_,n)| n.parse()),
+        map(tag("-$inf"), |_| f32::NEG_INFINITY),
+    ))(i)
 }
 
 fn float64(i: &str) -> IResult<&str, f64> {
-    map_res(tuple((tag("$$"), float)), |(_,n)| n.parse())(i)
+    alt((
+        verify(map(preceded(tag("$$"), nan_bits), f64::from_bits), |v| v.is_nan()),
+        map_res(tuple((tag("$$"), float)), |(_,n)| n.parse()),
+        map(tag("-$$inf"), |_| f64::NEG_INFINITY),
+    ))(i)
 }
 
-fn intn(i: &str) -> IResult<&str, u64> {
-    map_res(tuple((tag("-"), digit1)), |(_,n): (&str, &str)| n.parse())(i)
+/// Digits which may be grouped by underscores, e.g. `1_000_000`
+fn digits<'a>(digit: fn(char) -> bool) -> impl FnMut(&'a str) -> IResult<&'a str, String> {
+    map(recognize(tuple((take_while_m_n(1, 1, digit), take_while(move |c| digit(c) || c == '_')))), |d: &str| d.replace('_', ""))
 }
 
-fn intp(i: &str) -> IResult<&str, u64> {
-    map_res(digit1, |n: &str| n.parse())(i)
+/// An integer in decimal or, prefixed with `0x`, hexadecimal notation with an optional sign. Negative zero is
+/// transparently converted to zero.
+fn integer(i: &str) -> IResult<&str, (Sign, u64)> {
+    map(tuple((
+        opt(alt((value(Sign::Neg, tag("-")), value(Sign::Pos, tag("+"))))),
+        alt((
+            map_res(preceded(tag_no_case("0x"), digits(|c| c.is_ascii_hexdigit())), |d| u64::from_str_radix(&d, 16)),
+            map_res(digits(|c| c.is_ascii_digit()), |d| d.parse()),
+        )),
+    )), |(sign, n)| match sign {
+        Some(Sign::Neg) if n > 0 => (Sign::Neg, n),
+        _ => (Sign::Pos, n),
+    })(i)
 }
 
 fn b64(i: &str) -> IResult<&str, &str> {
@@ -74,6 +191,17 @@ fn bytes(i: &str) -> IResult<&str, Vec<u8>> {
     )(i)
 }
 
+/// Bytes in hexadecimal, e.g. `x'deadbeef'`
+fn hex_bytes(i: &str) -> IResult<&str, Vec<u8>> {
+    map_opt(delimited(
+        tag("x'"),
+        take_while(|c: char| c.is_ascii_hexdigit()),
+        tag("'")), |hex: &str| {
+            (0..hex.len()).step_by(2).map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok())).collect()
+        }
+    )(i)
+}
+
 fn escaped_string(i: &str) -> IResult<&str, String> {
     delimited(
         tag("\""),
@@ -82,9 +210,12 @@ fn escaped_string(i: &str) -> IResult<&str, String> {
                 is_not("\\\""),
                 '\\',
                 alt((
-                    value("\\", tag("\\")),
-                    value("\n", tag("n")),
-                    value("\"", tag("\"")),
+                    value('\\', tag("\\")),
+                    value('\n', tag("n")),
+                    value('\r', tag("r")),
+                    value('\t', tag("t")),
+                    value('"', tag("\"")),
+                    unicode,
                 ))
             ),
             map(tag(""), String::from)
@@ -93,6 +224,14 @@ fn escaped_string(i: &str) -> IResult<&str, String> {
     )(i)
 }
 
+/// An escaped character given by its hexadecimal code point, e.g. `u{1b}`
+fn unicode(i: &str) -> IResult<&str, char> {
+    map_opt(
+        delimited(tag("u{"), take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()), tag("}")),
+        |hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+    )(i)
+}
+
 fn symbol(i: &str) -> IResult<&str, String> {
     alt((
             map(tuple((tag("#"), identifier)), |(_,i)| String::from(i)),
@@ -100,65 +239,101 @@ fn symbol(i: &str) -> IResult<&str, String> {
     ))(i)
 }
 
-fn array(i: &str) -> IResult<&str, Vec<Value>> {
+/// The elements of a container, separated by commas with an optional trailing comma. In relaxed mode, the commas
+/// between elements may also be omitted or repeated.
+fn elements<'a, O, F>(mut element: F, relaxed: bool) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>>
+where F: FnMut(&'a str) -> IResult<&'a str, O> {
+    move |i| {
+        let (i, elements) = if relaxed {
+            many0(terminated(&mut element, many0(tuple((white, tag(","))))))(i)?
+        } else {
+            separated_list0(tag(","), &mut element)(i)?
+        };
+        let (i, _) = tuple((white, opt(tag(",")), white))(i)?;
+        Ok((i, elements))
+    }
+}
+
+fn array(i: &str, relaxed: bool) -> IResult<&str, Vec<Node>> {
     delimited(
         tag("["),
-        map(tuple((separated_list0(tag(","), nch_value), white, opt(tag(",")), white)), |(l,_,_,_)| l),
+        elements(|i| nch_value(i, relaxed), relaxed),
         tag("]"),
     )(i)
 }
 
-fn nch_map(i: &str) -> IResult<&str, Vec<(Value, Value)>> {
+fn nch_map(i: &str, relaxed: bool) -> IResult<&str, Vec<(Node, Node)>> {
     delimited(
         tag("{"),
-        map(tuple((separated_list0(tag(","), entry), white, opt(tag(",")), white)), |(l,_,_,_)| l),
+        elements(|i| entry(i, relaxed), relaxed),
         tag("}"),
     )(i)
 }
 
-fn record(i: &str) -> IResult<&str, Vec<(String, Value)>> {
+fn record(i: &str, relaxed: bool) -> IResult<&str, Vec<(String, Node)>> {
     delimited(
         tag("("),
-        map(tuple((separated_list0(tag(","), field), white, opt(tag(",")), white)), |(l,_,_,_)| l),
+        elements(|i| field(i, relaxed), relaxed),
         tag(")"),
     )(i)
 }
 
-fn entry(i: &str) -> IResult<&str, (Value, Value)> {
-    map(tuple((nch_value, white, tag(":"), white, nch_value)), |(l,_,_,_,r)| (l, r))(i)
+fn entry(i: &str, relaxed: bool) -> IResult<&str, (Node, Node)> {
+    map(tuple((|i| nch_value(i, relaxed), white, tag(":"), white, |i| nch_value(i, relaxed))), |(l,_,_,_,r)| (l, r))(i)
+}
+
+fn field(i: &str, relaxed: bool) -> IResult<&str, (String, Node)> {
+    map(tuple((white, key, white, tag(":"), white, |i| nch_value(i, relaxed))), |(_,l,_,_,_,r)| (l, r))(i)
+}
+
+/// A string without quotes, which the relaxed syntax accepts if it cannot be mistaken for anything else: it has to
+/// consist of characters allowed in bare symbols, must not be a keyword and must not start with a digit, a sign or `&`.
+fn unquoted(i: &str) -> IResult<&str, &str> {
+    verify(identifier, |s: &str| !["null", "true", "false"].contains(&s) && !s.starts_with(|c: char| c.is_ascii_digit() || "-+&".contains(c)))(i)
 }
 
-fn field(i: &str) -> IResult<&str, (String, Value)> {
-    map(tuple((white, key, white, tag(":"), white, nch_value)), |(_,l,_,_,_,r)| (l, r))(i)
+/// A reference to a symbol by its index in the symbol table, e.g. `&3`
+fn reference(i: &str) -> IResult<&str, usize> {
+    map_res(preceded(tag("&"), digit1), |n: &str| n.parse())(i)
 }
 
-fn nch_value(i: &str) -> IResult<&str, Value> {
+fn nch_value(i: &str, relaxed: bool) -> IResult<&str, Node> {
     map(tuple((
             white,
             alt((
-                map(array, |f| Value::Array(f)),
-                map(nch_map, |f| Value::Map(f)),
-                map(record, |f| Value::Record(f.into_iter().map(|(k, v)| (Cow::Owned(k), v)).collect())),
-                map(symbol, |s| Value::Symbol(Cow::Owned(s))),
-                map(escaped_string, |s| Value::Str(Cow::Owned(s))),
-                map(bytes, |b| Value::Bytes(Cow::Owned(b))),
-                map(intn, |i| Value::Int(Sign::Neg, i)),
-                map(intp, |i| Value::Int(Sign::Pos, i)),
-                map(float32, |f| Value::F32(f)),
-                map(float64, |f| Value::F64(f)),
-                map(keyword, |k| match k {
-                    Keyword::Null => Value::Null,
-                    Keyword::True => Value::Bool(true),
-                    Keyword::False => Value::Bool(false)
-                })
+                map(|i| array(i, relaxed), Node::Array),
+                map(|i| nch_map(i, relaxed), Node::Map),
+                map(|i| record(i, relaxed), Node::Record),
+                map(reference, Node::Ref),
+                map(scalar(relaxed), Node::Value),
             )),
+            // without commas, adjacent values need to be separated by something, e.g. `1abc` is not `1, abc`
+            cond(relaxed, not(identifier)),
             white
-        )), |(_,v,_)| v)(i)
+        )), |(_,v,_,_)| v)(i)
+}
+
+fn scalar<'a>(relaxed: bool) -> impl FnMut(&'a str) -> IResult<&'a str, Value<'static>> {
+    alt((
+        map(hex_bytes, |b| Value::Bytes(Cow::Owned(b))),
+        map(verify(unquoted, move |_: &str| relaxed), |s| Value::Str(Cow::Owned(s.to_owned()))),
+        map(symbol, |s| Value::Symbol(Cow::Owned(s))),
+        map(escaped_string, |s| Value::Str(Cow::Owned(s))),
+        map(bytes, |b| Value::Bytes(Cow::Owned(b))),
+        map(integer, |(s, i)| Value::Int(s, i)),
+        map(float32, Value::F32),
+        map(float64, Value::F64),
+        map(keyword, |k| match k {
+            Keyword::Null => Value::Null,
+            Keyword::True => Value::Bool(true),
+            Keyword::False => Value::Bool(false)
+        })
+    ))
 }
 
 fn key(i: &str) -> IResult<&str, String> {
     alt((
-            map(identifier, |i| String::from(i)),
+            map(identifier, String::from),
             escaped_string,
     ))(i)
 }
@@ -181,12 +356,45 @@ mod tests {
     fn integers() {
         assert_eq!(super::parse("123").unwrap(), Value::Int(Sign::Pos, 123));
         assert_eq!(super::parse("-123").unwrap(), Value::Int(Sign::Neg, 123));
+        assert_eq!(super::parse("+5").unwrap(), Value::Int(Sign::Pos, 5));
+        assert_eq!(super::parse("-0").unwrap(), Value::Int(Sign::Pos, 0));
+        assert_eq!(super::parse("1_000_000").unwrap(), Value::Int(Sign::Pos, 1_000_000));
+        assert_eq!(super::parse("0xFF").unwrap(), Value::Int(Sign::Pos, 255));
+        assert!(super::parse("-0x_dead_beef").is_err());
+        assert_eq!(super::parse("-0Xdead_BEEF").unwrap(), Value::Int(Sign::Neg, 0xdead_beef));
+        assert_eq!(super::parse("0xffff_ffff_ffff_ffff").unwrap(), Value::Int(Sign::Pos, u64::MAX));
+        assert!(super::parse("0x1_0000_0000_0000_0000").is_err());
+        assert!(super::parse("_1").is_err());
+        assert!(super::parse("0x").is_err());
+        assert_eq!(super::parse_relaxed("[+5 0x10]").unwrap(), Value::Array(vec![Value::Int(Sign::Pos, 5), Value::Int(Sign::Pos, 16)]));
     }
 
     #[test]
     fn floats() {
         assert_eq!(super::parse("$123").unwrap(), Value::F32(123f32));
         assert_eq!(super::parse("$$123").unwrap(), Value::F64(123f64));
+        assert_eq!(super::parse("$1.5e-3").unwrap(), Value::F32(1.5e-3));
+        assert_eq!(super::parse("$$-2E10").unwrap(), Value::F64(-2e10));
+        assert_eq!(super::parse("$inf").unwrap(), Value::F32(f32::INFINITY));
+        assert_eq!(super::parse("-$inf").unwrap(), Value::F32(f32::NEG_INFINITY));
+        assert_eq!(super::parse("$$-inf").unwrap(), Value::F64(f64::NEG_INFINITY));
+        assert_eq!(super::parse("-$$inf").unwrap(), Value::F64(f64::NEG_INFINITY));
+        assert!(matches!(super::parse("$nan").unwrap(), Value::F32(v) if v.is_nan()));
+        assert!(matches!(super::parse("$$NaN").unwrap(), Value::F64(v) if v.is_nan()));
+        for v in [f32::MAX, f32::MIN_POSITIVE, f32::EPSILON, 1e-45, -0.1, 16_777_217.0, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(super::parse(&Value::F32(v).to_string()).unwrap(), Value::F32(v));
+        }
+        for v in [f64::MAX, f64::MIN, f64::MIN_POSITIVE, 5e-324, 0.1 + 0.2, std::f64::consts::PI, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(super::parse(&Value::F64(v).to_string()).unwrap(), Value::F64(v));
+        }
+        assert!(matches!(super::parse(&Value::F64(f64::NAN).to_string()).unwrap(), Value::F64(v) if v.is_nan()));
+        // NaNs other than the default one keep their sign and payload
+        let nan = f32::from_bits(0xffc0_0001);
+        assert_eq!(Value::F32(nan).to_string(), "$nan(0xffc00001)");
+        assert!(matches!(super::parse("$nan(0xffc00001)").unwrap(), Value::F32(v) if v.to_bits() == nan.to_bits()));
+        assert!(matches!(super::parse("$$NaN(0x7ff0000000000001)").unwrap(), Value::F64(v) if v.to_bits() == 0x7ff0_0000_0000_0001));
+        assert!(super::parse("$nan(0x7f800000)").is_err());
+        assert!(super::parse("$nan(0x7ff0000000000001)").is_err());
     }
 
     #[test]
@@ -195,6 +403,11 @@ mod tests {
         assert_eq!(super::parse("\"abc\"").unwrap(), Value::Str(Cow::Borrowed("abc")));
         assert_eq!(super::parse("\"abc\\\"def\"").unwrap(), Value::Str(Cow::Borrowed("abc\"def")));
         assert_eq!(super::parse("\"abc\\\\def\"").unwrap(), Value::Str(Cow::Borrowed("abc\\def")));
+        assert_eq!(super::parse("\"a\\tb\\r\\u{1b}\\u{1F408}\"").unwrap(), Value::Str(Cow::Borrowed("a\tb\r\u{1b}\u{1f408}")));
+        assert!(super::parse("\"\\u{d800}\"").is_err());
+        let escaped = Value::Str(Cow::Borrowed("\"\\\n\r\t\u{0}\u{7f}\u{85}"));
+        assert_eq!(escaped.to_string(), "\"\\\"\\\\\\n\\r\\t\\u{0}\\u{7f}\\u{85}\"");
+        assert_eq!(super::parse(&escaped.to_string()).unwrap(), escaped);
     }
 
     #[test]
@@ -202,10 +415,31 @@ mod tests {
         assert_eq!(super::parse("'base64//'").unwrap(), Value::Bytes(Cow::Borrowed(&[109, 171, 30, 235, 143, 255])));
     }
 
+    #[test]
+    fn hex() {
+        assert_eq!(super::parse("x'DEADbeef'").unwrap(), Value::Bytes(Cow::Borrowed(&[0xde, 0xad, 0xbe, 0xef])));
+        assert_eq!(super::parse("x''").unwrap(), Value::Bytes(Cow::Borrowed(&[])));
+        assert!(super::parse("x'abc'").is_err());
+        assert_eq!(super::parse_relaxed("[x'00' x]").unwrap(), Value::Array(vec![Value::Bytes(Cow::Borrowed(&[0])), Value::Str(Cow::Borrowed("x"))]));
+        let value = Value::Bytes(Cow::Borrowed(&[1, 2]));
+        assert_eq!(super::parse(&value.to_text(TextOptions { hex_bytes: 2, ..Default::default() })).unwrap(), value);
+    }
+
     #[test]
     fn symbol() {
         assert_eq!(super::parse("#abc").unwrap(), Value::Symbol(Cow::Borrowed("abc")));
         assert_eq!(super::parse("#\"a\\\"bc\"").unwrap(), Value::Symbol(Cow::Borrowed("a\"bc")));
+        assert_eq!(super::parse("#\u{1f408}").unwrap(), Value::Symbol(Cow::Borrowed("\u{1f408}")));
+        assert_eq!(super::parse("#猫").unwrap(), Value::Symbol(Cow::Borrowed("猫")));
+        assert!(super::parse("#a\u{3000}b").is_err());
+        for text in ["", "a\u{3000}b", "a\u{200b}b", "a[b"] {
+            let symbol = Value::Symbol(Cow::Borrowed(text));
+            assert!(symbol.to_string().starts_with("#\""));
+            assert_eq!(super::parse(&symbol.to_string()).unwrap(), symbol);
+        }
+        let tab = Value::Symbol(Cow::Borrowed("a\tb"));
+        assert_eq!(tab.to_string(), "#\"a\\tb\"");
+        assert_eq!(super::parse(&tab.to_string()).unwrap(), tab);
     }
 
     #[test]
@@ -224,6 +458,11 @@ mod tests {
                     (Cow::Borrowed("x"), Value::Bool(true)),
                     (Cow::Borrowed("y"), Value::Bool(false)),
         ])));
+        assert!(super::parse("(x: true, x: false)").is_err());
+        assert_eq!(super::parse("(名前: \"Jessica\", \"🐈 \": true)").unwrap(), Value::Record(BTreeMap::from([
+                    (Cow::Borrowed("名前"), Value::Str(Cow::Borrowed("Jessica"))),
+                    (Cow::Borrowed("🐈 "), Value::Bool(true)),
+        ])));
     }
 
     #[test]
@@ -235,6 +474,68 @@ mod tests {
         ]));
     }
 
+    #[test]
+    fn relaxed() {
+        let message = "(name: Jessica, species: #FelisCatus
+            toys: [ball, mouse,, 3 null] owner: (name: \"Liv\"),,
+            stats: {height: $0.3 weight: $$4.5} nullable: nullable)";
+        let expected = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+            (Cow::Borrowed("nullable"), Value::Str(Cow::Borrowed("nullable"))),
+            (Cow::Borrowed("owner"), Value::Record(BTreeMap::from([(Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Liv")))]))),
+            (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus"))),
+            (Cow::Borrowed("stats"), Value::Map(vec![
+                (Value::Str(Cow::Borrowed("height")), Value::F32(0.3)),
+                (Value::Str(Cow::Borrowed("weight")), Value::F64(4.5)),
+            ])),
+            (Cow::Borrowed("toys"), Value::Array(vec![
+                Value::Str(Cow::Borrowed("ball")),
+                Value::Str(Cow::Borrowed("mouse")),
+                Value::Int(Sign::Pos, 3),
+                Value::Null,
+            ])),
+        ]));
+        assert_eq!(super::parse_relaxed(message).unwrap(), expected);
+        assert!(super::parse(message).is_err());
+        assert!(super::parse("[1 2]").is_err());
+        assert!(super::parse_relaxed("[1abc]").is_err());
+        assert_eq!(super::parse_relaxed(&expected.to_string()).unwrap(), expected);
+    }
+
+    #[test]
+    fn references() {
+        // species is defined as a key at 0, followed by the layout at 1
+        let value = super::parse("[(species: #FelisCatus), (species: &2), #x, &0, &3]").unwrap();
+        let cat = Value::Record(BTreeMap::from([(Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus")))]));
+        assert_eq!(value, Value::Array(vec![cat.clone(), cat, Value::Symbol(Cow::Borrowed("x")), Value::Symbol(Cow::Borrowed("species")), Value::Symbol(Cow::Borrowed("x"))]));
+        // the fields of a record are counted in the order of their keys
+        assert_eq!(super::parse("(b: &3, a: #x)").unwrap(), Value::Record(BTreeMap::from([
+            (Cow::Borrowed("a"), Value::Symbol(Cow::Borrowed("x"))),
+            (Cow::Borrowed("b"), Value::Symbol(Cow::Borrowed("x"))),
+        ])));
+        assert!(super::parse("[#x, &1]").is_err());
+        assert!(super::parse("[(a: 1), &1]").is_err());
+        assert!(super::parse_relaxed("{&0: #a}").is_err());
+        assert_eq!(super::parse("#&0").unwrap(), Value::Symbol(Cow::Borrowed("&0")));
+    }
+
+    #[test]
+    fn comments() {
+        let message = "// a cat
+            ( /* the name */ name: \"Jess//ica\", // until the end of the line
+              species: #Felis/Catus/* a symbol */, url: #a/b//c,
+            ) /* trailing */";
+        assert_eq!(super::parse(message).unwrap(), Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jess//ica"))),
+            (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("Felis/Catus"))),
+            (Cow::Borrowed("url"), Value::Symbol(Cow::Borrowed("a/b"))),
+        ])));
+        assert!(super::parse("[1, /* unterminated ]").is_err());
+        let symbol = Value::Symbol(Cow::Borrowed("a//b"));
+        assert_eq!(symbol.to_string(), "#\"a//b\"");
+        assert_eq!(super::parse(&symbol.to_string()).unwrap(), symbol);
+    }
+
     #[test]
     fn canonical() {
         let message = "( cats: [ ( name: \"Jessica\", species: #PrionailurusViverrinus, ), ( name: \"Wantan\", species: #LynxLynx, ), ( name: \"Sphinx\", species: #FelisCatus, ), ( name: \"Chandra\", species: #PrionailurusViverrinus, ), ], version: 1, )";
@@ -259,7 +560,7 @@ mod tests {
             ])),
             (Cow::Borrowed("version"), Value::Int(Sign::Pos, 1)),
         ]));
-        assert_eq!(super::parse(&message).unwrap(), expected);
+        assert_eq!(super::parse(message).unwrap(), expected);
     }
 
 }
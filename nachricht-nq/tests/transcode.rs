@@ -0,0 +1,132 @@
+//! Messages have to survive the way from the wire format to the textual representation and back without any loss, so
+//! that text can be used wherever the wire format is: fixtures, logs, `nq --text --encode` in scripts. These tests
+//! check this with the `proptest` strategies of `nachricht` for arbitrary values and for messages written by
+//! `nachricht-serde` with all its options.
+//!
+//! The decoder only keeps what a `Value` can express, e.g. records forget the order of their fields on the wire, so the
+//! comparison is made between the canonical encodings. Messages which are canonical already have to come back
+//! byte for byte.
+
+use nachricht::*;
+use nachricht_nq::{parse, parse_relaxed, render_with_options, Format};
+use nachricht_serde::{to_bytes_with_options, FieldOrder, SerializerOptions};
+use proptest::collection::{btree_map, hash_map, vec};
+use proptest::option;
+use proptest::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+fn encoded(value: &Value) -> Vec<u8> {
+    let mut buf = Vec::new();
+    Encoder::encode(value, &mut buf).unwrap();
+    buf
+}
+
+/// The printer options worth checking, starting with the canonical form
+fn printers() -> Vec<TextOptions> {
+    vec![
+        TextOptions::default(),
+        TextOptions { hex_bytes: 8, references: true, ..Default::default() },
+        TextOptions { hex_bytes: 4, references: true, indent: 3, align: true, ..Default::default() },
+        TextOptions { indent: 0, align: true, ..Default::default() },
+        TextOptions { indent: 7, references: true, align: true, ..Default::default() },
+    ]
+}
+
+/// Decode the message, print it in all variants and parse each back, in both syntaxes. Returns the canonical encoding.
+fn assert_lossless(wire: &[u8]) -> Vec<u8> {
+    let decoded = Decoder::decode(wire).unwrap().0;
+    let canonical = encoded(&decoded);
+    for options in printers() {
+        let text = render_with_options(&decoded, Format::Text, options).unwrap();
+        let parsed = parse(&text).unwrap_or_else(|e| panic!("{}\n{}", text, e));
+        assert_eq!(canonical, encoded(&parsed), "{}", text);
+        let relaxed = parse_relaxed(&text).unwrap_or_else(|e| panic!("{}\n{}", text, e));
+        assert_eq!(canonical, encoded(&relaxed), "{}", text);
+    }
+    // maps sorted for printing are sorted on the wire as well
+    let text = decoded.to_text(TextOptions { sort_maps: true, ..Default::default() });
+    let mut sorted = decoded.clone();
+    canonicalize(&mut sorted).unwrap();
+    assert_eq!(encoded(&sorted), encoded(&parse(&text).unwrap()), "{}", text);
+    canonical
+}
+
+#[derive(Debug, Clone, Serialize)]
+enum Species {
+    FelisCatus,
+    LynxLynx { subspecies: Option<String> },
+    Hybrid(Box<Species>, Box<Species>),
+}
+
+/// The fields are not declared in alphabetical order
+#[derive(Debug, Clone, Serialize)]
+struct Cat {
+    name: String,
+    species: Species,
+    weight: f32,
+    height: f64,
+    lives: i8,
+    #[serde(with = "serde_bytes")]
+    chip: Vec<u8>,
+    tags: BTreeMap<String, Option<String>>,
+    scores: HashMap<u32, Vec<f64>>,
+    nickname: Option<Option<String>>,
+    kittens: Vec<Cat>,
+}
+
+fn species() -> impl Strategy<Value = Species> {
+    prop_oneof![
+        Just(Species::FelisCatus),
+        option::of(any_string(6)).prop_map(|subspecies| Species::LynxLynx { subspecies }),
+        Just(Species::Hybrid(Box::new(Species::FelisCatus), Box::new(Species::LynxLynx { subspecies: None }))),
+    ]
+}
+
+fn cats(depth: u32) -> BoxedStrategy<Vec<Cat>> {
+    if depth == 0 {
+        return Just(Vec::new()).boxed();
+    }
+    let cat = (
+        any_string(6),
+        species(),
+        prop_oneof![any::<f32>(), any::<u32>().prop_map(f32::from_bits)],
+        prop_oneof![any::<f64>(), any::<u64>().prop_map(f64::from_bits)],
+        any::<i8>(),
+        vec(any::<u8>(), 0..8),
+        btree_map(any_string(6), option::of(any_string(6)), 0..3),
+        hash_map(any::<u32>(), vec(any::<f64>(), 0..3), 0..3),
+        option::of(option::of(any_string(6))),
+        cats(depth - 1),
+    );
+    vec(cat, 0..3).prop_map(|cats| cats.into_iter().map(|(name, species, weight, height, lives, chip, tags, scores, nickname, kittens)| {
+        Cat { name, species, weight, height, lives, chip, tags, scores, nickname, kittens }
+    }).collect()).boxed()
+}
+
+proptest! {
+    #[test]
+    fn generated(value in any::<Value>()) {
+        let wire = encoded(&value);
+        prop_assert_eq!(&wire, &assert_lossless(&wire));
+    }
+
+    #[test]
+    fn encoder_options(value in any::<Value>(), symbol_keys in any::<bool>()) {
+        let options = EncoderOptions { symbol_keys, symbol_strings: !symbol_keys, ..Default::default() };
+        let mut wire = Vec::new();
+        Encoder::encode_with_options(&value, &mut wire, options).unwrap();
+        // strings come back as symbols, which is canonical for them
+        prop_assert_eq!(&wire, &assert_lossless(&wire));
+    }
+
+    #[test]
+    fn serialized(cats in cats(3), symbol_keys in any::<bool>(), symbol_strings in any::<bool>(), tagged_options in any::<bool>()) {
+        let options = SerializerOptions { symbol_keys, symbol_strings, tagged_options, ..Default::default() };
+        assert_lossless(&to_bytes_with_options(&cats, options).unwrap());
+        // sorted fields are what the `Encoder` writes, so these messages are canonical
+        let options = SerializerOptions { field_order: FieldOrder::Alphabetical, ..options };
+        let wire = to_bytes_with_options(&cats, options).unwrap();
+        prop_assert_eq!(&wire, &assert_lossless(&wire));
+    }
+}
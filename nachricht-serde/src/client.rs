@@ -0,0 +1,113 @@
+//! Talk to HTTP services which speak `nachricht` with a `ureq` client. Requests are labelled and negotiated with
+//! `nachricht::MEDIA_TYPE`, responses are checked for it and limited in size before they get decoded:
+//!
+//! ```no_run
+//! use nachricht_serde::{read_nachricht, send_nachricht};
+//!
+//! let agent = ureq::Agent::new();
+//! let response = send_nachricht(&agent, "http://localhost:8080/cats", &("Jessica", 9)).unwrap();
+//! let (name, lives): (String, u8) = read_nachricht(response, 1 << 20).unwrap();
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use nachricht::{is_media_type, MEDIA_TYPE};
+use std::io::Read;
+use ureq::{Agent, Response};
+
+use crate::de::from_bytes;
+use crate::error::{Error, Result};
+use crate::ser::to_bytes;
+
+/// POST the value to the URL. Responses with an error status are returned as `Error::Http`, whose response can still
+/// be read with `read_nachricht`.
+pub fn send_nachricht<T: ?Sized + Serialize>(agent: &Agent, url: &str, value: &T) -> Result<Response> {
+    let body = to_bytes(&value)?;
+    agent.post(url)
+        .set("Content-Type", MEDIA_TYPE)
+        .set("Accept", MEDIA_TYPE)
+        .send_bytes(&body)
+        .map_err(|e| Error::Http(Box::new(e)))
+}
+
+/// Decode the body of a response. Fails with `Error::ContentType` if it is not labelled as `nachricht` and with
+/// `Error::Limit` if it exceeds `limit` bytes, in which case only the first `limit + 1` bytes have been read.
+pub fn read_nachricht<T: DeserializeOwned>(response: Response, limit: usize) -> Result<T> {
+    let content_type = response.header("Content-Type").unwrap_or_default();
+    if !is_media_type(content_type) {
+        return Err(Error::ContentType(content_type.to_owned()));
+    }
+    let mut body = Vec::new();
+    response.into_reader().take(limit as u64 + 1).read_to_end(&mut body)?;
+    if body.len() > limit {
+        return Err(Error::Limit(limit));
+    }
+    from_bytes(&body).map_err(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use super::{read_nachricht, send_nachricht};
+    use crate::error::Error;
+    use crate::ser::to_bytes;
+
+    /// Answer one request per response with the given status and content type. A response without a body echoes the
+    /// body of the request.
+    fn serve(responses: Vec<(u16, &'static str, Option<Vec<u8>>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for (status, content_type, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let (mut length, mut labelled) = (0, false);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(value) = lower.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    labelled |= lower.trim() == "content-type: application/x-nachricht";
+                    line.clear();
+                }
+                assert!(labelled);
+                let mut request = vec![0; length];
+                reader.read_exact(&mut request).unwrap();
+                let body = body.unwrap_or(request);
+                let mut stream = reader.into_inner();
+                write!(stream, "HTTP/1.1 {} X\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn roundtrip() {
+        let cats = vec![("Jessica".to_string(), 9u8), ("Wantan".to_string(), 7)];
+        let url = serve(vec![
+            (200, "application/x-nachricht", None),
+            (200, "application/x-nachricht; charset=binary", None),
+            (200, "application/json", Some(b"[]".to_vec())),
+            (500, "application/x-nachricht", Some(to_bytes(&"oops").unwrap())),
+        ]);
+        let agent = ureq::Agent::new();
+        let response = send_nachricht(&agent, &url, &cats).unwrap();
+        assert_eq!(cats, read_nachricht::<Vec<(String, u8)>>(response, 1024).unwrap());
+        let response = send_nachricht(&agent, &url, &cats).unwrap();
+        assert!(matches!(read_nachricht::<Vec<(String, u8)>>(response, 8), Err(Error::Limit(8))));
+        let response = send_nachricht(&agent, &url, &cats).unwrap();
+        assert!(matches!(read_nachricht::<Vec<(String, u8)>>(response, 1024), Err(Error::ContentType(t)) if t == "application/json"));
+        match send_nachricht(&agent, &url, &cats) {
+            Err(Error::Http(e)) => match *e {
+                ureq::Error::Status(500, response) => assert_eq!("oops", read_nachricht::<String>(response, 1024).unwrap()),
+                e => panic!("unexpected error {}", e),
+            },
+            _ => panic!("expected an error status"),
+        }
+    }
+
+}
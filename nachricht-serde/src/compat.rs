@@ -0,0 +1,96 @@
+//! Checks that messages stay compatible between released versions. For every release, `compat/` holds a corpus
+//! message which that release serialized from `corpus()`. The current version needs to deserialize each of them into
+//! the corpus and, unless the wire format changed in a way which older releases can read, serialize the corpus into
+//! the very same bytes, so that the releases can keep talking to each other in both directions.
+//!
+//! When publishing a release, serialize `corpus()` with it into `compat/<version>.hex` and add the version below. The
+//! corpus must only ever be extended with fields carrying `#[serde(default)]`, so that old messages stay valid.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::fixtures::parse;
+use crate::{from_bytes, to_bytes};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum Species {
+    PrionailurusViverrinus,
+    LynxLynx,
+    FelisCatus,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum Event {
+    Born,
+    Renamed(String),
+    Moved { from: (f64, f64), to: (f64, f64) },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Cat {
+    name: String,
+    species: Species,
+    lives: u8,
+    weight: f32,
+    chip: Option<u64>,
+    #[serde(with = "serde_bytes")]
+    photo: Vec<u8>,
+    history: Vec<Event>,
+    kittens: Vec<Cat>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Corpus {
+    version: u32,
+    offset: i64,
+    cats: Vec<Cat>,
+    by_id: BTreeMap<u32, String>,
+    tags: BTreeMap<String, Vec<char>>,
+    nothing: (),
+}
+
+/// A message exercising every part of the data model, with repeated layouts and symbols as well as nested records
+fn corpus() -> Corpus {
+    let cat = |name: &str, species, kittens| Cat {
+        name: name.to_string(),
+        species,
+        lives: 9,
+        weight: 4.5,
+        chip: None,
+        photo: vec![],
+        history: vec![Event::Born],
+        kittens,
+    };
+    let mut jessica = cat("Jessica", Species::PrionailurusViverrinus, vec![
+        cat("Wantan", Species::LynxLynx, vec![]),
+        cat("Sphinx", Species::FelisCatus, vec![]),
+    ]);
+    jessica.chip = Some(u64::MAX);
+    jessica.photo = vec![0x00, 0x7f, 0x80, 0xff];
+    jessica.history.push(Event::Renamed("Jess".to_string()));
+    jessica.history.push(Event::Moved { from: (48.1, 11.6), to: (-33.9, 18.4) });
+    Corpus {
+        version: 1,
+        offset: i64::MIN,
+        cats: vec![jessica, cat("Chandra", Species::PrionailurusViverrinus, vec![])],
+        by_id: BTreeMap::from([(0, "Jessica".to_string()), (1337, "Chandra".to_string())]),
+        tags: BTreeMap::from([("síla".to_string(), vec!['ß', '🐈']), ("".to_string(), vec![])]),
+        nothing: (),
+    }
+}
+
+macro_rules! releases {
+    ($($name:ident: $version:literal,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let bytes = parse(include_str!(concat!("../compat/", $version, ".hex")));
+                assert_eq!(corpus(), from_bytes::<Corpus>(&bytes).unwrap(), "message of {} differs from the corpus", $version);
+                assert_eq!(bytes, to_bytes(&corpus()).unwrap(), "serialized corpus differs from the one of {}", $version);
+            }
+        )*
+    };
+}
+
+releases! {
+    v0_4_0: "0.4.0",
+}
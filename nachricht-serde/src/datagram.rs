@@ -0,0 +1,83 @@
+//! Distribute a sequence of values across fixed-size datagrams for transports like UDP or QUIC which neither fragment
+//! nor reassemble. Values are never split: every datagram contains one or more complete messages, each with its own
+//! symbol table, so datagrams can be decoded independently of each other and in any order.
+
+use serde::{Deserialize, Serialize};
+use nachricht::EncodeError;
+
+use crate::de::from_prefix;
+use crate::error::{Error, Result};
+use crate::ser::to_slice;
+
+/// Serialize the values into as many datagrams of at most `size` bytes as needed. Fails with
+/// `EncodeError::BufferTooSmall` if a single value does not fit into a datagram on its own.
+pub fn to_datagrams<I>(values: I, size: usize) -> Result<Vec<Vec<u8>>>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let mut datagrams = Vec::new();
+    let mut current = vec![0; size];
+    let mut filled = 0;
+    for value in values {
+        match to_slice(&value, &mut current[filled..]) {
+            Ok(c) => { filled += c; },
+            Err(Error::Encode(EncodeError::BufferTooSmall(required))) if filled > 0 && required <= size => {
+                current.truncate(filled);
+                datagrams.push(std::mem::replace(&mut current, vec![0; size]));
+                filled = to_slice(&value, &mut current)?;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+    if filled > 0 {
+        current.truncate(filled);
+        datagrams.push(current);
+    }
+    Ok(datagrams)
+}
+
+/// Deserialize all values contained in a single datagram.
+pub fn from_datagram<'a, T: Deserialize<'a>>(mut datagram: &'a [u8]) -> Result<Vec<T>> {
+    let mut values = Vec::new();
+    while !datagram.is_empty() {
+        let (value, c) = from_prefix(datagram)?;
+        values.push(value);
+        datagram = &datagram[c..];
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Serialize, Deserialize};
+    use nachricht::EncodeError;
+    use super::{to_datagrams, from_datagram};
+    use crate::{to_bytes, Error};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        name: String,
+        lives: u8,
+    }
+
+    #[test]
+    fn spillover() {
+        let cats: Vec<Cat> = (0..100).map(|i| Cat { name: format!("Cat {}", i), lives: 9 }).collect();
+        let size = 3 * to_bytes(&cats[99]).unwrap().len() + 1;
+        let datagrams = to_datagrams(&cats, size).unwrap();
+        assert_eq!(34, datagrams.len());
+        assert!(datagrams.iter().all(|d| d.len() <= size));
+        let decoded: Vec<Cat> = datagrams.iter().flat_map(|d| from_datagram::<Cat>(d).unwrap()).collect();
+        assert_eq!(cats, decoded);
+    }
+
+    #[test]
+    fn oversized() {
+        let cat = Cat { name: "Jessica".to_string(), lives: 9 };
+        let required = to_bytes(&cat).unwrap().len();
+        assert!(matches!(to_datagrams([&cat], required - 1), Err(Error::Encode(EncodeError::BufferTooSmall(r))) if r == required));
+        assert!(to_datagrams(Vec::<Cat>::new(), 16).unwrap().is_empty());
+    }
+
+}
@@ -1,155 +1,133 @@
 use serde::{Deserialize};
 use serde::de::{self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor};
-use nachricht::{DecodeError, Header, Refable, Sign};
+use nachricht::{allocate, measure, DecodeError, DecoderOptions, Header, Metrics, Reader, Token, Variant};
 use std::convert::TryInto;
+use std::rc::Rc;
 use serde::de::value::StrDeserializer;
 
 use crate::error::{DeserializationError, Error, Result};
-
-/// Like a Header but with all symbol table references
-/// resolved and inlined. More than a header less than a value.
-enum Atom<'de> {
-    Null,
-    Bool(bool),
-    F32(f32),
-    F64(f64),
-    Bin(usize),
-    Int(i128),
-    Str(&'de str),
-    Sym(&'de str),
-    Arr(usize),
-    Rec(Vec<&'de str>),
-    Map(usize),
-}
-
-impl<'de> Atom<'de> {
-    fn name(&self) -> &'static str {
-        match *self {
-            Atom::Null => "Null",
-            Atom::Bool(_) => "Bool",
-            Atom::F32(_) => "F32",
-            Atom::F64(_) => "F64",
-            Atom::Bin(_) => "Bin",
-            Atom::Int(_) => "Int",
-            Atom::Str(_) => "Str",
-            Atom::Sym(_) => "Sym",
-            Atom::Arr(_) => "Arr",
-            Atom::Rec(_) => "Rec",
-            Atom::Map(_) => "Map",
-        }
-    }
-}
+use crate::value::signed;
 
 pub struct Deserializer<'de> {
-    input:  &'de [u8],
-    pos: usize,
-    symbols: Vec<Refable<'de>>,
+    reader: Reader<'de>,
+    depth: usize,
+    tagged_options: bool,
 }
 
 impl<'de> Deserializer<'de> {
+
+    /// Values which are nested deeper than this are rejected to protect the stack. Every container, `Some` and
+    /// newtype counts as one level.
+    pub const MAX_DEPTH: usize = crate::MAX_DEPTH;
+
     pub fn from_bytes(input: &'de [u8]) -> Self {
-        Deserializer { input, pos: 0, symbols: Vec::new() }
+        Self::from_bytes_with_options(input, DecoderOptions::default())
+    }
+
+    pub fn from_bytes_with_options(input: &'de [u8], options: DecoderOptions) -> Self {
+        Self::from_reader(Reader::with_options(input, options))
     }
+
+    /// Continue with a reader whose symbol table has been filled in advance, e.g. from a `Dictionary`.
+    pub(crate) fn from_reader(reader: Reader<'de>) -> Self {
+        Deserializer { reader, depth: 0, tagged_options: false }
+    }
+
+    /// Expect `Option`s as written with `SerializerOptions::tagged_options`.
+    pub fn tagged_options(mut self) -> Self {
+        self.tagged_options = true;
+        self
+    }
+
 }
 
 pub fn from_bytes<'a, T: Deserialize<'a>>(s: &'a [u8]) -> std::result::Result<T, DeserializationError> {
+    from_bytes_with_options(s, DecoderOptions::default())
+}
+
+pub fn from_bytes_with_options<'a, T: Deserialize<'a>>(s: &'a [u8], options: DecoderOptions) -> std::result::Result<T, DeserializationError> {
+    deserialize_all(Deserializer::from_bytes_with_options(s, options))
+}
+
+/// Deserialize a message which has been serialized with `SerializerOptions::tagged_options`.
+pub fn from_bytes_tagged<'a, T: Deserialize<'a>>(s: &'a [u8], options: DecoderOptions) -> std::result::Result<T, DeserializationError> {
+    deserialize_all(Deserializer::from_bytes_with_options(s, options).tagged_options())
+}
+
+pub(crate) fn deserialize_all<'a, T: Deserialize<'a>>(mut deserializer: Deserializer<'a>) -> std::result::Result<T, DeserializationError> {
+    measure(deserializer.reader.options().metrics, <dyn Metrics>::decoded, || {
+        let t = T::deserialize(&mut deserializer).map_err(|e| e.at(deserializer.reader.position()))?;
+        if deserializer.reader.remaining().is_empty() {
+            Ok((t, deserializer.reader.position()))
+        } else {
+            Err(Error::Trailing.at(deserializer.reader.position()))
+        }
+    })
+}
+
+/// Deserialize a value from the beginning of the buffer and return it together with the number of consumed bytes.
+/// Trailing input is not an error but left for the caller to process.
+pub(crate) fn from_prefix<'a, T: Deserialize<'a>>(s: &'a [u8]) -> Result<(T, usize)> {
     let mut deserializer = Deserializer::from_bytes(s);
-    let t = T::deserialize(&mut deserializer).map_err(|e| e.at(deserializer.pos))?;
-    if deserializer.input[deserializer.pos..].is_empty() {
-        Ok(t)
-    } else {
-        Err(Error::Trailing.at(deserializer.pos))
-    }
+    let t = T::deserialize(&mut deserializer)?;
+    Ok((t, deserializer.reader.position()))
 }
 
 impl<'de> Deserializer<'de> {
 
-    fn decode_atom(&mut self) -> Result<Atom<'de>> {
-        let (header, c) = Header::decode(&self.input[self.pos..])?;
-        self.pos += c;
-        Ok(match header {
-            Header::Null => Atom::Null,
-            Header::True => Atom::Bool(true),
-            Header::False => Atom::Bool(false),
-            Header::F32 => Atom::F32(<f32>::from_be_bytes(self.decode_slice(4)?.try_into().unwrap())),
-            Header::F64 => Atom::F64(<f64>::from_be_bytes(self.decode_slice(8)?.try_into().unwrap())),
-            Header::Bin(v) => Atom::Bin(v),
-            Header::Int(s, v) => Atom::Int(match s { Sign::Pos => 1, Sign::Neg => -1 } * v as i128),
-            Header::Str(v) => Atom::Str(std::str::from_utf8(self.decode_slice(v)?)?),
-            Header::Sym(v) => {
-                let str = std::str::from_utf8(self.decode_slice(v)?)?;
-                self.symbols.push(Refable::Sym(str));
-                Atom::Sym(str)
-            }
-            Header::Arr(v) => Atom::Arr(v),
-            Header::Rec(v) => {
-                let mut lay = Vec::with_capacity(v);
-                for _ in 0..v {
-                    lay.push(self.decode_stringy()?);
-                }
-                self.symbols.push(Refable::Rec(lay.clone()));
-                Atom::Rec(lay)
-            }
-            Header::Map(v) => Atom::Map(v),
-            Header::Ref(v) => {
-                match self.symbols.get(v) {
-                    Some(Refable::Sym(s)) => Atom::Sym(s),
-                    Some(Refable::Rec(s)) => Atom::Rec(s.clone()),
-                    _ => { return Err(Error::Decode(DecodeError::InvalidRef(v))); },
-                }
-            }
-        })
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth == Self::MAX_DEPTH {
+            return Err(Error::Depth(Self::MAX_DEPTH));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
     }
 
-    #[inline]
-    fn decode_int(&mut self) -> Result<i128> {
-        match self.decode_atom()? {
-            Atom::Int(i) => Ok(i),
-            o => Err(Error::UnexpectedHeader(&["Int"], o.name())),
-        }
+    fn decode_token(&mut self) -> Result<Token<'de>> {
+        Ok(self.reader.next_token()?)
     }
 
     #[inline]
-    fn decode_slice(&mut self, len: usize) -> Result<&'de [u8]> {
-        if self.input[self.pos..].len() < len {
-            Err(Error::Decode(DecodeError::Eof))
-        } else {
-            self.pos += len;
-            Ok(&self.input[self.pos - len..self.pos])
+    fn decode_int(&mut self) -> Result<i128> {
+        match self.decode_token()? {
+            Token::Int(s, v) => Ok(signed(s, v)),
+            o => Err(Error::UnexpectedHeader(&["Int"], o.name())),
         }
     }
 
     fn decode_stringy(&mut self) -> Result<&'de str> {
-        match self.decode_atom()? {
-            Atom::Str(v) | Atom::Sym(v) => Ok(v),
+        match self.decode_token()? {
+            Token::Str(v) | Token::Sym(v) => Ok(v),
             o => Err(Error::UnexpectedHeader(&["Str", "Sym", "Ref"], o.name())),
         }
     }
 
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Null => visitor.visit_unit(),
-            Atom::Bool(v) => visitor.visit_bool(v),
-            Atom::F32(v) => visitor.visit_f32(v),
-            Atom::F64(v) => visitor.visit_f64(v),
-            Atom::Bin(v) => visitor.visit_borrowed_bytes(self.decode_slice(v)?),
-            Atom::Int(v) => visitor.visit_i64(v.try_into()?),
-            Atom::Str(v) => visitor.visit_borrowed_str(v),
-            Atom::Sym(v) => visitor.visit_borrowed_str(v),
-            Atom::Arr(v) => visitor.visit_seq(SeqDeserializer::new(self, v)),
-            Atom::Map(v) => visitor.visit_map(MapDeserializer::new(self, v)),
-            Atom::Rec(lay) => visitor.visit_map(StructDeserializer::new(self, lay)),
+        match self.decode_token()? {
+            Token::Null => visitor.visit_unit(),
+            Token::Bool(v) => visitor.visit_bool(v),
+            Token::F32(v) => visitor.visit_f32(v),
+            Token::F64(v) => visitor.visit_f64(v),
+            Token::Bin(v) => visitor.visit_borrowed_bytes(v),
+            Token::Int(s, v) => visitor.visit_i64(signed(s, v).try_into()?),
+            Token::Str(v) => visitor.visit_borrowed_str(v),
+            Token::Sym(v) => visitor.visit_borrowed_str(v),
+            Token::Arr(v) => self.nested(|de| visitor.visit_seq(SeqDeserializer::new(de, v))),
+            Token::Map(v) => self.nested(|de| visitor.visit_map(MapDeserializer::new(de, v))),
+            Token::Rec(lay) => self.nested(|de| visitor.visit_map(StructDeserializer::new(de, lay))),
         }
     }
 
     fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Bool(v) => visitor.visit_bool(v),
+        match self.decode_token()? {
+            Token::Bool(v) => visitor.visit_bool(v),
             o => Err(Error::UnexpectedHeader(&["True", "False"], o.name())),
         }
     }
@@ -187,15 +165,15 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 
     fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::F32(v) => visitor.visit_f32(v),
+        match self.decode_token()? {
+            Token::F32(v) => visitor.visit_f32(v),
             o => Err(Error::UnexpectedHeader(&["F32"], o.name())),
         }
     }
 
     fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::F64(v) => visitor.visit_f64(v),
+        match self.decode_token()? {
+            Token::F64(v) => visitor.visit_f64(v),
             o => Err(Error::UnexpectedHeader(&["F64"], o.name())),
         }
     }
@@ -219,17 +197,17 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 
     fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Bin(v) => visitor.visit_borrowed_bytes(self.decode_slice(v)?),
+        match self.decode_token()? {
+            Token::Bin(v) => visitor.visit_borrowed_bytes(v),
             o => Err(Error::UnexpectedHeader(&["Bin"], o.name())),
         }
     }
 
     fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Bin(v) => visitor.visit_byte_buf(self.decode_slice(v)?.to_vec()),
-            Atom::Arr(v) => {
-                let mut bytes = Vec::with_capacity(v);
+        match self.decode_token()? {
+            Token::Bin(v) => visitor.visit_byte_buf(v.to_vec()),
+            Token::Arr(v) => {
+                let mut bytes = allocate(v, self.reader.remaining())?;
                 for _ in 0..v {
                     bytes.push(self.decode_int()?.try_into()?);
                 }
@@ -240,19 +218,26 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 
     fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        let (header, c) = Header::decode(&self.input[self.pos..])?;
-        match header {
+        if self.tagged_options {
+            return match self.reader.next_variant()? {
+                Variant::Token(Token::Null) => visitor.visit_none(),
+                Variant::Rec("some") => self.nested(|de| visitor.visit_some(de)),
+                Variant::Rec(key) => Err(de::Error::unknown_field(key, &["some"])),
+                Variant::Token(o) => Err(Error::UnexpectedHeader(&["Null", "Rec", "Ref"], o.name())),
+            };
+        }
+        match self.reader.peek()? {
             Header::Null => {
-                self.pos += c;
+                self.decode_token()?;
                 visitor.visit_none()
             },
-            _ => visitor.visit_some(self),
+            _ => self.nested(|de| visitor.visit_some(de)),
         }
     }
 
     fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Null => visitor.visit_unit(),
+        match self.decode_token()? {
+            Token::Null => visitor.visit_unit(),
             o => Err(Error::UnexpectedHeader(&["Null"], o.name())),
         }
     }
@@ -262,12 +247,12 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 
     fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
-        visitor.visit_newtype_struct(self)
+        self.nested(|de| visitor.visit_newtype_struct(de))
     }
 
-    fn deserialize_seq<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Arr(v) => visitor.visit_seq(SeqDeserializer::new(&mut self, v)),
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.decode_token()? {
+            Token::Arr(v) => self.nested(|de| visitor.visit_seq(SeqDeserializer::new(de, v))),
             o => Err(Error::UnexpectedHeader(&["Arr"], o.name())),
         }
     }
@@ -280,29 +265,29 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_seq(visitor)
     }
 
-    fn deserialize_map<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Map(v) => visitor.visit_map(MapDeserializer::new(&mut self, v)),
-            o => Err(Error::UnexpectedHeader(&["Map"], o.name())),
+    /// Records are maps whose keys are symbols, so they are accepted as well. Among others, this is what structs with
+    /// flattened fields ask for.
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.decode_token()? {
+            Token::Map(v) => self.nested(|de| visitor.visit_map(MapDeserializer::new(de, v))),
+            Token::Rec(lay) => self.nested(|de| visitor.visit_map(StructDeserializer::new(de, lay))),
+            o => Err(Error::UnexpectedHeader(&["Map", "Rec", "Ref"], o.name())),
         }
     }
 
     fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Rec(lay) => visitor.visit_map(StructDeserializer::new(self, lay)),
+        match self.decode_token()? {
+            Token::Rec(lay) => self.nested(|de| visitor.visit_map(StructDeserializer::new(de, lay))),
             o => Err(Error::UnexpectedHeader(&["Rec", "Ref"], o.name())),
         }
     }
 
     fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str],  visitor: V) -> Result<V::Value> {
-        match self.decode_atom()? {
-            Atom::Rec(lay) if lay.len() == 1 => {
-                let variant = lay[0];
-                visitor.visit_enum(EnumDeserializer::new(self, variant))
-            },
-            Atom::Sym(s) => visitor.visit_enum(s.into_deserializer()),
-            Atom::Str(s) => visitor.visit_enum(s.into_deserializer()),
-            o => Err(Error::UnexpectedHeader(&["Rec", "Ref", "Str", "Sym"], o.name())),
+        match self.reader.next_variant()? {
+            Variant::Rec(variant) => self.nested(|de| visitor.visit_enum(EnumDeserializer::new(de, variant))),
+            Variant::Token(Token::Sym(s)) => visitor.visit_enum(s.into_deserializer()),
+            Variant::Token(Token::Str(s)) => visitor.visit_enum(s.into_deserializer()),
+            Variant::Token(o) => Err(Error::UnexpectedHeader(&["Rec", "Ref", "Str", "Sym"], o.name())),
         }
     }
 
@@ -310,8 +295,10 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_borrowed_str(self.decode_stringy()?.as_ref())
     }
 
+    /// Fields which the target type does not know are skipped at header level without being decoded.
     fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        self.deserialize_any(visitor)
+        self.reader.skip()?;
+        visitor.visit_unit()
     }
 
 }
@@ -351,12 +338,12 @@ impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a, 'de> {
 
 struct StructDeserializer<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
-    layout: Vec<&'de str>,
+    layout: Rc<[&'de str]>,
     pos: usize,
 }
 
 impl<'a, 'de> StructDeserializer<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>, layout: Vec<&'de str>) -> Self {
+    fn new(de: &'a mut Deserializer<'de>, layout: Rc<[&'de str]>) -> Self {
         Self { de, layout, pos: 0 }
     }
 }
@@ -374,7 +361,10 @@ impl<'de, 'a> MapAccess<'de> for StructDeserializer<'a, 'de> {
     }
 
     fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
-        seed.deserialize(&mut *self.de)
+        seed.deserialize(&mut *self.de).map_err(|e| match (e, self.pos.checked_sub(1).and_then(|i| self.layout.get(i))) {
+            (Error::Decode(DecodeError::Eof), Some(field)) => Error::Decode(DecodeError::TruncatedRecord((*field).to_owned())),
+            (e, _) => e,
+        })
     }
 
     #[inline]
@@ -409,8 +399,8 @@ impl<'de, 'a> VariantAccess<'de> for EnumDeserializer<'a, 'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        match self.de.decode_atom()? {
-            Atom::Null => Ok(()),
+        match self.de.decode_token()? {
+            Token::Null => Ok(()),
             o => Err(Error::UnexpectedHeader(&["Null"], o.name())),
         }
     }
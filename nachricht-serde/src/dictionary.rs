@@ -0,0 +1,258 @@
+//! Shared dictionaries for streams of small messages. Every message starts with an empty symbol table, so it defines
+//! all of its symbols and layouts itself, which can easily make up most of its size. A `Dictionary` holds symbols and
+//! layouts which are known to both ends in advance. Messages serialized with it refer to them instead of defining them,
+//! and only deserialize with the same dictionary:
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use nachricht_serde::*;
+//!
+//! #[derive(Serialize, Deserialize, PartialEq, Debug)]
+//! struct Cat {
+//!     name: String,
+//!     lives: u8,
+//! }
+//!
+//! let cat = Cat { name: "Jessica".to_string(), lives: 9 };
+//! let dictionary = Dictionary::from_layouts(&layouts_of(&cat).unwrap());
+//! let bytes = to_bytes_with_dictionary(&cat, SerializerOptions::default(), &dictionary).unwrap();
+//! assert_eq!(11, bytes.len());
+//! assert_eq!(22, to_bytes(&cat).unwrap().len());
+//! assert_eq!(cat, from_bytes_with_dictionary(&bytes, &dictionary).unwrap());
+//! ```
+//!
+//! Dictionaries implement `Serialize` and `Deserialize` themselves, so they can be distributed like any other message,
+//! e.g. on a compacted topic. Entries must never be changed or removed while messages which use them are in flight;
+//! publish a new dictionary instead.
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use nachricht::{Reader, Refable, SymbolTable};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::de::deserialize_all;
+use crate::error::{DeserializationError, Result};
+use crate::preser::{preserialize, Layouts};
+use crate::ser::SerializerOptions;
+
+/// An entry of the symbol table
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Entry {
+    Symbol(String),
+    Layout(Vec<String>),
+}
+
+/// Symbols and record layouts which both ends of a connection know in advance, see the module documentation.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    entries: Vec<Entry>,
+}
+
+impl Dictionary {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A dictionary with all layouts of structs and enum variants, e.g. from `layouts_of` or
+    /// `Nachricht::collect_layouts`. The entries are sorted, so that the same layouts always yield the same dictionary.
+    /// Unit variants are not part of the layouts; add them with `insert_symbol`.
+    pub fn from_layouts(layouts: &Layouts) -> Self {
+        let structs = layouts.layouts().map(|(_, _, layout)| layout.keys().map(str::to_owned).collect::<Vec<_>>());
+        let variants = layouts.variants().map(|(_, variant)| vec![variant.to_owned()]);
+        let unique = structs.chain(variants).collect::<BTreeSet<_>>();
+        Self { entries: unique.into_iter().map(Entry::Layout).collect() }
+    }
+
+    /// Add a symbol, e.g. a unit variant or a frequent map key, unless it is already present.
+    pub fn insert_symbol(&mut self, symbol: &str) {
+        if !self.entries.iter().any(|e| matches!(e, Entry::Symbol(s) if s == symbol)) {
+            self.entries.push(Entry::Symbol(symbol.to_owned()));
+        }
+    }
+
+    /// Add a record layout with the given keys in the order in which they appear on the wire, unless it is already
+    /// present.
+    pub fn insert_layout(&mut self, keys: &[&str]) {
+        if !self.entries.iter().any(|e| matches!(e, Entry::Layout(l) if l.iter().eq(keys))) {
+            self.entries.push(Entry::Layout(keys.iter().map(|k| k.to_string()).collect()));
+        }
+    }
+
+    /// The number of entries, which is the index of the first symbol or layout defined by a message.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The symbols with their index, in order
+    pub(crate) fn symbols(&self) -> impl Iterator<Item = (usize, &str)> + '_ {
+        self.entries.iter().enumerate().filter_map(|(i, e)| match e {
+            Entry::Symbol(s) => Some((i, s.as_str())),
+            Entry::Layout(_) => None,
+        })
+    }
+
+    /// The layouts with their index, in order
+    pub(crate) fn layouts(&self) -> impl Iterator<Item = (usize, &[String])> + '_ {
+        self.entries.iter().enumerate().filter_map(|(i, e)| match e {
+            Entry::Symbol(_) => None,
+            Entry::Layout(l) => Some((i, l.as_slice())),
+        })
+    }
+
+    /// The symbol table a message starts with
+    pub(crate) fn table(&self) -> SymbolTable<'_> {
+        let mut table = SymbolTable::new();
+        for entry in &self.entries {
+            table.push(match entry {
+                Entry::Symbol(s) => Refable::Sym(s),
+                Entry::Layout(l) => Refable::Rec(l.iter().map(String::as_str).collect::<Rc<[_]>>()),
+            });
+        }
+        table
+    }
+
+}
+
+/// Serialize like `to_bytes_with_options`, but refer to the entries of the dictionary instead of defining them. Fields
+/// are always written in `FieldOrder::Declaration`.
+pub fn to_bytes_with_dictionary<T: Serialize>(value: &T, options: SerializerOptions, dictionary: &Dictionary) -> Result<Vec<u8>> {
+    let mut serializer = crate::ser::Serializer::new(Vec::new(), preserialize(value)?, options);
+    serializer.prime(dictionary);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output())
+}
+
+/// Deserialize a message which has been serialized with `to_bytes_with_dictionary` and the same dictionary.
+pub fn from_bytes_with_dictionary<'a, T: Deserialize<'a>>(s: &'a [u8], dictionary: &'a Dictionary) -> std::result::Result<T, DeserializationError> {
+    deserialize_all(crate::de::Deserializer::from_reader(Reader::resume(s, 0, dictionary.table())))
+}
+
+/// Symbols are written as strings and layouts as sequences of strings
+impl Serialize for Dictionary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.entries.len()))?;
+        for entry in &self.entries {
+            match entry {
+                Entry::Symbol(s) => seq.serialize_element(s)?,
+                Entry::Layout(l) => seq.serialize_element(l)?,
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Dictionary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct DictionaryVisitor;
+
+        impl<'de> Visitor<'de> for DictionaryVisitor {
+            type Value = Dictionary;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a sequence of symbols and layouts")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = seq.next_element()? {
+                    entries.push(entry);
+                }
+                Ok(Dictionary { entries })
+            }
+        }
+
+        deserializer.deserialize_seq(DictionaryVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = Entry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a symbol or a layout")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                Ok(Entry::Symbol(v.to_owned()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+                let mut keys = Vec::new();
+                while let Some(key) = seq.next_element()? {
+                    keys.push(key);
+                }
+                Ok(Entry::Layout(keys))
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use super::{from_bytes_with_dictionary, to_bytes_with_dictionary, Dictionary};
+    use crate::de::from_bytes;
+    use crate::preser::layouts_of;
+    use crate::ser::{to_bytes, SerializerOptions};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Species {
+        FelisCatus,
+        Hybrid { parents: Vec<Species> },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        name: String,
+        species: Species,
+        tags: BTreeMap<String, u8>,
+        nickname: Option<Box<Cat>>,
+    }
+
+    #[test]
+    fn dictionaries() {
+        let cat = Cat {
+            name: "Jessica".to_string(),
+            species: Species::Hybrid { parents: vec![Species::FelisCatus, Species::FelisCatus] },
+            tags: BTreeMap::from([("weight".to_string(), 4)]),
+            nickname: Some(Box::new(Cat { name: "Jessy".to_string(), species: Species::FelisCatus, tags: BTreeMap::new(), nickname: None })),
+        };
+        let mut dictionary = Dictionary::from_layouts(&layouts_of(&cat).unwrap());
+        // the layouts of `Cat`, of the variant `Hybrid` and of its fields
+        assert_eq!(3, dictionary.len());
+        dictionary.insert_symbol("FelisCatus");
+        dictionary.insert_symbol("FelisCatus");
+        dictionary.insert_layout(&["parents"]);
+        assert_eq!(4, dictionary.len());
+        let options = SerializerOptions { symbol_keys: true, ..Default::default() };
+        let bytes = to_bytes_with_dictionary(&cat, options, &dictionary).unwrap();
+        assert_eq!(cat, from_bytes_with_dictionary(&bytes, &dictionary).unwrap());
+        assert!(bytes.len() + 40 < to_bytes(&cat).unwrap().len());
+        // the message refers to entries which it does not define
+        assert!(from_bytes::<Cat>(&bytes).is_err());
+        assert!(from_bytes_with_dictionary::<Cat>(&bytes, &Dictionary::new()).is_err());
+        // without a dictionary, the output is the same as usual
+        assert_eq!(to_bytes(&cat).unwrap(), to_bytes_with_dictionary(&cat, SerializerOptions::default(), &Dictionary::new()).unwrap());
+        // dictionaries are messages of their own
+        let encoded = to_bytes(&dictionary).unwrap();
+        let decoded = from_bytes::<Dictionary>(&encoded).unwrap();
+        assert_eq!(dictionary, decoded);
+        assert_eq!(cat, from_bytes_with_dictionary(&bytes, &decoded).unwrap());
+        assert_eq!(Dictionary::from_layouts(&layouts_of(&cat).unwrap()), Dictionary::from_layouts(&layouts_of(&cat).unwrap()));
+    }
+
+}
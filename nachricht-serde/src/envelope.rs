@@ -0,0 +1,265 @@
+//! A standard envelope for messages: an id, a timestamp and a content type which describe a typed payload. The payload
+//! is embedded as a message of its own within a byte field, so that routers and gateways can read the metadata with a
+//! `RawEnvelope` without decoding the payload, forward it unchanged and decode it later or not at all:
+//!
+//! ```
+//! use nachricht_serde::{from_bytes, to_bytes, Envelope, RawEnvelope};
+//!
+//! let bytes = to_bytes(&Envelope::new(4711, "cat", "Jessica".to_string())).unwrap();
+//! let raw: RawEnvelope = from_bytes(&bytes).unwrap();
+//! assert_eq!((4711, "cat"), (raw.id, raw.content_type.as_ref()));
+//! assert_eq!("Jessica", raw.payload::<&str>().unwrap());
+//! assert_eq!("Jessica", from_bytes::<Envelope<String>>(&bytes).unwrap().payload);
+//! ```
+//!
+//! The content type is written as a string: since every message carries a single envelope, a symbol would not be any
+//! shorter.
+
+use serde::de::{self, DeserializeOwned, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{self, SerializeStruct};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::de::from_bytes;
+use crate::error::{DeserializationError, Result};
+use crate::preser::Layouts;
+use crate::schema::Nachricht;
+use crate::ser::to_bytes;
+
+const FIELDS: &[&str] = &["id", "timestamp", "content_type", "payload"];
+
+/// Metadata and a typed payload, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope<T> {
+    pub id: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub content_type: String,
+    pub payload: T,
+}
+
+/// The same as an `Envelope`, but with the payload still encoded. Deserializing it borrows the payload from the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawEnvelope<'a> {
+    pub id: u64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub content_type: Cow<'a, str>,
+    /// The payload as a message of its own
+    pub encoded: Cow<'a, [u8]>,
+}
+
+impl<T> Envelope<T> {
+
+    /// Create an envelope with the current time as its timestamp.
+    pub fn new(id: u64, content_type: impl Into<String>, payload: T) -> Self {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default();
+        Self { id, timestamp, content_type: content_type.into(), payload }
+    }
+
+}
+
+impl<T: Serialize> Envelope<T> {
+
+    /// Encode the payload, e.g. to forward the envelope with a `RawEnvelope`.
+    pub fn to_raw(&self) -> Result<RawEnvelope<'static>> {
+        let encoded = Cow::Owned(to_bytes(&self.payload)?);
+        Ok(RawEnvelope { id: self.id, timestamp: self.timestamp, content_type: Cow::Owned(self.content_type.clone()), encoded })
+    }
+
+}
+
+impl<'a> RawEnvelope<'a> {
+
+    /// Decode the payload. Borrowed strings and bytes point into the encoded payload.
+    pub fn payload<'de, T: Deserialize<'de>>(&'de self) -> std::result::Result<T, DeserializationError> {
+        from_bytes(&self.encoded)
+    }
+
+    /// Decode the payload into a typed `Envelope`.
+    pub fn decode<T: DeserializeOwned>(&self) -> std::result::Result<Envelope<T>, DeserializationError> {
+        Ok(Envelope { id: self.id, timestamp: self.timestamp, content_type: self.content_type.to_string(), payload: self.payload()? })
+    }
+
+}
+
+fn serialize_envelope<S: Serializer>(serializer: S, id: u64, timestamp: u64, content_type: &str, encoded: &[u8]) -> std::result::Result<S::Ok, S::Error> {
+    /// Bytes rather than a sequence of integers
+    struct Encoded<'a>(&'a [u8]);
+
+    impl Serialize for Encoded<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    let mut envelope = serializer.serialize_struct("Envelope", FIELDS.len())?;
+    envelope.serialize_field("id", &id)?;
+    envelope.serialize_field("timestamp", &timestamp)?;
+    envelope.serialize_field("content_type", content_type)?;
+    envelope.serialize_field("payload", &Encoded(encoded))?;
+    envelope.end()
+}
+
+impl<T: Serialize> Serialize for Envelope<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let encoded = to_bytes(&self.payload).map_err(ser::Error::custom)?;
+        serialize_envelope(serializer, self.id, self.timestamp, &self.content_type, &encoded)
+    }
+}
+
+impl Serialize for RawEnvelope<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serialize_envelope(serializer, self.id, self.timestamp, &self.content_type, &self.encoded)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Envelope<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        RawEnvelope::deserialize(deserializer)?.decode().map_err(de::Error::custom)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawEnvelope<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_struct("Envelope", FIELDS, EnvelopeVisitor)
+    }
+}
+
+struct EnvelopeVisitor;
+
+impl<'de> Visitor<'de> for EnvelopeVisitor {
+    type Value = RawEnvelope<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an envelope")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Self::Value, A::Error> {
+        let (mut id, mut timestamp, mut content_type, mut encoded) = (None, None, None, None);
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            match key.as_ref() {
+                "id" => id = Some(map.next_value()?),
+                "timestamp" => timestamp = Some(map.next_value()?),
+                "content_type" => content_type = Some(map.next_value::<CowStr>()?.0),
+                "payload" => encoded = Some(map.next_value::<CowBytes>()?.0),
+                _ => { map.next_value::<IgnoredAny>()?; },
+            }
+        }
+        Ok(RawEnvelope {
+            id: id.ok_or_else(|| de::Error::missing_field("id"))?,
+            timestamp: timestamp.ok_or_else(|| de::Error::missing_field("timestamp"))?,
+            content_type: content_type.ok_or_else(|| de::Error::missing_field("content_type"))?,
+            encoded: encoded.ok_or_else(|| de::Error::missing_field("payload"))?,
+        })
+    }
+}
+
+/// A string which is borrowed from the input if possible
+struct CowStr<'de>(Cow<'de, str>);
+
+impl<'de> Deserialize<'de> for CowStr<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct StrVisitor;
+
+        impl<'de> Visitor<'de> for StrVisitor {
+            type Value = CowStr<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> std::result::Result<Self::Value, E> {
+                Ok(CowStr(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Self::Value, E> {
+                Ok(CowStr(Cow::Owned(v.to_owned())))
+            }
+        }
+
+        deserializer.deserialize_str(StrVisitor)
+    }
+}
+
+/// Bytes which are borrowed from the input if possible
+struct CowBytes<'de>(Cow<'de, [u8]>);
+
+impl<'de> Deserialize<'de> for CowBytes<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = CowBytes<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("bytes")
+            }
+
+            fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> std::result::Result<Self::Value, E> {
+                Ok(CowBytes(Cow::Borrowed(v)))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> std::result::Result<Self::Value, E> {
+                Ok(CowBytes(Cow::Owned(v.to_vec())))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> std::result::Result<Self::Value, E> {
+                Ok(CowBytes(Cow::Owned(v)))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+/// The payload is a message of its own, so its layouts do not belong to the envelope
+impl<T> Nachricht for Envelope<T> {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        layouts.insert_layout("Envelope", None, FIELDS)
+    }
+}
+
+impl Nachricht for RawEnvelope<'_> {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        layouts.insert_layout("Envelope", None, FIELDS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use std::borrow::Cow;
+    use super::{Envelope, RawEnvelope};
+    use crate::{from_bytes, from_value, to_bytes, to_value, Error, TypedSerializer};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Cat {
+        name: String,
+        lives: u8,
+    }
+
+    #[test]
+    fn envelopes() {
+        let envelope = Envelope { id: 1, timestamp: 1700000000000, content_type: "cat".to_string(), payload: Cat { name: "Jessica".to_string(), lives: 9 } };
+        let bytes = to_bytes(&envelope).unwrap();
+        assert_eq!(envelope, from_bytes::<Envelope<Cat>>(&bytes).unwrap());
+        let raw = from_bytes::<RawEnvelope>(&bytes).unwrap();
+        assert!(matches!(raw.encoded, Cow::Borrowed(_)));
+        assert_eq!(to_bytes(&envelope.payload).unwrap(), raw.encoded.as_ref());
+        assert_eq!(envelope.to_raw().unwrap(), raw);
+        assert_eq!(envelope, raw.decode().unwrap());
+        // forwarding keeps the bytes
+        assert_eq!(bytes, to_bytes(&raw).unwrap());
+        assert_eq!(bytes, TypedSerializer::<RawEnvelope>::derived().unwrap().to_bytes(&raw).unwrap());
+        // the data model holds the payload as bytes, too
+        assert_eq!(envelope, from_value::<Envelope<Cat>>(&to_value(&envelope).unwrap()).unwrap());
+        assert!(matches!(raw.payload::<String>().unwrap_err().into_inner(), Error::UnexpectedHeader(_, _)));
+        assert!(matches!(from_bytes::<Envelope<String>>(&bytes).unwrap_err().into_inner(), Error::Message(_)));
+        assert!(matches!(from_bytes::<RawEnvelope>(&to_bytes(&envelope.payload).unwrap()).unwrap_err().into_inner(), Error::Message(_)));
+        assert!(Envelope::new(1, "cat", ()).timestamp > envelope.timestamp);
+    }
+
+}
@@ -1,4 +1,3 @@
-use std;
 use std::fmt::{self, Display};
 use std::str::Utf8Error;
 use serde::{de, ser};
@@ -12,6 +11,12 @@ pub struct DeserializationError {
     at: usize,
 }
 
+impl DeserializationError {
+    pub fn into_inner(self) -> Error {
+        self.inner
+    }
+}
+
 impl std::error::Error for DeserializationError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         Some(&self.inner)
@@ -30,15 +35,39 @@ pub enum Error {
     Decode(DecodeError),
     Trailing,
     UnexpectedHeader(&'static [&'static str], &'static str),
+    UnexpectedValue(&'static [&'static str], &'static str),
     Int,
     Utf8(Utf8Error),
+    Depth(usize),
     // Encode
     Length,
     Encode(EncodeError),
     UnknownStructLayout(&'static str),
     UnknownVariantLayout(&'static str, &'static str),
+    LayoutMismatch(&'static str, Option<&'static str>),
     // Preser
     DuplicateLayout(&'static str, Option<&'static str>),
+    // Stream
+    Io(std::io::Error),
+    Limit(usize),
+    // Validation
+    /// A record with the given fields is not declared by the schema
+    UndeclaredLayout(Vec<String>),
+    // Values
+    /// There is no value at the given JSON pointer
+    Missing(String),
+    /// The error occurred within the value at the given JSON pointer
+    Path(String, Box<Error>),
+    // Client and messaging
+    /// The request failed or the server answered with an error status
+    #[cfg(feature = "client")]
+    Http(Box<ureq::Error>),
+    /// Publishing a message failed
+    #[cfg(feature = "nats")]
+    Nats(Box<async_nats::PublishError>),
+    /// The response or message has the given content type instead of `nachricht::MEDIA_TYPE`
+    #[cfg(any(feature = "client", feature = "kafka", feature = "nats"))]
+    ContentType(String),
     // Both
     Message(String),
 }
@@ -65,15 +94,29 @@ impl Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Message(msg) => fmt.write_str(msg),
-            Error::Encode(e) => write!(fmt, "Encoding error: {}", e.to_string()),
-            Error::Decode(e) => write!(fmt, "Decoding error: {}", e.to_string()),
+            Error::UndeclaredLayout(k) => write!(fmt, "Record with fields ({}) is not declared by the schema", k.join(", ")),
+            Error::Missing(p) => write!(fmt, "No value at {}", p),
+            Error::Path(p, e) => write!(fmt, "{} at {}", e, p),
+            Error::Encode(e) => write!(fmt, "Encoding error: {}", e),
+            Error::Decode(e) => write!(fmt, "Decoding error: {}", e),
             Error::Length => fmt.write_str("Length required"),
             Error::Trailing => fmt.write_str("Trailing characters in input"),
             Error::UnexpectedHeader(expected, actual) => write!(fmt, "Unexpected header: expected one of ({}), found {}", expected.join(", "), actual),
-            Error::Utf8(e) => write!(fmt, "Bytes aren't valid Utf-8: {}", e.to_string()),
+            Error::UnexpectedValue(expected, actual) => write!(fmt, "Unexpected value: expected one of ({}), found {}", expected.join(", "), actual),
+            Error::Utf8(e) => write!(fmt, "Bytes aren't valid Utf-8: {}", e),
+            Error::Depth(d) => write!(fmt, "Nesting exceeds the maximum depth of {}", d),
             Error::Int => fmt.write_str("Integer didn't fit into target type"),
             Error::UnknownStructLayout(l) => write!(fmt, "Layout for struct `{}` is unknown", l),
             Error::UnknownVariantLayout(l, m) => write!(fmt, "Layout for variant`{}::{}` is unknown", l, m),
+            Error::Io(e) => write!(fmt, "IO error: {}", e),
+            Error::Limit(l) => write!(fmt, "Message exceeds the limit of {} bytes", l),
+            Error::LayoutMismatch(l, m) => write!(fmt, "Fields of `{}{}` do not match the registered layout", l, match m { Some(x) => format!("::{}", x), None => "".into() }),
+            #[cfg(feature = "client")]
+            Error::Http(e) => write!(fmt, "HTTP error: {}", e),
+            #[cfg(feature = "nats")]
+            Error::Nats(e) => write!(fmt, "NATS error: {}", e),
+            #[cfg(any(feature = "client", feature = "kafka", feature = "nats"))]
+            Error::ContentType(t) => write!(fmt, "Unexpected content type `{}`", t),
             Error::DuplicateLayout(l, m) => write!(fmt, "Duplicate layout for name `{}{}`: conditionally skipping fields is not supported", l, match m { Some(x) => format!("::{}", x), None => "".into() }),
         }
     }
@@ -85,6 +128,12 @@ impl From<EncodeError> for Error {
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
 impl From<DecodeError> for Error {
     fn from(e: DecodeError) -> Error {
         Error::Decode(e)
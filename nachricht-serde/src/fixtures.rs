@@ -0,0 +1,111 @@
+//! Checks the serializer and deserializer against the annotated hex listings in `fixtures/`, which pin the wire format
+//! across releases. A listing consists of hexadecimal bytes separated by whitespace, `#` starts a comment which runs
+//! until the end of the line. Every listing needs an entry in the `fixtures!` invocation below, which names the Rust
+//! value it represents and generates a test for it. If a listing has to change, the wire format has changed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use nachricht::Decoder;
+use crate::{from_bytes, to_bytes};
+
+/// The bytes of a listing
+pub(crate) fn parse(listing: &str) -> Vec<u8> {
+    listing.lines()
+        .flat_map(|line| line.split('#').next().unwrap_or_default().split_whitespace())
+        .map(|byte| u8::from_str_radix(byte, 16).unwrap_or_else(|_| panic!("invalid byte {} in listing", byte)))
+        .collect()
+}
+
+/// Serializing the value needs to yield the listing exactly, and deserializing the listing needs to yield the value.
+/// The generic `Decoder` needs to accept it as well.
+fn check<'de, T: Serialize + Deserialize<'de> + PartialEq + Debug>(bytes: &'de [u8], value: &T) {
+    assert_eq!(bytes, to_bytes(value).unwrap(), "serializer output differs from the listing");
+    assert_eq!(value, &from_bytes::<T>(bytes).unwrap(), "deserialized listing differs from the value");
+    assert_eq!(bytes.len(), Decoder::decode(bytes).unwrap().1, "listing contains trailing bytes");
+}
+
+macro_rules! fixtures {
+    ($($name:ident: $ty:ty = $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let bytes = parse(include_str!(concat!("../fixtures/", stringify!($name), ".hex")));
+                check::<$ty>(&bytes, &$value);
+            }
+        )*
+
+        /// Every listing needs to be checked, so that none is forgotten when adding it.
+        #[test]
+        fn complete() {
+            let mut listings = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures")).unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            listings.sort();
+            let mut checked = vec![$(concat!(stringify!($name), ".hex")),*];
+            checked.sort_unstable();
+            assert_eq!(listings, checked);
+        }
+    };
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum Species {
+    PrionailurusViverrinus,
+    LynxLynx,
+    FelisCatus,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Cat<'a> {
+    name: &'a str,
+    species: Species,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Message<'a> {
+    version: u32,
+    #[serde(borrow)]
+    cats: Vec<Cat<'a>>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum Shape {
+    Point,
+    Circle(f64),
+    Rect(u8, u8),
+    Poly { sides: u8, regular: bool },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Unit;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Meters(u32);
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Pair(i8, Option<u8>);
+
+fixtures! {
+    cats: Message = Message {
+        version: 1,
+        cats: vec![
+            Cat { name: "Jessica", species: Species::PrionailurusViverrinus },
+            Cat { name: "Wantan", species: Species::LynxLynx },
+            Cat { name: "Sphinx", species: Species::FelisCatus },
+            Cat { name: "Chandra", species: Species::PrionailurusViverrinus },
+        ],
+    },
+    enums: Vec<Shape> = vec![
+        Shape::Point,
+        Shape::Circle(0.5),
+        Shape::Rect(3, 4),
+        Shape::Poly { sides: 6, regular: true },
+        Shape::Poly { sides: 5, regular: false },
+    ],
+    maps: (BTreeMap<String, Vec<u16>>, serde_bytes::ByteBuf) = (
+        BTreeMap::from([("Felis".to_string(), vec![]), ("Lynx".to_string(), vec![1, 65535])]),
+        serde_bytes::ByteBuf::from(vec![0xde, 0xad, 0xbe, 0xef]),
+    ),
+    structs: (Unit, Meters, Pair, Pair, (), char, f32) = (Unit, Meters(300), Pair(-3, None), Pair(100, Some(200)), (), 'ß', 1.5),
+}
@@ -0,0 +1,129 @@
+//! Produce and consume `nachricht` payloads with `rdkafka`. Records are labelled with a `content-type` header of
+//! `nachricht::MEDIA_TYPE`. Every partition may have a `Dictionary` of its own, which producers and consumers share,
+//! e.g. by publishing it to a compacted topic keyed by partition:
+//!
+//! ```no_run
+//! use nachricht_serde::{from_kafka, kafka_headers, to_kafka_payload, PartitionDictionaries};
+//! use rdkafka::message::OwnedMessage;
+//! use rdkafka::producer::FutureRecord;
+//!
+//! let dictionaries = PartitionDictionaries::default();
+//! let payload = to_kafka_payload(&("Jessica", 9), 0, &dictionaries).unwrap();
+//! let record: FutureRecord<(), _> = FutureRecord::to("cats").partition(0).payload(&payload).headers(kafka_headers());
+//! # let message: OwnedMessage = unimplemented!();
+//! let (name, lives): (&str, u8) = from_kafka(&message, &dictionaries).unwrap();
+//! ```
+//!
+//! Since the dictionary depends on the partition, records have to name their partition explicitly instead of leaving
+//! it to the partitioner of the producer.
+
+use serde::{Deserialize, Serialize};
+use nachricht::{is_media_type, MEDIA_TYPE};
+use rdkafka::message::{Header, Headers, Message, OwnedHeaders};
+use std::collections::HashMap;
+
+use crate::de::from_bytes;
+use crate::dictionary::{from_bytes_with_dictionary, to_bytes_with_dictionary, Dictionary};
+use crate::error::{DeserializationError, Error, Result};
+use crate::ser::{to_bytes, SerializerOptions};
+
+/// The name of the header which carries the media type
+pub const KAFKA_CONTENT_TYPE: &str = "content-type";
+
+/// The dictionaries of the partitions of a topic. Partitions without one use none.
+#[derive(Debug, Default, Clone)]
+pub struct PartitionDictionaries {
+    partitions: HashMap<i32, Dictionary>,
+}
+
+impl PartitionDictionaries {
+
+    /// Use the dictionary for the partition from now on and return the previous one, if any.
+    pub fn insert(&mut self, partition: i32, dictionary: Dictionary) -> Option<Dictionary> {
+        self.partitions.insert(partition, dictionary)
+    }
+
+    pub fn remove(&mut self, partition: i32) -> Option<Dictionary> {
+        self.partitions.remove(&partition)
+    }
+
+    pub fn get(&self, partition: i32) -> Option<&Dictionary> {
+        self.partitions.get(&partition)
+    }
+
+}
+
+/// Headers which label a record as `nachricht`
+pub fn kafka_headers() -> OwnedHeaders {
+    OwnedHeaders::new().insert(Header { key: KAFKA_CONTENT_TYPE, value: Some(MEDIA_TYPE) })
+}
+
+/// Serialize the value as the payload of a record for the given partition.
+pub fn to_kafka_payload<T: Serialize>(value: &T, partition: i32, dictionaries: &PartitionDictionaries) -> Result<Vec<u8>> {
+    match dictionaries.get(partition) {
+        Some(dictionary) => to_bytes_with_dictionary(value, SerializerOptions::default(), dictionary),
+        None => to_bytes(value),
+    }
+}
+
+/// Deserialize the payload of a message with the dictionary of its partition. Fails with `Error::ContentType` if the
+/// message has a `content-type` header other than `MEDIA_TYPE`, while messages without one are accepted. A missing
+/// payload is treated as an empty one.
+pub fn from_kafka<'a, T: Deserialize<'a>, M: Message>(message: &'a M, dictionaries: &'a PartitionDictionaries) -> std::result::Result<T, DeserializationError> {
+    let content_type = message.headers().and_then(|headers| {
+        headers.iter().find(|h| h.key.eq_ignore_ascii_case(KAFKA_CONTENT_TYPE)).map(|h| h.value.unwrap_or_default())
+    });
+    if let Some(content_type) = content_type {
+        let content_type = String::from_utf8_lossy(content_type);
+        if !is_media_type(&content_type) {
+            return Err(Error::ContentType(content_type.into_owned()).at(0));
+        }
+    }
+    let payload = message.payload().unwrap_or_default();
+    match dictionaries.get(message.partition()) {
+        Some(dictionary) => from_bytes_with_dictionary(payload, dictionary),
+        None => from_bytes(payload),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rdkafka::message::{Header, OwnedHeaders, OwnedMessage, Timestamp};
+    use serde::{Deserialize, Serialize};
+    use super::{from_kafka, kafka_headers, to_kafka_payload, PartitionDictionaries};
+    use crate::dictionary::Dictionary;
+    use crate::error::Error;
+    use crate::preser::layouts_of;
+    use crate::ser::to_bytes;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        name: String,
+        lives: u8,
+    }
+
+    fn message(payload: Vec<u8>, partition: i32, headers: Option<OwnedHeaders>) -> OwnedMessage {
+        OwnedMessage::new(Some(payload), None, "cats".to_string(), Timestamp::NotAvailable, partition, 0, headers)
+    }
+
+    #[test]
+    fn partitions() {
+        let cats = vec![Cat { name: "Jessica".to_string(), lives: 9 }, Cat { name: "Wantan".to_string(), lives: 7 }];
+        let mut dictionaries = PartitionDictionaries::default();
+        assert!(dictionaries.insert(1, Dictionary::from_layouts(&layouts_of(&cats).unwrap())).is_none());
+        let plain = to_kafka_payload(&cats, 0, &dictionaries).unwrap();
+        let primed = to_kafka_payload(&cats, 1, &dictionaries).unwrap();
+        assert_eq!(to_bytes(&cats).unwrap(), plain);
+        assert!(primed.len() < plain.len());
+        assert_eq!(cats, from_kafka::<Vec<Cat>, _>(&message(plain, 0, Some(kafka_headers())), &dictionaries).unwrap());
+        assert_eq!(cats, from_kafka::<Vec<Cat>, _>(&message(primed.clone(), 1, None), &dictionaries).unwrap());
+        // the dictionary of another partition does not fit
+        assert!(from_kafka::<Vec<Cat>, _>(&message(primed.clone(), 2, None), &dictionaries).is_err());
+        let json = OwnedHeaders::new().insert(Header { key: "Content-Type", value: Some("application/json") });
+        let error = from_kafka::<Vec<Cat>, _>(&message(primed, 1, Some(json)), &dictionaries).unwrap_err();
+        assert!(matches!(error.into_inner(), Error::ContentType(t) if t == "application/json"));
+        assert!(dictionaries.remove(1).is_some());
+        assert!(dictionaries.get(1).is_none());
+    }
+
+}
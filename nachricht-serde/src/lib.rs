@@ -11,12 +11,34 @@
 //! conditionally skipping fields, for instance with
 //! `#[serde(skip_serializing_if = "Option::is_none")]`. This is a shortcoming of serde, not nachricht!
 //!
+//! # Recursive and shared data
+//!
+//! Recursive types like trees or linked lists only transmit their layout once, no matter how deeply they are nested.
+//! When deserializing, nesting is limited to [Deserializer::MAX_DEPTH] levels, where every container, `Some` and
+//! newtype counts as one level, so that malicious input cannot overflow the stack. The same limit applies when
+//! serializing, which turns `Serialize` implementations that recurse endlessly into an `Error::Depth` instead of a
+//! stack overflow.
+//!
+//! With serde's `rc` feature, `Rc` and `Arc` can be serialized, but every occurrence gets written in full and
+//! deserializing yields independent copies. To transmit repeated values only once, serialize with [to_value], share
+//! them with [nachricht::Value::deduplicate] and call [nachricht::Value::resolve_shared] before [from_value] on the
+//! receiving side. Deserializing still yields independent copies, so if identity matters, store the shared values once,
+//! e.g. in a `Vec`, and refer to them by index.
+//!
+//! # Values
+//!
+//! [to_value] and [from_value] convert between Rust data structures and [nachricht::Value] with the same mapping as
+//! the wire format. [Extract] deserializes only the part of a value at a given JSON pointer, with errors pointing to
+//! the offending value, and [Splice] serializes into such a part. These remain available if the default `wire` feature
+//! is disabled.
+//!
 //! # Examples
 //!
 //! This example demonstrates some of `nachricht`'s capabilities, including the re-use of struct
 //! layouts and enum constants with the help of an internal symbol table.
 //!
 //! ```
+//! # #[cfg(feature = "wire")] {
 //! use serde::{Deserialize, Serialize};
 //!
 //! #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -114,7 +136,7 @@
 //!
 //! let deserialized = nachricht_serde::from_bytes(&bytes).unwrap();
 //! assert_eq!(msg, deserialized);
-//!
+//! # }
 //! ```
 //!
 //! Note how efficient the encoding becomes for repetetive data structures: the last `species: #PrionailurusViverrinus`
@@ -123,23 +145,105 @@
 //! For comparison, `serde_json` produces a string of 210 bytes for the given input while msgpack in self-describing
 //! mode still needs 176 bytes. Non-self-describing formats like flatbuffers or bincode can of course achieve even
 //! smaller sizes at the expense of needing prior knowledge to make sense of the message.
+//!
+//! This listing and others covering enums, maps and the remaining serde data model are kept as machine-readable
+//! fixtures in the `fixtures` directory of the repository. The test suite checks that the serializer produces them
+//! byte for byte and that the deserializer accepts them, so any change of the wire format is caught.
+//! Additionally, the `compat` directory holds a message which every release serialized from the same data. Each of them
+//! has to deserialize with the current version and has to be reproduced byte for byte, so that releases can talk to
+//! each other in both directions.
+
+#[cfg(all(test, feature = "derive"))]
+extern crate self as nachricht_serde;
 
+#[cfg(feature = "client")]
+mod client;
+#[cfg(all(test, feature = "wire"))]
+mod compat;
+#[cfg(feature = "wire")]
+mod datagram;
+#[cfg(feature = "wire")]
 mod de;
+#[cfg(feature = "wire")]
+mod dictionary;
+#[cfg(feature = "wire")]
+mod envelope;
 mod error;
+#[cfg(all(test, feature = "wire"))]
+mod fixtures;
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "nats")]
+mod nats;
+#[cfg(feature = "wire")]
 mod preser;
+#[cfg(feature = "wire")]
+mod sansio;
+#[cfg(feature = "wire")]
+mod schema;
+#[cfg(feature = "wire")]
 mod ser;
+#[cfg(feature = "wire")]
+mod sizes;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "wire")]
+mod testing;
+#[cfg(feature = "wire")]
+mod validate;
+mod value;
 
-pub use de::{from_bytes, Deserializer};
+#[cfg(feature = "client")]
+pub use client::{read_nachricht, send_nachricht};
+#[cfg(feature = "wire")]
+pub use datagram::{from_datagram, to_datagrams};
+#[cfg(feature = "wire")]
+pub use de::{from_bytes, from_bytes_tagged, from_bytes_with_options, Deserializer};
+#[cfg(feature = "wire")]
+pub use dictionary::{from_bytes_with_dictionary, to_bytes_with_dictionary, Dictionary};
+#[cfg(feature = "wire")]
+pub use envelope::{Envelope, RawEnvelope};
 pub use error::{Error, Result};
-pub use ser::{to_bytes, to_writer, Serializer};
+#[cfg(feature = "kafka")]
+pub use kafka::{from_kafka, kafka_headers, to_kafka_payload, PartitionDictionaries, KAFKA_CONTENT_TYPE};
+#[cfg(feature = "nats")]
+pub use nats::{from_nats, nats_headers, publish_nachricht, NATS_CONTENT_TYPE};
+#[cfg(feature = "wire")]
+pub use preser::{layouts_of, Layout, Layouts, VERSION_FIELD};
+#[cfg(feature = "wire")]
+pub use sansio::{MessageDecoder, MessageEncoder};
+#[cfg(feature = "wire")]
+pub use schema::Nachricht;
+#[cfg(feature = "derive")]
+pub use nachricht_derive::Nachricht;
+#[cfg(feature = "wire")]
+pub use ser::{to_bytes, to_bytes_with_options, to_slice, to_writer, to_writer_with_options, FieldOrder, Serializer, SerializerOptions, TypedSerializer};
+#[cfg(feature = "wire")]
+pub use sizes::{to_bytes_with_report, PathSize, SizeReport};
+#[cfg(feature = "async")]
+pub use stream::MessageStream;
+#[cfg(feature = "wire")]
+pub use testing::{test_canonical_roundtrip, test_roundtrip};
+#[cfg(feature = "wire")]
+pub use validate::Validator;
+pub use value::{from_value, to_value, Extract, Splice};
 
-#[cfg(test)]
+/// The maximum nesting depth, see the section on recursive data above.
+pub(crate) const MAX_DEPTH: usize = 512;
+
+#[cfg(all(test, feature = "wire"))]
 mod tests {
     use serde::{Serialize, Deserialize};
-    use std::collections::HashMap;
-    use super::{to_bytes, from_bytes};
+    use std::collections::{BTreeMap, HashMap};
+    use std::rc::Rc;
+    use super::{to_bytes, to_bytes_with_options, to_slice, to_value, to_writer_with_options, from_bytes, from_bytes_tagged, from_bytes_with_options, from_value, layouts_of, Error, FieldOrder, Layouts, SerializerOptions, TypedSerializer};
+    use nachricht::{DecodeError, Decoder, DecoderOptions, EncodeError, Encoder, EncoderOptions, Header, Metrics, Sign, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use std::borrow::Cow;
 
     #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[allow(clippy::enum_variant_names)]
     enum Enum {
         UnitVariant,
         NewtypeVariant(bool),
@@ -185,6 +289,7 @@ mod tests {
         newtype_struct: NewtypeStruct,
         seq: Vec<String>,
         tuple: (u16, u16, u16),
+        tuple_struct: TupleStruct,
         map: HashMap<usize, String>,
         r#struct: Struct,
         unit_variant: Enum,
@@ -193,9 +298,8 @@ mod tests {
         struct_variant: Enum,
     }
 
-    #[test]
-    fn roundtrip() {
-        let message = Test {
+    fn message() -> Test {
+        Test {
             bool: true,
             i8: -1,
             i16: -20,
@@ -231,6 +335,7 @@ mod tests {
                 "omentielvo".to_string(),
             ],
             tuple: (0, 0, 0),
+            tuple_struct: TupleStruct('a', 'b', 'c'),
             map: [
                 (1701, "Enterprise".to_string()),
                 (74656, "Voyager".to_string())
@@ -246,8 +351,449 @@ mod tests {
                 b: 0,
                 c: 33,
             }
-        };
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let message = message();
         println!("{:02x?}", to_bytes(&message));
         assert_eq!(message, from_bytes::<Test>(&to_bytes(&message).unwrap()).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn value() {
+        let message = message();
+        let value = to_value(&message).unwrap();
+        assert_eq!(Decoder::decode(&to_bytes(&message).unwrap()).unwrap().0, value);
+        assert_eq!(message, from_value::<Test>(&value).unwrap());
+        assert!(matches!(from_value::<Struct>(&Value::Null), Err(Error::UnexpectedValue(&["record"], "null"))));
+    }
+
+    #[test]
+    fn symbol_keys() {
+        let maps: Vec<BTreeMap<String, u8>> = vec![
+            [("first".to_string(), 1), ("second".to_string(), 2)].into_iter().collect(),
+            [("first".to_string(), 3), ("second".to_string(), 4)].into_iter().collect(),
+        ];
+        let plain = to_bytes(&maps).unwrap();
+        let interned = to_bytes_with_options(&maps, SerializerOptions { symbol_keys: true, ..Default::default() }).unwrap();
+        assert_eq!(plain.len() - interned.len(), 11);
+        assert_eq!(maps, from_bytes::<Vec<BTreeMap<String, u8>>>(&interned).unwrap());
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum Untagged {
+        Counts(HashMap<String, u8>),
+        Other(Vec<u8>),
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Flattened {
+        name: String,
+        #[serde(flatten)]
+        rest: HashMap<String, String>,
+    }
+
+    #[derive(Deserialize, PartialEq, Eq, Hash, Debug)]
+    enum Key {
+        Jessica,
+        Wantan,
+    }
+
+    #[test]
+    fn interned_map_keys() {
+        // keys which are symbols or references to symbols defined as record keys, values or earlier map keys
+        let key = |k: &'static str| Value::Symbol(Cow::Borrowed(k));
+        let int = |v: u64| Value::Int(Sign::Pos, v);
+        let message = Value::Array(vec![
+            Value::Record(BTreeMap::from([(Cow::Borrowed("Jessica"), int(1))])),
+            Value::Map(vec![(key("Jessica"), int(2)), (key("Wantan"), int(3))]),
+            Value::Map(vec![(key("Wantan"), int(4)), (key("Jessica"), int(5))]),
+        ]);
+        let mut buf = Vec::new();
+        Encoder::encode(&message, &mut buf).unwrap();
+        type Counts<'a> = (BTreeMap<String, u8>, HashMap<String, u8>, HashMap<&'a str, u8>);
+        let (record, first, second): Counts = from_bytes(&buf).unwrap();
+        assert_eq!(BTreeMap::from([("Jessica".to_string(), 1)]), record);
+        assert_eq!(HashMap::from([("Jessica".to_string(), 2), ("Wantan".to_string(), 3)]), first);
+        assert_eq!(HashMap::from([("Jessica", 5), ("Wantan", 4)]), second);
+        let (_, first, second): (Untagged, Untagged, HashMap<Key, u8>) = from_bytes(&buf).unwrap();
+        assert_eq!(Untagged::Counts(HashMap::from([("Jessica".to_string(), 2), ("Wantan".to_string(), 3)])), first);
+        assert_eq!(HashMap::from([(Key::Jessica, 5), (Key::Wantan, 4)]), second);
+        let mut buf = Vec::new();
+        Encoder::encode(&Value::Array(vec![key("a"), Value::Map(vec![(key("a"), int(1))])]), &mut buf).unwrap();
+        assert_eq!(HashMap::from([('a', 1)]), from_bytes::<(serde::de::IgnoredAny, HashMap<char, u8>)>(&buf).unwrap().1);
+        // flattened fields ask for a map, which may also be a record
+        let message = Value::Array(vec![
+            Value::Record(BTreeMap::from([(Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))), (Cow::Borrowed("owner"), key("Liv"))])),
+            Value::Map(vec![(key("owner"), key("Liv")), (key("name"), Value::Str(Cow::Borrowed("Wantan")))]),
+        ]);
+        let mut buf = Vec::new();
+        Encoder::encode(&message, &mut buf).unwrap();
+        let (first, second): (Flattened, Flattened) = from_bytes(&buf).unwrap();
+        assert_eq!(Flattened { name: "Jessica".to_string(), rest: HashMap::from([("owner".to_string(), "Liv".to_string())]) }, first);
+        assert_eq!(Flattened { name: "Wantan".to_string(), rest: HashMap::from([("owner".to_string(), "Liv".to_string())]) }, second);
+    }
+
+    #[test]
+    fn symbol_strings() {
+        let cats = (0..3).map(|lives| Cat { name: "Jessica".to_string(), lives }).collect::<Vec<_>>();
+        let plain = to_bytes(&cats).unwrap();
+        let interned = to_bytes_with_options(&cats, SerializerOptions { symbol_strings: true, ..Default::default() }).unwrap();
+        assert_eq!(plain.len() - interned.len(), 14);
+        assert_eq!(cats, from_bytes::<Vec<Cat>>(&interned).unwrap());
+    }
+
+    #[test]
+    fn field_order() {
+        let cats = (0..3).map(|lives| Cat { name: "Jessica".to_string(), lives }).collect::<Vec<_>>();
+        let options = SerializerOptions { symbol_strings: true, field_order: FieldOrder::Alphabetical, ..Default::default() };
+        let sorted = to_bytes_with_options(&cats, options).unwrap();
+        let mut expected = Vec::new();
+        Encoder::encode_with_options(&to_value(&cats).unwrap(), &mut expected, EncoderOptions { symbol_strings: true, ..Default::default() }).unwrap();
+        assert_eq!(expected, sorted);
+        // the array header, the layout header and the header of the first key precede it
+        assert_eq!(b"lives", &sorted[3..8]);
+        assert_ne!(to_bytes_with_options(&cats, SerializerOptions { field_order: FieldOrder::Declaration, ..options }).unwrap(), sorted);
+        assert_eq!(sorted, TypedSerializer::<Vec<Cat>>::with_options(options).to_bytes(&cats).unwrap());
+        assert_eq!(cats, from_bytes::<Vec<Cat>>(&sorted).unwrap());
+    }
+
+    #[test]
+    fn tagged_options() {
+        let values = vec![None, Some(None), Some(Some(()))];
+        let plain = to_bytes(&values).unwrap();
+        assert_eq!(vec![None, None, None], from_bytes::<Vec<Option<Option<()>>>>(&plain).unwrap());
+        let options = SerializerOptions { tagged_options: true, ..Default::default() };
+        let tagged = to_bytes_with_options(&values, options).unwrap();
+        let mut expected = Vec::new();
+        for header in [Header::Arr(3), Header::Null, Header::Rec(1), Header::Sym(4)] {
+            header.encode(&mut expected).unwrap();
+        }
+        expected.extend_from_slice(b"some");
+        for header in [Header::Null, Header::Ref(1), Header::Ref(1), Header::Null] {
+            header.encode(&mut expected).unwrap();
+        }
+        assert_eq!(expected, tagged);
+        assert_eq!(tagged, to_bytes_with_options(&values, SerializerOptions { field_order: FieldOrder::Alphabetical, ..options }).unwrap());
+        assert_eq!(values, from_bytes_tagged::<Vec<Option<Option<()>>>>(&tagged, DecoderOptions::default()).unwrap());
+        assert!(from_bytes::<Vec<Option<Option<()>>>>(&tagged).is_err());
+        let cat = to_bytes(&Some(Struct { field: 1 })).unwrap();
+        assert!(matches!(from_bytes_tagged::<Option<Struct>>(&cat, DecoderOptions::default()).unwrap_err().into_inner(), Error::Message(_)));
+    }
+
+    #[test]
+    fn str_keys() {
+        let mut buf = Vec::new();
+        Header::Rec(2).encode(&mut buf).unwrap();
+        for key in ["lives", "name"] {
+            Header::Str(key.len()).encode(&mut buf).unwrap();
+            buf.extend_from_slice(key.as_bytes());
+        }
+        Header::Int(Sign::Pos, 9).encode(&mut buf).unwrap();
+        Header::Str(7).encode(&mut buf).unwrap();
+        buf.extend_from_slice(b"Jessica");
+        assert!(matches!(from_bytes::<Cat>(&buf).unwrap_err().into_inner(), Error::Decode(DecodeError::IllegalKey("string"))));
+        let cat = from_bytes_with_options::<Cat>(&buf, DecoderOptions { str_keys: true, ..Default::default() }).unwrap();
+        assert_eq!(Cat { name: "Jessica".to_string(), lives: 9 }, cat);
+    }
+
+    /// Serializes a map of sequences through iterators without an exact size hint
+    struct Unsized<'a>(&'a BTreeMap<String, Vec<Enum>>);
+
+    struct UnsizedSeq<'a>(&'a [Enum]);
+
+    impl Serialize for Unsized<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().filter(|_| true).map(|(k, v)| (k, UnsizedSeq(v))))
+        }
+    }
+
+    impl Serialize for UnsizedSeq<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.iter().filter(|_| true))
+        }
+    }
+
+    #[test]
+    fn unknown_length() {
+        let map = BTreeMap::from([
+            ("first".to_string(), vec![Enum::UnitVariant, Enum::NewtypeVariant(true)]),
+            ("second".to_string(), vec![]),
+            ("third".to_string(), vec![Enum::NewtypeVariant(false), Enum::UnitVariant]),
+        ]);
+        let options = SerializerOptions { symbol_keys: true, ..Default::default() };
+        let bytes = to_bytes_with_options(&Unsized(&map), options).unwrap();
+        assert_eq!(to_bytes_with_options(&map, options).unwrap(), bytes);
+        assert_eq!(map, from_bytes::<BTreeMap<String, Vec<Enum>>>(&bytes).unwrap());
+    }
+
+    #[test]
+    fn truncated_record() {
+        let bytes = to_bytes(&Cat { name: "Jessica".to_string(), lives: 9 }).unwrap();
+        let name = match from_bytes::<Cat>(&bytes[..bytes.len() - 3]).unwrap_err().into_inner() {
+            Error::Decode(DecodeError::TruncatedRecord(name)) => name,
+            e => panic!("unexpected error {}", e),
+        };
+        assert_eq!("name", name);
+    }
+
+    #[test]
+    fn ignored_fields() {
+        let cat = |name| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+            (Cow::Borrowed("lives"), Value::Int(Sign::Pos, 9)),
+            (Cow::Borrowed("mood"), Value::Symbol(Cow::Borrowed("grumpy"))),
+            (Cow::Borrowed("note"), Value::Str(Cow::Borrowed("~~"))),
+        ]));
+        let mut bytes = Vec::new();
+        Encoder::encode(&Value::Array(vec![cat("Jessica"), cat("Wantan")]), &mut bytes).unwrap();
+        // unknown fields are skipped without being decoded, but symbols defined within them stay known
+        let note = bytes.windows(2).position(|w| w == b"~~").unwrap();
+        bytes[note..note + 2].copy_from_slice(&[0xff, 0xff]);
+        let cats = from_bytes::<Vec<Cat>>(&bytes).unwrap();
+        assert_eq!(vec!["Jessica", "Wantan"], cats.iter().map(|c| c.name.as_str()).collect::<Vec<_>>());
+        assert!(Decoder::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn slice() {
+        let cat = Cat { name: "Jessica".to_string(), lives: 9 };
+        let bytes = to_bytes(&cat).unwrap();
+        let mut buf = [0; 64];
+        let c = to_slice(&cat, &mut buf).unwrap();
+        assert_eq!(bytes, &buf[..c]);
+        assert!(matches!(to_slice(&cat, &mut buf[..c - 1]), Err(Error::Encode(EncodeError::BufferTooSmall(r))) if r == c));
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        name: String,
+        lives: u8,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Pet {
+        Cat { name: String, lives: u8 },
+        Dog { name: String, good: bool },
+    }
+
+    fn occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack.windows(needle.len()).filter(|w| *w == needle).count()
+    }
+
+    #[test]
+    fn layouts() {
+        let pets = vec![
+            Pet::Cat { name: "Jessica".to_string(), lives: 9 },
+            Pet::Cat { name: "Wantan".to_string(), lives: 7 },
+        ];
+        let layouts = layouts_of(&(pets, Cat { name: "Sphinx".to_string(), lives: 3 })).unwrap();
+        assert_eq!(["name", "lives"], layouts.get("Cat", None).unwrap().fields());
+        assert_eq!(["name", "lives"], layouts.get("Pet", Some("Cat")).unwrap().fields());
+        assert!(layouts.get("Pet", Some("Dog")).is_none());
+        assert_eq!(2, layouts.layouts().count());
+        assert_eq!(vec![("Pet", "Cat")], layouts.variants().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn typed_serializer() {
+        let mut serializer = TypedSerializer::new();
+        let messages = [
+            vec![Pet::Cat { name: "Jessica".to_string(), lives: 9 }],
+            vec![Pet::Cat { name: "Wantan".to_string(), lives: 7 }, Pet::Dog { name: "Laika".to_string(), good: true }],
+            vec![],
+            vec![Pet::Dog { name: "Hachiko".to_string(), good: true }],
+        ];
+        let mut written = Vec::new();
+        for message in messages.iter() {
+            let bytes = to_bytes(message).unwrap();
+            assert_eq!(bytes, serializer.to_bytes(message).unwrap());
+            serializer.to_writer(&mut written, message).unwrap();
+            assert!(written.ends_with(&bytes));
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Stray {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        lives: Option<u8>,
+    }
+
+    #[test]
+    fn typed_serializer_skipped_fields() {
+        let mut serializer = TypedSerializer::new();
+        let messages = [
+            vec![Stray { name: "Jessica".to_string(), lives: Some(9) }],
+            vec![Stray { name: "Wantan".to_string(), lives: None }],
+            vec![Stray { name: "Sphinx".to_string(), lives: Some(1) }],
+        ];
+        for message in messages.iter() {
+            let bytes = serializer.to_bytes(message).unwrap();
+            assert_eq!(to_bytes(message).unwrap(), bytes);
+            assert_eq!(*message, from_bytes::<Vec<Stray>>(&bytes).unwrap());
+        }
+        // all structs of a value still need the same fields
+        let mixed = vec![Stray { name: "Jessica".to_string(), lives: Some(9) }, Stray { name: "Wantan".to_string(), lives: None }];
+        assert!(matches!(serializer.to_bytes(&mixed), Err(Error::DuplicateLayout("Stray", None))));
+    }
+
+    #[test]
+    fn registered_layouts() {
+        let cats = vec![Cat { name: "Jessica".to_string(), lives: 9 }];
+        let serializer = |fields: &[&'static str]| {
+            let mut layouts = Layouts::default();
+            layouts.insert_layout("Cat", None, fields).unwrap();
+            TypedSerializer::<Vec<Cat>>::from_layouts(layouts, SerializerOptions::default())
+        };
+        let mut valid = serializer(&["name", "lives"]);
+        assert_eq!(to_bytes(&cats).unwrap(), valid.to_bytes(&cats).unwrap());
+        assert_eq!(to_bytes(&cats).unwrap(), valid.to_bytes(&cats).unwrap());
+        assert!(matches!(serializer(&["lives", "name"]).to_bytes(&cats), Err(Error::LayoutMismatch("Cat", None))));
+        assert!(matches!(serializer(&["name"]).to_bytes(&cats), Err(Error::LayoutMismatch("Cat", None))));
+        assert!(matches!(serializer(&["name", "lives", "mood"]).to_bytes(&cats), Err(Error::LayoutMismatch("Cat", None))));
+        assert!(matches!(TypedSerializer::from_layouts(Layouts::default(), SerializerOptions::default()).to_bytes(&cats),
+            Err(Error::UnknownStructLayout("Cat"))));
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Tree {
+        name: String,
+        children: Vec<Tree>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Node {
+        value: u32,
+        next: Option<Box<Node>>,
+    }
+
+    fn list(len: u32) -> Option<Box<Node>> {
+        (0..len).fold(None, |next, value| Some(Box::new(Node { value, next })))
+    }
+
+    #[test]
+    fn recursive() {
+        let leaf = |name: &str| Tree { name: name.to_string(), children: vec![] };
+        let tree = Tree { name: "root".to_string(), children: vec![
+            Tree { name: "left".to_string(), children: vec![leaf("a"), leaf("b")] },
+            Tree { name: "right".to_string(), children: vec![leaf("c")] },
+        ]};
+        let bytes = to_bytes(&tree).unwrap();
+        assert_eq!(1, occurrences(&bytes, b"children"));
+        assert_eq!(tree, from_bytes::<Tree>(&bytes).unwrap());
+        let list = list(100);
+        let bytes = to_bytes(&list).unwrap();
+        assert_eq!(1, occurrences(&bytes, b"next"));
+        assert_eq!(list, from_bytes::<Option<Box<Node>>>(&bytes).unwrap());
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct Endless(Option<Box<Endless>>);
+
+    #[test]
+    fn depth() {
+        let deep = list(250);
+        assert_eq!(deep, from_bytes::<Option<Box<Node>>>(&to_bytes(&deep).unwrap()).unwrap());
+        // the serializer refuses to produce this, so go through the generic encoder
+        let deep = (0..300).fold(Value::Null, |next, value| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("value"), Value::Int(Sign::Pos, value)),
+            (Cow::Borrowed("next"), next),
+        ])));
+        let mut bytes = Vec::new();
+        Encoder::encode(&deep, &mut bytes).unwrap();
+        assert!(matches!(from_bytes::<Option<Box<Node>>>(&bytes).unwrap_err().into_inner(), Error::Depth(_)));
+        assert!(matches!(from_bytes::<Endless>(&[0x21]).unwrap_err().into_inner(), Error::Depth(_)));
+    }
+
+    /// A `Serialize` implementation which never terminates
+    struct Cycle;
+
+    impl Serialize for Cycle {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            serializer.serialize_newtype_struct("Cycle", self)
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        assert!(matches!(to_bytes(&Cycle), Err(Error::Depth(_))));
+        assert!(matches!(to_bytes(&list(300)), Err(Error::Depth(_))));
+        let mut serializer = TypedSerializer::new();
+        assert!(matches!(serializer.to_bytes(&list(300)), Err(Error::Depth(_))));
+    }
+
+    #[test]
+    fn shared() {
+        let jessica = Rc::new(Cat { name: "Jessica".to_string(), lives: 9 });
+        let cats = vec![jessica.clone(), jessica.clone()];
+        let decoded = from_bytes::<Vec<Rc<Cat>>>(&to_bytes(&cats).unwrap()).unwrap();
+        assert_eq!(cats, decoded);
+        assert!(!Rc::ptr_eq(&decoded[0], &decoded[1]));
+    }
+
+    #[test]
+    fn map_value_layouts() {
+        let cats: HashMap<u64, Cat> = (0..1000).map(|i| (i, Cat { name: format!("Cat {}", i), lives: 9 })).collect();
+        let bytes = to_bytes(&cats).unwrap();
+        assert_eq!(1, occurrences(&bytes, b"name"));
+        assert_eq!(1, occurrences(&bytes, b"lives"));
+        assert_eq!(cats, from_bytes::<HashMap<u64, Cat>>(&bytes).unwrap());
+    }
+
+    #[test]
+    fn map_variant_layouts() {
+        let pets: BTreeMap<u64, Pet> = (0..100).map(|i| (i, match i % 2 {
+            0 => Pet::Cat { name: format!("Cat {}", i), lives: 9 },
+            _ => Pet::Dog { name: format!("Dog {}", i), good: true },
+        })).collect();
+        let bytes = to_bytes(&pets).unwrap();
+        assert_eq!(1, occurrences(&bytes, b"lives"));
+        assert_eq!(1, occurrences(&bytes, b"good"));
+        assert_eq!(pets, from_bytes::<BTreeMap<u64, Pet>>(&bytes).unwrap());
+    }
+
+    struct Counter {
+        encoded: AtomicUsize,
+        decoded: AtomicUsize,
+    }
+
+    impl Metrics for Counter {
+
+        fn encoded(&self, bytes: usize, _elapsed: Duration) {
+            self.encoded.fetch_add(bytes, Ordering::SeqCst);
+        }
+
+        fn decoded(&self, bytes: usize, _elapsed: Duration) {
+            self.decoded.fetch_add(bytes, Ordering::SeqCst);
+        }
+
+    }
+
+    static COUNTER: Counter = Counter { encoded: AtomicUsize::new(0), decoded: AtomicUsize::new(0) };
+    static UNSIZED: Counter = Counter { encoded: AtomicUsize::new(0), decoded: AtomicUsize::new(0) };
+
+    #[test]
+    fn metrics() {
+        let cats = vec![Cat { name: "Jessica".to_string(), lives: 9 }, Cat { name: "Wantan".to_string(), lives: 7 }];
+        let options = SerializerOptions { metrics: Some(&COUNTER), ..Default::default() };
+        let bytes = to_bytes_with_options(&cats, options).unwrap();
+        to_bytes_with_options(&cats, SerializerOptions { field_order: FieldOrder::Alphabetical, ..options }).unwrap();
+        TypedSerializer::with_options(options).to_bytes(&cats).unwrap();
+        assert_eq!(3 * bytes.len(), COUNTER.encoded.load(Ordering::SeqCst));
+        let options = DecoderOptions { metrics: Some(&COUNTER), ..Default::default() };
+        assert!(from_bytes_with_options::<Vec<Cat>>(&bytes[..bytes.len() - 1], options).is_err());
+        assert_eq!(cats, from_bytes_with_options::<Vec<Cat>>(&bytes, options).unwrap());
+        assert_eq!(bytes.len(), COUNTER.decoded.load(Ordering::SeqCst));
+        // sequences of unknown length are buffered before they reach the output
+        let variants = [Enum::UnitVariant, Enum::NewtypeVariant(true), Enum::UnitVariant];
+        let options = SerializerOptions { metrics: Some(&UNSIZED), ..Default::default() };
+        let bytes = to_bytes_with_options(&UnsizedSeq(&variants), options).unwrap();
+        assert_eq!(to_bytes(&variants).unwrap(), bytes);
+        to_writer_with_options(&mut Vec::new(), &UnsizedSeq(&variants), options).unwrap();
+        assert_eq!(2 * bytes.len(), UNSIZED.encoded.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,100 @@
+//! Publish and receive `nachricht` payloads with `async-nats`. Messages are labelled with a `Content-Type` header of
+//! `nachricht::MEDIA_TYPE` and may use a `Dictionary` which publishers and subscribers of a subject share:
+//!
+//! ```no_run
+//! # async fn example() -> nachricht_serde::Result<()> {
+//! use futures::StreamExt;
+//! use nachricht_serde::{from_nats, publish_nachricht};
+//!
+//! let client = async_nats::connect("localhost:4222").await.unwrap();
+//! let mut subscriber = client.subscribe("cats").await.unwrap();
+//! publish_nachricht(&client, "cats", &("Jessica", 9), None).await?;
+//! let message = subscriber.next().await.unwrap();
+//! let (name, lives): (&str, u8) = from_nats(&message, None).unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+use async_nats::header::HeaderMap;
+use async_nats::subject::ToSubject;
+use async_nats::{Client, Message};
+use nachricht::{is_media_type, MEDIA_TYPE};
+
+use crate::de::from_bytes;
+use crate::dictionary::{from_bytes_with_dictionary, to_bytes_with_dictionary, Dictionary};
+use crate::error::{DeserializationError, Error, Result};
+use crate::ser::{to_bytes, SerializerOptions};
+
+/// The name of the header which carries the media type. Names of NATS headers are case-sensitive.
+pub const NATS_CONTENT_TYPE: &str = "Content-Type";
+
+/// Headers which label a message as `nachricht`
+pub fn nats_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(NATS_CONTENT_TYPE, MEDIA_TYPE);
+    headers
+}
+
+/// Serialize the value with the dictionary, if any, and publish it to the subject. Errors of the client are returned
+/// as `Error::Nats`.
+pub async fn publish_nachricht<S: ToSubject, T: Serialize>(client: &Client, subject: S, value: &T, dictionary: Option<&Dictionary>) -> Result<()> {
+    let payload = match dictionary {
+        Some(dictionary) => to_bytes_with_dictionary(value, SerializerOptions::default(), dictionary)?,
+        None => to_bytes(value)?,
+    };
+    client.publish_with_headers(subject, nats_headers(), payload.into()).await.map_err(|e| Error::Nats(Box::new(e)))
+}
+
+/// Deserialize the payload of a message with the dictionary, if any. Fails with `Error::ContentType` if the message
+/// has a `Content-Type` header other than `MEDIA_TYPE`, while messages without one are accepted.
+pub fn from_nats<'a, T: Deserialize<'a>>(message: &'a Message, dictionary: Option<&'a Dictionary>) -> std::result::Result<T, DeserializationError> {
+    if let Some(content_type) = message.headers.as_ref().and_then(|h| h.get(NATS_CONTENT_TYPE)) {
+        if !is_media_type(content_type.as_str()) {
+            return Err(Error::ContentType(content_type.to_string()).at(0));
+        }
+    }
+    match dictionary {
+        Some(dictionary) => from_bytes_with_dictionary(&message.payload, dictionary),
+        None => from_bytes(&message.payload),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_nats::header::HeaderMap;
+    use async_nats::Message;
+    use serde::{Deserialize, Serialize};
+    use super::{from_nats, nats_headers, NATS_CONTENT_TYPE};
+    use crate::dictionary::{to_bytes_with_dictionary, Dictionary};
+    use crate::error::Error;
+    use crate::preser::layouts_of;
+    use crate::ser::{to_bytes, SerializerOptions};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat<'a> {
+        name: &'a str,
+        lives: u8,
+    }
+
+    fn message(payload: Vec<u8>, headers: Option<HeaderMap>) -> Message {
+        let length = payload.len();
+        Message { subject: "cats".into(), reply: None, payload: payload.into(), headers, status: None, description: None, length }
+    }
+
+    #[test]
+    fn messages() {
+        let cat = Cat { name: "Jessica", lives: 9 };
+        let dictionary = Dictionary::from_layouts(&layouts_of(&cat).unwrap());
+        let plain = message(to_bytes(&cat).unwrap(), Some(nats_headers()));
+        let primed = message(to_bytes_with_dictionary(&cat, SerializerOptions::default(), &dictionary).unwrap(), None);
+        assert_eq!(cat, from_nats(&plain, None).unwrap());
+        assert_eq!(cat, from_nats(&primed, Some(&dictionary)).unwrap());
+        assert!(from_nats::<Cat>(&primed, None).is_err());
+        let mut headers = HeaderMap::new();
+        headers.insert(NATS_CONTENT_TYPE, "application/json");
+        let error = from_nats::<Cat>(&message(b"{}".to_vec(), Some(headers)), None).unwrap_err();
+        assert!(matches!(error.into_inner(), Error::ContentType(t) if t == "application/json"));
+    }
+
+}
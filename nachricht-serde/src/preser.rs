@@ -1,44 +1,162 @@
 use serde::ser::{self, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use crate::de::Deserializer;
 use crate::error::{Error, Result};
 
 /// For enum identifiers: name => variant => T
 /// Structs don't have variants, hence the second parameter is optional
-pub type Variant<T> = HashMap<&'static str, HashMap<Option<&'static str>, T>>;
+pub(crate) type Variant<T> = HashMap<&'static str, HashMap<Option<&'static str>, T>>;
 
+/// The field which precedes all others in records of a versioned layout, see `Layouts::set_version`.
+pub const VERSION_FIELD: &str = "@version";
+
+/// The field names of a struct or struct variant, which get encoded as a record layout.
 #[derive(Default,Debug)]
 pub struct Layout {
-    pub fields: Vec<&'static str>,
-    pub idx: Option<usize>,
+    pub(crate) fields: Vec<&'static str>,
+    pub(crate) idx: Option<usize>,
+    /// Whether the fields of a static layout have been checked against an actual value
+    pub(crate) verified: bool,
+    pub(crate) version: Option<u64>,
 }
 
 impl Layout {
     fn from(fields: Vec<&'static str>) -> Self {
-        Self { fields, idx: None }
+        Self { fields, idx: None, verified: false, version: None }
+    }
+
+    /// The field names in the order in which they get encoded, without `VERSION_FIELD`.
+    pub fn fields(&self) -> &[&'static str] {
+        &self.fields
+    }
+
+    /// The version which gets written with every record of this layout, if any.
+    pub fn version(&self) -> Option<u64> {
+        self.version
+    }
+
+    /// The keys of the record layout on the wire
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.version.map(|_| VERSION_FIELD).into_iter().chain(self.fields.iter().copied())
     }
 }
 
+/// The record layouts and enum variants which occur within a value. Every layout gets encoded once per message and
+/// referenced afterwards. The order of iteration is unspecified.
 #[derive(Default,Debug)]
 pub struct Layouts {
     /// The name of the variant already defines the layout of the used record, hence we only have to
     /// track the index
-    pub variants: HashMap<&'static str, HashMap<&'static str, Option<usize>>>,
-    pub structs: Variant<Layout>,
+    pub(crate) variants: HashMap<&'static str, HashMap<&'static str, Option<usize>>>,
+    pub(crate) structs: Variant<Layout>,
+    /// Types which have already been traversed by `Nachricht::collect_layouts`
+    visited: HashSet<&'static str>,
+    /// Whether the layouts were collected statically and thus contain conditionally skipped fields
+    pub(crate) complete: bool,
+}
+
+impl Layouts {
+
+    /// The layout of the given struct or, if a variant is given, struct variant.
+    pub fn get(&self, name: &str, variant: Option<&str>) -> Option<&Layout> {
+        self.structs.get(name).and_then(|m| m.iter().find(|(v, _)| **v == variant)).map(|(_, l)| l)
+    }
+
+    /// All layouts as `(name, variant, layout)`, where `variant` is `None` for plain structs.
+    pub fn layouts(&self) -> impl Iterator<Item = (&'static str, Option<&'static str>, &Layout)> + '_ {
+        self.structs.iter().flat_map(|(n, m)| m.iter().map(move |(v, l)| (*n, *v, l)))
+    }
+
+    /// Mark a type as traversed. Returns false if it had already been traversed before.
+    pub fn visit(&mut self, type_name: &'static str) -> bool {
+        self.visited.insert(type_name)
+    }
+
+    /// Add the layout of a struct or, if a variant is given, struct variant. Fails if a different layout with the same
+    /// name is already known.
+    pub fn insert_layout(&mut self, name: &'static str, variant: Option<&'static str>, fields: &[&'static str]) -> Result<()> {
+        let known = self.structs.entry(name).or_default();
+        match known.get(&variant) {
+            Some(old) if old.fields != fields => Err(Error::DuplicateLayout(name, variant)),
+            Some(_) => Ok(()),
+            None => { known.insert(variant, Layout::from(fields.to_vec())); Ok(()) },
+        }
+    }
+
+    /// Write the version with every record of the given struct or struct variant, so that receivers can tell apart
+    /// revisions of a schema without an envelope. It becomes the first field of the record, named `VERSION_FIELD`,
+    /// which receivers can declare like any other field, e.g. with `#[serde(rename = "@version", default)]`, and which
+    /// is ignored by types which do not declare it. The layout needs to be known already and must not contain a field
+    /// of that name. Versions are only written by a `TypedSerializer` which uses these layouts, with
+    /// `FieldOrder::Declaration`.
+    pub fn set_version(&mut self, name: &'static str, variant: Option<&'static str>, version: u64) -> Result<()> {
+        let layout = self.structs.get_mut(name).and_then(|m| m.get_mut(&variant)).ok_or(Error::UnknownStructLayout(name))?;
+        if layout.fields.contains(&VERSION_FIELD) {
+            return Err(Error::LayoutMismatch(name, variant));
+        }
+        layout.version = Some(version);
+        Ok(())
+    }
+
+    /// Add an enum variant which carries data.
+    pub fn insert_variant(&mut self, name: &'static str, variant: &'static str) {
+        self.variants.entry(name).or_default().entry(variant).or_insert(None);
+    }
+
+    /// Forget the symbol table indices of the previous message.
+    pub(crate) fn reset(&mut self) {
+        self.structs.values_mut().flat_map(|m| m.values_mut()).for_each(|l| l.idx = None);
+        self.variants.values_mut().flat_map(|m| m.values_mut()).for_each(|i| *i = None);
+    }
+
+    /// Add the layouts of another value, replacing those which have different fields now, e.g. due to
+    /// `#[serde(skip_serializing_if)]`.
+    pub(crate) fn replace(&mut self, other: Layouts) {
+        for (name, layouts) in other.structs {
+            self.structs.entry(name).or_default().extend(layouts);
+        }
+        for (name, variants) in other.variants {
+            self.variants.entry(name).or_default().extend(variants);
+        }
+    }
+
+    /// All enum variants which carry data as `(name, variant)`. Unit variants are plain symbols and not tracked.
+    pub fn variants(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        self.variants.iter().flat_map(|(n, m)| m.keys().map(move |v| (*n, *v)))
+    }
+
 }
 
 pub struct Preserializer {
     layouts: Layouts,
+    depth: usize,
 }
 
-pub fn preserialize<T: Serialize>(value: &T) -> Result<Layouts> {
-    let mut preserializer = Preserializer { layouts: Default::default() };
+/// Collect the layouts a value will produce when serialized. Fails if the same struct has different layouts within the
+/// value, e.g. due to `#[serde(skip_serializing_if)]`.
+pub fn layouts_of<T: Serialize>(value: &T) -> Result<Layouts> {
+    preserialize(value)
+}
+
+pub(crate) fn preserialize<T: ?Sized + Serialize>(value: &T) -> Result<Layouts> {
+    let mut preserializer = Preserializer { layouts: Default::default(), depth: 0 };
     value.serialize(&mut preserializer)?;
     Ok(preserializer.layouts)
 }
 
 impl Preserializer {
 
+    fn nested(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        if self.depth == Deserializer::MAX_DEPTH {
+            return Err(Error::Depth(Deserializer::MAX_DEPTH));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
     fn add_struct_layout(&mut self, name: &'static str, variant: Option<&'static str>, layout: Vec<&'static str>) -> Result<()> {
         match self.layouts.structs.entry(name).or_default().insert(variant, Layout::from(layout.clone())) {
             Some(old) if old.fields != *layout => Err(Error::DuplicateLayout(name, variant)),
@@ -124,7 +242,7 @@ impl<'a> ser::Serializer for &'a mut Preserializer {
     }
 
     fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
-        value.serialize(self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn serialize_unit(self) -> Result<()> {
@@ -140,12 +258,12 @@ impl<'a> ser::Serializer for &'a mut Preserializer {
     }
 
     fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<()> {
-        value.serialize(self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn serialize_newtype_variant<T: ?Sized + Serialize>(self, name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<()> {
         self.add_variant(name, variant);
-        value.serialize(self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
@@ -185,7 +303,7 @@ impl ser::SerializeSeq for &mut Preserializer {
     type Error = Error;
 
     fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -199,7 +317,7 @@ impl ser::SerializeTuple for &mut Preserializer {
     type Error = Error;
 
     fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -212,7 +330,7 @@ impl ser::SerializeTupleStruct for &mut Preserializer {
     type Error = Error;
 
     fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -225,7 +343,7 @@ impl ser::SerializeTupleVariant for &mut Preserializer {
     type Error = Error;
 
     fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -238,11 +356,11 @@ impl ser::SerializeMap for &mut Preserializer {
     type Error = Error;
 
     fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
-        key.serialize(&mut **self)
+        self.nested(|s| key.serialize(s))
     }
 
     fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -263,7 +381,7 @@ impl<'a> ser::SerializeStruct for StructPreserializer<'a> {
 
     fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
         self.fields.push(key);
-        value.serialize(&mut *self.ser)?;
+        self.ser.nested(|s| value.serialize(s))?;
         Ok(())
     }
 
@@ -287,7 +405,7 @@ impl<'a> ser::SerializeStructVariant for VariantPreserializer<'a> {
 
     fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
         self.fields.push(key);
-        value.serialize(&mut *self.ser)?;
+        self.ser.nested(|s| value.serialize(s))?;
         Ok(())
     }
 
@@ -0,0 +1,210 @@
+//! IO-free state machines for sending and receiving streams of `nachricht` messages. They never read or write on their
+//! own; instead the caller moves bytes in and out of their buffers. This allows embedding `nachricht` into any runtime,
+//! be it blocking, asynchronous, completion-based or bare metal.
+//!
+//! ```
+//! use nachricht_serde::{MessageDecoder, MessageEncoder};
+//!
+//! let mut encoder = MessageEncoder::new();
+//! encoder.encode(&"first").unwrap();
+//! encoder.encode(&"second").unwrap();
+//!
+//! let mut decoder = MessageDecoder::<String>::new();
+//! while !encoder.is_empty() {
+//!     // Pretend that the transport only carries three bytes at a time
+//!     let buf = decoder.buffer().unwrap();
+//!     let n = buf.len().min(encoder.pending().len()).min(3);
+//!     buf[..n].copy_from_slice(&encoder.pending()[..n]);
+//!     decoder.fill(n);
+//!     encoder.consume(n);
+//! }
+//! assert_eq!("first", decoder.next_message().unwrap().unwrap());
+//! assert_eq!("second", decoder.next_message().unwrap().unwrap());
+//! assert!(decoder.next_message().is_none());
+//! decoder.finish().unwrap();
+//! ```
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use nachricht::DecodeError;
+use std::marker::PhantomData;
+
+use crate::de::from_prefix;
+use crate::error::{Error, Result};
+use crate::ser::to_writer;
+
+const CHUNK: usize = 4096;
+
+/// Reassembles typed messages from bytes supplied by the caller. Since every value is self-delimiting, no additional
+/// framing is needed: messages are simply concatenated, each with its own symbol table.
+pub struct MessageDecoder<T> {
+    buf: Vec<u8>,
+    /// Number of bytes in `buf` which have been supplied but not yet consumed
+    filled: usize,
+    limit: usize,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> MessageDecoder<T> {
+
+    /// Messages larger than this are rejected unless a different limit is configured
+    pub const DEFAULT_LIMIT: usize = 1 << 20;
+
+    /// Create a decoder which rejects messages larger than `DEFAULT_LIMIT`.
+    pub fn new() -> Self {
+        Self::with_limit(Self::DEFAULT_LIMIT)
+    }
+
+    /// Create a decoder which rejects messages larger than `limit` bytes.
+    pub fn with_limit(limit: usize) -> Self {
+        Self { buf: Vec::new(), filled: 0, limit, _type: PhantomData }
+    }
+
+    /// Space for the next input. Write into it and call `fill` with the number of bytes written. Fails if the
+    /// currently buffered message would exceed the limit.
+    pub fn buffer(&mut self) -> Result<&mut [u8]> {
+        if self.filled >= self.limit {
+            return Err(Error::Limit(self.limit));
+        }
+        if self.buf.len() == self.filled {
+            let len = (self.filled + CHUNK).min(self.limit);
+            self.buf.resize(len, 0);
+        }
+        Ok(&mut self.buf[self.filled..])
+    }
+
+    /// Mark `n` bytes of the slice returned by `buffer` as filled.
+    pub fn fill(&mut self, n: usize) {
+        self.filled = (self.filled + n).min(self.buf.len());
+    }
+
+    /// Copy the given input into the buffer.
+    pub fn feed(&mut self, mut input: &[u8]) -> Result<()> {
+        while !input.is_empty() {
+            let buf = self.buffer()?;
+            let n = buf.len().min(input.len());
+            buf[..n].copy_from_slice(&input[..n]);
+            self.fill(n);
+            input = &input[n..];
+        }
+        Ok(())
+    }
+
+    /// Decode the next message if it is complete. Returns `None` if more input is needed.
+    pub fn next_message(&mut self) -> Option<Result<T>> {
+        if self.filled == 0 {
+            return None;
+        }
+        match from_prefix::<T>(&self.buf[..self.filled]) {
+            Ok((t, c)) => {
+                self.buf.copy_within(c..self.filled, 0);
+                self.filled -= c;
+                Some(Ok(t))
+            },
+            Err(Error::Decode(e)) if e.is_eof() => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Signal the end of input. Fails if there is an incomplete message left in the buffer.
+    pub fn finish(&self) -> Result<()> {
+        if self.filled == 0 {
+            Ok(())
+        } else {
+            Err(Error::Decode(DecodeError::Eof))
+        }
+    }
+
+}
+
+impl<T: DeserializeOwned> Default for MessageDecoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serializes typed messages into an internal buffer from which the caller takes the bytes to send.
+#[derive(Default)]
+pub struct MessageEncoder {
+    buf: Vec<u8>,
+    /// Number of bytes at the start of `buf` which have already been taken
+    pos: usize,
+}
+
+impl MessageEncoder {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serialize a message and append it to the pending bytes.
+    pub fn encode<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        if self.pos == self.buf.len() {
+            self.buf.clear();
+            self.pos = 0;
+        }
+        to_writer(&mut self.buf, value)
+    }
+
+    /// Bytes which still need to be sent.
+    pub fn pending(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Mark `n` pending bytes as sent.
+    pub fn consume(&mut self, n: usize) {
+        self.pos = (self.pos + n).min(self.buf.len());
+    }
+
+    /// Returns true if there are no pending bytes.
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Serialize, Deserialize};
+    use super::{MessageDecoder, MessageEncoder};
+    use crate::Error;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        name: String,
+        lives: u8,
+    }
+
+    #[test]
+    fn bytewise() {
+        let cats = vec![Cat { name: "Jessica".into(), lives: 9 }, Cat { name: "Wantan".into(), lives: 7 }];
+        let mut encoder = MessageEncoder::new();
+        for cat in cats.iter() {
+            encoder.encode(cat).unwrap();
+        }
+        let mut decoder = MessageDecoder::new();
+        let mut decoded = Vec::new();
+        while let Some(b) = encoder.pending().first().copied() {
+            decoder.feed(&[b]).unwrap();
+            encoder.consume(1);
+            decoded.extend(decoder.next_message().map(|c| c.unwrap()));
+        }
+        assert!(encoder.is_empty());
+        decoder.finish().unwrap();
+        assert_eq!(cats, decoded);
+    }
+
+    #[test]
+    fn errors() {
+        let mut decoder = MessageDecoder::<Cat>::with_limit(4);
+        assert!(matches!(decoder.feed(&[0xa2, 0x64, 0x6e, 0x61, 0x6d]), Err(Error::Limit(4))));
+        let mut decoder = MessageDecoder::<Cat>::new();
+        decoder.feed(&[0xa2, 0x64]).unwrap();
+        assert!(decoder.next_message().is_none());
+        assert!(matches!(decoder.finish(), Err(Error::Decode(nachricht::DecodeError::Eof))));
+        let mut decoder = MessageDecoder::<Cat>::new();
+        decoder.feed(&[0x21]).unwrap();
+        assert!(matches!(decoder.next_message(), Some(Err(Error::UnexpectedHeader(_, "Int")))));
+    }
+
+}
@@ -0,0 +1,247 @@
+//! Layouts which are known at compile time. Types implementing `Nachricht` can be serialized by a
+//! `TypedSerializer::derived` without the additional pass over every value. Usually, the implementation is derived with
+//! `#[derive(Nachricht)]` which requires the `derive` feature.
+
+use std::borrow::{Cow, ToOwned};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, LinkedList, VecDeque};
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::error::Result;
+use crate::preser::Layouts;
+
+/// A type which knows all record layouts and enum variants it can produce when serialized.
+pub trait Nachricht {
+    /// Add the layouts of this type and of all types it contains. Implementations need to call `Layouts::visit` first
+    /// and return early if it yields false, otherwise recursive types would never terminate.
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()>;
+}
+
+macro_rules! leaf {
+    ($($t:ty),*) => {
+        $(
+            impl Nachricht for $t {
+                fn collect_layouts(_layouts: &mut Layouts) -> Result<()> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+leaf!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, str, String, ());
+
+macro_rules! container {
+    ($($t:ident<$($p:ident),*> => $($inner:ident),*;)*) => {
+        $(
+            impl<$($p),*> Nachricht for $t<$($p),*> where $($inner: Nachricht),* {
+                fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+                    $($inner::collect_layouts(layouts)?;)*
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+container! {
+    Option<T> => T;
+    Vec<T> => T;
+    VecDeque<T> => T;
+    LinkedList<T> => T;
+    BTreeSet<T> => T;
+    HashSet<T, S> => T;
+    BTreeMap<K, V> => K, V;
+    HashMap<K, V, S> => K, V;
+}
+
+impl<T: ?Sized + Nachricht> Nachricht for Box<T> {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: ?Sized + Nachricht> Nachricht for Rc<T> {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: ?Sized + Nachricht> Nachricht for Arc<T> {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: ?Sized + Nachricht> Nachricht for &T {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: ?Sized + Nachricht> Nachricht for &mut T {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: ?Sized + Nachricht + ToOwned> Nachricht for Cow<'_, T> {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: Nachricht> Nachricht for [T] {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: Nachricht, const N: usize> Nachricht for [T; N] {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        T::collect_layouts(layouts)
+    }
+}
+
+impl<T: ?Sized> Nachricht for PhantomData<T> {
+    fn collect_layouts(_layouts: &mut Layouts) -> Result<()> {
+        Ok(())
+    }
+}
+
+macro_rules! tuple {
+    ($($t:ident)+) => {
+        impl<$($t: Nachricht),+> Nachricht for ($($t,)+) {
+            fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+                $($t::collect_layouts(layouts)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+tuple!(A);
+tuple!(A B);
+tuple!(A B C);
+tuple!(A B C D);
+tuple!(A B C D E);
+tuple!(A B C D E F);
+tuple!(A B C D E F G);
+tuple!(A B C D E F G H);
+tuple!(A B C D E F G H I);
+tuple!(A B C D E F G H I J);
+tuple!(A B C D E F G H I J K);
+tuple!(A B C D E F G H I J K L);
+
+// serde serializes these as structs
+
+impl Nachricht for Duration {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        layouts.insert_layout("Duration", None, &["secs", "nanos"])
+    }
+}
+
+impl Nachricht for SystemTime {
+    fn collect_layouts(layouts: &mut Layouts) -> Result<()> {
+        layouts.insert_layout("SystemTime", None, &["secs_since_epoch", "nanos_since_epoch"])
+    }
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use serde::{Serialize, Deserialize};
+    use crate::Nachricht;
+    use crate::{from_bytes, to_bytes, Error, Layouts, TypedSerializer, Validator};
+
+    #[derive(Serialize, Deserialize, Nachricht, Debug, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    struct Cat {
+        given_name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lives: Option<u8>,
+        #[serde(skip)]
+        mood: u8,
+    }
+
+    #[derive(Serialize, Deserialize, Nachricht, Debug, PartialEq)]
+    enum Pet {
+        Cat(Cat),
+        #[serde(rename = "Hund")]
+        Dog { name: String },
+        Fish,
+    }
+
+    #[derive(Serialize, Deserialize, Nachricht, Debug, PartialEq)]
+    struct Tree<T> {
+        value: T,
+        children: Vec<Tree<T>>,
+    }
+
+    #[derive(Serialize, Deserialize, Nachricht, Debug, PartialEq)]
+    #[nachricht(version = 2)]
+    struct Owner {
+        name: String,
+        pets: Vec<Pet>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Versioned {
+        #[serde(rename = "@version", default)]
+        version: u64,
+        name: String,
+    }
+
+    #[test]
+    fn versions() {
+        let owner = Owner { name: "Liv".to_string(), pets: vec![Pet::Fish] };
+        let bytes = TypedSerializer::derived().unwrap().to_bytes(&owner).unwrap();
+        assert_eq!(owner, from_bytes::<Owner>(&bytes).unwrap());
+        assert_eq!(Versioned { version: 2, name: "Liv".to_string() }, from_bytes::<Versioned>(&bytes).unwrap());
+        assert_eq!(Versioned { version: 0, name: "Liv".to_string() }, from_bytes::<Versioned>(&to_bytes(&owner).unwrap()).unwrap());
+        // the version is a field like any other
+        let value = nachricht::Decoder::decode(&bytes).unwrap().0;
+        assert_eq!(Some(&nachricht::Value::Int(nachricht::Sign::Pos, 2)), value.pointer("/@version"));
+        let mut validator = Validator::derived::<Owner>().unwrap();
+        assert_eq!(1, validator.feed(&bytes).unwrap());
+        assert!(validator.feed(&to_bytes(&owner).unwrap()).is_err());
+        let mut layouts = Layouts::default();
+        assert!(matches!(layouts.set_version("Owner", None, 1), Err(Error::UnknownStructLayout("Owner"))));
+        layouts.insert_layout("Owner", None, &["@version"]).unwrap();
+        assert!(matches!(layouts.set_version("Owner", None, 1), Err(Error::LayoutMismatch("Owner", None))));
+    }
+
+    #[test]
+    fn layouts() {
+        let mut layouts = Layouts::default();
+        Tree::<Pet>::collect_layouts(&mut layouts).unwrap();
+        assert_eq!(["givenName", "lives"], layouts.get("Cat", None).unwrap().fields());
+        assert_eq!(["name"], layouts.get("Pet", Some("Hund")).unwrap().fields());
+        assert_eq!(["value", "children"], layouts.get("Tree", None).unwrap().fields());
+        let mut variants = layouts.variants().collect::<Vec<_>>();
+        variants.sort_unstable();
+        assert_eq!(vec![("Pet", "Cat"), ("Pet", "Hund")], variants);
+    }
+
+    #[test]
+    fn derived() {
+        let tree = Tree {
+            value: Pet::Fish,
+            children: vec![
+                Tree { value: Pet::Dog { name: "Laika".to_string() }, children: vec![] },
+                Tree { value: Pet::Cat(Cat { given_name: "Jessica".to_string(), lives: Some(9), mood: 0 }), children: vec![] },
+            ],
+        };
+        let mut serializer = TypedSerializer::derived().unwrap();
+        assert_eq!(to_bytes(&tree).unwrap(), serializer.to_bytes(&tree).unwrap());
+        let cats = vec![
+            Cat { given_name: "Wantan".to_string(), lives: Some(7), mood: 0 },
+            Cat { given_name: "Sphinx".to_string(), lives: None, mood: 0 },
+        ];
+        assert!(to_bytes(&cats).is_err());
+        let bytes = TypedSerializer::derived().unwrap().to_bytes(&cats).unwrap();
+        assert_eq!(cats, from_bytes::<Vec<Cat>>(&bytes).unwrap());
+    }
+
+}
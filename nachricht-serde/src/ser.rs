@@ -1,48 +1,423 @@
 use serde::ser::{self, Serialize};
-use nachricht::{EncodeError, Header, Sign};
-use std::io::Write;
+use nachricht::{measure, EncodeError, Encoder, EncoderOptions, Header, Metrics, Sign};
+use std::io::{ErrorKind, Write};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::marker::PhantomData;
 
+use crate::de::Deserializer;
+use crate::dictionary::Dictionary;
 use crate::error::{Error, Result};
-use crate::preser::{Layout, Layouts, preserialize};
+use crate::preser::{Layout, Layouts, preserialize, VERSION_FIELD};
+use crate::schema::Nachricht;
+use crate::sizes::SizeReport;
+use crate::value::to_value_with;
+
+/// Options which influence the wire format produced by the `Serializer`. The defaults yield the canonical encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SerializerOptions {
+    /// Encode strings within map keys as symbols, so that repeated keys only need to be transmitted once. This is
+    /// transparent to deserialization.
+    pub symbol_keys: bool,
+    /// Encode all strings as symbols, so that repeated strings anywhere in the message only need to be transmitted
+    /// once. This is transparent to deserialization. Shared values other than strings, e.g. the contents of an `Rc`,
+    /// are still serialized once per occurrence; use `to_value` and `Value::share` to encode them only once.
+    pub symbol_strings: bool,
+    /// The order of the fields of structs on the wire.
+    pub field_order: FieldOrder,
+    /// Encode `Some(value)` as a record with the single field `some` instead of just the value, while `None` stays
+    /// `null`. This keeps `Some(())`, `Some(None)` and other nested `Option`s apart from `None`, which they are
+    /// otherwise indistinguishable from. The deserializer needs to expect this, see `from_bytes_tagged`.
+    pub tagged_options: bool,
+    /// Report the size and duration of every serialized message to these hooks.
+    pub metrics: Option<&'static dyn Metrics>,
+}
+
+/// The order in which the fields of structs are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldOrder {
+    /// The order in which `Serialize` provides them, which is the declaration order for derived implementations
+    Declaration,
+    /// Sorted by their bytes like the fields of a `nachricht::Value::Record`, so that the output is identical to the
+    /// one of `nachricht::Encoder` for the same data. The value is converted with `to_value` and encoded from there,
+    /// which is slower, reuses layouts by their fields rather than by the name of their struct and ignores the versions
+    /// of layouts.
+    Alphabetical,
+}
+
+impl Default for FieldOrder {
+    fn default() -> Self {
+        Self::Declaration
+    }
+}
+
+impl SerializerOptions {
+    fn encoder_options(self) -> EncoderOptions {
+        EncoderOptions { symbol_keys: self.symbol_keys, symbol_strings: self.symbol_strings, metrics: self.metrics }
+    }
+}
 
 pub struct Serializer<W> {
     layouts: Layouts,
-    symbols: HashMap<&'static str, usize>,
+    symbols: HashMap<Cow<'static, str>, usize>,
     next_free: usize,
-    output: W,
+    output: Output<W>,
+    /// For every open sequence or map, the number of elements so far if its length was not known in advance
+    counts: Vec<Option<usize>>,
+    options: SerializerOptions,
+    /// Whether a map key is currently being serialized
+    in_key: bool,
+    /// For every open struct, the next field of its static layout which still needs to be checked, if any
+    verifying: Vec<Option<(&'static str, Option<&'static str>, usize)>>,
+    depth: usize,
+    /// The index of the layout which tags `Some`, once it has been written
+    some_layout: Option<usize>,
+    /// The bytes written per path and the path of the current value, only if they are observed
+    sizes: Option<(SizeReport, Vec<&'static str>)>,
 }
 
 pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>> {
-    let mut serializer = Serializer {
-        output: Vec::new(),
-        symbols: HashMap::new(),
-        layouts: preserialize(value)?,
-        next_free: 0
-    };
-    value.serialize(&mut serializer)?;
-    Ok(serializer.output())
+    to_bytes_with_options(value, SerializerOptions::default())
+}
+
+pub fn to_bytes_with_options<T: Serialize>(value: &T, options: SerializerOptions) -> Result<Vec<u8>> {
+    if options.field_order == FieldOrder::Alphabetical {
+        let mut output = Vec::new();
+        encode_sorted(value, &mut output, options)?;
+        return Ok(output);
+    }
+    measure(options.metrics, <dyn Metrics>::encoded, || {
+        let mut serializer = Serializer::new(Vec::new(), preserialize(value)?, options);
+        value.serialize(&mut serializer)?;
+        let output = serializer.output();
+        let len = output.len();
+        Ok((output, len))
+    })
 }
 
 pub fn to_writer<T: Serialize, W: Write>(writer: W, value: &T) -> Result<()> {
-    let mut serializer = Serializer {
-        output: writer,
-        symbols: HashMap::new(),
-        layouts: preserialize(value)?,
-        next_free: 0
-    };
-    value.serialize(&mut serializer)?;
+    to_writer_with_options(writer, value, SerializerOptions::default())
+}
+
+pub fn to_writer_with_options<T: Serialize, W: Write>(mut writer: W, value: &T, options: SerializerOptions) -> Result<()> {
+    if options.field_order == FieldOrder::Alphabetical {
+        return encode_sorted(value, &mut writer, options);
+    }
+    measure(options.metrics, <dyn Metrics>::encoded, || {
+        let mut serializer = Serializer::new(writer, preserialize(value)?, options);
+        value.serialize(&mut serializer)?;
+        Ok(((), serializer.output.written))
+    })
+}
+
+/// Serialize with `FieldOrder::Alphabetical`, see there
+fn encode_sorted<T: ?Sized + Serialize, W: Write>(value: &T, writer: &mut W, options: SerializerOptions) -> Result<()> {
+    Encoder::encode_with_options(&to_value_with(value, options.tagged_options)?, writer, options.encoder_options())?;
     Ok(())
 }
 
 impl Serializer<Vec<u8>> {
-    fn output(self) -> Vec<u8> {
-        self.output
+    pub(crate) fn output(self) -> Vec<u8> {
+        self.output.writer
+    }
+
+    /// The output together with the bytes written per path since `observe`
+    pub(crate) fn into_report(self) -> (Vec<u8>, SizeReport) {
+        (self.output.writer, self.sizes.map(|(report, _)| report).unwrap_or_default())
+    }
+}
+
+/// The writer of a `Serializer`. The header of a sequence or map precedes its elements, so the elements of one whose
+/// length is not known in advance get buffered until it is complete.
+struct Output<W> {
+    writer: W,
+    buffers: Vec<Vec<u8>>,
+    /// The number of bytes written so far, including buffered ones
+    written: usize,
+}
+
+impl<W: Write> Output<W> {
+    /// Move the elements of a completed sequence or map to the enclosing buffer or the writer. They have been counted
+    /// as written when they were buffered, so they are not counted again.
+    fn unbuffer(&mut self, buffer: &[u8]) -> std::io::Result<()> {
+        match self.buffers.last_mut() {
+            Some(parent) => parent.write_all(buffer),
+            None => self.writer.write_all(buffer),
+        }
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = match self.buffers.last_mut() {
+            Some(buffer) => buffer.write(buf),
+            None => self.writer.write(buf),
+        }?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self.buffers.last_mut() {
+            Some(buffer) => buffer.write_all(buf),
+            None => self.writer.write_all(buf),
+        }?;
+        self.written += buf.len();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Serialize into a preallocated buffer, e.g. for a datagram. The buffer is never grown, but serializing still allocates
+/// like `to_writer` does, e.g. for the layouts collected in advance and for sequences whose length is not known up
+/// front. If the buffer is too small, the value is serialized a second time to report the required size in
+/// `EncodeError::BufferTooSmall` and the contents of the buffer are unspecified. Returns the amount of bytes written.
+pub fn to_slice<T: Serialize>(value: &T, buf: &mut [u8]) -> Result<usize> {
+    let len = buf.len();
+    let mut cursor = buf;
+    match to_writer(&mut cursor, value) {
+        Ok(()) => Ok(len - cursor.len()),
+        Err(Error::Encode(EncodeError::Io(e))) if e.kind() == ErrorKind::WriteZero => {
+            let mut counter = Counter(0);
+            to_writer(&mut counter, value)?;
+            Err(Error::Encode(EncodeError::BufferTooSmall(counter.0)))
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Serializes many values of the same type and reuses the layouts collected for previous values, so that the
+/// additional pass over the data only happens when a value contains a layout which has not been seen before. Every
+/// struct is checked against its cached layout, so if its fields have changed since, e.g. due to
+/// `#[serde(skip_serializing_if)]`, the value is inspected again as well. Within a single value, all structs of the same
+/// type still need the same fields, unless the serializer is `derived`.
+///
+/// The layouts are cached per serializer, not per type: a new serializer starts out empty and inspects the first values
+/// again, so keep one serializer around, e.g. per thread, rather than creating one per value or request. Layouts known
+/// in advance can be supplied with `from_layouts`, and `derived` serializers never inspect values at all.
+pub struct TypedSerializer<T: ?Sized> {
+    layouts: Layouts,
+    options: SerializerOptions,
+    buf: Vec<u8>,
+    _type: PhantomData<fn(&T)>,
+}
+
+impl<T: ?Sized + Serialize> TypedSerializer<T> {
+
+    pub fn new() -> Self {
+        Self::with_options(SerializerOptions::default())
+    }
+
+    pub fn with_options(options: SerializerOptions) -> Self {
+        Self { layouts: Layouts::default(), options, buf: Vec::new(), _type: PhantomData }
+    }
+
+    /// Create a serializer which exclusively uses the given layouts and never inspects values in advance, like a
+    /// `derived` one. Every layout is checked against the fields of the first struct which uses it.
+    ///
+    /// ```
+    /// use serde::Serialize;
+    /// use nachricht_serde::{Layouts, SerializerOptions, TypedSerializer};
+    ///
+    /// #[derive(Serialize)]
+    /// struct Cat {
+    ///     name: &'static str,
+    ///     lives: u8,
+    /// }
+    ///
+    /// impl Cat {
+    ///     const LAYOUT: &'static [&'static str] = &["name", "lives"];
+    /// }
+    ///
+    /// let mut layouts = Layouts::default();
+    /// layouts.insert_layout("Cat", None, Cat::LAYOUT).unwrap();
+    /// let mut serializer = TypedSerializer::<Vec<Cat>>::from_layouts(layouts, SerializerOptions::default());
+    /// let cats = vec![Cat { name: "Jessica", lives: 9 }, Cat { name: "Wantan", lives: 7 }];
+    /// assert_eq!(nachricht_serde::to_bytes(&cats).unwrap(), serializer.to_bytes(&cats).unwrap());
+    /// ```
+    pub fn from_layouts(mut layouts: Layouts, options: SerializerOptions) -> Self {
+        layouts.complete = true;
+        Self { layouts, ..Self::with_options(options) }
+    }
+
+    pub fn to_bytes(&mut self, value: &T) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        self.serialize_into(value, &mut output)?;
+        Ok(output)
+    }
+
+    /// Serialize to the writer. The message is buffered internally, so the writer never receives partial messages.
+    pub fn to_writer<W: Write>(&mut self, mut writer: W, value: &T) -> Result<()> {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        let result = self.serialize_into(value, &mut buf)
+            .and_then(|_| writer.write_all(&buf).map_err(|e| Error::Encode(EncodeError::from(e))));
+        self.buf = buf;
+        result
+    }
+
+    fn serialize_into(&mut self, value: &T, output: &mut Vec<u8>) -> Result<()> {
+        if self.options.field_order == FieldOrder::Alphabetical {
+            return encode_sorted(value, output, self.options);
+        }
+        let start = output.len();
+        measure(self.options.metrics, <dyn Metrics>::encoded, || {
+            match self.serialize_cached(value, output) {
+                Err(Error::UnknownStructLayout(_)) | Err(Error::UnknownVariantLayout(_, _)) | Err(Error::LayoutMismatch(_, _))
+                    if !self.layouts.complete => {
+                    output.truncate(start);
+                    self.layouts.replace(preserialize(value)?);
+                    self.serialize_cached(value, output)
+                },
+                result => result,
+            }?;
+            Ok(((), output.len() - start))
+        })
+    }
+
+    fn serialize_cached(&mut self, value: &T, output: &mut Vec<u8>) -> Result<()> {
+        let mut serializer = Serializer::new(output, std::mem::take(&mut self.layouts), self.options);
+        let result = value.serialize(&mut serializer);
+        self.layouts = serializer.layouts;
+        self.layouts.reset();
+        result
+    }
+
+}
+
+impl<T: ?Sized + Serialize + Nachricht> TypedSerializer<T> {
+
+    /// Create a serializer which uses the layouts known at compile time and never inspects values in advance. This
+    /// allows for conditionally skipped fields, which are encoded as `null` and need to deserialize from it, e.g.
+    /// `Option`s skipped if they are `None`.
+    pub fn derived() -> Result<Self> {
+        Self::derived_with_options(SerializerOptions::default())
+    }
+
+    pub fn derived_with_options(options: SerializerOptions) -> Result<Self> {
+        let mut layouts = Layouts::default();
+        T::collect_layouts(&mut layouts)?;
+        Ok(Self::from_layouts(layouts, options))
+    }
+
+}
+
+impl<T: ?Sized + Serialize> Default for TypedSerializer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Discards its input but keeps track of its length
+struct Counter(usize);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 }
 
 impl<W: Write> Serializer<W> {
 
+    pub(crate) fn new(output: W, layouts: Layouts, options: SerializerOptions) -> Self {
+        let output = Output { writer: output, buffers: Vec::new(), written: 0 };
+        Self { output, counts: Vec::new(), layouts, options, symbols: HashMap::new(), next_free: 0, in_key: false, verifying: Vec::new(), depth: 0, some_layout: None, sizes: None }
+    }
+
+    /// Refer to the entries of the dictionary instead of defining them. Only works before anything has been written.
+    pub(crate) fn prime(&mut self, dictionary: &Dictionary) {
+        for (i, symbol) in dictionary.symbols() {
+            self.symbols.entry(Cow::Owned(symbol.to_owned())).or_insert(i);
+        }
+        for (i, keys) in dictionary.layouts() {
+            let layouts = self.layouts.structs.values_mut().flat_map(|m| m.values_mut());
+            for layout in layouts.filter(|l| l.idx.is_none() && l.keys().eq(keys.iter().map(String::as_str))) {
+                layout.idx = Some(i);
+            }
+            if let [variant] = keys {
+                for idx in self.layouts.variants.values_mut().filter_map(|m| m.get_mut(variant.as_str())).filter(|i| i.is_none()) {
+                    *idx = Some(i);
+                }
+                if variant == "some" && self.some_layout.is_none() {
+                    self.some_layout = Some(i);
+                }
+            }
+        }
+        self.next_free = dictionary.len();
+    }
+
+    /// Record the bytes written for every field, element and map value from now on
+    pub(crate) fn observe(&mut self) {
+        self.sizes = Some((SizeReport::default(), Vec::new()));
+    }
+
+    /// Start a sequence or map. Without a length, its elements are buffered until `end_unsized`.
+    fn begin_unsized(&mut self, len: Option<usize>, header: fn(usize) -> Header) -> Result<()> {
+        match len {
+            Some(len) => {
+                header(len).encode(&mut self.output)?;
+                self.counts.push(None);
+            },
+            None => {
+                self.output.buffers.push(Vec::new());
+                self.counts.push(Some(0));
+            },
+        }
+        Ok(())
+    }
+
+    /// Count an element of the innermost sequence or an entry of the innermost map
+    fn count_unsized(&mut self) {
+        if let Some(Some(count)) = self.counts.last_mut() {
+            *count += 1;
+        }
+    }
+
+    /// Write the header and the buffered elements of the innermost sequence or map if its length was unknown
+    fn end_unsized(&mut self, header: fn(usize) -> Header) -> Result<()> {
+        if let Some(Some(count)) = self.counts.pop() {
+            let buffer = self.output.buffers.pop().unwrap_or_default();
+            header(count).encode(&mut self.output)?;
+            self.output.unbuffer(&buffer).map_err(EncodeError::from)?;
+        }
+        Ok(())
+    }
+
+    /// Values nested deeper than `Deserializer::MAX_DEPTH` could not be deserialized anyway. Failing early also catches
+    /// `Serialize` implementations which recurse endlessly.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth == Deserializer::MAX_DEPTH {
+            return Err(Error::Depth(Deserializer::MAX_DEPTH));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Like `nested`, but attributes the written bytes to the path of the parent extended by the segment
+    fn observed<T>(&mut self, segment: &'static str, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        match self.sizes.as_mut() {
+            Some((_, path)) => path.push(segment),
+            None => return self.nested(f),
+        }
+        let start = self.output.written;
+        let result = self.nested(f);
+        if let Some((report, path)) = self.sizes.as_mut() {
+            report.record(path, self.output.written - start);
+            path.pop();
+        }
+        result
+    }
+
     fn next(&mut self) -> usize {
         self.next_free += 1;
         self.next_free - 1
@@ -50,41 +425,107 @@ impl<W: Write> Serializer<W> {
 
     #[inline(always)]
     fn get_variant_idx(&mut self, name: &'static str, variant: &'static str) -> Result<&mut Option<usize>> {
-        Ok(self.layouts.variants.get_mut(name).and_then(|m| m.get_mut(variant)).ok_or(Error::UnknownVariantLayout(name, variant))?)
+        self.layouts.variants.get_mut(name).and_then(|m| m.get_mut(variant)).ok_or(Error::UnknownVariantLayout(name, variant))
     }
 
     #[inline(always)]
     fn get_layout(&mut self, name: &'static str, variant: Option<&'static str>) -> Result<&mut Layout> {
-        Ok(self.layouts.structs.get_mut(name).and_then(|m| m.get_mut(&variant)).ok_or(Error::UnknownStructLayout(name))?)
+        self.layouts.structs.get_mut(name).and_then(|m| m.get_mut(&variant)).ok_or(Error::UnknownStructLayout(name))
     }
 
     fn serialize_symbol(&mut self, symbol: &'static str) -> Result<()> {
+        self.serialize_interned(symbol, Cow::Borrowed)
+    }
+
+    /// Serialize a symbol which is only known at runtime. An allocation only happens on its first occurrence.
+    fn serialize_dynamic_symbol(&mut self, symbol: &str) -> Result<()> {
+        self.serialize_interned(symbol, |s| Cow::Owned(s.to_owned()))
+    }
+
+    fn serialize_interned<'s>(&mut self, symbol: &'s str, key: impl FnOnce(&'s str) -> Cow<'static, str>) -> Result<()> {
         match self.symbols.get(symbol) {
             Some(i) => { Header::Ref(*i).encode(&mut self.output)?; },
             None    => {
                 Header::Sym(symbol.len()).encode(&mut self.output)?;
                 self.output.write_all(symbol.as_bytes()).map_err(EncodeError::from)?;
                 let next = self.next();
-                self.symbols.insert(symbol, next);
+                self.symbols.insert(key(symbol), next);
             }
         }
         Ok(())
     }
 
+    /// The fields of a new layout are looked up one by one instead of cloned, since serializing them needs `self`
     fn serialize_layout(&mut self, name: &'static str, variant: Option<&'static str>) -> Result<()> {
+        let complete = self.layouts.complete;
         let layout = self.get_layout(name, variant)?;
-        let fields = layout.fields.clone();
-        match layout.idx {
+        // layouts collected from values may stem from a previous one, in which different fields were skipped
+        let (idx, len, verify, version) = (layout.idx, layout.fields.len(), !complete || !layout.verified, layout.version);
+        match idx {
             Some(i) => { Header::Ref(i).encode(&mut self.output)?; },
             None    => {
-                Header::Rec(fields.len()).encode(&mut self.output)?;
-                for sym in fields.iter() {
+                Header::Rec(len + usize::from(version.is_some())).encode(&mut self.output)?;
+                if version.is_some() {
+                    self.serialize_symbol(VERSION_FIELD)?;
+                }
+                for pos in 0..len {
+                    let sym = self.get_layout(name, variant)?.fields[pos];
                     self.serialize_symbol(sym)?;
                 }
                 let next = self.next();
                 self.get_layout(name, variant)?.idx.replace(next);
             }
         };
+        if let Some(version) = version {
+            Header::Int(Sign::Pos, version).encode(&mut self.output)?;
+        }
+        self.verifying.push(if verify { Some((name, variant, 0)) } else { None });
+        Ok(())
+    }
+
+    /// Static layouts are checked on their first use, since they are not derived from the actual value. All others are
+    /// checked on every use.
+    fn verify_field(&mut self, key: &'static str) -> Result<()> {
+        if let Some(Some((name, variant, pos))) = self.verifying.last().copied() {
+            if self.get_layout(name, variant)?.fields.get(pos) != Some(&key) {
+                return Err(Error::LayoutMismatch(name, variant));
+            }
+            self.verifying.last_mut().unwrap().replace((name, variant, pos + 1));
+        }
+        Ok(())
+    }
+
+    fn end_layout(&mut self) -> Result<()> {
+        if let Some(Some((name, variant, pos))) = self.verifying.pop() {
+            let layout = self.get_layout(name, variant)?;
+            if layout.fields.len() != pos {
+                return Err(Error::LayoutMismatch(name, variant));
+            }
+            layout.verified = true;
+        }
+        Ok(())
+    }
+
+    /// Static layouts contain conditionally skipped fields, hence they need a placeholder to keep the record intact.
+    /// Layouts collected from values do not contain skipped fields at all.
+    fn serialize_placeholder(&mut self, key: &'static str) -> Result<()> {
+        if self.layouts.complete {
+            self.verify_field(key)?;
+            Header::Null.encode(&mut self.output)?;
+        }
+        Ok(())
+    }
+
+    /// The layout `(some)`, see `SerializerOptions::tagged_options`
+    fn serialize_some_layout(&mut self) -> Result<()> {
+        match self.some_layout {
+            Some(i) => { Header::Ref(i).encode(&mut self.output)?; },
+            None    => {
+                Header::Rec(1).encode(&mut self.output)?;
+                self.serialize_symbol("some")?;
+                self.some_layout = Some(self.next());
+            }
+        };
         Ok(())
     }
 
@@ -103,7 +544,7 @@ impl<W: Write> Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+impl<W: Write> ser::Serializer for &mut Serializer<W> {
 
     type Ok = ();
     type Error = Error;
@@ -171,6 +612,9 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
+        if self.in_key || self.options.symbol_strings {
+            return self.serialize_dynamic_symbol(v);
+        }
         Header::Str(v.len()).encode(&mut self.output)?;
         self.output.write_all(v.as_bytes()).map_err(EncodeError::from)?;
         Ok(())
@@ -188,7 +632,10 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
-        value.serialize(self)
+        if self.options.tagged_options {
+            self.serialize_some_layout()?;
+        }
+        self.nested(|s| value.serialize(s))
     }
 
     fn serialize_unit(self) -> Result<()> {
@@ -205,30 +652,26 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<()> {
-        value.serialize(self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn serialize_newtype_variant<T: ?Sized + Serialize>(self, name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<()> {
         self.serialize_variant(name, variant)?;
-        value.serialize(self)
+        self.nested(|s| value.serialize(s))
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        match len {
-            Some(l) => {
-                Header::Arr(l).encode(&mut self.output)?;
-                Ok(self)
-            },
-            None => Err(Error::Length),
-        }
+        self.begin_unsized(len, Header::Arr)?;
+        Ok(self)
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
-        self.serialize_seq(Some(len))
+        Header::Arr(len).encode(&mut self.output)?;
+        Ok(self)
     }
 
     fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct> {
-        self.serialize_seq(Some(len))
+        self.serialize_tuple(len)
     }
 
     fn serialize_tuple_variant(self, name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Self::SerializeTupleVariant> {
@@ -238,13 +681,8 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        match len {
-            Some(len) => {
-                Header::Map(len).encode(&mut self.output)?;
-                Ok(self)
-            },
-            None => Err(Error::Length)
-        }
+        self.begin_unsized(len, Header::Map)?;
+        Ok(self)
     }
 
     fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
@@ -260,26 +698,27 @@ impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
 
 }
 
-impl<'a, W: Write> ser::SerializeSeq for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeSeq for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
     fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.count_unsized();
+        self.observed("*", |s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_unsized(Header::Arr)
     }
 
 }
 
-impl<'a, W: Write> ser::SerializeTuple for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTuple for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
     fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.observed("*", |s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -287,12 +726,12 @@ impl<'a, W: Write> ser::SerializeTuple for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleStruct for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTupleStruct for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
     fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.observed("*", |s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -300,12 +739,12 @@ impl<'a, W: Write> ser::SerializeTupleStruct for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeTupleVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
     fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.observed("*", |s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
@@ -313,48 +752,62 @@ impl<'a, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<W> {
     }
 }
 
-impl<'a, W: Write> ser::SerializeMap for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeMap for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
     fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
-        key.serialize(&mut **self)
+        self.in_key = self.options.symbol_keys;
+        let result = self.nested(|s| key.serialize(s));
+        self.in_key = false;
+        result
     }
 
     fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+        self.count_unsized();
+        self.observed("*", |s| value.serialize(s))
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_unsized(Header::Map)
     }
 
 }
 
-impl<'a, W: Write> ser::SerializeStructVariant for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeStructVariant for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized + Serialize>(&mut self, _key: &'static str, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.verify_field(key)?;
+        self.observed(key, |s| value.serialize(s))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<()> {
+        self.serialize_placeholder(key)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_layout()
     }
 
 }
 
-impl<'a, W: Write> ser::SerializeStruct for &'a mut Serializer<W> {
+impl<W: Write> ser::SerializeStruct for &mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized + Serialize>(&mut self, _key: &'static str, value: &T) -> Result<()> {
-        value.serialize(&mut **self)
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.verify_field(key)?;
+        self.observed(key, |s| value.serialize(s))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<()> {
+        self.serialize_placeholder(key)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_layout()
     }
 
 }
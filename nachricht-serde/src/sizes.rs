@@ -0,0 +1,218 @@
+//! Find out where the bytes of serialized messages go, e.g. to decide which fields to shorten, intern or drop for
+//! bandwidth-sensitive deployments. Bytes are attributed to the path of the value which wrote them in the syntax of
+//! `nachricht::Statistics`: struct fields are addressed by name, while elements of sequences and values of maps are
+//! aggregated under `*`. Every value also counts towards the paths of its parents, so the path of the outermost value,
+//! the empty string, holds the total size of all messages. Keys of maps are counted for the map itself.
+//!
+//! Symbols are written in full on their first occurrence and as short references afterwards. This includes field names,
+//! which are written by the first struct of each layout, so the same value can take a different amount of bytes depending
+//! on what came before it.
+//!
+//! ```
+//! use serde::Serialize;
+//! use nachricht_serde::{to_bytes_with_report, SerializerOptions, SizeReport};
+//!
+//! #[derive(Serialize)]
+//! struct Cat {
+//!     name: &'static str,
+//! }
+//!
+//! let mut report = SizeReport::default();
+//! let cats = vec![Cat { name: "Jessica" }, Cat { name: "Wantan" }];
+//! let bytes = to_bytes_with_report(&cats, SerializerOptions::default(), &mut report).unwrap();
+//! assert_eq!(bytes.len(), report.path("").unwrap().bytes);
+//! assert_eq!(15, report.path("*.name").unwrap().bytes);
+//! assert_eq!("*.name: 15 bytes total in 2 values", report.to_string().lines().nth(2).unwrap());
+//! ```
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::Result;
+use crate::preser::preserialize;
+use crate::ser::{Serializer, SerializerOptions};
+
+/// The bytes written for all values found at a single path.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PathSize {
+    /// The number of values found at this path
+    pub count: usize,
+    /// The number of bytes written for these values, including their children
+    pub bytes: usize,
+}
+
+/// The bytes written per path across any number of serialized messages, see the module documentation. Its `Display`
+/// implementation lists one path per line in the style of `cats.*.name: 31 bytes total in 4 values`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SizeReport {
+    messages: usize,
+    paths: BTreeMap<String, PathSize>,
+}
+
+impl SizeReport {
+
+    /// The number of messages serialized into this report so far.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    /// The sizes for the given path, if any value was found there.
+    pub fn path(&self, path: &str) -> Option<&PathSize> {
+        self.paths.get(path)
+    }
+
+    /// The sizes of all paths ordered by path.
+    pub fn paths(&self) -> impl Iterator<Item = (&str, &PathSize)> + '_ {
+        self.paths.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub(crate) fn record(&mut self, path: &[&str], bytes: usize) {
+        let size = self.paths.entry(path.join(".")).or_default();
+        size.count += 1;
+        size.bytes += bytes;
+    }
+
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, size) in &self.paths {
+            writeln!(f, "{}: {} bytes total in {} values", if path.is_empty() { "." } else { path }, size.bytes, size.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialize like `to_bytes_with_options` and add the bytes written per path to the report. Tracking the paths slows
+/// serialization down, so this is meant for sampling rather than for every message. Fields are always written in
+/// `FieldOrder::Declaration`.
+pub fn to_bytes_with_report<T: Serialize>(value: &T, options: SerializerOptions, report: &mut SizeReport) -> Result<Vec<u8>> {
+    let mut serializer = Serializer::new(Vec::new(), preserialize(value)?, options);
+    serializer.observe();
+    value.serialize(&mut serializer)?;
+    let (output, observed) = serializer.into_report();
+    report.messages += 1;
+    report.record(&[], output.len());
+    for (path, size) in observed.paths {
+        let total = report.paths.entry(path).or_default();
+        total.count += size.count;
+        total.bytes += size.bytes;
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use super::{to_bytes_with_report, PathSize, SizeReport};
+    use crate::error::Error;
+    use crate::ser::{to_bytes, SerializerOptions};
+
+    #[derive(Serialize)]
+    enum Species {
+        FelisCatus,
+        Hybrid { parents: (Box<Species>, Box<Species>) },
+    }
+
+    #[derive(Serialize)]
+    struct Cat {
+        name: &'static str,
+        species: Species,
+    }
+
+    #[derive(Serialize)]
+    struct Message {
+        cats: Vec<Cat>,
+        owners: BTreeMap<&'static str, u8>,
+    }
+
+    #[test]
+    fn sizes() {
+        let message = Message {
+            cats: vec![
+                Cat { name: "Jessica", species: Species::FelisCatus },
+                Cat { name: "Wantan", species: Species::Hybrid { parents: (Box::new(Species::FelisCatus), Box::new(Species::FelisCatus)) } },
+            ],
+            owners: BTreeMap::from([("Liv", 33)]),
+        };
+        let mut report = SizeReport::default();
+        let bytes = to_bytes_with_report(&message, SerializerOptions::default(), &mut report).unwrap();
+        assert_eq!(to_bytes(&message).unwrap(), bytes);
+        let size = |count, bytes| Some(PathSize { count, bytes });
+        assert_eq!(1, report.messages());
+        assert_eq!(size(1, bytes.len()), report.path("").copied());
+        assert_eq!(size(2, 8 + 7), report.path("cats.*.name").copied());
+        // the first cat defines #FelisCatus, the second one its variant and the layout of the variant
+        assert_eq!(size(2, 11 + (1 + 7) + (1 + 8) + 3), report.path("cats.*.species").copied());
+        assert_eq!(size(1, 1 + 4 + 2), report.path("owners").copied());
+        assert_eq!(size(1, 2), report.path("owners.*").copied());
+        assert_eq!(size(2, 2), report.path("cats.*.species.parents.*").copied());
+        let paths = report.paths().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(vec!["", "cats", "cats.*", "cats.*.name", "cats.*.species", "cats.*.species.parents", "cats.*.species.parents.*", "owners", "owners.*"], paths);
+        let children = ["cats", "owners"].iter().map(|path| report.path(path).unwrap().bytes).sum::<usize>();
+        // the layout of the message itself
+        assert_eq!(bytes.len() - children, 1 + 5 + 7);
+        to_bytes_with_report(&message, SerializerOptions::default(), &mut report).unwrap();
+        assert_eq!(size(2, 2 * bytes.len()), report.path("").copied());
+        assert_eq!(size(4, 2 * 15), report.path("cats.*.name").copied());
+        assert_eq!(Some(".: 164 bytes total in 2 values"), report.to_string().lines().next());
+    }
+
+    /// Buffers its elements since the filter hides their number from serde
+    struct Unsized(&'static [u32]);
+
+    impl Serialize for Unsized {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.iter().filter(|_| true))
+        }
+    }
+
+    struct UnsizedMap(&'static [(&'static str, Unsized)]);
+
+    impl Serialize for UnsizedMap {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().filter(|_| true).map(|(k, v)| (k, v)))
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Lists {
+        xs: Unsized,
+        ys: UnsizedMap,
+    }
+
+    #[test]
+    fn unknown_lengths() {
+        let lists = Lists { xs: Unsized(&[1, 2, 3, 4, 5]), ys: UnsizedMap(&[("a", Unsized(&[6, 7])), ("b", Unsized(&[]))]) };
+        let mut report = SizeReport::default();
+        let bytes = to_bytes_with_report(&lists, SerializerOptions::default(), &mut report).unwrap();
+        let root = report.path("").unwrap().bytes;
+        assert_eq!(bytes.len(), root);
+        for (path, size) in report.paths() {
+            assert!(size.bytes <= root, "{}", path);
+        }
+        // small integers fit into their header
+        assert_eq!(1 + 5, report.path("xs").unwrap().bytes);
+        assert_eq!(1 + (2 + 1 + 2) + (2 + 1), report.path("ys").unwrap().bytes);
+        assert_eq!(Some(&PathSize { count: 2, bytes: (1 + 2) + 1 }), report.path("ys.*"));
+    }
+
+    #[test]
+    fn errors() {
+        #[derive(Serialize)]
+        struct Deep(Vec<Deep>);
+        let mut deep = Deep(Vec::new());
+        for _ in 0..600 {
+            deep = Deep(vec![deep]);
+        }
+        let mut report = SizeReport::default();
+        to_bytes_with_report(&1u8, SerializerOptions::default(), &mut report).unwrap();
+        assert!(matches!(to_bytes_with_report(&deep, SerializerOptions::default(), &mut report), Err(Error::Depth(_))));
+        // the report survives failed messages, which do not count
+        assert_eq!(1, report.messages());
+        assert_eq!(Some(&PathSize { count: 1, bytes: 1 }), report.path(""));
+    }
+
+}
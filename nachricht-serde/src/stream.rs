@@ -0,0 +1,144 @@
+//! Consume a stream of `nachricht` messages from an asynchronous reader. Since every value is self-delimiting, no
+//! additional framing is needed: messages are simply concatenated on the wire, each with its own symbol table.
+//!
+//! The reader is only polled when the consumer asks for the next message, so a slow consumer naturally exerts
+//! backpressure on the producer. The stream is runtime-agnostic; tokio users can adapt their readers with
+//! `tokio_util::compat`.
+
+use futures_core::Stream;
+use futures_io::AsyncRead;
+use serde::de::DeserializeOwned;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::error::Result;
+use crate::sansio::MessageDecoder;
+
+/// A `Stream` of typed messages read from an `AsyncRead`. The read buffer is reused across messages.
+pub struct MessageStream<T, R> {
+    reader: R,
+    decoder: MessageDecoder<T>,
+    done: bool,
+}
+
+impl<T: DeserializeOwned, R> MessageStream<T, R> {
+
+    /// Create a stream which rejects messages larger than `MessageDecoder::DEFAULT_LIMIT`.
+    pub fn new(reader: R) -> Self {
+        Self { reader, decoder: MessageDecoder::new(), done: false }
+    }
+
+    /// Create a stream which rejects messages larger than `limit` bytes.
+    pub fn with_limit(reader: R, limit: usize) -> Self {
+        Self { reader, decoder: MessageDecoder::with_limit(limit), done: false }
+    }
+
+    /// Return the underlying reader. Bytes which have already been read but not yet decoded are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+}
+
+impl<T: DeserializeOwned, R: AsyncRead + Unpin> Stream for MessageStream<T, R> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        loop {
+            if let Some(result) = this.decoder.next_message() {
+                this.done = result.is_err();
+                return Poll::Ready(Some(result));
+            }
+            let buf = match this.decoder.buffer() {
+                Ok(buf) => buf,
+                Err(e) => return this.fail(e),
+            };
+            match Pin::new(&mut this.reader).poll_read(cx, buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return this.fail(e.into()),
+                Poll::Ready(Ok(0)) => {
+                    this.done = true;
+                    return Poll::Ready(this.decoder.finish().err().map(Err));
+                },
+                Poll::Ready(Ok(n)) => this.decoder.fill(n),
+            }
+        }
+    }
+}
+
+impl<T, R> MessageStream<T, R> {
+    fn fail(&mut self, e: crate::Error) -> Poll<Option<Result<T>>> {
+        self.done = true;
+        Poll::Ready(Some(Err(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use futures::io::{AsyncRead, Cursor};
+    use futures::stream::StreamExt;
+    use serde::{Serialize, Deserialize};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use super::MessageStream;
+    use crate::{to_bytes, Error};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        name: String,
+        lives: u8,
+    }
+
+    fn cats() -> Vec<Cat> {
+        vec![
+            Cat { name: "Jessica".into(), lives: 9 },
+            Cat { name: "Wantan".into(), lives: 7 },
+            Cat { name: "Sphinx".into(), lives: 3 },
+        ]
+    }
+
+    fn wire() -> Vec<u8> {
+        cats().iter().flat_map(|c| to_bytes(c).unwrap()).collect()
+    }
+
+    /// Hands out a single byte per read to exercise reassembly
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl AsyncRead for Trickle {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+            let len = buf.len().min(1);
+            Pin::new(&mut self.0).poll_read(cx, &mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn messages() {
+        let stream = MessageStream::<Cat, _>::new(Cursor::new(wire()));
+        let decoded: Vec<Cat> = block_on(stream.map(|c| c.unwrap()).collect());
+        assert_eq!(cats(), decoded);
+    }
+
+    #[test]
+    fn trickle() {
+        let stream = MessageStream::<Cat, _>::new(Trickle(Cursor::new(wire())));
+        let decoded: Vec<Cat> = block_on(stream.map(|c| c.unwrap()).collect());
+        assert_eq!(cats(), decoded);
+    }
+
+    #[test]
+    fn errors() {
+        let mut wire = wire();
+        wire.pop();
+        let results: Vec<_> = block_on(MessageStream::<Cat, _>::new(Cursor::new(wire)).collect());
+        assert_eq!(3, results.len());
+        assert!(matches!(results[2], Err(Error::Decode(nachricht::DecodeError::Eof))));
+        let results: Vec<_> = block_on(MessageStream::<Cat, _>::with_limit(Cursor::new(self::wire()), 8).collect());
+        assert!(matches!(results[..], [Err(Error::Limit(8))]));
+    }
+
+}
@@ -0,0 +1,98 @@
+//! Helpers for downstream crates to guard their message types against regressions. All of them panic on failure, so
+//! they are meant to be used within tests:
+//!
+//! ```
+//! use serde::{Serialize, Deserialize};
+//! use nachricht_serde::assert_roundtrip;
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Cat {
+//!     name: String,
+//!     lives: u8,
+//! }
+//!
+//! assert_roundtrip!(Cat { name: "Jessica".to_string(), lives: 9 }, Some(7u8), vec!["a".to_string()]);
+//! ```
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use nachricht::{Decoder, Encoder};
+use std::fmt::Debug;
+
+use crate::de::from_bytes;
+use crate::ser::to_bytes;
+
+/// Serialize and deserialize the value and assert that the result equals the original. Serializing it again has to
+/// yield the same bytes. Returns the serialized form.
+pub fn test_roundtrip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) -> Vec<u8> {
+    let bytes = to_bytes(value).expect("value could not be serialized");
+    let decoded = from_bytes::<T>(&bytes).expect("value could not be deserialized");
+    assert_eq!(value, &decoded, "deserialized value differs from the original");
+    assert_eq!(bytes, to_bytes(&decoded).expect("value could not be serialized again"), "serialization is not stable");
+    bytes
+}
+
+/// Like `test_roundtrip` but additionally pass the serialized form through the canonical `Encoder` of `nachricht`,
+/// as relays or tools like `nq` would, and assert that the value survives this as well.
+pub fn test_canonical_roundtrip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+    let bytes = test_roundtrip(value);
+    let (generic, c) = Decoder::decode(&bytes).expect("serialized form is not a valid nachricht");
+    assert_eq!(bytes.len(), c, "serialized form contains trailing bytes");
+    let mut canonical = Vec::new();
+    Encoder::encode(&generic, &mut canonical).expect("canonical encoding failed");
+    let decoded = from_bytes::<T>(&canonical).expect("canonical encoding could not be deserialized");
+    assert_eq!(value, &decoded, "value differs after canonical encoding");
+}
+
+/// Assert that each of the given values survives serialization, deserialization and canonical re-encoding. See
+/// `test_canonical_roundtrip`.
+#[macro_export]
+macro_rules! assert_roundtrip {
+    ($($value:expr),+ $(,)?) => {
+        $( $crate::test_canonical_roundtrip(&$value); )+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Serialize, Deserialize};
+    use std::collections::BTreeMap;
+    use super::test_roundtrip;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Species {
+        PrionailurusViverrinus,
+        LynxLynx,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        species: Species,
+        name: String,
+    }
+
+    #[test]
+    fn roundtrips() {
+        assert_roundtrip!(
+            Cat { species: Species::LynxLynx, name: "Wantan".to_string() },
+            vec![Cat { species: Species::PrionailurusViverrinus, name: "Jessica".to_string() }],
+            BTreeMap::from([(1u8, "one".to_string())]),
+            (),
+        );
+    }
+
+    /// Deserializes into a different value
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Lossy {
+        kept: u8,
+        #[serde(skip_deserializing)]
+        lost: u8,
+    }
+
+    #[test]
+    #[should_panic(expected = "deserialized value differs")]
+    fn lossy() {
+        test_roundtrip(&Lossy { kept: 1, lost: 2 });
+    }
+
+}
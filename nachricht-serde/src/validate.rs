@@ -0,0 +1,154 @@
+//! Check incoming messages against the layouts of a schema while they are being decoded, without materializing any
+//! values. Invalid messages are rejected as soon as the first undeclared record arrives, so ingestion services can drop
+//! them before spending any effort on deserialization.
+//!
+//! A record is valid if its fields equal those of a declared struct or struct variant in the same order, or if it has
+//! a single field named after a declared enum variant which carries data. Since layouts are all a schema knows about,
+//! scalars and unit variants are not checked.
+//!
+//! ```
+//! use serde::Serialize;
+//! use nachricht_serde::{layouts_of, to_bytes, Validator};
+//!
+//! #[derive(Serialize)]
+//! struct Cat { name: &'static str, lives: u8 }
+//!
+//! #[derive(Serialize)]
+//! struct Dog { name: &'static str }
+//!
+//! let mut validator = Validator::new(&layouts_of(&Cat { name: "Jessica", lives: 9 }).unwrap());
+//! assert_eq!(1, validator.feed(&to_bytes(&Cat { name: "Wantan", lives: 7 }).unwrap()).unwrap());
+//! assert!(validator.feed(&to_bytes(&Dog { name: "Laika" }).unwrap()).is_err());
+//! ```
+
+use nachricht::{Event, PushDecoder};
+use std::collections::HashSet;
+
+use crate::error::{Error, Result};
+use crate::preser::Layouts;
+use crate::schema::Nachricht;
+
+/// Validates a stream of messages which arrives in arbitrary chunks, see the module documentation.
+pub struct Validator {
+    decoder: PushDecoder,
+    /// The field names of every valid record
+    layouts: HashSet<Vec<String>>,
+    /// Number of open containers
+    depth: usize,
+}
+
+impl Validator {
+
+    /// Create a validator which accepts the records declared by the given layouts.
+    pub fn new(layouts: &Layouts) -> Self {
+        let records = layouts.layouts().map(|(_, _, layout)| layout.keys().map(|f| f.to_string()).collect());
+        let variants = layouts.variants().map(|(_, variant)| vec![variant.to_string()]);
+        Self { decoder: PushDecoder::new(), layouts: records.chain(variants).collect(), depth: 0 }
+    }
+
+    /// Create a validator which accepts the records of `T` as known at compile time.
+    pub fn derived<T: ?Sized + Nachricht>() -> Result<Self> {
+        let mut layouts = Layouts::default();
+        T::collect_layouts(&mut layouts)?;
+        Ok(Self::new(&layouts))
+    }
+
+    /// Feed the next chunk of input and return the number of messages which have been completed and found valid. Fails
+    /// on the first invalid record or malformed input, after which the state of the validator is unspecified.
+    pub fn feed(&mut self, input: &[u8]) -> Result<usize> {
+        let mut messages = 0;
+        for event in self.decoder.feed(input).map_err(|e| Error::Decode(e.into_inner()))? {
+            match event {
+                Event::Record(keys) if !self.layouts.contains(&keys) => return Err(Error::UndeclaredLayout(keys)),
+                Event::Array(_) | Event::Map(_) | Event::Record(_) => { self.depth += 1; continue; },
+                Event::End => { self.depth -= 1; },
+                _ => {},
+            }
+            if self.depth == 0 {
+                messages += 1;
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Signal the end of input. Fails if the stream ended within a message.
+    pub fn finish(self) -> Result<()> {
+        self.decoder.finish().map_err(|e| Error::Decode(e.into_inner()))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use super::Validator;
+    use crate::{layouts_of, to_bytes, Error};
+    use nachricht::DecodeError;
+
+    #[derive(Serialize)]
+    struct Cat {
+        name: &'static str,
+        lives: u8,
+    }
+
+    #[derive(Serialize)]
+    enum Pet {
+        Cat(Cat),
+        Fish,
+    }
+
+    #[derive(Serialize)]
+    struct Cats {
+        cats: Vec<Cat>,
+    }
+
+    #[derive(Serialize)]
+    struct Dog {
+        name: &'static str,
+    }
+
+    #[test]
+    fn streams() {
+        let layouts = layouts_of(&vec![Pet::Cat(Cat { name: "Jessica", lives: 9 }), Pet::Fish]).unwrap();
+        let mut stream = to_bytes(&vec![Pet::Fish, Pet::Cat(Cat { name: "Wantan", lives: 7 })]).unwrap();
+        stream.extend(to_bytes(&Pet::Fish).unwrap());
+        stream.extend(to_bytes(&Vec::<Cat>::new()).unwrap());
+        let mut validator = Validator::new(&layouts);
+        let (first, second) = stream.split_at(5);
+        assert_eq!(0, validator.feed(first).unwrap());
+        assert_eq!(3, validator.feed(second).unwrap());
+        validator.finish().unwrap();
+        let mut validator = Validator::new(&layouts);
+        validator.feed(&stream[..5]).unwrap();
+        assert!(matches!(validator.finish(), Err(Error::Decode(DecodeError::Eof))));
+    }
+
+    #[test]
+    fn invalid() {
+        let layouts = layouts_of(&Cats { cats: vec![Cat { name: "Jessica", lives: 9 }] }).unwrap();
+        let stream = to_bytes(&(Cat { name: "Wantan", lives: 7 }, Dog { name: "Laika" })).unwrap();
+        let mut validator = Validator::new(&layouts);
+        assert!(matches!(validator.feed(&stream), Err(Error::UndeclaredLayout(keys)) if keys == ["name"]));
+        let mut validator = Validator::new(&layouts);
+        assert!(matches!(validator.feed(&to_bytes(&Pet::Cat(Cat { name: "Sphinx", lives: 1 })).unwrap()), Err(Error::UndeclaredLayout(keys)) if keys == ["Cat"]));
+    }
+
+    #[test]
+    fn hostile() {
+        let layouts = layouts_of(&Cats { cats: vec![Cat { name: "Jessica", lives: 9 }] }).unwrap();
+        // record headers where their keys should be
+        let records = vec![0xa1; 1 << 20];
+        assert!(matches!(Validator::new(&layouts).feed(&records), Err(Error::Decode(DecodeError::IllegalKey("record")))));
+        let mut validator = Validator::new(&layouts);
+        assert!(records.chunks(1000).map(|chunk| validator.feed(chunk)).any(|r| r.is_err()));
+        // arrays nested a million levels deep are fine, as long as they end
+        let mut arrays = vec![0x81; 1_000_000];
+        let mut validator = Validator::new(&layouts);
+        assert_eq!(0, validator.feed(&arrays).unwrap());
+        arrays.truncate(1);
+        arrays[0] = 0x00;
+        assert_eq!(1, validator.feed(&arrays).unwrap());
+        validator.finish().unwrap();
+    }
+
+}
@@ -0,0 +1,872 @@
+//! Convert between Rust data structures and `nachricht::Value` without going through the wire format, e.g. to inspect
+//! or transform a message generically before it gets encoded. The mapping is the same as for the wire format: decoding
+//! the output of `to_bytes` yields the same `Value` as `to_value`. This module is available without the `wire` feature
+//! for builds which only need the data model.
+
+use serde::{Deserialize, Serialize};
+use serde::de::{self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::de::value::BorrowedStrDeserializer;
+use serde::ser::{self, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple, SerializeTupleStruct, SerializeTupleVariant};
+use nachricht::{Sign, Value};
+use std::borrow::Cow;
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::MAX_DEPTH;
+
+/// Serialize a value into the `nachricht` data model.
+pub fn to_value<T: ?Sized + Serialize>(value: &T) -> Result<Value<'static>> {
+    to_value_with(value, false)
+}
+
+/// Serialize a value, with `Option`s as with `SerializerOptions::tagged_options` if requested.
+pub(crate) fn to_value_with<T: ?Sized + Serialize>(value: &T, tagged_options: bool) -> Result<Value<'static>> {
+    value.serialize(ValueSerializer { depth: 0, tagged_options })
+}
+
+/// Deserialize a value from the `nachricht` data model. Strings and bytes are borrowed from the value if possible.
+pub fn from_value<'de, T: Deserialize<'de>>(value: &'de Value<'de>) -> Result<T> {
+    T::deserialize(ValueDeserializer { value, depth: 0, paths: false })
+}
+
+/// Deserialize a part of a value into a typed structure, e.g. in gateway code which mostly forwards messages but needs
+/// to understand some parts of them.
+pub trait Extract<'de> {
+    /// Deserialize the value at the given JSON pointer as selected by `Value::pointer`, e.g. `/cats/0`. Errors are
+    /// reported as `Error::Path` with the pointer to the offending value, or as `Error::Missing` if there is no value at
+    /// the given pointer.
+    fn extract<T: Deserialize<'de>>(&'de self, pointer: &str) -> Result<T>;
+}
+
+impl<'de> Extract<'de> for Value<'de> {
+    fn extract<T: Deserialize<'de>>(&'de self, pointer: &str) -> Result<T> {
+        let value = self.pointer(pointer).ok_or_else(|| Error::Missing(pointer.to_owned()))?;
+        T::deserialize(ValueDeserializer { value, depth: 0, paths: true }).map_err(|e| match e {
+            Error::Path(path, e) => Error::Path(format!("{}{}", pointer, path), e),
+            e => Error::Path(pointer.to_owned(), Box::new(e)),
+        })
+    }
+}
+
+/// Serialize typed parts into an existing value, e.g. a payload into an envelope whose headers are built generically.
+/// This avoids encoding the part on its own and decoding it again.
+pub trait Splice {
+    /// Serialize `value` into the place given by a JSON pointer as understood by `Value::pointer`. A value already at
+    /// that place gets replaced. Otherwise, the last segment of the pointer is added as a field to the record or as an
+    /// entry with a string key to the map which the rest of the pointer selects. If there is no such record or map,
+    /// `Error::Missing` is returned.
+    fn splice<T: ?Sized + Serialize>(&mut self, pointer: &str, value: &T) -> Result<()>;
+}
+
+impl<'a> Splice for Value<'a> {
+    fn splice<T: ?Sized + Serialize>(&mut self, pointer: &str, value: &T) -> Result<()> {
+        let spliced = to_value(value)?;
+        if let Some(target) = self.pointer_mut(pointer) {
+            *target = spliced;
+            return Ok(());
+        }
+        let missing = || Error::Missing(pointer.to_owned());
+        let (parent, key) = pointer.rsplit_once('/').ok_or_else(missing)?;
+        let key = Cow::Owned(key.replace("~1", "/").replace("~0", "~"));
+        match self.pointer_mut(parent) {
+            Some(Value::Record(fields)) => { fields.insert(key, spliced); },
+            Some(Value::Map(entries)) => entries.push((Value::Str(key), spliced)),
+            _ => return Err(missing()),
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn signed(sign: Sign, magnitude: u64) -> i128 {
+    magnitude as i128 * match sign { Sign::Pos => 1, Sign::Neg => -1 }
+}
+
+fn variant(variant: &'static str, value: Value<'static>) -> Value<'static> {
+    Value::Record(BTreeMap::from([(Cow::Borrowed(variant), value)]))
+}
+
+#[derive(Clone, Copy)]
+struct ValueSerializer {
+    depth: usize,
+    tagged_options: bool,
+}
+
+impl ValueSerializer {
+    fn nested<T: ?Sized + Serialize>(self, value: &T) -> Result<Value<'static>> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::Depth(MAX_DEPTH));
+        }
+        value.serialize(ValueSerializer { depth: self.depth + 1, ..self })
+    }
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value<'static>> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value<'static>> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value<'static>> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value<'static>> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value<'static>> {
+        Ok(Value::Int(if v < 0 { Sign::Neg } else { Sign::Pos }, v.unsigned_abs()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value<'static>> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value<'static>> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value<'static>> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value<'static>> {
+        Ok(Value::Int(Sign::Pos, v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value<'static>> {
+        Ok(Value::F32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value<'static>> {
+        Ok(Value::F64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value<'static>> {
+        Ok(Value::Str(Cow::Owned(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value<'static>> {
+        Ok(Value::Str(Cow::Owned(v.to_owned())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value<'static>> {
+        Ok(Value::Bytes(Cow::Owned(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Value<'static>> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value<'static>> {
+        match self.tagged_options {
+            true => Ok(variant("some", self.nested(value)?)),
+            false => self.nested(value),
+        }
+    }
+
+    fn serialize_unit(self) -> Result<Value<'static>> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value<'static>> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Value<'static>> {
+        Ok(Value::Symbol(Cow::Borrowed(variant)))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<Value<'static>> {
+        self.nested(value)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str, _index: u32, name: &'static str, value: &T) -> Result<Value<'static>> {
+        Ok(variant(name, self.nested(value)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer { ser: self, variant: None, elements: Vec::with_capacity(len.unwrap_or_default()) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<SeqSerializer> {
+        Ok(SeqSerializer { ser: self, variant: Some(variant), elements: Vec::with_capacity(len) })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer { ser: self, entries: Vec::with_capacity(len.unwrap_or_default()), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<StructSerializer> {
+        Ok(StructSerializer { ser: self, variant: None, fields: BTreeMap::new() })
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, _len: usize) -> Result<StructSerializer> {
+        Ok(StructSerializer { ser: self, variant: Some(variant), fields: BTreeMap::new() })
+    }
+
+}
+
+struct SeqSerializer {
+    ser: ValueSerializer,
+    variant: Option<&'static str>,
+    elements: Vec<Value<'static>>,
+}
+
+impl SeqSerializer {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.elements.push(self.ser.nested(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value<'static>> {
+        let array = Value::Array(self.elements);
+        Ok(match self.variant {
+            Some(name) => variant(name, array),
+            None => array,
+        })
+    }
+}
+
+impl SerializeSeq for SeqSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value<'static>> {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for SeqSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value<'static>> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for SeqSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value<'static>> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for SeqSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value<'static>> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    ser: ValueSerializer,
+    entries: Vec<(Value<'static>, Value<'static>)>,
+    key: Option<Value<'static>>,
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(self.ser.nested(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().ok_or_else(|| <Error as ser::Error>::custom("value without key"))?;
+        self.entries.push((key, self.ser.nested(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value<'static>> {
+        Ok(Value::Map(self.entries))
+    }
+}
+
+struct StructSerializer {
+    ser: ValueSerializer,
+    variant: Option<&'static str>,
+    fields: BTreeMap<Cow<'static, str>, Value<'static>>,
+}
+
+impl StructSerializer {
+    fn insert<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.fields.insert(Cow::Borrowed(key), self.ser.nested(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value<'static>> {
+        let record = Value::Record(self.fields);
+        Ok(match self.variant {
+            Some(name) => variant(name, record),
+            None => record,
+        })
+    }
+}
+
+impl SerializeStruct for StructSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value<'static>> {
+        self.finish()
+    }
+}
+
+impl SerializeStructVariant for StructSerializer {
+    type Ok = Value<'static>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value<'static>> {
+        self.finish()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ValueDeserializer<'de> {
+    value: &'de Value<'de>,
+    depth: usize,
+    /// Whether errors within containers should be wrapped in `Error::Path`
+    paths: bool,
+}
+
+impl<'de> ValueDeserializer<'de> {
+
+    /// Descend into the value, which counts as one level of nesting
+    fn nested(self) -> Result<Self> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::Depth(MAX_DEPTH));
+        }
+        Ok(Self { depth: self.depth + 1, ..self })
+    }
+
+    fn child(self, value: &'de Value<'de>) -> Self {
+        Self { value, ..self }
+    }
+
+    /// Prefix the path of an error within the child at the given segment, if paths are tracked
+    fn within<T>(self, result: Result<T>, segment: impl FnOnce() -> String) -> Result<T> {
+        match result {
+            Err(e) if self.paths => {
+                let segment = format!("/{}", segment().replace('~', "~0").replace('/', "~1"));
+                Err(match e {
+                    Error::Path(path, e) => Error::Path(segment + &path, e),
+                    e => Error::Path(segment, Box::new(e)),
+                })
+            },
+            result => result,
+        }
+    }
+
+    fn unexpected(self, expected: &'static [&'static str]) -> Error {
+        Error::UnexpectedValue(expected, self.value.typename())
+    }
+
+    fn int(self) -> Result<i128> {
+        match self.value {
+            Value::Int(s, v) => Ok(signed(*s, *v)),
+            _ => Err(self.unexpected(&["integer"])),
+        }
+    }
+
+    fn stringy(self) -> Result<&'de str> {
+        match self.value {
+            Value::Str(v) | Value::Symbol(v) => Ok(v),
+            _ => Err(self.unexpected(&["string", "symbol"])),
+        }
+    }
+
+    fn visit_array<V: Visitor<'de>>(self, elements: &'de [Value<'de>], visitor: V) -> Result<V::Value> {
+        let de = self.nested()?;
+        visitor.visit_seq(ArrayAccess { de, elements: elements.iter(), index: 0 })
+    }
+
+    fn visit_record<V: Visitor<'de>>(self, fields: &'de BTreeMap<Cow<'de, str>, Value<'de>>, visitor: V) -> Result<V::Value> {
+        let de = self.nested()?;
+        visitor.visit_map(RecordAccess { de, fields: fields.iter(), value: None })
+    }
+
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(*v),
+            Value::F32(v) => visitor.visit_f32(*v),
+            Value::F64(v) => visitor.visit_f64(*v),
+            Value::Bytes(v) => visitor.visit_borrowed_bytes(v),
+            Value::Int(s, v) => visitor.visit_i64(signed(*s, *v).try_into()?),
+            Value::Str(v) | Value::Symbol(v) => visitor.visit_borrowed_str(v),
+            Value::Array(v) => self.visit_array(v, visitor),
+            Value::Map(v) => {
+                let de = self.nested()?;
+                visitor.visit_map(MapEntries { de, entries: v.iter(), value: None })
+            },
+            Value::Record(v) => self.visit_record(v, visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Bool(v) => visitor.visit_bool(*v),
+            _ => Err(self.unexpected(&["bool"])),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8(self.int()?.try_into()?)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i16(self.int()?.try_into()?)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i32(self.int()?.try_into()?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i64(self.int()?.try_into()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.int()?.try_into()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u16(self.int()?.try_into()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(self.int()?.try_into()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u64(self.int()?.try_into()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::F32(v) => visitor.visit_f32(*v),
+            _ => Err(self.unexpected(&["f32"])),
+        }
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::F64(v) => visitor.visit_f64(*v),
+            _ => Err(self.unexpected(&["f64"])),
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut chars = self.stringy()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(self.unexpected(&["string of length 1"])),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.stringy()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Bytes(v) => visitor.visit_borrowed_bytes(v),
+            _ => Err(self.unexpected(&["bytes"])),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Bytes(v) => visitor.visit_byte_buf(v.to_vec()),
+            Value::Array(v) => {
+                let bytes = v.iter().map(|b| self.child(b).int()?.try_into().map_err(Error::from)).collect::<Result<Vec<u8>>>()?;
+                visitor.visit_byte_buf(bytes)
+            },
+            _ => Err(self.unexpected(&["bytes", "array"])),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self.nested()?),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            _ => Err(self.unexpected(&["null"])),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self.nested()?)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Array(v) => self.visit_array(v, visitor),
+            _ => Err(self.unexpected(&["array"])),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Map(v) => {
+                let de = self.nested()?;
+                visitor.visit_map(MapEntries { de, entries: v.iter(), value: None })
+            },
+            _ => Err(self.unexpected(&["map"])),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Record(v) => self.visit_record(v, visitor),
+            _ => Err(self.unexpected(&["record"])),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        match self.value {
+            Value::Record(v) if v.len() == 1 => {
+                let (variant, value) = v.iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer { variant, de: self.nested()?.child(value) })
+            },
+            Value::Str(v) | Value::Symbol(v) => visitor.visit_enum(v.as_ref().into_deserializer()),
+            _ => Err(self.unexpected(&["record", "string", "symbol"])),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_any(visitor)
+    }
+
+}
+
+struct ArrayAccess<'de> {
+    de: ValueDeserializer<'de>,
+    elements: std::slice::Iter<'de, Value<'de>>,
+    /// The index of the next element
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for ArrayAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.elements.next() {
+            Some(value) => {
+                self.index += 1;
+                self.de.within(seed.deserialize(self.de.child(value)), || (self.index - 1).to_string()).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+struct MapEntries<'de> {
+    de: ValueDeserializer<'de>,
+    entries: std::slice::Iter<'de, (Value<'de>, Value<'de>)>,
+    /// The value of the current entry and its key
+    value: Option<(&'de Value<'de>, &'de Value<'de>)>,
+}
+
+impl<'de> MapAccess<'de> for MapEntries<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some((value, key));
+                seed.deserialize(self.de.child(key)).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let (value, key) = self.value.take().ok_or_else(|| <Error as de::Error>::custom("value without key"))?;
+        self.de.within(seed.deserialize(self.de.child(value)), || match key {
+            Value::Str(k) | Value::Symbol(k) => k.to_string(),
+            k => k.to_string(),
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct RecordAccess<'de> {
+    de: ValueDeserializer<'de>,
+    fields: btree_map::Iter<'de, Cow<'de, str>, Value<'de>>,
+    /// The value of the current field and its key
+    value: Option<(&'de Value<'de>, &'de str)>,
+}
+
+impl<'de> MapAccess<'de> for RecordAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.fields.next() {
+            Some((key, value)) => {
+                self.value = Some((value, key));
+                seed.deserialize(BorrowedStrDeserializer::new(key)).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let (value, key) = self.value.take().ok_or_else(|| <Error as de::Error>::custom("value without key"))?;
+        self.de.within(seed.deserialize(self.de.child(value)), || key.to_owned())
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+struct EnumDeserializer<'de> {
+    variant: &'de str,
+    de: ValueDeserializer<'de>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = Error;
+    type Variant = ValueDeserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, self.de))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.value {
+            Value::Null => Ok(()),
+            _ => Err(self.unexpected(&["null"])),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use super::{from_value, to_value, Extract, Splice};
+    use crate::error::Error;
+    use crate::MAX_DEPTH;
+    use nachricht::{Sign, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cat {
+        name: String,
+        lives: u8,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Pet {
+        Fish,
+        Cat(Cat),
+        Dog { name: String, good: bool },
+        Pair(u8, i8),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Household {
+        pets: Vec<Pet>,
+        #[serde(with = "serde_bytes")]
+        chip: Vec<u8>,
+        owner: Option<String>,
+        scores: BTreeMap<u8, f64>,
+    }
+
+    fn str(s: &'static str) -> Value<'static> {
+        Value::Str(Cow::Borrowed(s))
+    }
+
+    fn record(fields: Vec<(&'static str, Value<'static>)>) -> Value<'static> {
+        Value::Record(fields.into_iter().map(|(k, v)| (Cow::Borrowed(k), v)).collect())
+    }
+
+    #[test]
+    fn roundtrip() {
+        let household = Household {
+            pets: vec![
+                Pet::Fish,
+                Pet::Cat(Cat { name: "Jessica".to_string(), lives: 9 }),
+                Pet::Dog { name: "Laika".to_string(), good: true },
+                Pet::Pair(1, -1),
+            ],
+            chip: vec![0xca, 0xfe],
+            owner: None,
+            scores: BTreeMap::from([(1, 0.5)]),
+        };
+        let value = to_value(&household).unwrap();
+        assert_eq!(record(vec![
+            ("pets", Value::Array(vec![
+                Value::Symbol(Cow::Borrowed("Fish")),
+                record(vec![("Cat", record(vec![("name", str("Jessica")), ("lives", Value::Int(Sign::Pos, 9))]))]),
+                record(vec![("Dog", record(vec![("name", str("Laika")), ("good", Value::Bool(true))]))]),
+                record(vec![("Pair", Value::Array(vec![Value::Int(Sign::Pos, 1), Value::Int(Sign::Neg, 1)]))]),
+            ])),
+            ("chip", Value::Bytes(Cow::Borrowed(&[0xca, 0xfe]))),
+            ("owner", Value::Null),
+            ("scores", Value::Map(vec![(Value::Int(Sign::Pos, 1), Value::F64(0.5))])),
+        ]), value);
+        assert_eq!(household, from_value::<Household>(&value).unwrap());
+        assert!(matches!(from_value::<Cat>(&Value::Null), Err(Error::UnexpectedValue(&["record"], "null"))));
+    }
+
+    #[test]
+    fn depth() {
+        let mut value = Value::Null;
+        for _ in 0..MAX_DEPTH + 1 {
+            value = Value::Array(vec![value]);
+        }
+        assert!(matches!(from_value::<serde::de::IgnoredAny>(&value), Err(Error::Depth(MAX_DEPTH))));
+    }
+
+    #[test]
+    fn extract() {
+        let cats = vec![Cat { name: "Jessica".to_string(), lives: 9 }, Cat { name: "Wantan".to_string(), lives: 7 }];
+        let mut value = to_value(&BTreeMap::from([("cats", &cats)])).unwrap();
+        assert_eq!(cats[1], value.extract::<Cat>("/cats/1").unwrap());
+        assert_eq!(9, value.extract::<u8>("/cats/0/lives").unwrap());
+        assert!(matches!(value.extract::<Cat>("/cats/2"), Err(Error::Missing(p)) if p == "/cats/2"));
+        if let Value::Map(entries) = &mut value {
+            entries[0].1 = Value::Array(vec![to_value(&cats[0]).unwrap(), Value::Record(BTreeMap::from([
+                (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Sphinx"))),
+                (Cow::Borrowed("lives"), Value::Int(Sign::Neg, 1)),
+            ]))]);
+        }
+        let error = value.extract::<Vec<Cat>>("/cats").unwrap_err();
+        assert!(matches!(&error, Error::Path(p, e) if p == "/cats/1/lives" && matches!(**e, Error::Int)));
+        assert_eq!("Integer didn't fit into target type at /cats/1/lives", error.to_string());
+        assert!(matches!(value.extract::<Cat>("/cats/1"), Err(Error::Path(p, _)) if p == "/cats/1/lives"));
+        assert!(matches!(value.extract::<u8>(""), Err(Error::Path(p, _)) if p.is_empty()));
+    }
+
+    #[test]
+    fn splice() {
+        let cat = Cat { name: "Jessica".to_string(), lives: 9 };
+        let mut envelope = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("headers"), Value::Map(vec![(Value::Str(Cow::Borrowed("to")), Value::Str(Cow::Borrowed("vet")))])),
+            (Cow::Borrowed("payload"), Value::Null),
+        ]));
+        envelope.splice("/payload", &cat).unwrap();
+        envelope.splice("/headers/from", "shelter").unwrap();
+        envelope.splice("/payload/lives", &8).unwrap();
+        assert_eq!(Cat { lives: 8, ..cat }, envelope.extract::<Cat>("/payload").unwrap());
+        assert_eq!("shelter", envelope.extract::<String>("/headers/from").unwrap());
+        assert_eq!("vet", envelope.extract::<String>("/headers/to").unwrap());
+        assert!(matches!(envelope.splice("/payload/lives/count", &1), Err(Error::Missing(p)) if p == "/payload/lives/count"));
+        assert!(matches!(envelope.splice("/trailer/checksum", &1), Err(Error::Missing(_))));
+        envelope.splice("", &[1, 2]).unwrap();
+        assert_eq!(vec![1, 2], from_value::<Vec<u8>>(&envelope).unwrap());
+    }
+
+}
@@ -0,0 +1,141 @@
+//! Random values for property testing with `proptest`. `any::<Value>()` generates messages of every kind, including
+//! NaNs with arbitrary payloads, empty containers, repeated symbols, map keys of any type and strings which are hard to
+//! represent as text:
+//!
+//! ```
+//! use nachricht::*;
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     fn roundtrip(value in any::<Value>()) {
+//!         let mut buf = Vec::new();
+//!         Encoder::encode(&value, &mut buf).unwrap();
+//!         prop_assert_eq!(buf.len(), Decoder::decode(&buf).unwrap().1);
+//!     }
+//! }
+//!
+//! roundtrip();
+//! ```
+//!
+//! Use `any_with` and `ValueParameters` to change the size of the generated values. Values are compared with `==`,
+//! which never holds for NaNs; compare their encodings instead. Shrinking removes elements and replaces containers with
+//! their contents, so failures are reported with a small message. `any_string` generates such strings on their own,
+//! e.g. for the fields of structs.
+
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+use std::borrow::Cow;
+
+use crate::header::Sign;
+use crate::value::Value;
+
+/// Symbols which are picked often, so that generated messages reference symbols and layouts they have defined before
+const SYMBOLS: &[&str] = &["", "id", "name", "FelisCatus", "🐈"];
+
+/// Characters which need to be escaped in the textual representation, are part of its syntax or are invisible
+const CHARS: &[char] = &['a', 'Z', '0', '9', '-', '_', '.', 'x', 'e', ' ', '"', '\\', '\n', '\r', '\t', '\u{0}', '\u{8}',
+    '\u{1b}', '\u{7f}', '\u{85}', '\u{a0}', '$', '&', ',', ':', '#', '(', ')', '[', ']', '{', '}', '\'', '/', '*', '=', 'é',
+    'ß', '猫', '🐈', '\u{200b}', '\u{200d}', '\u{2028}', '\u{3000}', '\u{fe0f}', '\u{feff}', '\u{10ffff}'];
+
+/// Strings which mean something else in the textual representation unless they are quoted
+const WORDS: &[&str] = &["null", "true", "false", "nan", "inf", "-inf", "0", "-0", "1e5", "0x1f", "&0", "&1", "//", "/*",
+    "*/", "x''", "''", "u{1b}"];
+
+/// Bounds for the values generated by `any_with::<Value>`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueParameters {
+    /// The maximum number of nested containers
+    pub depth: u32,
+    /// The maximum number of elements, entries or fields of a single container
+    pub width: usize,
+    /// The maximum number of characters of strings, symbols and keys and of bytes of byte strings
+    pub length: usize,
+}
+
+impl Default for ValueParameters {
+    fn default() -> Self {
+        Self { depth: 4, width: 6, length: 12 }
+    }
+}
+
+impl Arbitrary for Value<'static> {
+    type Parameters = ValueParameters;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(parameters: Self::Parameters) -> Self::Strategy {
+        let ValueParameters { depth, width, length } = parameters;
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::Bool),
+            prop_oneof![any::<f32>(), any::<u32>().prop_map(f32::from_bits)].prop_map(Value::F32),
+            prop_oneof![any::<f64>(), any::<u64>().prop_map(f64::from_bits)].prop_map(Value::F64),
+            vec(any::<u8>(), 0..=length).prop_map(|b| Value::Bytes(Cow::Owned(b))),
+            (any::<bool>(), any::<u64>()).prop_map(|(negative, n)| match negative && n > 0 {
+                true => Value::Int(Sign::Neg, n),
+                false => Value::Int(Sign::Pos, n),
+            }),
+            any_string(length).prop_map(|s| Value::Str(Cow::Owned(s))),
+            prop_oneof![symbol(), any_string(length)].prop_map(|s| Value::Symbol(Cow::Owned(s))),
+        ];
+        leaf.prop_recursive(depth, depth * width as u32, width as u32, move |inner| prop_oneof![
+            vec(inner.clone(), 0..=width).prop_map(Value::Array),
+            vec((inner.clone(), inner.clone()), 0..=width).prop_map(Value::Map),
+            btree_map(prop_oneof![symbol(), any_string(length)].prop_map(Cow::Owned), inner, 0..=width).prop_map(Value::Record),
+        ]).boxed()
+    }
+}
+
+/// Strings of up to `length` characters as they occur within generated values. Characters which are special to the
+/// textual representation are picked often, and some strings are words which mean something else without quotes.
+pub fn any_string(length: usize) -> impl Strategy<Value = String> {
+    prop_oneof![
+        3 => vec(prop_oneof![any::<char>(), proptest::sample::select(CHARS)], 0..=length)
+            .prop_map(|chars| chars.into_iter().collect()),
+        1 => proptest::sample::select(WORDS).prop_map(move |word| word.chars().take(length).collect()),
+    ]
+}
+
+fn symbol() -> impl Strategy<Value = String> {
+    proptest::sample::select(SYMBOLS).prop_map(str::to_owned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::ValueParameters;
+    use crate::canon::canonicalize;
+    use crate::value::{Decoder, Encoder, Value};
+    use proptest::prelude::*;
+
+    fn encoded(value: &Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        Encoder::encode(value, &mut buf).unwrap();
+        buf
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip(value in any::<Value>()) {
+            let buf = encoded(&value);
+            let decoded = Decoder::decode_untrusted(&buf).unwrap();
+            prop_assert_eq!(&buf, &encoded(&decoded));
+        }
+
+        #[test]
+        fn canonical(value in any_with::<Value>(ValueParameters { depth: 2, width: 3, length: 2 })) {
+            let mut once = value;
+            canonicalize(&mut once).unwrap();
+            let mut twice = once.clone();
+            canonicalize(&mut twice).unwrap();
+            prop_assert_eq!(encoded(&once), encoded(&twice));
+        }
+
+        #[test]
+        fn deduplicate(value in any::<Value>()) {
+            let mut resolved = value.clone();
+            resolved.deduplicate().unwrap();
+            resolved.resolve_shared(usize::MAX).unwrap();
+            prop_assert_eq!(encoded(&value), encoded(&resolved));
+        }
+    }
+
+}
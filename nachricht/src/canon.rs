@@ -0,0 +1,102 @@
+//! Bring a value into canonical form. The `Encoder` already sorts the fields of records, writes headers in their
+//! shortest form and interns symbols in the order of their first occurrence, but it keeps the entries of maps in the
+//! order in which they are given. `canonicalize` sorts them by the canonical encoding of their keys, so that messages
+//! which only differ in the order of map entries yield identical bytes:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//!
+//! let entry = |k: &'static str, v| (Value::Str(Cow::Borrowed(k)), Value::Int(Sign::Pos, v));
+//! let mut value = Value::Map(vec![entry("b", 2), entry("a", 1)]);
+//! canonicalize(&mut value).unwrap();
+//! assert_eq!(Value::Map(vec![entry("a", 1), entry("b", 2)]), value);
+//! ```
+//!
+//! Keys are compared by their bytes as encoded on their own, so the order does not depend on the symbols which have
+//! been defined before. Entries with equal keys keep their order.
+
+use crate::error::LengthError;
+use crate::value::{Encoder, Value};
+
+/// Sort the entries of all maps within the value, see the module documentation. Only fails if a key cannot be encoded.
+pub fn canonicalize(value: &mut Value) -> Result<(), LengthError> {
+    let mut stack = vec![value];
+    while let Some(value) = stack.pop() {
+        match value {
+            Value::Array(elements) => stack.extend(elements.iter_mut()),
+            Value::Record(fields) => stack.extend(fields.values_mut()),
+            Value::Map(entries) => {
+                let order = map_order(entries.iter().map(|(k, _)| k))?;
+                let mut unsorted = entries.drain(..).map(Some).collect::<Vec<_>>();
+                entries.extend(order.into_iter().filter_map(|i| unsorted.get_mut(i).and_then(Option::take)));
+                for (key, val) in entries.iter_mut() {
+                    stack.push(key);
+                    stack.push(val);
+                }
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+/// The indices of the keys in canonical order
+pub(crate) fn map_order<'k, 'a: 'k>(keys: impl Iterator<Item = &'k Value<'a>>) -> Result<Vec<usize>, LengthError> {
+    let mut encoded = Vec::new();
+    for (i, key) in keys.enumerate() {
+        let mut buf = Vec::new();
+        Encoder::encode_to_vec(key, &mut buf)?;
+        encoded.push((buf, i));
+    }
+    // ties are broken by the index, which keeps entries with equal keys in order
+    encoded.sort();
+    Ok(encoded.into_iter().map(|(_, i)| i).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::canonicalize;
+    use crate::header::Sign;
+    use crate::value::{Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn str(s: &'static str) -> Value<'static> {
+        Value::Str(Cow::Borrowed(s))
+    }
+
+    fn int(i: u64) -> Value<'static> {
+        Value::Int(Sign::Pos, i)
+    }
+
+    #[test]
+    fn sorted() {
+        let mut value = Value::Array(vec![
+            Value::Map(vec![(str("b"), int(2)), (int(1), str("one")), (str("a"), Value::Map(vec![(str("z"), int(0)), (str("y"), int(0))]))]),
+            Value::Record(BTreeMap::from([(Cow::Borrowed("map"), Value::Map(vec![(int(2), Value::Null), (int(1), Value::Null)]))])),
+        ]);
+        canonicalize(&mut value).unwrap();
+        let expected = Value::Array(vec![
+            // integers are encoded with a lower type code than strings
+            Value::Map(vec![(int(1), str("one")), (str("a"), Value::Map(vec![(str("y"), int(0)), (str("z"), int(0))])), (str("b"), int(2))]),
+            Value::Record(BTreeMap::from([(Cow::Borrowed("map"), Value::Map(vec![(int(1), Value::Null), (int(2), Value::Null)]))])),
+        ]);
+        assert_eq!(expected, value);
+    }
+
+    #[test]
+    fn stable() {
+        let original = Value::Map(vec![(str("a"), int(2)), (Value::Symbol(Cow::Borrowed("b")), int(0)), (str("a"), int(1))]);
+        let mut value = original.clone();
+        canonicalize(&mut value).unwrap();
+        assert_eq!(Value::Map(vec![(str("a"), int(2)), (str("a"), int(1)), (Value::Symbol(Cow::Borrowed("b")), int(0))]), value);
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        Encoder::encode(&value, &mut first).unwrap();
+        let mut again = value.clone();
+        canonicalize(&mut again).unwrap();
+        Encoder::encode(&again, &mut second).unwrap();
+        assert_eq!(first, second);
+    }
+
+}
@@ -0,0 +1,362 @@
+//! Test vectors for the `nachricht` wire format. Every vector consists of a value, its canonical encoding and its
+//! canonical textual representation as produced by `Display`. Alternative implementations, possibly in other
+//! languages, can use them to check that they agree with this reference implementation: decoding the bytes needs to
+//! yield the value and encoding the value needs to yield exactly the bytes. Implementations which print values as text
+//! need to print exactly the text, those which do not are only checked against the bytes.
+//!
+//! Implementations written in Rust can use `verify` as a ready-made harness:
+//!
+//! ```
+//! use nachricht::conformance::{verify, Reference};
+//!
+//! verify(&Reference).unwrap();
+//! ```
+
+use crate::header::Sign;
+use crate::value::{Decoder, Encoder, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// A single test vector.
+pub struct Vector {
+    /// A short description of what is being tested
+    pub name: &'static str,
+    /// The canonical encoding of the value
+    pub bytes: &'static [u8],
+    /// The canonical textual representation of the value
+    pub text: &'static str,
+    value: fn() -> Value<'static>,
+}
+
+impl Vector {
+    /// The value which is represented by this vector.
+    pub fn value(&self) -> Value<'static> {
+        (self.value)()
+    }
+}
+
+/// An implementation of the wire format which is subject to verification.
+pub trait Implementation {
+    /// Encode the value into its canonical form.
+    fn encode(&self, value: &Value) -> Result<Vec<u8>, String>;
+    /// Decode a single value which occupies the whole buffer.
+    fn decode(&self, bytes: &[u8]) -> Result<Value<'static>, String>;
+    /// Print the value in its canonical textual representation, or `None` if the implementation has none, in which case
+    /// the texts of the vectors are not checked.
+    fn print(&self, _value: &Value) -> Option<String> {
+        None
+    }
+}
+
+/// The implementation contained in this crate.
+pub struct Reference;
+
+impl Implementation for Reference {
+    fn encode(&self, value: &Value) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::new();
+        Encoder::encode(value, &mut buf).map_err(|e| e.to_string())?;
+        Ok(buf)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Value<'static>, String> {
+        match Decoder::decode(bytes) {
+            Ok((value, c)) if c == bytes.len() => Ok(value.into_owned()),
+            Ok((_, c)) => Err(format!("{} trailing bytes", bytes.len() - c)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn print(&self, value: &Value) -> Option<String> {
+        Some(value.to_string())
+    }
+}
+
+/// A vector for which an implementation deviated from the expected behaviour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub vector: &'static str,
+    pub reason: String,
+}
+
+/// Check the implementation against all vectors and collect every deviation.
+pub fn verify<I: Implementation>(implementation: &I) -> Result<(), Vec<Failure>> {
+    let mut failures = Vec::new();
+    for vector in VECTORS {
+        let value = vector.value();
+        let fail = |reason| Failure { vector: vector.name, reason };
+        match implementation.decode(vector.bytes) {
+            Ok(v) if v == value => {},
+            Ok(v) => failures.push(fail(format!("decoded {} but expected {}", v, value))),
+            Err(e) => failures.push(fail(format!("decoding failed: {}", e))),
+        }
+        match implementation.encode(&value) {
+            Ok(b) if b == vector.bytes => {},
+            Ok(b) => failures.push(fail(format!("encoded {:02x?} but expected {:02x?}", b, vector.bytes))),
+            Err(e) => failures.push(fail(format!("encoding failed: {}", e))),
+        }
+        match implementation.print(&value) {
+            Some(t) if t != vector.text => failures.push(fail(format!("printed {:?} but expected {:?}", t, vector.text))),
+            _ => {},
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+fn record(fields: Vec<(&'static str, Value<'static>)>) -> Value<'static> {
+    Value::Record(fields.into_iter().map(|(k, v)| (Cow::Borrowed(k), v)).collect::<BTreeMap<_, _>>())
+}
+
+fn cat(name: &'static str, species: &'static str) -> Value<'static> {
+    record(vec![("name", Value::Str(Cow::Borrowed(name))), ("species", Value::Symbol(Cow::Borrowed(species)))])
+}
+
+/// All test vectors. New vectors may be added in future versions. The bytes of existing ones only change along with the
+/// wire format, while their texts follow the textual representation, which has changed before, e.g. for empty
+/// containers.
+pub const VECTORS: &[Vector] = &[
+    Vector {
+        name: "null",
+        bytes: &[0x00],
+        text: "null",
+        value: || Value::Null,
+    },
+    Vector {
+        name: "true",
+        bytes: &[0x01],
+        text: "true",
+        value: || Value::Bool(true),
+    },
+    Vector {
+        name: "false",
+        bytes: &[0x02],
+        text: "false",
+        value: || Value::Bool(false),
+    },
+    Vector {
+        name: "f32",
+        bytes: &[0x03, 0x3f, 0xc0, 0x00, 0x00],
+        text: "$1.5",
+        value: || Value::F32(1.5),
+    },
+    Vector {
+        name: "f64",
+        bytes: &[0x04, 0xbf, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        text: "$$-0.25",
+        value: || Value::F64(-0.25),
+    },
+    Vector {
+        name: "zero",
+        bytes: &[0x20],
+        text: "0",
+        value: || Value::Int(Sign::Pos, 0),
+    },
+    Vector {
+        name: "largest inline positive integer",
+        bytes: &[0x27],
+        text: "7",
+        value: || Value::Int(Sign::Pos, 7),
+    },
+    Vector {
+        name: "positive integer with one length byte",
+        bytes: &[0x28, 0x08],
+        text: "8",
+        value: || Value::Int(Sign::Pos, 8),
+    },
+    Vector {
+        name: "positive integer with two length bytes",
+        bytes: &[0x29, 0x05, 0x39],
+        text: "1337",
+        value: || Value::Int(Sign::Pos, 1337),
+    },
+    Vector {
+        name: "largest positive integer",
+        bytes: &[0x2f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+        text: "18446744073709551615",
+        value: || Value::Int(Sign::Pos, u64::MAX),
+    },
+    Vector {
+        name: "smallest inline negative integer",
+        bytes: &[0x37],
+        text: "-8",
+        value: || Value::Int(Sign::Neg, 8),
+    },
+    Vector {
+        name: "negative integer with one length byte",
+        bytes: &[0x38, 0x08],
+        text: "-9",
+        value: || Value::Int(Sign::Neg, 9),
+    },
+    Vector {
+        name: "smallest negative integer",
+        bytes: &[0x3f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe],
+        text: "-18446744073709551615",
+        value: || Value::Int(Sign::Neg, u64::MAX),
+    },
+    Vector {
+        name: "empty bytes",
+        bytes: &[0x05],
+        text: "''",
+        value: || Value::Bytes(Cow::Borrowed(&[])),
+    },
+    Vector {
+        name: "bytes",
+        bytes: &[0x0c, 0x6d, 0xab, 0x1e, 0xeb, 0x8f, 0xff, 0x00],
+        text: "'base64//AA=='",
+        value: || Value::Bytes(Cow::Borrowed(&[0x6d, 0xab, 0x1e, 0xeb, 0x8f, 0xff, 0x00])),
+    },
+    Vector {
+        name: "empty string",
+        bytes: &[0x40],
+        text: "\"\"",
+        value: || Value::Str(Cow::Borrowed("")),
+    },
+    Vector {
+        name: "string",
+        bytes: &[0x47, 0x4a, 0x65, 0x73, 0x73, 0x69, 0x63, 0x61],
+        text: "\"Jessica\"",
+        value: || Value::Str(Cow::Borrowed("Jessica")),
+    },
+    Vector {
+        name: "string with escapes",
+        bytes: &[0x4f, 0x61, 0x20, 0x22, 0x71, 0x75, 0x6f, 0x74, 0x65, 0x64, 0x22, 0x0a, 0x6c, 0x69, 0x6e, 0x65],
+        text: "\"a \\\"quoted\\\"\\nline\"",
+        value: || Value::Str(Cow::Borrowed("a \"quoted\"\nline")),
+    },
+    Vector {
+        name: "string with control characters",
+        bytes: &[0x44, 0x09, 0x0d, 0x1b, 0x7f],
+        text: "\"\\t\\r\\u{1b}\\u{7f}\"",
+        value: || Value::Str(Cow::Borrowed("\t\r\u{1b}\u{7f}")),
+    },
+    Vector {
+        name: "unicode string",
+        bytes: &[0x45, 0x73, 0xc3, 0xad, 0x6c, 0x61],
+        text: "\"síla\"",
+        value: || Value::Str(Cow::Borrowed("síla")),
+    },
+    Vector {
+        name: "long string",
+        bytes: &[
+            0x58, 0x30, 0x4e, 0x6f, 0x6e, 0x2d, 0x73, 0x65, 0x6c, 0x66, 0x2d, 0x64, 0x65, 0x73,
+            0x63, 0x72, 0x69, 0x62, 0x69, 0x6e, 0x67, 0x20, 0x66, 0x6f, 0x72, 0x6d, 0x61, 0x74,
+            0x73, 0x20, 0x6e, 0x65, 0x65, 0x64, 0x20, 0x70, 0x72, 0x69, 0x6f, 0x72, 0x20, 0x6b,
+            0x6e, 0x6f, 0x77, 0x6c, 0x65, 0x64, 0x67, 0x65,
+        ],
+        text: "\"Non-self-describing formats need prior knowledge\"",
+        value: || Value::Str(Cow::Borrowed("Non-self-describing formats need prior knowledge")),
+    },
+    Vector {
+        name: "symbol",
+        bytes: &[0x67, 0x65, 0x64, 0x32, 0x35, 0x35, 0x31, 0x39],
+        text: "#ed25519",
+        value: || Value::Symbol(Cow::Borrowed("ed25519")),
+    },
+    Vector {
+        name: "symbol with protected characters",
+        bytes: &[0x63, 0x61, 0x20, 0x62],
+        text: "#\"a b\"",
+        value: || Value::Symbol(Cow::Borrowed("a b")),
+    },
+    Vector {
+        name: "empty array",
+        bytes: &[0x80],
+        text: "[]",
+        value: || Value::Array(vec![]),
+    },
+    Vector {
+        name: "array",
+        bytes: &[0x82, 0x21, 0x00],
+        text: "[\n  1,\n  null,\n]",
+        value: || Value::Array(vec![Value::Int(Sign::Pos, 1), Value::Null]),
+    },
+    Vector {
+        name: "empty map",
+        bytes: &[0xc0],
+        text: "{}",
+        value: || Value::Map(vec![]),
+    },
+    Vector {
+        name: "map",
+        bytes: &[0xc2, 0x41, 0x78, 0x01, 0x30, 0x02],
+        text: "{\n  \"x\": true,\n  -1: false,\n}",
+        value: || Value::Map(vec![(Value::Str(Cow::Borrowed("x")), Value::Bool(true)), (Value::Int(Sign::Neg, 1), Value::Bool(false))]),
+    },
+    Vector {
+        name: "empty record",
+        bytes: &[0xa0],
+        text: "()",
+        value: || record(vec![]),
+    },
+    Vector {
+        name: "record",
+        bytes: &[
+            0xa2, 0x64, 0x6e, 0x61, 0x6d, 0x65, 0x67, 0x73, 0x70, 0x65, 0x63, 0x69, 0x65, 0x73,
+            0x47, 0x4a, 0x65, 0x73, 0x73, 0x69, 0x63, 0x61, 0x76, 0x50, 0x72, 0x69, 0x6f, 0x6e,
+            0x61, 0x69, 0x6c, 0x75, 0x72, 0x75, 0x73, 0x56, 0x69, 0x76, 0x65, 0x72, 0x72, 0x69,
+            0x6e, 0x75, 0x73,
+        ],
+        text: "(\n  name: \"Jessica\",\n  species: #PrionailurusViverrinus,\n)",
+        value: || cat("Jessica", "PrionailurusViverrinus"),
+    },
+    Vector {
+        name: "repeated symbols and layouts",
+        bytes: &[
+            0x83, 0xa2, 0x64, 0x6e, 0x61, 0x6d, 0x65, 0x67, 0x73, 0x70, 0x65, 0x63, 0x69, 0x65,
+            0x73, 0x47, 0x4a, 0x65, 0x73, 0x73, 0x69, 0x63, 0x61, 0x76, 0x50, 0x72, 0x69, 0x6f,
+            0x6e, 0x61, 0x69, 0x6c, 0x75, 0x72, 0x75, 0x73, 0x56, 0x69, 0x76, 0x65, 0x72, 0x72,
+            0x69, 0x6e, 0x75, 0x73, 0xe2, 0x46, 0x57, 0x61, 0x6e, 0x74, 0x61, 0x6e, 0x68, 0x4c,
+            0x79, 0x6e, 0x78, 0x4c, 0x79, 0x6e, 0x78, 0xe2, 0x47, 0x43, 0x68, 0x61, 0x6e, 0x64,
+            0x72, 0x61, 0xe3,
+        ],
+        text: "[\n  (\n    name: \"Jessica\",\n    species: #PrionailurusViverrinus,\n  ),\n  (\n    name: \"Wantan\",\n    species: #LynxLynx,\n  ),\n  (\n    name: \"Chandra\",\n    species: #PrionailurusViverrinus,\n  ),\n]",
+        value: || Value::Array(vec![cat("Jessica", "PrionailurusViverrinus"), cat("Wantan", "LynxLynx"), cat("Chandra", "PrionailurusViverrinus")]),
+    },
+    Vector {
+        name: "symbol shared between value and key",
+        bytes: &[0xa2, 0x64, 0x6e, 0x61, 0x6d, 0x65, 0x65, 0x6f, 0x74, 0x68, 0x65, 0x72, 0xe0, 0xe0],
+        text: "(\n  name: #name,\n  other: #name,\n)",
+        value: || record(vec![("name", Value::Symbol(Cow::Borrowed("name"))), ("other", Value::Symbol(Cow::Borrowed("name")))]),
+    },
+];
+
+
+#[cfg(test)]
+mod test {
+    use super::{verify, Implementation, Reference};
+    use crate::Value;
+
+    /// Encodes the array vector as an empty array and prints `true` as `1`
+    struct Broken;
+
+    impl Implementation for Broken {
+        fn encode(&self, value: &Value) -> Result<Vec<u8>, String> {
+            Reference.encode(value).map(|b| if b == [0x82, 0x21, 0x00] { vec![0x80] } else { b })
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Result<Value<'static>, String> {
+            Reference.decode(bytes)
+        }
+
+        fn print(&self, value: &Value) -> Option<String> {
+            Some(if *value == Value::Bool(true) { "1".to_string() } else { value.to_string() })
+        }
+    }
+
+    #[test]
+    fn reference() {
+        verify(&Reference).unwrap();
+    }
+
+    #[test]
+    fn deviation() {
+        let failures = verify(&Broken).unwrap_err();
+        assert_eq!(2, failures.len());
+        assert_eq!("true", failures[0].vector);
+        assert_eq!("array", failures[1].vector);
+    }
+
+}
@@ -0,0 +1,410 @@
+//! Keep a receiver in sync with a changing state by sending a snapshot once and only the changes afterwards. A
+//! `StatePublisher` numbers every state with a sequence number and turns it into an `Update`, which is either the full
+//! state or a delta to the previous one, whichever encodes smaller. A `StateSubscriber` applies the updates in order:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let cat = |lives| Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+//!     (Cow::Borrowed("lives"), Value::Int(Sign::Pos, lives)),
+//! ]));
+//! let mut publisher = StatePublisher::new();
+//! let mut subscriber = StateSubscriber::new();
+//! for lives in (7..=9).rev() {
+//!     let update = publisher.publish(cat(lives));
+//!     let mut buf = Vec::new();
+//!     Encoder::encode(&update.to_value(), &mut buf).unwrap();
+//!     subscriber.apply(Update::from_value(Decoder::decode(&buf).unwrap().0).unwrap()).unwrap();
+//! }
+//! assert_eq!(Some(&cat(7)), subscriber.state());
+//! assert_eq!(Some(2), subscriber.sequence());
+//! ```
+//!
+//! Deltas are lists of `Change`s which address values with JSON pointers as understood by `Value::pointer`. Records
+//! and arrays are compared field by field and element by element, while any other value, including maps, is replaced as
+//! a whole once it differs. On the wire, an update is a record `(sequence, snapshot)` or `(sequence, changes)`, where
+//! every change is a record `(path, value)` or, for removals, `(path)`.
+//!
+//! A delta only applies to the state with the preceding sequence number. Receivers which missed an update get a
+//! `DeltaError::Gap` and need a new snapshot, e.g. from `StatePublisher::snapshot`. Updates which they have already
+//! seen are ignored, so transports may deliver duplicates.
+
+use crate::error::DeltaError;
+use crate::header::Sign;
+use crate::value::{Encoder, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// A single difference between two values, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<'a> {
+    /// Replace the value at the pointer or add it as a new field, map entry or array element
+    Set(String, Value<'a>),
+    /// Remove the field, map entry or last array element at the pointer
+    Remove(String),
+}
+
+/// A state or the changes since the previous one, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update<'a> {
+    Snapshot(u64, Value<'a>),
+    Delta(u64, Vec<Change<'a>>),
+}
+
+impl<'a> Value<'a> {
+
+    /// The changes which turn this value into `new` when passed to `Value::apply`.
+    pub fn diff(&self, new: &Value<'a>) -> Vec<Change<'a>> {
+        let mut changes = Vec::new();
+        diff(self, new, &mut String::new(), &mut changes);
+        changes
+    }
+
+    /// Apply the changes in order. Fails with `DeltaError::Path` at the first change whose pointer does not address a
+    /// value or a place for a new one, in which case the changes before it have already been applied.
+    pub fn apply(&mut self, changes: &[Change<'a>]) -> Result<(), DeltaError> {
+        changes.iter().try_for_each(|change| apply(self, change))
+    }
+
+}
+
+fn diff<'a>(old: &Value, new: &Value<'a>, pointer: &mut String, changes: &mut Vec<Change<'a>>) {
+    if old == new {
+        return;
+    }
+    let mut child = |segment: &str, old: Option<&Value>, new: Option<&Value<'a>>, changes: &mut Vec<Change<'a>>| {
+        let len = pointer.len();
+        pointer.push('/');
+        pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+        match (old, new) {
+            (Some(old), Some(new)) => diff(old, new, pointer, changes),
+            (None, Some(new)) => changes.push(Change::Set(pointer.clone(), new.clone())),
+            (_, None) => changes.push(Change::Remove(pointer.clone())),
+        }
+        pointer.truncate(len);
+    };
+    match (old, new) {
+        (Value::Record(old), Value::Record(new)) => {
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                child(key, None, None, changes);
+            }
+            for (key, value) in new {
+                child(key, old.get(key), Some(value), changes);
+            }
+        },
+        (Value::Array(old), Value::Array(new)) => {
+            for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                child(&i.to_string(), Some(old), Some(new), changes);
+            }
+            for i in (new.len()..old.len()).rev() {
+                child(&i.to_string(), None, None, changes);
+            }
+            for (i, value) in new.iter().enumerate().skip(old.len()) {
+                child(&i.to_string(), None, Some(value), changes);
+            }
+        },
+        _ => changes.push(Change::Set(pointer.clone(), new.clone())),
+    }
+}
+
+fn apply<'a>(value: &mut Value<'a>, change: &Change<'a>) -> Result<(), DeltaError> {
+    let pointer = match change {
+        Change::Set(pointer, _) | Change::Remove(pointer) => pointer,
+    };
+    let missing = || DeltaError::Path(pointer.clone());
+    let (parent, segment) = match pointer.rfind('/') {
+        Some(i) => (pointer.get(..i).unwrap_or_default(), pointer.get(i + 1..).unwrap_or_default().replace("~1", "/").replace("~0", "~")),
+        None if pointer.is_empty() => match change {
+            Change::Set(_, new) => { *value = new.clone(); return Ok(()); },
+            Change::Remove(_) => return Err(missing()),
+        },
+        None => return Err(missing()),
+    };
+    match (value.pointer_mut(parent).ok_or_else(missing)?, change) {
+        (Value::Record(fields), Change::Set(_, new)) => { fields.insert(Cow::Owned(segment), new.clone()); },
+        (Value::Record(fields), Change::Remove(_)) => { fields.remove(segment.as_str()).ok_or_else(missing)?; },
+        (Value::Array(elements), Change::Set(_, new)) => match segment.parse::<usize>() {
+            Ok(i) if i == elements.len() => elements.push(new.clone()),
+            Ok(i) => *elements.get_mut(i).ok_or_else(missing)? = new.clone(),
+            _ => return Err(missing()),
+        },
+        (Value::Array(elements), Change::Remove(_)) => match segment.parse::<usize>() {
+            Ok(i) if i + 1 == elements.len() => { elements.pop(); },
+            _ => return Err(missing()),
+        },
+        (Value::Map(entries), change) => {
+            let position = entries.iter().position(|(key, _)| matches!(key, Value::Str(k) | Value::Symbol(k) if *k == segment));
+            match (position, change) {
+                (Some(i), Change::Set(_, new)) => { entries.get_mut(i).ok_or_else(missing)?.1 = new.clone(); },
+                (None, Change::Set(_, new)) => entries.push((Value::Str(Cow::Owned(segment)), new.clone())),
+                (Some(i), Change::Remove(_)) => { entries.remove(i); },
+                (None, Change::Remove(_)) => return Err(missing()),
+            }
+        },
+        _ => return Err(missing()),
+    }
+    Ok(())
+}
+
+impl<'a> Update<'a> {
+
+    /// The sequence number of the state after this update.
+    pub fn sequence(&self) -> u64 {
+        match self {
+            Update::Snapshot(sequence, _) | Update::Delta(sequence, _) => *sequence,
+        }
+    }
+
+    /// The update as a value, e.g. for encoding it. See the module documentation for its layout.
+    pub fn to_value(&self) -> Value<'a> {
+        let field = |key: &'static str, value| (Cow::Borrowed(key), value);
+        let (key, content) = match self {
+            Update::Snapshot(_, value) => ("snapshot", value.clone()),
+            Update::Delta(_, changes) => ("changes", Value::Array(changes.iter().map(|change| Value::Record(match change {
+                Change::Set(path, value) => BTreeMap::from([field("path", Value::Str(Cow::Owned(path.clone()))), field("value", value.clone())]),
+                Change::Remove(path) => BTreeMap::from([field("path", Value::Str(Cow::Owned(path.clone())))]),
+            })).collect())),
+        };
+        Value::Record(BTreeMap::from([field("sequence", Value::Int(Sign::Pos, self.sequence())), field(key, content)]))
+    }
+
+    /// Read an update which has been written with `to_value`.
+    pub fn from_value(value: Value<'a>) -> Result<Self, DeltaError> {
+        let mut fields = match value {
+            Value::Record(fields) => fields,
+            _ => return Err(DeltaError::Malformed("update needs to be a record")),
+        };
+        let sequence = match fields.remove("sequence") {
+            Some(Value::Int(Sign::Pos, sequence)) => sequence,
+            _ => return Err(DeltaError::Malformed("sequence needs to be a positive integer")),
+        };
+        match (fields.remove("snapshot"), fields.remove("changes")) {
+            (Some(snapshot), None) => Ok(Update::Snapshot(sequence, snapshot)),
+            (None, Some(Value::Array(changes))) => Ok(Update::Delta(sequence, changes.into_iter().map(|change| {
+                let mut fields = match change {
+                    Value::Record(fields) => fields,
+                    _ => return Err(DeltaError::Malformed("change needs to be a record")),
+                };
+                let path = match fields.remove("path") {
+                    Some(Value::Str(path)) => path.into_owned(),
+                    _ => return Err(DeltaError::Malformed("path needs to be a string")),
+                };
+                Ok(match fields.remove("value") {
+                    Some(value) => Change::Set(path, value),
+                    None => Change::Remove(path),
+                })
+            }).collect::<Result<_, _>>()?)),
+            _ => Err(DeltaError::Malformed("update needs either a snapshot or changes")),
+        }
+    }
+
+}
+
+/// Turns successive states into updates, see the module documentation.
+#[derive(Debug, Default, Clone)]
+pub struct StatePublisher {
+    /// The current state and its sequence number
+    current: Option<(u64, Value<'static>)>,
+}
+
+impl StatePublisher {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the value the current state and return the update which gets a subscriber there. This is a snapshot for the
+    /// first state and whenever it encodes smaller than the delta, e.g. because everything changed.
+    pub fn publish(&mut self, value: Value<'static>) -> Update<'static> {
+        let update = match &self.current {
+            None => Update::Snapshot(0, value.clone()),
+            Some((sequence, current)) => {
+                let delta = Update::Delta(sequence + 1, current.diff(&value));
+                let snapshot = Update::Snapshot(sequence + 1, value.clone());
+                if encoded_len(&delta) < encoded_len(&snapshot) { delta } else { snapshot }
+            },
+        };
+        self.current = Some((update.sequence(), value));
+        update
+    }
+
+    /// A snapshot of the current state, e.g. for subscribers which join late or got a `DeltaError::Gap`.
+    pub fn snapshot(&self) -> Option<Update<'static>> {
+        self.current.as_ref().map(|(sequence, value)| Update::Snapshot(*sequence, value.clone()))
+    }
+
+}
+
+fn encoded_len(update: &Update) -> usize {
+    Encoder::encode_to_vec(&update.to_value(), &mut Vec::new()).unwrap_or(usize::MAX)
+}
+
+/// Follows the state of a `StatePublisher` by applying its updates, see the module documentation.
+#[derive(Debug, Default, Clone)]
+pub struct StateSubscriber {
+    current: Option<(u64, Value<'static>)>,
+}
+
+impl StateSubscriber {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply the update. Snapshots replace the state unless they are older than it. Deltas fail with `DeltaError::Gap`
+    /// unless they follow the current state immediately, and with `DeltaError::Path` if they do not fit it, which both
+    /// leave the state untouched. Updates which are not newer than the current state are ignored.
+    pub fn apply(&mut self, update: Update) -> Result<(), DeltaError> {
+        match (&mut self.current, update) {
+            (Some((sequence, _)), update) if update.sequence() <= *sequence => {},
+            (_, Update::Snapshot(sequence, value)) => self.current = Some((sequence, value.into_owned())),
+            (Some((sequence, state)), Update::Delta(next, changes)) if next == *sequence + 1 => {
+                let mut updated = state.clone();
+                updated.apply(&changes.into_iter().map(Change::into_owned).collect::<Vec<_>>())?;
+                *state = updated;
+                *sequence = next;
+            },
+            (current, Update::Delta(next, _)) => return Err(DeltaError::Gap(current.as_ref().map(|(sequence, _)| *sequence), next)),
+        }
+        Ok(())
+    }
+
+    /// The current state, if a snapshot has been received.
+    pub fn state(&self) -> Option<&Value<'static>> {
+        self.current.as_ref().map(|(_, value)| value)
+    }
+
+    /// The sequence number of the current state, if a snapshot has been received.
+    pub fn sequence(&self) -> Option<u64> {
+        self.current.as_ref().map(|(sequence, _)| *sequence)
+    }
+
+}
+
+impl<'a> Change<'a> {
+
+    /// Copy all borrowed data so that the change is no longer tied to the lifetime of its source.
+    pub fn into_owned(self) -> Change<'static> {
+        match self {
+            Change::Set(pointer, value) => Change::Set(pointer, value.into_owned()),
+            Change::Remove(pointer) => Change::Remove(pointer),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Change, StatePublisher, StateSubscriber, Update};
+    use crate::error::DeltaError;
+    use crate::header::Sign;
+    use crate::value::Value;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn record(fields: Vec<(&'static str, Value<'static>)>) -> Value<'static> {
+        Value::Record(fields.into_iter().map(|(k, v)| (Cow::Borrowed(k), v)).collect::<BTreeMap<_, _>>())
+    }
+
+    fn int(v: u64) -> Value<'static> {
+        Value::Int(Sign::Pos, v)
+    }
+
+    fn str(v: &'static str) -> Value<'static> {
+        Value::Str(Cow::Borrowed(v))
+    }
+
+    #[test]
+    fn diff() {
+        let old = record(vec![
+            ("a/b", int(1)),
+            ("gone", Value::Null),
+            ("list", Value::Array(vec![int(1), int(2), int(3)])),
+            ("map", Value::Map(vec![(int(1), int(2))])),
+        ]);
+        let new = record(vec![
+            ("a/b", int(2)),
+            ("list", Value::Array(vec![int(1), int(5)])),
+            ("map", Value::Map(vec![(int(1), int(3))])),
+            ("new", Value::Array(vec![])),
+        ]);
+        let changes = old.diff(&new);
+        assert_eq!(vec![
+            Change::Remove("/gone".to_string()),
+            Change::Set("/a~1b".to_string(), int(2)),
+            Change::Set("/list/1".to_string(), int(5)),
+            Change::Remove("/list/2".to_string()),
+            Change::Set("/map".to_string(), Value::Map(vec![(int(1), int(3))])),
+            Change::Set("/new".to_string(), Value::Array(vec![])),
+        ], changes);
+        let mut patched = old.clone();
+        patched.apply(&changes).unwrap();
+        assert_eq!(new, patched);
+        patched.apply(&new.diff(&old)).unwrap();
+        assert_eq!(old, patched);
+        assert!(old.diff(&old).is_empty());
+        assert_eq!(vec![Change::Set("".to_string(), int(1))], old.diff(&int(1)));
+        // maps with string keys can be changed by pointer, too
+        let mut map = Value::Map(vec![(str("a"), int(1))]);
+        map.apply(&[Change::Set("/b".to_string(), int(2)), Change::Remove("/a".to_string())]).unwrap();
+        assert_eq!(Value::Map(vec![(str("b"), int(2))]), map);
+    }
+
+    #[test]
+    fn paths() {
+        let mut value = record(vec![("list", Value::Array(vec![int(1), int(2)]))]);
+        let error = |pointer: &str| Err(DeltaError::Path(pointer.to_string()));
+        assert_eq!(error("/missing/x"), value.apply(&[Change::Set("/missing/x".to_string(), int(1))]));
+        assert_eq!(error("/list/3"), value.apply(&[Change::Set("/list/3".to_string(), int(1))]));
+        assert_eq!(error("/list/0"), value.apply(&[Change::Remove("/list/0".to_string())]));
+        assert_eq!(error("/other"), value.apply(&[Change::Remove("/other".to_string())]));
+        assert_eq!(error(""), value.apply(&[Change::Remove("".to_string())]));
+        assert_eq!(error("list"), value.apply(&[Change::Set("list".to_string(), int(1))]));
+        assert_eq!(error("/list/0/x"), value.apply(&[Change::Set("/list/0/x".to_string(), int(1))]));
+    }
+
+    #[test]
+    fn sync() {
+        let state = |lives, toys: Vec<&'static str>| record(vec![
+            ("name", str("Jessica")),
+            ("lives", int(lives)),
+            ("toys", Value::Array(toys.into_iter().map(str).collect())),
+        ]);
+        let mut publisher = StatePublisher::new();
+        assert_eq!(None, publisher.snapshot());
+        let first = publisher.publish(state(9, vec!["mouse"]));
+        assert!(matches!(first, Update::Snapshot(0, _)));
+        let second = publisher.publish(state(8, vec!["mouse", "ball"]));
+        assert!(matches!(&second, Update::Delta(1, changes) if changes.len() == 2));
+        // a delta which would be larger than the state is sent as a snapshot
+        let third = publisher.publish(int(0));
+        assert!(matches!(third, Update::Snapshot(2, _)));
+        let fourth = publisher.publish(int(1));
+        assert!(matches!(fourth, Update::Snapshot(3, _)));
+
+        let mut subscriber = StateSubscriber::new();
+        assert_eq!(Err(DeltaError::Gap(None, 1)), subscriber.apply(second.clone()));
+        subscriber.apply(first.clone()).unwrap();
+        subscriber.apply(second.clone()).unwrap();
+        assert_eq!(Some(&state(8, vec!["mouse", "ball"])), subscriber.state());
+        // duplicates and stale snapshots are ignored
+        subscriber.apply(second.clone()).unwrap();
+        subscriber.apply(first).unwrap();
+        assert_eq!(Some(1), subscriber.sequence());
+        assert_eq!(Err(DeltaError::Gap(Some(1), 3)), subscriber.apply(Update::Delta(3, vec![])));
+        assert_eq!(Err(DeltaError::Path("/x/y".to_string())), subscriber.apply(Update::Delta(2, vec![Change::Set("/x/y".to_string(), int(1))])));
+        assert_eq!(Some(1), subscriber.sequence());
+        subscriber.apply(publisher.snapshot().unwrap()).unwrap();
+        assert_eq!((Some(3), Some(&int(1))), (subscriber.sequence(), subscriber.state()));
+
+        for update in [second, fourth, Update::Delta(4, vec![Change::Remove("/x".to_string())])] {
+            assert_eq!(update, Update::from_value(update.to_value()).unwrap());
+        }
+        assert_eq!(Err(DeltaError::Malformed("update needs either a snapshot or changes")),
+            Update::from_value(record(vec![("sequence", int(1))])));
+    }
+
+}
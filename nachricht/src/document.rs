@@ -0,0 +1,217 @@
+//! Documents consisting of several top-level values, e.g. the records of a log file. A document is simply the
+//! concatenation of its roots without any framing, since every value is self-delimiting. How the roots relate to each
+//! other is determined by `Roots` and needs to be known by the reader, the document does not record it:
+//!
+//! * `Roots::Independent`: every root is a message on its own with an empty symbol table. Roots can be decoded in any
+//!   order given their offset, which enables random access, appending to a document and splitting it. Every root can
+//!   also be read on its own by `Decoder::decode` or `PushDecoder`.
+//! * `Roots::Shared`: all roots share one symbol table, so symbols and record layouts are only transmitted once per
+//!   document instead of once per root. This yields smaller documents of many similar roots, but a root may refer to
+//!   symbols defined by any root before it, so decoding always has to start at the beginning of the document.
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//!
+//! let mut buf = Vec::new();
+//! let mut writer = DocumentWriter::new(&mut buf, Roots::Independent);
+//! writer.push(&Value::Symbol(Cow::Borrowed("first"))).unwrap();
+//! writer.push(&Value::Symbol(Cow::Borrowed("first"))).unwrap();
+//! let offsets = writer.offsets().to_vec();
+//!
+//! let document = Document::new(&buf, Roots::Independent);
+//! assert_eq!(offsets, document.offsets().unwrap());
+//! assert_eq!(Value::Symbol(Cow::Borrowed("first")), document.get(offsets[1]).unwrap());
+//! ```
+
+use crate::error::{DecodeError, DecoderError, LengthError};
+use crate::value::{Decoder, Encoder, Value};
+
+/// Whether the roots of a document share a symbol table, see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Roots {
+    Independent,
+    Shared,
+}
+
+/// Appends roots to a document.
+pub struct DocumentWriter<'w> {
+    encoder: Encoder<'w, Vec<u8>>,
+    roots: Roots,
+    /// The offset of every root so far and the length of the document
+    offsets: Vec<usize>,
+    len: usize,
+}
+
+impl<'w> DocumentWriter<'w> {
+
+    /// Create a writer which appends to the buffer. Offsets are relative to its current end.
+    pub fn new(buf: &'w mut Vec<u8>, roots: Roots) -> Self {
+        Self { encoder: Encoder::new(buf), roots, offsets: Vec::new(), len: 0 }
+    }
+
+    /// Append a root and return its offset.
+    pub fn push(&mut self, root: &Value) -> Result<usize, LengthError> {
+        if self.roots == Roots::Independent {
+            self.encoder.reset();
+        }
+        let offset = self.len;
+        self.len += self.encoder.encode_next(root)?;
+        self.offsets.push(offset);
+        Ok(offset)
+    }
+
+    /// The offsets of all roots so far, e.g. to store them in an index for random access.
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+
+}
+
+/// Reads the roots of a document.
+#[derive(Debug, Clone, Copy)]
+pub struct Document<'a> {
+    buf: &'a [u8],
+    roots: Roots,
+}
+
+impl<'a> Document<'a> {
+
+    pub fn new<B: ?Sized + AsRef<[u8]>>(buf: &'a B, roots: Roots) -> Self {
+        Self { buf: buf.as_ref(), roots }
+    }
+
+    /// Decode all roots in order.
+    pub fn decode(&self) -> Result<Vec<Value<'a>>, DecoderError> {
+        let mut roots = Vec::new();
+        self.walk(|decoder, _| {
+            roots.push(decoder.decode_next()?);
+            Ok(())
+        })?;
+        Ok(roots)
+    }
+
+    /// The offsets of all roots. They are found by skipping over the roots without decoding them.
+    pub fn offsets(&self) -> Result<Vec<usize>, DecoderError> {
+        let mut offsets = Vec::new();
+        self.walk(|decoder, offset| {
+            offsets.push(offset);
+            decoder.skip_value().map(drop)
+        })?;
+        Ok(offsets)
+    }
+
+    /// Decode the root at the given offset. With independent roots, only this root is read. With a shared symbol
+    /// table, all roots before it need to be skipped over first. Fails with `DecodeError::Eof` if no root starts at the
+    /// offset.
+    pub fn get(&self, offset: usize) -> Result<Value<'a>, DecoderError> {
+        match self.roots {
+            Roots::Independent => {
+                let root = self.buf.get(offset..).ok_or_else(|| DecodeError::Eof.at(self.buf.len()))?;
+                Decoder::new(root).decode_next().map_err(|e| e.shift(offset))
+            },
+            Roots::Shared => {
+                let mut decoder = Decoder::new(self.buf);
+                while decoder.position() < offset {
+                    decoder.skip_value()?;
+                }
+                if decoder.position() != offset {
+                    return Err(DecodeError::Eof.at(offset));
+                }
+                decoder.decode_next()
+            },
+        }
+    }
+
+    /// Call `f` with a decoder positioned at the start of every root and the offset of that root. `f` needs to consume
+    /// exactly that root.
+    fn walk(&self, mut f: impl FnMut(&mut Decoder<'a>, usize) -> Result<(), DecoderError>) -> Result<(), DecoderError> {
+        match self.roots {
+            Roots::Independent => {
+                let mut offset = 0;
+                while let Some(rest) = self.buf.get(offset..).filter(|rest| !rest.is_empty()) {
+                    // every root starts out with an empty symbol table
+                    let mut root = Decoder::new(rest);
+                    f(&mut root, offset).map_err(|e| e.shift(offset))?;
+                    offset += root.position();
+                }
+            },
+            Roots::Shared => {
+                let mut decoder = Decoder::new(self.buf);
+                while !decoder.remaining().is_empty() {
+                    let offset = decoder.position();
+                    f(&mut decoder, offset)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Document, DocumentWriter, Roots};
+    use crate::error::DecodeError;
+    use crate::header::Sign;
+    use crate::value::{Decoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn cat(name: &'static str, lives: u64) -> Value<'static> {
+        Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+            (Cow::Borrowed("lives"), Value::Int(Sign::Pos, lives)),
+            (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus"))),
+        ]))
+    }
+
+    fn write(roots: Roots) -> (Vec<u8>, Vec<usize>) {
+        let mut buf = vec![0xff];
+        let mut writer = DocumentWriter::new(&mut buf, roots);
+        for (name, lives) in [("Jessica", 9), ("Wantan", 7), ("Sphinx", 1)] {
+            writer.push(&cat(name, lives)).unwrap();
+        }
+        let offsets = writer.offsets().to_vec();
+        buf.remove(0);
+        (buf, offsets)
+    }
+
+    #[test]
+    fn independent() {
+        let (buf, offsets) = write(Roots::Independent);
+        let document = Document::new(&buf, Roots::Independent);
+        assert_eq!(offsets, document.offsets().unwrap());
+        assert_eq!(vec![cat("Jessica", 9), cat("Wantan", 7), cat("Sphinx", 1)], document.decode().unwrap());
+        assert_eq!(cat("Sphinx", 1), document.get(offsets[2]).unwrap());
+        // every root is a message on its own
+        assert_eq!(cat("Wantan", 7), Decoder::decode(&buf[offsets[1]..]).unwrap().0);
+        assert_eq!(DecodeError::Eof, document.get(buf.len()).unwrap_err().into_inner());
+    }
+
+    #[test]
+    fn shared() {
+        let (buf, offsets) = write(Roots::Shared);
+        let (independent, _) = write(Roots::Independent);
+        assert!(buf.len() < independent.len());
+        let document = Document::new(&buf, Roots::Shared);
+        assert_eq!(offsets, document.offsets().unwrap());
+        assert_eq!(vec![cat("Jessica", 9), cat("Wantan", 7), cat("Sphinx", 1)], document.decode().unwrap());
+        assert_eq!(cat("Sphinx", 1), document.get(offsets[2]).unwrap());
+        assert_eq!(DecodeError::Eof, document.get(offsets[2] - 1).unwrap_err().into_inner());
+        // later roots refer to the symbols of the first one
+        assert!(Document::new(&buf, Roots::Independent).get(offsets[1]).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let (buf, offsets) = write(Roots::Independent);
+        // positions are relative to the start of the document
+        let error = Document::new(&buf[..buf.len() - 1], Roots::Independent).decode().unwrap_err();
+        let root = Decoder::decode(&buf[offsets[2]..buf.len() - 1]).unwrap_err();
+        assert_eq!(root.shift(offsets[2]).to_string(), error.to_string());
+        let error = Document::new(&buf[..offsets[1] + 1], Roots::Independent).offsets().unwrap_err();
+        assert_eq!(DecodeError::Eof, error.into_inner());
+    }
+
+}
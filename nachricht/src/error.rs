@@ -10,6 +10,16 @@ impl DecoderError {
     pub fn into_inner(self) -> DecodeError {
         self.inner
     }
+
+    pub fn inner(&self) -> &DecodeError {
+        &self.inner
+    }
+
+    /// Make the position relative to a buffer which starts `by` bytes earlier
+    pub(crate) fn shift(mut self, by: usize) -> DecoderError {
+        self.at += by;
+        self
+    }
 }
 
 impl std::error::Error for DecoderError {
@@ -32,12 +42,27 @@ pub enum DecodeError {
     IllegalKey(&'static str),
     Length(u64),
     Allocation,
+    /// The buffer contains the given amount of bytes after the end of the message
+    Trailing(usize),
+    /// The message is nested deeper than the given maximum
+    Depth(usize),
+    /// The symbol table of the message exceeds the given amount of bytes
+    SymbolLimit(usize),
+    /// The estimated decoded size of the message exceeds the given amount of bytes
+    OutputLimit(usize),
+    /// The buffer ended while decoding the value of the given record field
+    TruncatedRecord(String),
 }
 
 impl DecodeError {
     pub fn at(self, at: usize) -> DecoderError {
         DecoderError { inner: self, at }
     }
+
+    /// Whether the error means that the input ended prematurely, i.e. that more input might make it go away.
+    pub fn is_eof(&self) -> bool {
+        matches!(self, DecodeError::Eof | DecodeError::TruncatedRecord(_))
+    }
 }
 
 impl From<std::str::Utf8Error> for DecodeError {
@@ -69,15 +94,42 @@ impl Display for DecodeError {
             DecodeError::InvalidRef(value) => write!(f, "Invalid reference {}", value),
             DecodeError::Length(value) => write!(f, "Length {} exceeds maximum {}", value, usize::MAX),
             DecodeError::Allocation => f.write_str("An allocation failed"),
+            DecodeError::Trailing(v) => write!(f, "{} bytes of trailing input after the end of the message", v),
             DecodeError::IllegalKey(v) => write!(f, "Record key needs to be a symbol but was {}", v),
+            DecodeError::Depth(v) => write!(f, "Nesting exceeds the maximum depth of {}", v),
+            DecodeError::SymbolLimit(v) => write!(f, "Symbol table exceeds the limit of {} bytes", v),
+            DecodeError::OutputLimit(v) => write!(f, "Decoded message exceeds the limit of {} bytes", v),
+            DecodeError::TruncatedRecord(v) => write!(f, "Unexpected end of buffer while decoding the value of record field `{}`", v),
         }
     }
 }
 
+/// A length which cannot be represented on the wire. This is the only way encoding into a `Vec` can fail.
+#[derive(Debug, PartialEq)]
+pub struct LengthError(pub usize);
+
+impl std::error::Error for LengthError {}
+
+impl Display for LengthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "Length {} exceeds maximum {}", self.0, u64::MAX)
+    }
+}
+
 #[derive(Debug)]
 pub enum EncodeError {
     Io(std::io::Error),
     Length(usize),
+    /// The output buffer is too small, the value needs the given amount of bytes
+    BufferTooSmall(usize),
+    /// A container produced on the fly did not yield the announced number of elements, which is given
+    Count(usize),
+}
+
+impl From<LengthError> for EncodeError {
+    fn from(e: LengthError) -> EncodeError {
+        EncodeError::Length(e.0)
+    }
 }
 
 impl From<std::io::Error> for EncodeError {
@@ -100,6 +152,312 @@ impl Display for EncodeError {
         match self {
             EncodeError::Io(e) => write!(f, "IO error {}", e),
             EncodeError::Length(value) => write!(f, "Length {} exceeds maximum {}", value, u64::MAX),
+            EncodeError::BufferTooSmall(value) => write!(f, "Buffer too small, {} bytes are required", value),
+            EncodeError::Count(value) => write!(f, "Container did not yield the announced {} elements", value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    Decode(DecoderError),
+    Malformed(&'static str),
+    Algorithm(String),
+    Invalid,
+}
+
+impl From<DecoderError> for SignatureError {
+    fn from(e: DecoderError) -> SignatureError {
+        SignatureError::Decode(e)
+    }
+}
+
+impl std::error::Error for SignatureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SignatureError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SignatureError::Decode(e) => write!(f, "Envelope could not be decoded: {}", e),
+            SignatureError::Malformed(v) => write!(f, "Malformed envelope: {}", v),
+            SignatureError::Algorithm(v) => write!(f, "Verifier does not support algorithm {}", v),
+            SignatureError::Invalid => f.write_str("Signature is invalid"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SharedError {
+    /// The reference with the given number occurs within the shared value it refers to
+    Cycle(u64),
+    /// The reference with the given number occurs before the definition of its shared value, or there is none
+    Undefined(u64),
+    /// A definition or reference does not have the expected layout
+    Malformed(&'static str),
+    /// References expand to more than the given number of values
+    Limit(usize),
+    /// The resolved value is nested deeper than the given maximum
+    Depth(usize),
+}
+
+impl std::error::Error for SharedError {}
+
+impl Display for SharedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SharedError::Cycle(v) => write!(f, "Shared value {} refers to itself", v),
+            SharedError::Undefined(v) => write!(f, "Shared value {} is not defined before it is referenced", v),
+            SharedError::Malformed(v) => write!(f, "Malformed shared value: {}", v),
+            SharedError::Limit(v) => write!(f, "References expand to more than {} values", v),
+            SharedError::Depth(v) => write!(f, "Resolved value exceeds the maximum depth of {}", v),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TranscodeError {
+    Decode(DecoderError),
+    Encode(EncodeError),
+}
+
+impl From<DecoderError> for TranscodeError {
+    fn from(e: DecoderError) -> TranscodeError {
+        TranscodeError::Decode(e)
+    }
+}
+
+impl From<EncodeError> for TranscodeError {
+    fn from(e: EncodeError) -> TranscodeError {
+        TranscodeError::Encode(e)
+    }
+}
+
+impl std::error::Error for TranscodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TranscodeError::Decode(e) => Some(e),
+            TranscodeError::Encode(e) => Some(e),
+        }
+    }
+}
+
+impl Display for TranscodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            TranscodeError::Decode(e) => write!(f, "Input could not be decoded: {}", e),
+            TranscodeError::Encode(e) => write!(f, "Output could not be encoded: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProtectError {
+    Decode(DecoderError),
+    /// There is no protected field at the given path
+    NotProtected(String),
+    /// The field at the given path could not be decrypted
+    Decrypt(String),
+}
+
+impl From<DecoderError> for ProtectError {
+    fn from(e: DecoderError) -> ProtectError {
+        ProtectError::Decode(e)
+    }
+}
+
+impl std::error::Error for ProtectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtectError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ProtectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ProtectError::Decode(e) => write!(f, "Decrypted field could not be decoded: {}", e),
+            ProtectError::NotProtected(v) => write!(f, "No protected field at {}", v),
+            ProtectError::Decrypt(v) => write!(f, "Field at {} could not be decrypted", v),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    Decode(DecoderError),
+    /// There is no value at the given pointer
+    Missing(String),
+    /// Only scalars other than symbols can be patched, but a value of the given type was encountered
+    Type(&'static str),
+    /// The new value can not be encoded in the given amount of bytes which the old one occupies
+    Size(usize),
+}
+
+impl From<DecoderError> for PatchError {
+    fn from(e: DecoderError) -> PatchError {
+        PatchError::Decode(e)
+    }
+}
+
+impl std::error::Error for PatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PatchError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            PatchError::Decode(e) => write!(f, "Message could not be decoded: {}", e),
+            PatchError::Missing(v) => write!(f, "No value at {}", v),
+            PatchError::Type(v) => write!(f, "Values of type {} can not be patched", v),
+            PatchError::Size(v) => write!(f, "New value does not fit into {} bytes", v),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    Decode(DecoderError),
+    /// The frame does not have the expected layout
+    Malformed(&'static str),
+    /// The checksum does not match the message
+    Checksum,
+    /// The message could not be decompressed
+    Decompress,
+}
+
+impl From<DecoderError> for FrameError {
+    fn from(e: DecoderError) -> FrameError {
+        FrameError::Decode(e)
+    }
+}
+
+impl std::error::Error for FrameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FrameError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            FrameError::Decode(e) => write!(f, "Frame could not be decoded: {}", e),
+            FrameError::Malformed(v) => write!(f, "Malformed frame: {}", v),
+            FrameError::Checksum => f.write_str("Checksum does not match the message"),
+            FrameError::Decompress => f.write_str("Message could not be decompressed"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KeyHashError {
+    /// The given distinct keys have the same hash
+    Collision(String, String),
+    /// The given hash does not belong to any name in the dictionary
+    Unknown(String),
+}
+
+impl std::error::Error for KeyHashError {}
+
+impl Display for KeyHashError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            KeyHashError::Collision(a, b) => write!(f, "Keys {} and {} have the same hash", a, b),
+            KeyHashError::Unknown(v) => write!(f, "Hashed key {} is not in the dictionary", v),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IndexError {
+    Decode(DecoderError),
+    /// The value does not have the layout of an index
+    Malformed(&'static str),
+}
+
+impl From<DecoderError> for IndexError {
+    fn from(e: DecoderError) -> IndexError {
+        IndexError::Decode(e)
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IndexError::Decode(e) => Some(e),
+            IndexError::Malformed(_) => None,
+        }
+    }
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            IndexError::Decode(e) => write!(f, "Indexed value could not be decoded: {}", e),
+            IndexError::Malformed(v) => write!(f, "Malformed index: {}", v),
+        }
+    }
+}
+
+/// A value which cannot be converted to JSON under the chosen policy, identified by its JSON pointer
+#[cfg(feature = "json")]
+#[derive(Debug, PartialEq)]
+pub enum JsonError {
+    Bytes(String),
+    /// A map key which is neither a string nor a symbol or which occurs more than once
+    MapKey(String),
+    /// A non-finite float or a negative integer below `i64::MIN`
+    Number(String),
+}
+
+#[cfg(feature = "json")]
+impl std::error::Error for JsonError {}
+
+#[cfg(feature = "json")]
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            JsonError::Bytes(v) => write!(f, "Bytes at {} cannot be converted to JSON", v),
+            JsonError::MapKey(v) => write!(f, "Map at {} has keys which cannot be converted to JSON", v),
+            JsonError::Number(v) => write!(f, "Number at {} cannot be represented in JSON", v),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeltaError {
+    /// A delta for the given sequence number does not follow the current one, if any. A snapshot is needed.
+    Gap(Option<u64>, u64),
+    /// The change at the given pointer does not fit the value
+    Path(String),
+    /// The value is not an update
+    Malformed(&'static str),
+}
+
+impl std::error::Error for DeltaError {}
+
+impl Display for DeltaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            DeltaError::Gap(Some(current), next) => write!(f, "Delta {} does not follow state {}", next, current),
+            DeltaError::Gap(None, next) => write!(f, "Delta {} arrived before any snapshot", next),
+            DeltaError::Path(p) => write!(f, "Change at {} does not fit the value", p),
+            DeltaError::Malformed(m) => write!(f, "Malformed update: {}", m),
         }
     }
 }
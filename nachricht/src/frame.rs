@@ -0,0 +1,287 @@
+//! Put messages on a byte stream such as a socket. Values are self-delimiting, but a transport often needs more: the
+//! length of a message before decoding it, compression and a checksum. `MessageWriter` applies these layers in one
+//! pass and `MessageReader` peels them off again. A frame is made of `nachricht` headers itself:
+//!
+//! ```text
+//! Bin(n) <n bytes of the possibly compressed message> [Bin(m) <m bytes of the checksum>]
+//! ```
+//!
+//! The checksum covers the bytes of the message as transmitted, i.e. after compression. Both sides need to agree on
+//! the `FrameOptions` since the frame does not record them.
+//!
+//! ```
+//! use nachricht::*;
+//!
+//! struct Sum;
+//!
+//! impl Digest for Sum {
+//!     fn digest(&self, message: &[u8]) -> Vec<u8> {
+//!         vec![message.iter().fold(0u8, |acc, b| acc.wrapping_add(*b))]
+//!     }
+//! }
+//!
+//! let options = FrameOptions { checksum: Some(&Sum), ..Default::default() };
+//! let mut writer = MessageWriter::with_options(Vec::new(), options);
+//! writer.write(&Value::Bool(true)).unwrap();
+//! writer.write(&Value::Null).unwrap();
+//! let stream = writer.into_inner();
+//!
+//! let mut reader = MessageReader::with_options(options);
+//! let (first, c) = reader.read(&stream).unwrap();
+//! assert_eq!(Value::Bool(true), first);
+//! assert_eq!(Value::Null, reader.read(&stream[c..]).unwrap().0);
+//! ```
+//!
+//! This crate does not ship any compression or checksum algorithms. Bring your own by implementing `Compressor` and
+//! `Digest`.
+
+use crate::error::{DecodeError, EncodeError, FrameError};
+use crate::header::Header;
+use crate::redact::Digest;
+use crate::value::{Decoder, Encoder, Value};
+use std::io::Write;
+
+/// Space reserved in front of a message for its length prefix, which is at most this long
+const PREFIX: usize = 9;
+
+/// Compresses messages before they are framed.
+pub trait Compressor {
+    /// Append the compressed input to the output.
+    fn compress(&self, input: &[u8], output: &mut Vec<u8>);
+    /// Append the decompressed input to the output. Returns false if the input is invalid.
+    fn decompress(&self, input: &[u8], output: &mut Vec<u8>) -> bool;
+}
+
+/// The layers a frame consists of besides the message and its length. The defaults add neither.
+#[derive(Clone, Copy, Default)]
+pub struct FrameOptions<'o> {
+    /// Compress the encoded message
+    pub compressor: Option<&'o dyn Compressor>,
+    /// Append a checksum of the transmitted message, e.g. a CRC
+    pub checksum: Option<&'o dyn Digest>,
+}
+
+/// Writes framed messages, see the module documentation.
+pub struct MessageWriter<'o, W> {
+    writer: W,
+    options: FrameOptions<'o>,
+    /// The frame being assembled, starting with space for the length prefix. It is kept to avoid allocating for every
+    /// message.
+    frame: Vec<u8>,
+    /// The uncompressed message if there is a compressor
+    scratch: Vec<u8>,
+}
+
+impl<'o, W: Write> MessageWriter<'o, W> {
+
+    /// Create a writer which only prefixes messages with their length.
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, FrameOptions::default())
+    }
+
+    pub fn with_options(writer: W, options: FrameOptions<'o>) -> Self {
+        Self { writer, options, frame: Vec::new(), scratch: Vec::new() }
+    }
+
+    /// Encode the value and write it as a frame. Returns the size of the frame.
+    pub fn write(&mut self, value: &Value) -> Result<usize, EncodeError> {
+        self.frame.clear();
+        self.frame.resize(PREFIX, 0);
+        match self.options.compressor {
+            Some(compressor) => {
+                self.scratch.clear();
+                Encoder::encode_to_vec(value, &mut self.scratch)?;
+                compressor.compress(&self.scratch, &mut self.frame);
+            },
+            None => { Encoder::encode_to_vec(value, &mut self.frame)?; },
+        }
+        let length = Header::Bin(self.frame.len() - PREFIX);
+        length.validate()?;
+        let start = PREFIX - length.encoded_len();
+        self.frame[start..PREFIX].copy_from_slice(&length.to_bytes()[..length.encoded_len()]);
+        if let Some(checksum) = self.options.checksum {
+            let digest = checksum.digest(&self.frame[PREFIX..]);
+            Header::Bin(digest.len()).encode(&mut self.frame)?;
+            self.frame.extend_from_slice(&digest);
+        }
+        self.writer.write_all(&self.frame[start..])?;
+        Ok(self.frame.len() - start)
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<(), EncodeError> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+}
+
+/// Reads framed messages, see the module documentation.
+pub struct MessageReader<'o> {
+    options: FrameOptions<'o>,
+    /// The decompressed message, which the decoded value borrows from
+    buf: Vec<u8>,
+}
+
+impl<'o> MessageReader<'o> {
+
+    /// Create a reader for frames which only consist of the length prefix and the message.
+    pub fn new() -> Self {
+        Self::with_options(FrameOptions::default())
+    }
+
+    pub fn with_options(options: FrameOptions<'o>) -> Self {
+        Self { options, buf: Vec::new() }
+    }
+
+    /// Read the frame at the start of the input. Returns the message and the size of the frame. If the input ends
+    /// within the frame, `DecodeError::Eof` is returned, so the caller can retry once more input is available. The
+    /// message borrows from the input, or from the reader if it had to be decompressed.
+    pub fn read<'s>(&'s mut self, input: &'s [u8]) -> Result<(Value<'s>, usize), FrameError> {
+        let (message, mut c) = Self::bytes(input, 0)?;
+        if let Some(checksum) = self.options.checksum {
+            let (expected, d) = Self::bytes(input, c)?;
+            if checksum.digest(message) != expected {
+                return Err(FrameError::Checksum);
+            }
+            c = d;
+        }
+        let message = match self.options.compressor {
+            Some(compressor) => {
+                self.buf.clear();
+                if !compressor.decompress(message, &mut self.buf) {
+                    return Err(FrameError::Decompress);
+                }
+                &self.buf
+            },
+            None => message,
+        };
+        let mut decoder = Decoder::new(message);
+        let value = decoder.decode_next()?;
+        decoder.finish()?;
+        Ok((value, c))
+    }
+
+    /// The bytes of the `Bin` header at the given position and the position after them
+    fn bytes(input: &[u8], pos: usize) -> Result<(&[u8], usize), FrameError> {
+        let (header, c) = Header::decode(input.get(pos..).unwrap_or_default()).map_err(|e| e.at(pos))?;
+        let len = match header {
+            Header::Bin(len) => len,
+            _ => return Err(FrameError::Malformed("frame needs to consist of bytes")),
+        };
+        let start = pos + c;
+        let bytes = input.get(start..).and_then(|rest| rest.get(..len)).ok_or_else(|| DecodeError::Eof.at(input.len()))?;
+        Ok((bytes, start + len))
+    }
+
+}
+
+impl<'o> Default for MessageReader<'o> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Compressor, FrameOptions, MessageReader, MessageWriter};
+    use crate::error::{DecodeError, FrameError};
+    use crate::header::{Header, Sign};
+    use crate::redact::Digest;
+    use crate::value::{Encoder, Value};
+    use std::borrow::Cow;
+
+    /// Not a real compression, but good enough to test the plumbing
+    struct Rle;
+
+    impl Compressor for Rle {
+        fn compress(&self, input: &[u8], output: &mut Vec<u8>) {
+            let mut rest = input;
+            while let Some(&b) = rest.first() {
+                let n = rest.iter().take(255).take_while(|&&c| c == b).count();
+                output.extend([n as u8, b]);
+                rest = &rest[n..];
+            }
+        }
+        fn decompress(&self, input: &[u8], output: &mut Vec<u8>) -> bool {
+            input.chunks(2).all(|run| match run {
+                [n, b] => { output.extend(std::iter::repeat(*b).take(*n as usize)); true },
+                _ => false,
+            })
+        }
+    }
+
+    struct Sum;
+
+    impl Digest for Sum {
+        fn digest(&self, message: &[u8]) -> Vec<u8> {
+            vec![message.iter().fold(0u8, |acc, b| acc.wrapping_add(*b))]
+        }
+    }
+
+    fn value() -> Value<'static> {
+        Value::Array(vec![Value::Bytes(Cow::Owned(vec![0; 1000])), Value::Int(Sign::Neg, 7)])
+    }
+
+    #[test]
+    fn layers() {
+        let all = FrameOptions { compressor: Some(&Rle), checksum: Some(&Sum) };
+        let compressed = FrameOptions { compressor: Some(&Rle), ..Default::default() };
+        let checked = FrameOptions { checksum: Some(&Sum), ..Default::default() };
+        for options in [FrameOptions::default(), compressed, checked, all] {
+            let mut writer = MessageWriter::with_options(Vec::new(), options);
+            let c = writer.write(&value()).unwrap();
+            let d = writer.write(&Value::Null).unwrap();
+            let stream = writer.into_inner();
+            assert_eq!(c + d, stream.len());
+            let mut reader = MessageReader::with_options(options);
+            assert_eq!((value(), c), reader.read(&stream).unwrap());
+            assert_eq!((Value::Null, d), reader.read(&stream[c..]).unwrap());
+        }
+        // without options, a frame is just the message as bytes
+        let mut message = Vec::new();
+        Encoder::encode(&value(), &mut message).unwrap();
+        let mut frame = Vec::new();
+        Header::Bin(message.len()).encode(&mut frame).unwrap();
+        frame.extend_from_slice(&message);
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write(&value()).unwrap();
+        assert_eq!(frame, writer.into_inner());
+        let mut writer = MessageWriter::with_options(Vec::new(), all);
+        assert!(writer.write(&value()).unwrap() < 30);
+    }
+
+    fn decode_error<T>(result: Result<T, FrameError>) -> Option<DecodeError> {
+        match result {
+            Err(FrameError::Decode(e)) => Some(e.into_inner()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let options = FrameOptions { compressor: Some(&Rle), checksum: Some(&Sum) };
+        let mut writer = MessageWriter::with_options(Vec::new(), options);
+        writer.write(&value()).unwrap();
+        let mut stream = writer.into_inner();
+        let mut reader = MessageReader::with_options(options);
+        for len in 0..stream.len() {
+            assert_eq!(Some(DecodeError::Eof), decode_error(reader.read(&stream[..len])));
+        }
+        assert_eq!(Some(DecodeError::Trailing(1)), decode_error(MessageReader::new().read(&[0x07, 0x00, 0x00])));
+        let last = stream.len() - 1;
+        stream[last] ^= 1;
+        assert!(matches!(reader.read(&stream), Err(FrameError::Checksum)));
+        stream[last] ^= 1;
+        stream[2] = 0;
+        stream[last] = Sum.digest(&stream[1..last - 1])[0];
+        assert!(decode_error(reader.read(&stream)).is_some());
+        assert!(matches!(reader.read(&[0x22]), Err(FrameError::Malformed(_))));
+        let options = FrameOptions { compressor: Some(&Rle), ..Default::default() };
+        assert!(matches!(MessageReader::with_options(options).read(&[0x06, 0x01]), Err(FrameError::Decompress)));
+    }
+
+}
@@ -6,13 +6,13 @@
 //! depends on the code: it can either define the value of the whole field or the length of the
 //! field's content.
 
-use crate::error::{DecodeError, EncodeError};
-use std::convert::TryFrom;
+use crate::error::{DecodeError, EncodeError, LengthError};
 use std::io::Write;
 
 /// Define codes here as enum variants aren't types (yet)
 #[repr(u8)]
 #[derive(Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 enum Code {
     BIN = 0,
     INT = 1,
@@ -36,24 +36,20 @@ impl Code {
         }
     }
 
-}
-
-impl TryFrom<u8> for Code {
-    type Error = ();
-
-    fn try_from(v: u8) -> Result<Self, Self::Error> {
-        match v {
-            x if x == Code::BIN as u8 => Ok(Code::BIN),
-            x if x == Code::INT as u8 => Ok(Code::INT),
-            x if x == Code::STR as u8 => Ok(Code::STR),
-            x if x == Code::SYM as u8 => Ok(Code::SYM),
-            x if x == Code::ARR as u8 => Ok(Code::ARR),
-            x if x == Code::REC as u8 => Ok(Code::REC),
-            x if x == Code::MAP as u8 => Ok(Code::MAP),
-            x if x == Code::REF as u8 => Ok(Code::REF),
-            _ => Err(()),
+    /// The code in the upper three bits of a lead byte
+    const fn from_lead(v: u8) -> Self {
+        match v & 0b111 {
+            0 => Code::BIN,
+            1 => Code::INT,
+            2 => Code::STR,
+            3 => Code::SYM,
+            4 => Code::ARR,
+            5 => Code::REC,
+            6 => Code::MAP,
+            _ => Code::REF,
         }
     }
+
 }
 
 // sz values
@@ -75,7 +71,7 @@ const NEG: u8 = 1;
 pub enum Sign { Pos, Neg }
 
 impl Sign {
-    fn code(&self) -> u8 {
+    const fn code(&self) -> u8 {
         match *self {
             Sign::Pos => POS,
             Sign::Neg => NEG,
@@ -143,36 +139,140 @@ impl Header {
         }
     }
 
+    /// The kind of value which starts with this header, as reported by `DecodeError::IllegalKey`.
+    pub(crate) fn kind(&self) -> &'static str {
+        match *self {
+            Header::Null      => "null",
+            Header::True | Header::False => "bool",
+            Header::F32       => "f32",
+            Header::F64       => "f64",
+            Header::Bin(_)    => "bytes",
+            Header::Int(_, _) => "integer",
+            Header::Str(_)    => "string",
+            Header::Sym(_)    => "symbol",
+            Header::Arr(_)    => "array",
+            Header::Rec(_)    => "record",
+            Header::Map(_)    => "map",
+            Header::Ref(_)    => "reference",
+        }
+    }
+
     /// Returns the number of written bytes
     pub fn encode<W: Write>(&self, w: &mut W) -> Result<usize, EncodeError> {
+        self.validate()?;
+        let len = self.encoded_len();
+        w.write_all(&self.to_bytes()[..len])?;
+        Ok(len)
+    }
+
+    /// Returns the number of bytes `encode` writes for this header. Unlike `encode`, this can be used in constant
+    /// expressions.
+    pub const fn encoded_len(&self) -> usize {
         match *self {
-            Header::Null                    => { w.write_all(&[self.code_bits() << self.shift() | NIL])?; Ok(1) },
-            Header::True                    => { w.write_all(&[self.code_bits() << self.shift() | TRU])?; Ok(1) },
-            Header::False                   => { w.write_all(&[self.code_bits() << self.shift() | FAL])?; Ok(1) },
-            Header::F32                     => { w.write_all(&[self.code_bits() << self.shift() | F32])?; Ok(1) },
-            Header::F64                     => { w.write_all(&[self.code_bits() << self.shift() | F64])?; Ok(1) },
-            Header::Int(Sign::Neg, 0)       => { Header::Int(Sign::Pos, 0).encode(w) },
-            Header::Int(Sign::Pos, i)       => self.encode_long_header(i, w),
-            Header::Int(Sign::Neg, i)       => self.encode_long_header(i - 1, w),
-            Header::Bin(i)
-                | Header::Str(i)
-                | Header::Sym(i)
-                | Header::Arr(i)
-                | Header::Rec(i)
-                | Header::Map(i)
-                | Header::Ref(i)            => self.encode_long_header(Self::to_u64(i)?, w)
+            Header::Null | Header::True | Header::False | Header::F32 | Header::F64 => 1,
+            _ if self.payload() < self.code().sz_limit() as u64 => 1,
+            _ => 1 + Self::size(self.payload()) as usize,
+        }
+    }
+
+    /// Returns the encoded header in the first `encoded_len` bytes of the array. Unlike `encode`, this can be used in
+    /// constant expressions, see `encode_headers!`.
+    pub const fn to_bytes(&self) -> [u8; 9] {
+        if let Header::Int(Sign::Neg, 0) = *self {
+            return Header::Int(Sign::Pos, 0).to_bytes();
+        }
+        let mut buf = [0u8; 9];
+        let lead = self.code_bits() << self.shift();
+        let sz = match *self {
+            Header::Null  => NIL,
+            Header::True  => TRU,
+            Header::False => FAL,
+            Header::F32   => F32,
+            Header::F64   => F64,
+            _ => {
+                let i = self.payload();
+                let limit = self.code().sz_limit();
+                let offset = match *self { Header::Bin(_) => 5, _ => 0 };
+                if i < limit as u64 {
+                    i as u8 + offset
+                } else {
+                    let sz = Self::size(i);
+                    let bytes = i.to_be_bytes();
+                    let mut j = 0;
+                    while j < sz as usize {
+                        buf[1 + j] = bytes[8 - sz as usize + j];
+                        j += 1;
+                    }
+                    sz + limit + offset - 1
+                }
+            },
+        };
+        buf[0] = lead | sz;
+        buf
+    }
+
+    /// Like `to_bytes`, but with the value spread over exactly `len` bytes including the lead byte, even if fewer would
+    /// suffice. Decoders accept such an inefficient encoding like any other, which allows replacing a value in place
+    /// with one that is shorter. Returns `None` if the header does not fit into `len` bytes or if it does not have a
+    /// value which could be spread.
+    pub fn to_bytes_padded(&self, len: usize) -> Option<[u8; 9]> {
+        let header = match *self {
+            Header::Int(Sign::Neg, 0) => Header::Int(Sign::Pos, 0),
+            header => header,
+        };
+        if len == header.encoded_len() {
+            return Some(header.to_bytes());
+        }
+        if matches!(header, Header::Null | Header::True | Header::False | Header::F32 | Header::F64) || len < header.encoded_len() || len > 9 {
+            return None;
+        }
+        let mut buf = [0u8; 9];
+        let extra = len - 1;
+        buf[1..len].copy_from_slice(&header.payload().to_be_bytes()[8 - extra..]);
+        let offset = match header { Header::Bin(_) => 5, _ => 0 };
+        buf[0] = header.code_bits() << header.shift() | (extra as u8 + header.code().sz_limit() + offset - 1);
+        Some(buf)
+    }
+
+    /// The total number of bytes needed for the headers, used by `encode_headers!`.
+    #[doc(hidden)]
+    pub const fn encoded_len_of(headers: &[Header]) -> usize {
+        let mut len = 0;
+        let mut i = 0;
+        while i < headers.len() {
+            len += headers[i].encoded_len();
+            i += 1;
+        }
+        len
+    }
+
+    /// Concatenate the encoded headers, used by `encode_headers!`.
+    #[doc(hidden)]
+    pub const fn encode_all<const N: usize>(headers: &[Header]) -> [u8; N] {
+        let mut buf = [0u8; N];
+        let mut pos = 0;
+        let mut i = 0;
+        while i < headers.len() {
+            let bytes = headers[i].to_bytes();
+            let mut j = 0;
+            while j < headers[i].encoded_len() {
+                buf[pos] = bytes[j];
+                pos += 1;
+                j += 1;
+            }
+            i += 1;
         }
+        assert!(pos == N, "buffer size does not match the encoded headers");
+        buf
     }
 
     /// Returns the decoded header and the number of consumed bytes
+    #[deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable)]
     pub fn decode<B: ?Sized + AsRef<[u8]>>(buf: &B) -> Result<(Self, usize), DecodeError> {
         let shift = 5;
-        let buf = buf.as_ref();
-        if buf.len() < 1 {
-            return Err(DecodeError::Eof);
-        }
-        let code = (buf[0] >> shift).try_into().unwrap();
-        let sz = buf[0] & ((1 << shift) - 1);
+        let (lead, buf) = buf.as_ref().split_first().ok_or(DecodeError::Eof)?;
+        let code = Code::from_lead(*lead >> shift);
+        let sz = lead & ((1 << shift) - 1);
         match code {
             Code::BIN => {
                 match sz {
@@ -181,61 +281,60 @@ impl Header {
                     FAL => Ok((Header::False, 1)),
                     F32 => Ok((Header::F32,   1)),
                     F64 => Ok((Header::F64,   1)),
-                    x => Self::decode_u64(&buf[1..], x - 5, Code::BIN.sz_limit()).and_then(|(i, c)| Ok((Header::Bin(Self::to_usize(i)?), c + 1))),
+                    x => Self::decode_u64(buf, x - 5, Code::BIN.sz_limit()).and_then(|(i, c)| Ok((Header::Bin(Self::to_usize(i)?), c + 1))),
                 }
             },
             Code::INT => {
-                let sign = sz >> (shift - 1);
+                let sign = if sz >> (shift - 1) == POS { Sign::Pos } else { Sign::Neg };
                 let sz = sz & ((1 << (shift - 1)) - 1);
-                match sign {
-                    POS => Self::decode_u64(&buf[1..], sz, Code::INT.sz_limit()).map(|(i, c)| (Header::Int(Sign::Pos, i), c + 1)),
-                    NEG => Self::decode_u64(&buf[1..], sz, Code::INT.sz_limit()).map(|(i, c)| (Header::Int(Sign::Neg, i.saturating_add(1)), c + 1)),
-                    _   => unreachable!(),
-                }
+                Self::decode_u64(buf, sz, Code::INT.sz_limit()).map(|(i, c)| match sign {
+                    Sign::Pos => (Header::Int(Sign::Pos, i), c + 1),
+                    Sign::Neg => (Header::Int(Sign::Neg, i.saturating_add(1)), c + 1),
+                })
             },
-            Code::STR => Self::decode_u64(&buf[1..], sz, Code::STR.sz_limit()).and_then(|(i, c)| Ok((Header::Str(Self::to_usize(i)?), c + 1))),
-            Code::SYM => Self::decode_u64(&buf[1..], sz, Code::SYM.sz_limit()).and_then(|(i, c)| Ok((Header::Sym(Self::to_usize(i)?), c + 1))),
-            Code::ARR => Self::decode_u64(&buf[1..], sz, Code::ARR.sz_limit()).and_then(|(i, c)| Ok((Header::Arr(Self::to_usize(i)?), c + 1))),
-            Code::REC => Self::decode_u64(&buf[1..], sz, Code::REC.sz_limit()).and_then(|(i, c)| Ok((Header::Rec(Self::to_usize(i)?), c + 1))),
-            Code::MAP => Self::decode_u64(&buf[1..], sz, Code::MAP.sz_limit()).and_then(|(i, c)| Ok((Header::Map(Self::to_usize(i)?), c + 1))),
-            Code::REF => Self::decode_u64(&buf[1..], sz, Code::REF.sz_limit()).and_then(|(i, c)| Ok((Header::Ref(Self::to_usize(i)?), c + 1))),
+            Code::STR => Self::decode_u64(buf, sz, Code::STR.sz_limit()).and_then(|(i, c)| Ok((Header::Str(Self::to_usize(i)?), c + 1))),
+            Code::SYM => Self::decode_u64(buf, sz, Code::SYM.sz_limit()).and_then(|(i, c)| Ok((Header::Sym(Self::to_usize(i)?), c + 1))),
+            Code::ARR => Self::decode_u64(buf, sz, Code::ARR.sz_limit()).and_then(|(i, c)| Ok((Header::Arr(Self::to_usize(i)?), c + 1))),
+            Code::REC => Self::decode_u64(buf, sz, Code::REC.sz_limit()).and_then(|(i, c)| Ok((Header::Rec(Self::to_usize(i)?), c + 1))),
+            Code::MAP => Self::decode_u64(buf, sz, Code::MAP.sz_limit()).and_then(|(i, c)| Ok((Header::Map(Self::to_usize(i)?), c + 1))),
+            Code::REF => Self::decode_u64(buf, sz, Code::REF.sz_limit()).and_then(|(i, c)| Ok((Header::Ref(Self::to_usize(i)?), c + 1))),
         }
     }
 
+    /// The value of the header as written on the wire. Not meaningful for headers without a value.
     #[inline]
-    fn encode_long_header<W: Write>(&self, i: u64, w: &mut W) -> Result<usize, EncodeError> {
-        let limit = self.code().sz_limit();
-        let offset = match *self { Header::Bin(_) => 5, _ => 0 };
-        if i < limit as u64 {
-            w.write_all(&[self.code_bits() << self.shift() | i as u8 + offset])?;
-            Ok(1)
-        } else {
-            let sz = Self::size(i);
-            let buf = i.to_be_bytes();
-            w.write_all(&[self.code_bits() << self.shift() | (sz + limit + offset - 1)])?;
-            w.write_all(&buf[buf.len() - sz as usize ..])?;
-            Ok(1 + sz as usize)
+    const fn payload(&self) -> u64 {
+        match *self {
+            Header::Null | Header::True | Header::False | Header::F32 | Header::F64 => 0,
+            Header::Int(Sign::Neg, 0) => 0,
+            Header::Int(Sign::Pos, i) => i,
+            Header::Int(Sign::Neg, i) => i - 1,
+            Header::Bin(i)
+                | Header::Str(i)
+                | Header::Sym(i)
+                | Header::Arr(i)
+                | Header::Rec(i)
+                | Header::Map(i)
+                | Header::Ref(i) => i as u64,
         }
     }
 
+    /// The payload follows in `sz - limit + 1` bytes if it does not fit into `sz`. Since `sz` has at most five bits
+    /// and every limit is at least 24 below that, these are at most eight bytes.
     #[inline]
+    #[deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable)]
     fn decode_u64(buf: &[u8], sz: u8, limit: u8) -> Result<(u64, usize), DecodeError> {
         if sz < limit {
             Ok((sz as u64, 0))
         } else {
-            let bytes = sz as usize - limit as usize + 1;
-            if buf.len() < bytes {
-                Err(DecodeError::Eof)
-            } else {
-                let mut tmp = [0u8; 8];
-                tmp[8 - bytes..].copy_from_slice(&buf[..bytes]);
-                Ok((<u64>::from_be_bytes(tmp), bytes))
-            }
+            let bytes = (sz - limit) as usize + 1;
+            let value = buf.get(..bytes).ok_or(DecodeError::Eof)?.iter().fold(0u64, |acc, b| acc << 8 | *b as u64);
+            Ok((value, bytes))
         }
     }
 
     #[inline]
-    fn code(&self) -> Code {
+    const fn code(&self) -> Code {
         match *self {
             Header::Null | Header::True | Header::False | Header::F32 | Header::F64 | Header::Bin(_) => Code::BIN,
             Header::Int(_,_)                                                                         => Code::INT,
@@ -249,7 +348,7 @@ impl Header {
     }
 
     #[inline]
-    fn code_bits(&self) -> u8 {
+    const fn code_bits(&self) -> u8 {
         match *self {
             Header::Int(s, _) => ((self.code() as u8) << 1) | s.code(),
             _                 => self.code() as u8,
@@ -257,7 +356,7 @@ impl Header {
     }
 
     #[inline]
-    fn shift(&self) -> u8 {
+    const fn shift(&self) -> u8 {
         match *self {
             Header::Int(_,_) => 4,
             _                => 5,
@@ -266,7 +365,7 @@ impl Header {
 
     /// Returns the number of bytes needed to encode this value
     #[inline]
-    fn size(value: u64) -> u8 {
+    const fn size(value: u64) -> u8 {
         if value < 1 << 8 {
             1
         } else if value < 1 << 16 {
@@ -292,12 +391,40 @@ impl Header {
     }
 
     #[inline]
-    fn to_u64(value: usize) -> Result<u64, EncodeError> {
-        u64::try_from(value).map_err(|_| EncodeError::Length(value))
+    fn to_u64(value: usize) -> Result<u64, LengthError> {
+        u64::try_from(value).map_err(|_| LengthError(value))
+    }
+
+    /// Check that the value of the header can be represented on the wire, which `to_bytes` cannot do.
+    pub(crate) fn validate(&self) -> Result<(), LengthError> {
+        if let Header::Bin(i) | Header::Str(i) | Header::Sym(i) | Header::Arr(i) | Header::Rec(i) | Header::Map(i) | Header::Ref(i) = *self {
+            Self::to_u64(i)?;
+        }
+        Ok(())
     }
 
 }
 
+/// Encode headers at compile time and return them as a `&'static [u8; N]`. Together with `Header::to_bytes`, this
+/// allows senders without an allocator or a runtime encoder to compose messages from constant fragments. Payloads such
+/// as the bytes of a string have to be appended by the caller.
+///
+/// ```
+/// use nachricht::*;
+///
+/// const READY: &[u8] = encode_headers!(Header::Arr(2), Header::True, Header::Int(Sign::Neg, 300));
+/// assert_eq!(&[0x82, 0x01, 0x39, 0x01, 0x2b], READY);
+/// assert_eq!(Value::Array(vec![Value::Bool(true), Value::Int(Sign::Neg, 300)]), Decoder::decode(READY).unwrap().0);
+/// ```
+#[macro_export]
+macro_rules! encode_headers {
+    ($($header:expr),* $(,)?) => {{
+        const HEADERS: &[$crate::Header] = &[$($header),*];
+        const BYTES: [u8; $crate::Header::encoded_len_of(HEADERS)] = $crate::Header::encode_all(HEADERS);
+        &BYTES
+    }};
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Sign, Header};
@@ -314,6 +441,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn padded() {
+        for header in [Header::Int(Sign::Pos, 3), Header::Int(Sign::Neg, 300), Header::Int(Sign::Neg, 0), Header::Bin(2), Header::Str(30), Header::Ref(u32::MAX as usize)] {
+            assert_eq!(Some(header.to_bytes()), header.to_bytes_padded(header.encoded_len()));
+            assert_eq!(None, header.to_bytes_padded(header.encoded_len() - 1));
+            assert_eq!(None, header.to_bytes_padded(10));
+            for len in header.encoded_len().max(2)..=9 {
+                let bytes = header.to_bytes_padded(len).unwrap();
+                let expected = match header { Header::Int(Sign::Neg, 0) => Header::Int(Sign::Pos, 0), h => h };
+                assert_eq!((expected, len), Header::decode(&bytes).unwrap());
+            }
+        }
+        assert_eq!(Some(Header::Null.to_bytes()), Header::Null.to_bytes_padded(1));
+        assert_eq!(None, Header::F32.to_bytes_padded(2));
+    }
+
+    #[test]
+    fn const_encoding() {
+        let mut src = [0u8; 9];
+        let mut dst = Vec::with_capacity(9);
+        for l in 0..u8::MAX {
+            dst.clear();
+            src[0] = l;
+            let header = Header::decode(&src).unwrap().0;
+            let c = header.encode(&mut dst).unwrap();
+            assert_eq!((c, &dst[..]), (header.encoded_len(), &header.to_bytes()[..c]));
+        }
+        const NULL: [u8; 9] = Header::Null.to_bytes();
+        assert_eq!(0x00, NULL[0]);
+        assert_eq!(&[0xd9, 0x01, 0x00, 0xe0], encode_headers!(Header::Map(256), Header::Ref(0)));
+        assert_eq!(&[0x20], encode_headers!(Header::Int(Sign::Neg, 0)));
+    }
+
     #[test]
     fn negative_zero() {
         let mut buf = Vec::new();
@@ -360,7 +520,7 @@ mod tests {
         for i in (0..u64::MAX).step_by(3_203_431_780_337) {
             assert_roundtrip(Header::Bin(i as usize), &mut buf);
             assert_roundtrip(Header::Int(Sign::Pos, i), &mut buf);
-            assert_roundtrip(Header::Int(Sign::Neg, if i == 0 { 1 } else { i } as u64), &mut buf);
+            assert_roundtrip(Header::Int(Sign::Neg, if i == 0 { 1 } else { i }), &mut buf);
             assert_roundtrip(Header::Str(i as usize), &mut buf);
             assert_roundtrip(Header::Sym(i as usize), &mut buf);
             assert_roundtrip(Header::Arr(i as usize), &mut buf);
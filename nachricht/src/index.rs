@@ -0,0 +1,339 @@
+//! Random access to the values of an archive of concatenated messages. An `Index` records the offset of every value up
+//! to a given depth, addressed by paths in the syntax of `redact`. It can be persisted alongside the archive, so that
+//! later lookups neither rescan nor decode anything but the requested value:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let cat = |name| Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+//!     (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus"))),
+//! ]));
+//! let mut archive = Vec::new();
+//! Encoder::encode(&Value::Array(vec![cat("Jessica"), cat("Wantan")]), &mut archive).unwrap();
+//! Encoder::encode(&Value::Array(vec![cat("Sphinx")]), &mut archive).unwrap();
+//!
+//! let mut persisted = Vec::new();
+//! Encoder::encode(&Index::build(&archive, 1).unwrap().to_value(), &mut persisted).unwrap();
+//!
+//! let index = Index::from_value(&Decoder::decode(&persisted).unwrap().0).unwrap();
+//! assert_eq!(Some(cat("Wantan")), index.get(&archive, 0, "1").unwrap());
+//! assert_eq!(Some(Value::Symbol(Cow::Borrowed("FelisCatus"))), index.get(&archive, 0, "1.species").unwrap());
+//! ```
+//!
+//! A value can refer to symbols and record layouts which have been defined anywhere before it in its message. Instead
+//! of the symbols themselves, the index stores the positions of all headers which define them, so that the symbol
+//! table at any indexed value can be restored by reading just these headers. Paths below the indexed depth are
+//! resolved by decoding the deepest indexed value on the way.
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::{DecodeError, DecoderError, IndexError};
+use crate::header::{Header, Sign};
+use crate::reader::{Reader, Token};
+use crate::symbols::{Refable, SymbolTable};
+use crate::value::{Decoder, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Where an indexed value is located within its message
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    offset: usize,
+    /// The number of definitions which precede the value
+    definitions: usize,
+}
+
+/// The index of a single message. Positions are relative to its start.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Message {
+    offset: usize,
+    /// The positions of all headers which define a symbol or a record layout. The layout of a record includes the
+    /// definitions of its keys.
+    definitions: Vec<usize>,
+    paths: BTreeMap<String, Entry>,
+}
+
+/// The offsets of the values of an archive, see the module documentation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Index {
+    messages: Vec<Message>,
+}
+
+impl Index {
+
+    /// Scan all concatenated messages in the buffer and index every value which is nested at most `depth` levels deep.
+    /// A depth of zero only indexes the messages themselves. Indexing recurses, so depths beyond `Decoder::MAX_DEPTH`
+    /// are treated as `Decoder::MAX_DEPTH`.
+    pub fn build(buf: &[u8], depth: usize) -> Result<Self, DecoderError> {
+        let depth = depth.min(Decoder::MAX_DEPTH);
+        let mut messages = Vec::new();
+        let mut offset = 0;
+        while let Some(input) = buf.get(offset..).filter(|input| !input.is_empty()) {
+            let mut message = Message { offset, ..Message::default() };
+            let mut reader = Reader::new(input);
+            message.visit(&mut reader, String::new(), depth).map_err(|e| e.at(offset + reader.position()))?;
+            offset += reader.position();
+            messages.push(message);
+        }
+        Ok(Self { messages })
+    }
+
+    /// The number of indexed messages.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// The number of every message which contains an indexed value at the path, together with the offset of that
+    /// value within the archive. The empty path stands for the messages themselves.
+    pub fn lookup<'s>(&'s self, path: &'s str) -> impl Iterator<Item = (usize, usize)> + 's {
+        self.messages.iter().enumerate()
+            .filter_map(move |(i, message)| message.paths.get(path).map(|entry| (i, message.offset + entry.offset)))
+    }
+
+    /// Decode the value at the path within the given message of the archive. Returns `None` if there is no such
+    /// value. The archive has to be the one the index has been built from.
+    pub fn get<'a>(&self, buf: &'a [u8], message: usize, path: &str) -> Result<Option<Value<'a>>, IndexError> {
+        let message = match self.messages.get(message) {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+        let segments = if path.is_empty() { Vec::new() } else { path.split('.').collect::<Vec<_>>() };
+        // the value at the longest indexed prefix of the path and the segments which remain below it
+        let (entry, rest) = match (0..=segments.len()).rev().find_map(|i| {
+            let (prefix, rest) = segments.split_at(i);
+            message.paths.get(&prefix.join(".")).map(|entry| (*entry, rest))
+        }) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let input = buf.get(message.offset..).ok_or_else(|| DecodeError::Eof.at(buf.len()))?;
+        let mut symbols = SymbolTable::new();
+        for definition in message.definitions.iter().take(entry.definitions) {
+            let mut reader = Reader::resume(input, *definition, symbols);
+            reader.next_token().map_err(|e| e.at(message.offset + reader.position()))?;
+            symbols = reader.into_symbols();
+        }
+        let value = Decoder::resume(input, entry.offset, symbols).decode_next().map_err(|e| e.shift(message.offset))?;
+        Ok(rest.iter().try_fold(value, |value, segment| child(value, segment)))
+    }
+
+    /// Convert the index into a value, e.g. to persist it as a message of its own.
+    pub fn to_value(&self) -> Value<'static> {
+        let int = |v: usize| Value::Int(Sign::Pos, v as u64);
+        Value::Array(self.messages.iter().map(|message| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("definitions"), Value::Array(message.definitions.iter().copied().map(int).collect())),
+            (Cow::Borrowed("offset"), int(message.offset)),
+            (Cow::Borrowed("paths"), Value::Map(message.paths.iter().map(|(path, entry)| {
+                (Value::Str(Cow::Owned(path.clone())), Value::Array(vec![int(entry.offset), int(entry.definitions)]))
+            }).collect())),
+        ]))).collect())
+    }
+
+    /// Restore an index which has been converted by `to_value`.
+    pub fn from_value(value: &Value) -> Result<Self, IndexError> {
+        let int = |value: &Value| match value {
+            Value::Int(Sign::Pos, v) => usize::try_from(*v).map_err(|_| IndexError::Malformed("offset out of range")),
+            _ => Err(IndexError::Malformed("offsets have to be positive integers")),
+        };
+        let messages = match value {
+            Value::Array(messages) => messages,
+            _ => return Err(IndexError::Malformed("expected an array of messages")),
+        };
+        messages.iter().map(|message| {
+            let field = |name| match message {
+                Value::Record(fields) => fields.get(name).ok_or(IndexError::Malformed("missing field")),
+                _ => Err(IndexError::Malformed("expected a record per message")),
+            };
+            let definitions = match field("definitions")? {
+                Value::Array(definitions) => definitions.iter().map(int).collect::<Result<_, _>>()?,
+                _ => return Err(IndexError::Malformed("expected an array of definitions")),
+            };
+            let paths = match field("paths")? {
+                Value::Map(paths) => paths.iter().map(|(path, entry)| match (path, entry) {
+                    (Value::Str(path), Value::Array(entry)) => match entry.as_slice() {
+                        [offset, definitions] => Ok((path.to_string(), Entry { offset: int(offset)?, definitions: int(definitions)? })),
+                        _ => Err(IndexError::Malformed("expected an offset and a number of definitions per path")),
+                    },
+                    _ => Err(IndexError::Malformed("expected a string per path")),
+                }).collect::<Result<_, _>>()?,
+                _ => return Err(IndexError::Malformed("expected a map of paths")),
+            };
+            Ok(Message { offset: int(field("offset")?)?, definitions, paths })
+        }).collect::<Result<_, _>>().map(|messages| Self { messages })
+    }
+
+}
+
+impl Message {
+
+    /// Index the next value and everything within it up to the given depth. Recursion is limited by the depth, deeper
+    /// values are skipped without it.
+    fn visit(&mut self, reader: &mut Reader, path: String, depth: usize) -> Result<(), DecodeError> {
+        self.paths.entry(path.clone()).or_insert(Entry { offset: reader.position(), definitions: self.definitions.len() });
+        if depth == 0 {
+            return self.skip(reader);
+        }
+        let child = |segment: &str| match path.as_str() {
+            "" => segment.to_owned(),
+            parent => format!("{}.{}", parent, segment),
+        };
+        match self.next_token(reader)? {
+            Token::Arr(len) => {
+                for i in 0..len {
+                    self.visit(reader, child(&i.to_string()), depth - 1)?;
+                }
+            },
+            Token::Map(len) => {
+                for _ in 0..len {
+                    let key = match reader.peek()? {
+                        Header::Str(_) | Header::Sym(_) => true,
+                        Header::Ref(i) => matches!(reader.symbols().get(i), Some(Refable::Sym(_))),
+                        _ => false,
+                    };
+                    match key {
+                        true => match self.next_token(reader)? {
+                            Token::Str(key) | Token::Sym(key) => self.visit(reader, child(key), depth - 1)?,
+                            _ => self.skip(reader)?,
+                        },
+                        // entries whose keys cannot be addressed by a path
+                        false => { self.skip(reader)?; self.skip(reader)?; },
+                    }
+                }
+            },
+            Token::Rec(keys) => {
+                for key in keys.iter() {
+                    self.visit(reader, child(key), depth - 1)?;
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    /// Consume the next value without indexing anything within it but its definitions
+    fn skip(&mut self, reader: &mut Reader) -> Result<(), DecodeError> {
+        let mut remaining = 1usize;
+        while remaining > 0 {
+            remaining -= 1;
+            match self.next_token(reader)? {
+                Token::Arr(v) => { remaining = remaining.saturating_add(v); },
+                Token::Map(v) => { remaining = remaining.saturating_add(v.saturating_mul(2)); },
+                Token::Rec(keys) => { remaining = remaining.saturating_add(keys.len()); },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn next_token<'a>(&mut self, reader: &mut Reader<'a>) -> Result<Token<'a>, DecodeError> {
+        if matches!(reader.peek()?, Header::Sym(_) | Header::Rec(_)) {
+            self.definitions.push(reader.position());
+        }
+        reader.next_token()
+    }
+
+}
+
+/// Move the child at the segment out of the value
+fn child<'a>(value: Value<'a>, segment: &str) -> Option<Value<'a>> {
+    match value {
+        Value::Record(mut fields) => fields.remove(segment),
+        Value::Map(entries) => entries.into_iter()
+            .find(|(key, _)| matches!(key, Value::Str(k) | Value::Symbol(k) if k == segment))
+            .map(|(_, val)| val),
+        Value::Array(elements) => segment.parse::<usize>().ok().and_then(|i| elements.into_iter().nth(i)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Index;
+    use crate::error::{DecodeError, IndexError};
+    use crate::header::Sign;
+    use crate::value::{Decoder, Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn record(fields: Vec<(&'static str, Value<'static>)>) -> Value<'static> {
+        Value::Record(fields.into_iter().map(|(k, v)| (Cow::Borrowed(k), v)).collect::<BTreeMap<_, _>>())
+    }
+
+    fn cat(name: &'static str, species: &'static str) -> Value<'static> {
+        record(vec![("name", Value::Str(Cow::Borrowed(name))), ("species", Value::Symbol(Cow::Borrowed(species)))])
+    }
+
+    fn archive() -> (Vec<u8>, Vec<Value<'static>>) {
+        let messages = vec![
+            record(vec![
+                ("cats", Value::Array(vec![cat("Jessica", "FelisCatus"), cat("Wantan", "LynxLynx"), cat("Sphinx", "FelisCatus")])),
+                ("owners", Value::Map(vec![
+                    (Value::Int(Sign::Pos, 1), Value::Null),
+                    (Value::Str(Cow::Borrowed("Liv")), Value::Array(vec![Value::Symbol(Cow::Borrowed("LynxLynx"))])),
+                ])),
+            ]),
+            Value::Array(vec![cat("Chandra", "FelisCatus")]),
+        ];
+        let mut buf = Vec::new();
+        for message in &messages {
+            Encoder::encode(message, &mut buf).unwrap();
+        }
+        (buf, messages)
+    }
+
+    #[test]
+    fn lookup() {
+        let (buf, messages) = archive();
+        for depth in 0..4 {
+            let index = Index::build(&buf, depth).unwrap();
+            assert_eq!(2, index.len());
+            for (i, message) in messages.iter().enumerate() {
+                assert_eq!(Some(message), index.get(&buf, i, "").unwrap().as_ref());
+            }
+            // symbols and layouts have been defined by earlier values
+            assert_eq!(Some(cat("Sphinx", "FelisCatus")), index.get(&buf, 0, "cats.2").unwrap());
+            assert_eq!(Some(Value::Symbol(Cow::Borrowed("FelisCatus"))), index.get(&buf, 0, "cats.2.species").unwrap());
+            assert_eq!(Some(Value::Symbol(Cow::Borrowed("LynxLynx"))), index.get(&buf, 0, "owners.Liv.0").unwrap());
+            assert_eq!(Some(Value::Str(Cow::Borrowed("Chandra"))), index.get(&buf, 1, "0.name").unwrap());
+            assert_eq!(None, index.get(&buf, 0, "cats.3").unwrap());
+            assert_eq!(None, index.get(&buf, 2, "").unwrap());
+        }
+        let index = Index::build(&buf, 2).unwrap();
+        let second = buf.len() - Decoder::decode(&buf).unwrap().1;
+        assert_eq!(vec![(0, 0), (1, buf.len() - second)], index.lookup("").collect::<Vec<_>>());
+        assert_eq!(1, index.lookup("cats.1").count());
+        assert_eq!(0, index.lookup("cats.1.name").count());
+        // entries whose keys are not strings are only part of their map
+        assert_eq!(messages[0].pointer("/owners"), index.get(&buf, 0, "owners").unwrap().as_ref());
+        assert_eq!(0, Index::build(&[], 1).unwrap().len());
+    }
+
+    #[test]
+    fn persist() {
+        let (buf, _) = archive();
+        let index = Index::build(&buf, 2).unwrap();
+        let mut persisted = Vec::new();
+        Encoder::encode(&index.to_value(), &mut persisted).unwrap();
+        assert_eq!(index, Index::from_value(&Decoder::decode(&persisted).unwrap().0).unwrap());
+        assert_eq!(Err(IndexError::Malformed("expected an array of messages")), Index::from_value(&Value::Null));
+        let malformed = Value::Array(vec![record(vec![("offset", Value::Int(Sign::Neg, 1))])]);
+        assert_eq!(Err(IndexError::Malformed("missing field")), Index::from_value(&malformed));
+    }
+
+    #[test]
+    fn errors() {
+        let (buf, _) = archive();
+        let error = Index::build(&buf[..buf.len() - 1], 1).unwrap_err();
+        assert_eq!(DecodeError::Eof, error.into_inner());
+        let index = Index::build(&buf, 1).unwrap();
+        assert!(matches!(index.get(&buf[..10], 0, "owners"), Err(IndexError::Decode(_))));
+    }
+
+}
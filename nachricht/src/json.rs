@@ -0,0 +1,235 @@
+//! Conversions between `Value` and `serde_json::Value`. JSON lacks some of the types of nachricht, so converting to
+//! JSON may lose information. How is determined by `JsonOptions`, whose defaults match the JSON output of `nq`:
+//!
+//! * symbols become strings and bytes become base64 encoded strings
+//! * records become objects, while maps become arrays of key-value pairs since their keys need not be strings
+//! * non-finite floats become null, negative integers below `i64::MIN` become floats
+//! * `F32` become the number with the same shortest decimal representation, e.g. `0.1` rather than `0.10000000149011612`
+//!
+//! `From` converts with the defaults and never fails. Gateways which must not lose information silently can pick
+//! stricter policies with `Value::to_json`.
+//!
+//! Converting from JSON is lossless: objects become records, strings stay strings and numbers become `Value::Int` if
+//! `serde_json` parsed them as integers and `Value::F64` otherwise.
+//!
+//! ```
+//! use nachricht::*;
+//! use serde_json::json;
+//!
+//! let value = Value::from(json!({ "name": "Jessica", "lives": 9 }));
+//! assert_eq!(Some(&Value::Int(Sign::Pos, 9)), value.pointer("/lives"));
+//! assert_eq!(json!({ "name": "Jessica", "lives": 9 }), serde_json::Value::from(&value));
+//!
+//! let strict = JsonOptions { bytes: BytesPolicy::Reject, ..Default::default() };
+//! assert!(Value::Bytes(vec![1, 2].into()).to_json(strict).is_err());
+//! ```
+
+use crate::error::JsonError;
+use crate::header::Sign;
+use crate::value::Value;
+use serde_json::{Map, Number};
+use std::borrow::Cow;
+
+/// How to convert `Value::Bytes`, which JSON has no counterpart for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesPolicy {
+    /// A string with the base64 encoded bytes
+    Base64,
+    /// An array of numbers
+    Array,
+    Reject,
+}
+
+/// How to convert `Value::Map`, whose keys need not be strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapPolicy {
+    /// An array of key-value pairs, which keeps keys of any type
+    Pairs,
+    /// An object. Keys which are neither strings nor symbols are replaced with their textual representation, e.g. `1`
+    /// or `true`. Of several entries with the same key, the last one wins.
+    Stringify,
+    /// An object. Fails on keys which are neither strings nor symbols and on duplicate keys.
+    Reject,
+}
+
+/// How to convert numbers which JSON cannot represent: non-finite floats and integers which exceed the range of `i64`
+/// in the negative direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberPolicy {
+    /// Null for non-finite floats, the closest float for large negative integers
+    Approximate,
+    Reject,
+}
+
+/// Options for `Value::to_json`. The defaults never fail, see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JsonOptions {
+    pub bytes: BytesPolicy,
+    pub maps: MapPolicy,
+    pub numbers: NumberPolicy,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self { bytes: BytesPolicy::Base64, maps: MapPolicy::Pairs, numbers: NumberPolicy::Approximate }
+    }
+}
+
+impl<'a> Value<'a> {
+
+    /// Convert the value to JSON with the given policies. Errors contain the JSON pointer of the offending value.
+    pub fn to_json(&self, options: JsonOptions) -> Result<serde_json::Value, JsonError> {
+        convert(self, options, &mut String::new())
+    }
+
+}
+
+/// Convert the value at the given pointer. The pointer is restored before returning successfully.
+fn convert(value: &Value, options: JsonOptions, pointer: &mut String) -> Result<serde_json::Value, JsonError> {
+    let float = |v: f64, pointer: &str| match (Number::from_f64(v), options.numbers) {
+        (Some(v), _) => Ok(serde_json::Value::Number(v)),
+        (None, NumberPolicy::Approximate) => Ok(serde_json::Value::Null),
+        (None, NumberPolicy::Reject) => Err(JsonError::Number(pointer.to_owned())),
+    };
+    let escape = |key: &str| key.replace('~', "~0").replace('/', "~1");
+    let mut child = |segment: &str, value: &Value| {
+        let len = pointer.len();
+        pointer.push('/');
+        pointer.push_str(segment);
+        let converted = convert(value, options, pointer);
+        pointer.truncate(len);
+        converted
+    };
+    Ok(match value {
+        Value::Null              => serde_json::Value::Null,
+        Value::Bool(v)           => serde_json::Value::Bool(*v),
+        Value::F32(v)            => float(v.to_string().parse().unwrap_or(f64::NAN), pointer)?,
+        Value::F64(v)            => float(*v, pointer)?,
+        Value::Int(Sign::Pos, v) => serde_json::Value::Number(Number::from(*v)),
+        Value::Int(Sign::Neg, v) => match (i64::try_from(-i128::from(*v)), options.numbers) {
+            (Ok(v), _) => serde_json::Value::Number(Number::from(v)),
+            (Err(_), NumberPolicy::Approximate) => float(-(*v as f64), pointer)?,
+            (Err(_), NumberPolicy::Reject) => return Err(JsonError::Number(pointer.clone())),
+        },
+        Value::Str(v) | Value::Symbol(v) => serde_json::Value::String(v.to_string()),
+        Value::Bytes(v)          => match options.bytes {
+            BytesPolicy::Base64 => serde_json::Value::String(Value::b64(v)),
+            BytesPolicy::Array => serde_json::Value::Array(v.iter().map(|b| serde_json::Value::Number(Number::from(*b))).collect()),
+            BytesPolicy::Reject => return Err(JsonError::Bytes(pointer.clone())),
+        },
+        Value::Array(v)          => serde_json::Value::Array(v.iter().enumerate()
+            .map(|(i, element)| child(&i.to_string(), element)).collect::<Result<_, _>>()?),
+        Value::Record(v)         => serde_json::Value::Object(v.iter()
+            .map(|(k, v)| Ok((k.to_string(), child(&escape(k), v)?))).collect::<Result<_, _>>()?),
+        Value::Map(v) if options.maps == MapPolicy::Pairs => serde_json::Value::Array(v.iter().enumerate().map(|(i, (k, v))| {
+            Ok(serde_json::Value::Array(vec![child(&format!("{}/0", i), k)?, child(&format!("{}/1", i), v)?]))
+        }).collect::<Result<_, JsonError>>()?),
+        Value::Map(v)            => {
+            let mut object = Map::new();
+            for (k, v) in v {
+                let key = match k {
+                    Value::Str(k) | Value::Symbol(k) => k.to_string(),
+                    k if options.maps == MapPolicy::Stringify => k.to_string(),
+                    _ => return Err(JsonError::MapKey(pointer.clone())),
+                };
+                let converted = child(&escape(&key), v)?;
+                if object.insert(key, converted).is_some() && options.maps == MapPolicy::Reject {
+                    return Err(JsonError::MapKey(pointer.clone()));
+                }
+            }
+            serde_json::Value::Object(object)
+        },
+    })
+}
+
+impl<'a> From<&Value<'a>> for serde_json::Value {
+    fn from(value: &Value<'a>) -> Self {
+        value.to_json(JsonOptions::default()).unwrap_or_default()
+    }
+}
+
+impl From<serde_json::Value> for Value<'static> {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null      => Value::Null,
+            serde_json::Value::Bool(v)   => Value::Bool(v),
+            serde_json::Value::Number(v) => match (v.as_u64(), v.as_i64()) {
+                (Some(v), _) => Value::Int(Sign::Pos, v),
+                (None, Some(v)) => Value::Int(Sign::Neg, v.unsigned_abs()),
+                (None, None) => Value::F64(v.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(v) => Value::Str(Cow::Owned(v)),
+            serde_json::Value::Array(v)  => Value::Array(v.into_iter().map(Value::from).collect()),
+            serde_json::Value::Object(v) => Value::Record(v.into_iter().map(|(k, v)| (Cow::Owned(k), Value::from(v))).collect()),
+        }
+    }
+}
+
+impl<'a> From<Value<'a>> for serde_json::Value {
+    fn from(value: Value<'a>) -> Self {
+        Self::from(&value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BytesPolicy, JsonOptions, MapPolicy, NumberPolicy};
+    use crate::error::JsonError;
+    use crate::header::Sign;
+    use crate::value::Value;
+    use serde_json::json;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn to_json() {
+        let value = Value::Array(vec![
+            Value::Symbol(Cow::Borrowed("FelisCatus")),
+            Value::Bytes(Cow::Borrowed(&[0xde, 0xad, 0xbe, 0xef])),
+            Value::F32(f32::NAN),
+            Value::F32(0.1),
+            Value::F64(0.5),
+            Value::Int(Sign::Neg, 1 << 63),
+            Value::Int(Sign::Neg, u64::MAX),
+            Value::Map(vec![(Value::Int(Sign::Pos, 1), Value::Null)]),
+            Value::Record(BTreeMap::from([(Cow::Borrowed("key"), Value::Bool(true))])),
+        ]);
+        assert_eq!(json!(["FelisCatus", "3q2+7w==", null, 0.1, 0.5, i64::MIN, -(u64::MAX as f64), [[1, null]], { "key": true }]),
+            serde_json::Value::from(&value));
+    }
+
+    #[test]
+    fn from_json() {
+        let value = Value::from(json!({ "cats": [{ "name": "Jessica", "lives": -1, "weight": 4.5 }], "owner": null }));
+        let cat = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("lives"), Value::Int(Sign::Neg, 1)),
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+            (Cow::Borrowed("weight"), Value::F64(4.5)),
+        ]));
+        let expected = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("cats"), Value::Array(vec![cat])),
+            (Cow::Borrowed("owner"), Value::Null),
+        ]));
+        assert_eq!(expected, value);
+        assert_eq!(json!({ "cats": [{ "name": "Jessica", "lives": -1, "weight": 4.5 }], "owner": null }), serde_json::Value::from(value));
+    }
+
+    #[test]
+    fn policies() {
+        let map = |entries: Vec<(Value<'static>, Value<'static>)>| Value::Record(BTreeMap::from([(Cow::Borrowed("a/b"), Value::Map(entries))]));
+        let str = |s: &'static str| Value::Str(Cow::Borrowed(s));
+        let options = |bytes, maps, numbers| JsonOptions { bytes, maps, numbers };
+        let strict = options(BytesPolicy::Reject, MapPolicy::Reject, NumberPolicy::Reject);
+        let value = map(vec![(str("x"), Value::Bytes(Cow::Borrowed(&[1, 2])))]);
+        assert_eq!(Err(JsonError::Bytes("/a~1b/x".to_string())), value.to_json(strict));
+        assert_eq!(json!({ "a/b": { "x": [1, 2] } }), value.to_json(JsonOptions { bytes: BytesPolicy::Array, ..strict }).unwrap());
+        let value = map(vec![(Value::Int(Sign::Pos, 1), Value::F64(f64::INFINITY)), (str("1"), Value::Int(Sign::Neg, u64::MAX))]);
+        assert_eq!(Err(JsonError::Number("/a~1b/0/1".to_string())), value.to_json(JsonOptions { maps: MapPolicy::Pairs, ..strict }));
+        assert_eq!(Err(JsonError::MapKey("/a~1b".to_string())), value.to_json(JsonOptions { numbers: NumberPolicy::Approximate, ..strict }));
+        assert_eq!(json!({ "a/b": { "1": -(u64::MAX as f64) } }), value.to_json(options(BytesPolicy::Reject, MapPolicy::Stringify, NumberPolicy::Approximate)).unwrap());
+        let value = map(vec![(str("x"), Value::Null), (Value::Symbol(Cow::Borrowed("x")), Value::Null)]);
+        assert_eq!(Err(JsonError::MapKey("/a~1b".to_string())), value.to_json(strict));
+        assert_eq!(json!({ "a/b": { "x": null } }), value.to_json(JsonOptions { maps: MapPolicy::Stringify, ..strict }).unwrap());
+    }
+
+}
@@ -0,0 +1,188 @@
+//! Replace record keys with short hashes for links where every byte counts. `hash_keys` turns every key into the
+//! 32 bit FNV-1a hash of its name, written as six characters from the URL-safe base64 alphabet, so that a key costs
+//! seven bytes on the wire no matter how long its name is. The names never get transmitted: the receiver needs to know
+//! them in advance and passes them to `unhash_keys` as a `KeyDictionary`.
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let original = Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("temperature_celsius"), Value::F32(21.5)),
+//!     (Cow::Borrowed("relative_humidity"), Value::Int(Sign::Pos, 40)),
+//! ]));
+//! let mut value = original.clone();
+//! hash_keys(&mut value).unwrap();
+//! assert!(matches!(&value, Value::Record(r) if r.contains_key(key_hash("relative_humidity").as_str())));
+//!
+//! let dictionary = KeyDictionary::new(["temperature_celsius", "relative_humidity"]).unwrap();
+//! unhash_keys(&mut value, &dictionary).unwrap();
+//! assert_eq!(original, value);
+//! ```
+//!
+//! Distinct names may have the same hash. `hash_keys` fails if the keys of a value collide and so does
+//! `KeyDictionary::new` if the names do, so a collision can never result in a wrong key being restored.
+
+use crate::error::KeyHashError;
+use crate::value::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// The 32 bit FNV-1a hash of the name
+fn fnv1a(name: &str) -> u32 {
+    name.bytes().fold(0x811c9dc5, |hash, b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+/// The key which `hash_keys` uses in place of the given name.
+pub fn key_hash(name: &str) -> String {
+    let hash = fnv1a(name);
+    (0..6).rev().map(|i| ALPHABET[(hash >> (i * 6)) as usize & 63] as char).collect()
+}
+
+/// The names of all record keys which the receiver expects, see `unhash_keys`.
+#[derive(Debug, Clone, Default)]
+pub struct KeyDictionary {
+    names: HashMap<String, String>,
+}
+
+impl KeyDictionary {
+
+    /// Create a dictionary of the given names. Fails if two distinct names have the same hash.
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(names: I) -> Result<Self, KeyHashError> {
+        let mut dictionary = Self::default();
+        for name in names {
+            dictionary.insert(name.into())?;
+        }
+        Ok(dictionary)
+    }
+
+    /// Add a name to the dictionary. Fails if a distinct name with the same hash is already present.
+    pub fn insert(&mut self, name: String) -> Result<(), KeyHashError> {
+        match self.names.get(&key_hash(&name)) {
+            Some(existing) if *existing != name => Err(KeyHashError::Collision(existing.clone(), name)),
+            Some(_) => Ok(()),
+            None => {
+                self.names.insert(key_hash(&name), name);
+                Ok(())
+            },
+        }
+    }
+
+    /// The name with the given hash, if it is known
+    pub fn get(&self, hash: &str) -> Option<&str> {
+        self.names.get(hash).map(String::as_str)
+    }
+
+}
+
+/// Replace the keys of all records within the value with their hashes. Fails if two distinct keys anywhere in the
+/// value have the same hash, in which case the value is left unchanged.
+pub fn hash_keys(value: &mut Value) -> Result<(), KeyHashError> {
+    let mut seen = KeyDictionary::default();
+    visit(value, &mut |fields| {
+        fields.keys().try_for_each(|key| seen.insert(key.to_string()))
+    })?;
+    visit(value, &mut |fields| {
+        *fields = std::mem::take(fields).into_iter().map(|(key, val)| (Cow::Owned(key_hash(&key)), val)).collect();
+        Ok(())
+    })
+}
+
+/// Restore the keys of all records within the value from their hashes. Fails if a key is not a hash of any name in
+/// the dictionary, in which case the value may have been partially restored.
+pub fn unhash_keys(value: &mut Value, dictionary: &KeyDictionary) -> Result<(), KeyHashError> {
+    visit(value, &mut |fields| {
+        *fields = std::mem::take(fields).into_iter().map(|(key, val)| match dictionary.get(&key) {
+            Some(name) => Ok((Cow::Owned(name.to_owned()), val)),
+            None => Err(KeyHashError::Unknown(key.into_owned())),
+        }).collect::<Result<_, _>>()?;
+        Ok(())
+    })
+}
+
+type Fields<'a> = std::collections::BTreeMap<Cow<'a, str>, Value<'a>>;
+
+/// Call `f` with the fields of every record within the value, outer records first
+fn visit<'a>(value: &mut Value<'a>, f: &mut impl FnMut(&mut Fields<'a>) -> Result<(), KeyHashError>) -> Result<(), KeyHashError> {
+    match value {
+        Value::Record(fields) => {
+            f(fields)?;
+            fields.values_mut().try_for_each(|field| visit(field, f))
+        },
+        Value::Array(elements) => elements.iter_mut().try_for_each(|element| visit(element, f)),
+        Value::Map(entries) => entries.iter_mut().try_for_each(|(key, val)| {
+            visit(key, f)?;
+            visit(val, f)
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fnv1a, hash_keys, key_hash, unhash_keys, KeyDictionary};
+    use crate::error::KeyHashError;
+    use crate::header::Sign;
+    use crate::value::{Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn record(fields: Vec<(&'static str, Value<'static>)>) -> Value<'static> {
+        Value::Record(fields.into_iter().map(|(k, v)| (Cow::Borrowed(k), v)).collect::<BTreeMap<_, _>>())
+    }
+
+    fn reading() -> Value<'static> {
+        record(vec![
+            ("sensor_identifier", Value::Str(Cow::Borrowed("kitchen"))),
+            ("measurements", Value::Array(vec![
+                record(vec![("temperature_celsius", Value::F32(21.5))]),
+                record(vec![("temperature_celsius", Value::F32(21.75))]),
+            ])),
+            ("labels", Value::Map(vec![(Value::Str(Cow::Borrowed("floor")), record(vec![("level", Value::Int(Sign::Pos, 1))]))])),
+        ])
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(0x811c9dc5, fnv1a(""));
+        assert_eq!(0xe40c292c, fnv1a("a"));
+        assert_eq!(0xbf9cf968, fnv1a("foobar"));
+        assert_eq!("DkDCks", key_hash("a"));
+        assert_eq!(6, key_hash("").len());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut value = reading();
+        hash_keys(&mut value).unwrap();
+        let mut hashed = Vec::new();
+        Encoder::encode(&value, &mut hashed).unwrap();
+        let mut plain = Vec::new();
+        Encoder::encode(&reading(), &mut plain).unwrap();
+        assert!(hashed.len() < plain.len());
+        let dictionary = KeyDictionary::new(["level", "measurements", "labels", "sensor_identifier", "temperature_celsius"]).unwrap();
+        unhash_keys(&mut value, &dictionary).unwrap();
+        assert_eq!(reading(), value);
+    }
+
+    #[test]
+    fn errors() {
+        // "costarring" and "liquid" are a known FNV-1a collision
+        assert_eq!(key_hash("costarring"), key_hash("liquid"));
+        let collision = KeyHashError::Collision("costarring".to_string(), "liquid".to_string());
+        assert_eq!(collision, KeyDictionary::new(["costarring", "costarring", "liquid"]).unwrap_err());
+        let original = record(vec![("a", record(vec![("costarring", Value::Null)])), ("liquid", Value::Null)]);
+        let mut value = original.clone();
+        // outer records are visited first
+        assert_eq!(KeyHashError::Collision("liquid".to_string(), "costarring".to_string()), hash_keys(&mut value).unwrap_err());
+        assert_eq!(original, value);
+        let mut value = reading();
+        hash_keys(&mut value).unwrap();
+        let dictionary = KeyDictionary::new(["labels", "measurements", "sensor_identifier", "temperature_celsius"]).unwrap();
+        assert_eq!(KeyHashError::Unknown(key_hash("level")), unhash_keys(&mut value, &dictionary).unwrap_err());
+    }
+
+}
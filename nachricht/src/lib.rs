@@ -47,10 +47,68 @@
 //! assert_eq!(11, decoded.1);
 //! ```
 
+#[cfg(feature = "conformance")]
+pub mod conformance;
+#[cfg(feature = "test-support")]
+mod arbitrary;
+mod canon;
+mod delta;
+mod document;
 mod error;
+mod frame;
 mod header;
+mod index;
+#[cfg(feature = "json")]
+mod json;
+mod keyhash;
+mod lww;
+mod media;
+mod metrics;
+mod partial;
+mod patch;
+mod protect;
+mod push;
+mod reader;
+mod redact;
+mod scan;
+mod shared;
+mod signature;
+mod spanned;
+mod stats;
+#[cfg(feature = "valuable")]
+mod structured;
+mod symbols;
+mod transcode;
 mod value;
+mod verify;
 
 pub use value::*;
+#[cfg(feature = "test-support")]
+pub use arbitrary::*;
+pub use canon::*;
+pub use delta::*;
+pub use document::*;
 pub use error::*;
+pub use frame::*;
 pub use header::*;
+pub use index::*;
+#[cfg(feature = "json")]
+pub use json::*;
+pub use keyhash::*;
+pub use lww::*;
+pub use media::*;
+pub use metrics::*;
+pub use partial::*;
+pub use patch::*;
+pub use protect::*;
+pub use push::*;
+pub use reader::*;
+pub use redact::*;
+pub use scan::*;
+pub use shared::*;
+pub use signature::*;
+pub use spanned::*;
+pub use stats::*;
+pub use symbols::*;
+pub use transcode::*;
+pub use verify::*;
@@ -0,0 +1,186 @@
+//! Last-writer-wins maps for replicated state. Producers which change the same map concurrently may end up with several
+//! entries for a key. With the convention that every value of such a map is a record with a `@timestamp` field, these
+//! duplicates resolve deterministically: the entry with the highest timestamp wins and ties are broken by the encoding
+//! of the values. `merge_lww` combines two replicas and `resolve_lww` cleans up a single one, both leaving the entries
+//! sorted like `canonicalize` does. Since merging is commutative, associative and idempotent, replicas which have seen
+//! the same entries encode to identical bytes, no matter in which order they saw them:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let entry = |key: &'static str, lives, timestamp| (Value::Str(Cow::Borrowed(key)), Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed(TIMESTAMP_FIELD), Value::Int(Sign::Pos, timestamp)),
+//!     (Cow::Borrowed("lives"), Value::Int(Sign::Pos, lives)),
+//! ])));
+//! let mut left = Value::Map(vec![entry("jessica", 8, 20), entry("wantan", 7, 10)]);
+//! let mut right = Value::Map(vec![entry("jessica", 9, 10)]);
+//! let (left_copy, right_copy) = (left.clone(), right.clone());
+//! merge_lww(&mut left, right_copy).unwrap();
+//! merge_lww(&mut right, left_copy).unwrap();
+//! assert_eq!(left, right);
+//! assert_eq!(Value::Map(vec![entry("wantan", 7, 10), entry("jessica", 8, 20)]), left);
+//! ```
+//!
+//! Values without a timestamp count as written at time 0. Keys are compared by their encoding, so a string and a symbol
+//! with the same text are different keys. Removing a key has to be recorded as an entry as well, e.g. a record with
+//! just a timestamp, since an absent entry cannot win against an older one which another replica still has.
+//!
+//! The values of the winning entries are taken as a whole, but maps within them are resolved in the same way.
+
+use crate::error::LengthError;
+use crate::header::Sign;
+use crate::value::{Encoder, Value};
+
+/// The field which holds the time of the last write in the values of last-writer-wins maps
+pub const TIMESTAMP_FIELD: &str = "@timestamp";
+
+/// The time of the last write of the value according to its `TIMESTAMP_FIELD`, or 0 if it does not have one.
+pub fn lww_timestamp(value: &Value) -> u64 {
+    match value {
+        Value::Record(fields) => match fields.get(TIMESTAMP_FIELD) {
+            Some(Value::Int(Sign::Pos, timestamp)) => *timestamp,
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// Keep only the winning entry of every key in all maps within the value and sort them, see the module documentation.
+/// Only fails if a key or value cannot be encoded.
+pub fn resolve_lww(value: &mut Value) -> Result<(), LengthError> {
+    match value {
+        Value::Array(elements) => elements.iter_mut().try_for_each(resolve_lww),
+        Value::Record(fields) => fields.values_mut().try_for_each(resolve_lww),
+        Value::Map(entries) => {
+            let mut candidates = Vec::with_capacity(entries.len());
+            for (mut key, mut val) in entries.drain(..) {
+                resolve_lww(&mut key)?;
+                resolve_lww(&mut val)?;
+                candidates.push((encode(&key)?, lww_timestamp(&val), encode(&val)?, key, val));
+            }
+            candidates.sort_by(|a, b| (&a.0, a.1, &a.2).cmp(&(&b.0, b.1, &b.2)));
+            // the last candidate of every key wins
+            let mut winners: Vec<(Vec<u8>, Value, Value)> = Vec::with_capacity(candidates.len());
+            for (encoded, _, _, key, val) in candidates {
+                if winners.last().map(|(last, _, _)| *last == encoded).unwrap_or(false) {
+                    winners.pop();
+                }
+                winners.push((encoded, key, val));
+            }
+            entries.extend(winners.into_iter().map(|(_, key, val)| (key, val)));
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Merge another replica into the value. Two maps are combined entry by entry, while any other values are treated as a
+/// single register of which the later one wins. The result is resolved with `resolve_lww`.
+pub fn merge_lww<'a>(value: &mut Value<'a>, other: Value<'a>) -> Result<(), LengthError> {
+    match (&mut *value, other) {
+        (Value::Map(entries), Value::Map(others)) => entries.extend(others),
+        (_, mut other) => {
+            resolve_lww(value)?;
+            resolve_lww(&mut other)?;
+            if (lww_timestamp(&other), encode(&other)?) > (lww_timestamp(value), encode(value)?) {
+                *value = other;
+            }
+        },
+    }
+    resolve_lww(value)
+}
+
+fn encode(value: &Value) -> Result<Vec<u8>, LengthError> {
+    let mut buf = Vec::new();
+    Encoder::encode_to_vec(value, &mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lww_timestamp, merge_lww, resolve_lww, TIMESTAMP_FIELD};
+    use crate::canon::canonicalize;
+    use crate::header::Sign;
+    use crate::value::{Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn str(s: &'static str) -> Value<'static> {
+        Value::Str(Cow::Borrowed(s))
+    }
+
+    fn int(i: u64) -> Value<'static> {
+        Value::Int(Sign::Pos, i)
+    }
+
+    fn written(timestamp: u64, value: Value<'static>) -> Value<'static> {
+        Value::Record(BTreeMap::from([(Cow::Borrowed(TIMESTAMP_FIELD), int(timestamp)), (Cow::Borrowed("value"), value)]))
+    }
+
+    fn encoded(value: &Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        Encoder::encode(value, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn resolve() {
+        let mut value = Value::Array(vec![Value::Map(vec![
+            (str("b"), written(1, int(1))),
+            (str("a"), written(5, str("late"))),
+            (Value::Symbol(Cow::Borrowed("a")), int(0)),
+            (str("a"), written(3, str("early"))),
+            (str("b"), written(1, int(2))),
+            (str("c"), written(1, Value::Map(vec![(int(2), int(0)), (int(1), int(0)), (int(2), written(1, Value::Null))]))),
+        ])]);
+        resolve_lww(&mut value).unwrap();
+        let expected = Value::Array(vec![Value::Map(vec![
+            (str("a"), written(5, str("late"))),
+            // on a tie, the larger encoding wins
+            (str("b"), written(1, int(2))),
+            (str("c"), written(1, Value::Map(vec![(int(1), int(0)), (int(2), written(1, Value::Null))]))),
+            (Value::Symbol(Cow::Borrowed("a")), int(0)),
+        ])]);
+        assert_eq!(expected, value);
+        // without duplicates, the result is the canonical form
+        let mut canonical = value.clone();
+        canonicalize(&mut canonical).unwrap();
+        assert_eq!(canonical, value);
+        assert_eq!(0, lww_timestamp(&int(5)));
+        assert_eq!(0, lww_timestamp(&Value::Record(BTreeMap::from([(Cow::Borrowed(TIMESTAMP_FIELD), str("5"))]))));
+    }
+
+    #[test]
+    fn converge() {
+        let replicas = [
+            Value::Map(vec![(str("jessica"), written(1, int(9))), (str("wantan"), written(4, int(7)))]),
+            Value::Map(vec![(str("jessica"), written(3, int(8))), (str("sphinx"), written(2, int(1)))]),
+            Value::Map(vec![(str("sphinx"), written(2, int(2))), (str("jessica"), written(3, int(8)))]),
+        ];
+        let merged = |order: [usize; 3]| {
+            let mut value = replicas[order[0]].clone();
+            for i in &order[1..] {
+                merge_lww(&mut value, replicas[*i].clone()).unwrap();
+            }
+            encoded(&value)
+        };
+        let expected = merged([0, 1, 2]);
+        for order in [[0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]] {
+            assert_eq!(expected, merged(order));
+        }
+        let mut once = replicas[1].clone();
+        resolve_lww(&mut once).unwrap();
+        let mut twice = once.clone();
+        merge_lww(&mut twice, replicas[1].clone()).unwrap();
+        assert_eq!(encoded(&once), encoded(&twice));
+        // values other than maps are registers
+        let mut register = written(2, int(1));
+        merge_lww(&mut register, written(1, int(5))).unwrap();
+        assert_eq!(written(2, int(1)), register);
+        merge_lww(&mut register, written(3, int(0))).unwrap();
+        assert_eq!(written(3, int(0)), register);
+    }
+
+}
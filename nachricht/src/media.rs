@@ -0,0 +1,128 @@
+//! Identify `nachricht` content, e.g. in web services which offer several formats. Messages are labelled with
+//! `MEDIA_TYPE` and negotiated with `accepts_nachricht`:
+//!
+//! ```
+//! use nachricht::*;
+//!
+//! assert!(is_media_type("Application/X-Nachricht; charset=binary"));
+//! assert!(accepts_nachricht("application/json;q=0.9, application/*;q=0.5"));
+//! assert!(!accepts_nachricht("application/json, application/x-nachricht;q=0"));
+//! assert!(is_nachricht(&[0x21]));
+//! ```
+//!
+//! The format has no magic number, so `is_nachricht` has to check whether the whole buffer is a valid message. Short
+//! inputs like a single byte often are, so a declared content type should be preferred whenever there is one.
+//!
+//! With the `http` feature, the same checks are available for the headers of the `http` crate.
+
+use crate::verify::verify;
+
+/// The media type of a single `nachricht` message
+pub const MEDIA_TYPE: &str = "application/x-nachricht";
+
+/// Whether the buffer contains exactly one valid message, see `verify`.
+pub fn is_nachricht(buf: &[u8]) -> bool {
+    verify(buf).is_ok()
+}
+
+/// Whether the value of a `Content-Type` header denotes `MEDIA_TYPE`. Parameters are ignored.
+pub fn is_media_type(content_type: &str) -> bool {
+    content_type.split(';').next().map(|t| t.trim().eq_ignore_ascii_case(MEDIA_TYPE)).unwrap_or(false)
+}
+
+/// Whether the value of an `Accept` header allows `MEDIA_TYPE`. The most specific matching range decides, so that
+/// `*/*, application/x-nachricht;q=0` rejects it. A missing header accepts anything, so callers should pass `*/*` then.
+pub fn accepts_nachricht(accept: &str) -> bool {
+    let (kind, _) = MEDIA_TYPE.split_once('/').unwrap_or((MEDIA_TYPE, ""));
+    let mut best: Option<(u8, bool)> = None;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media = parts.next().unwrap_or_default().trim();
+        let specificity = match media.split_once('/') {
+            Some(("*", "*")) => 0,
+            Some((k, "*")) if k.eq_ignore_ascii_case(kind) => 1,
+            _ if media.eq_ignore_ascii_case(MEDIA_TYPE) => 2,
+            _ => continue,
+        };
+        let quality = parts
+            .filter_map(|p| p.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map(|(_, q)| q.trim().parse::<f32>().unwrap_or(0.0))
+            .unwrap_or(1.0);
+        if best.map(|(s, _)| specificity > s).unwrap_or(true) {
+            best = Some((specificity, quality > 0.0));
+        }
+    }
+    best.map(|(_, accepted)| accepted).unwrap_or(false)
+}
+
+#[cfg(feature = "http")]
+mod headers {
+    use super::{accepts_nachricht, is_media_type, MEDIA_TYPE};
+    use http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+
+    /// `MEDIA_TYPE` as the value of a `Content-Type` header
+    pub fn content_type() -> HeaderValue {
+        HeaderValue::from_static(MEDIA_TYPE)
+    }
+
+    /// Whether the `Content-Type` header denotes `MEDIA_TYPE`.
+    pub fn has_media_type(headers: &HeaderMap) -> bool {
+        headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(is_media_type).unwrap_or(false)
+    }
+
+    /// Whether the `Accept` headers allow `MEDIA_TYPE`. Without any, everything is acceptable.
+    pub fn accepts(headers: &HeaderMap) -> bool {
+        let values = headers.get_all(ACCEPT).iter().filter_map(|v| v.to_str().ok()).collect::<Vec<_>>();
+        values.is_empty() || accepts_nachricht(&values.join(","))
+    }
+}
+
+#[cfg(feature = "http")]
+pub use headers::*;
+
+#[cfg(test)]
+mod test {
+    use super::{accepts_nachricht, is_media_type, is_nachricht};
+    use crate::header::Header;
+
+    #[test]
+    fn sniff() {
+        let mut buf = Vec::new();
+        Header::Arr(1).encode(&mut buf).unwrap();
+        assert!(!is_nachricht(&buf));
+        Header::Null.encode(&mut buf).unwrap();
+        assert!(is_nachricht(&buf));
+        assert!(!is_nachricht(b"{\"a\": 1}"));
+        assert!(!is_nachricht(&[]));
+    }
+
+    #[test]
+    fn negotiation() {
+        assert!(is_media_type(" application/x-nachricht "));
+        assert!(!is_media_type("application/json; profile=application/x-nachricht"));
+        assert!(accepts_nachricht("*/*"));
+        assert!(accepts_nachricht("text/html, application/X-NACHRICHT; q=0.1"));
+        assert!(!accepts_nachricht("application/json"));
+        assert!(!accepts_nachricht(""));
+        assert!(!accepts_nachricht("application/*;q=0, */*"));
+        assert!(accepts_nachricht("application/*;q=0, application/x-nachricht"));
+        assert!(!accepts_nachricht("application/x-nachricht;q=nonsense"));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn headers() {
+        use http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+        let mut headers = HeaderMap::new();
+        assert!(super::accepts(&headers));
+        assert!(!super::has_media_type(&headers));
+        headers.insert(CONTENT_TYPE, super::content_type());
+        headers.append(ACCEPT, HeaderValue::from_static("application/json"));
+        assert!(super::has_media_type(&headers));
+        assert!(!super::accepts(&headers));
+        headers.append(ACCEPT, HeaderValue::from_static("application/x-nachricht"));
+        assert!(super::accepts(&headers));
+    }
+
+}
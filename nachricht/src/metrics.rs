@@ -0,0 +1,95 @@
+//! Hooks for exporting format-level metrics like message sizes and the time spent encoding and decoding them without
+//! wrapping every call site. A `Metrics` implementation is passed in `EncoderOptions::metrics` or
+//! `DecoderOptions::metrics` and invoked for every value encoded or decoded with these options, including by
+//! `nachricht-serde`. Without one, measuring costs a single branch per call.
+
+use std::fmt::{self, Debug, Formatter};
+use std::time::{Duration, Instant};
+
+/// Receives the size and duration of every encoding and decoding operation. Implementations are called from whichever
+/// thread performs the operation and should be cheap, e.g. update counters or histograms.
+pub trait Metrics: Sync {
+
+    /// A value has been encoded into `bytes` bytes.
+    fn encoded(&self, bytes: usize, elapsed: Duration);
+
+    /// A value has been decoded from `bytes` bytes.
+    fn decoded(&self, bytes: usize, elapsed: Duration);
+
+}
+
+/// Hooks are compared by identity, so that options are equal if they report to the same hooks.
+impl PartialEq for dyn Metrics {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq((self as *const Self).cast::<u8>(), (other as *const Self).cast::<u8>())
+    }
+}
+
+impl Debug for dyn Metrics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+/// Run `operation`, which returns its result and the amount of bytes it processed, and pass both the amount and the
+/// elapsed time to `report` if `metrics` is set. Failed operations are not reported. Encoders and decoders outside of
+/// this crate use this to report to the hooks in their options as well.
+pub fn measure<T, E>(metrics: Option<&'static dyn Metrics>, report: fn(&'static dyn Metrics, usize, Duration), operation: impl FnOnce() -> Result<(T, usize), E>) -> Result<T, E> {
+    match metrics {
+        None          => operation().map(|(result, _)| result),
+        Some(metrics) => {
+            let start = Instant::now();
+            let (result, bytes) = operation()?;
+            report(metrics, bytes, start.elapsed());
+            Ok(result)
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Metrics;
+    use crate::{Decoder, DecoderOptions, Encoder, EncoderOptions, Value};
+    use std::borrow::Cow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    struct Counter {
+        encoded: AtomicUsize,
+        decoded: AtomicUsize,
+    }
+
+    impl Metrics for Counter {
+
+        fn encoded(&self, bytes: usize, _elapsed: Duration) {
+            self.encoded.fetch_add(bytes, Ordering::SeqCst);
+        }
+
+        fn decoded(&self, bytes: usize, _elapsed: Duration) {
+            self.decoded.fetch_add(bytes, Ordering::SeqCst);
+        }
+
+    }
+
+    static COUNTER: Counter = Counter { encoded: AtomicUsize::new(0), decoded: AtomicUsize::new(0) };
+
+    #[test]
+    fn metrics() {
+        let value = Value::Bytes(Cow::Owned(vec![0; 12_345]));
+        let mut buf = Vec::new();
+        Encoder::encode(&value, &mut buf).unwrap();
+        Decoder::decode(&buf).unwrap();
+        assert_eq!(0, COUNTER.encoded.load(Ordering::SeqCst));
+        let encoder = EncoderOptions { metrics: Some(&COUNTER), ..Default::default() };
+        Encoder::encode_with_options(&value, &mut Vec::new(), encoder).unwrap();
+        Encoder::with_options(&mut Vec::new(), encoder).encode_next(&value).unwrap();
+        let decoder = DecoderOptions { metrics: Some(&COUNTER), ..Default::default() };
+        assert!(Decoder::decode_with_options(&buf[..100], decoder).is_err());
+        Decoder::decode_with_options(&buf, decoder).unwrap();
+        assert_eq!(2 * buf.len(), COUNTER.encoded.load(Ordering::SeqCst));
+        assert_eq!(buf.len(), COUNTER.decoded.load(Ordering::SeqCst));
+        assert_eq!(encoder, EncoderOptions { metrics: Some(&COUNTER), ..Default::default() });
+        assert_ne!(encoder, EncoderOptions::default());
+    }
+
+}
@@ -0,0 +1,191 @@
+//! Decode only the parts of a message which are actually needed. Services which consume a handful of fields out of
+//! hundreds select them by paths in the syntax of `redact`, everything else is skipped at header level with
+//! `Reader::skip` instead of being materialized:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let cat = Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+//!     (Cow::Borrowed("photo"), Value::Bytes(Cow::Owned(vec![0; 4096]))),
+//! ]));
+//! let mut buf = Vec::new();
+//! Encoder::encode(&Value::Array(vec![cat]), &mut buf).unwrap();
+//!
+//! let (value, _) = decode_selected(&buf, &["*.name"]).unwrap();
+//! assert_eq!(Some(&Value::Str(Cow::Borrowed("Jessica"))), value.pointer("/0/name"));
+//! assert_eq!(None, value.pointer("/0/photo"));
+//! ```
+//!
+//! Records and maps only contain the selected fields and entries. Arrays keep their length so that indices remain
+//! valid, with `Value::Null` in place of the elements which have not been selected. A selected value is decoded
+//! completely, including everything nested within it.
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::{DecodeError, DecoderError};
+use crate::reader::{allocate, Reader, Token};
+use crate::value::{Decoder, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// The remainders of all paths which reach a value, or `None` if the value is selected completely
+type States<'p> = Option<Vec<&'p [&'p str]>>;
+
+/// Decode a single value like `Decoder::decode` does, but only materialize the parts which are selected by any of the
+/// paths. Returns the value and the number of consumed bytes. Values nested deeper than `Decoder::MAX_DEPTH` are
+/// rejected, unless they are skipped.
+pub fn decode_selected<'a, B: ?Sized + AsRef<[u8]>>(buf: &'a B, paths: &[&str]) -> Result<(Value<'a>, usize), DecoderError> {
+    let paths = paths.iter().map(|p| p.split('.').collect::<Vec<_>>()).collect::<Vec<_>>();
+    let mut reader = Reader::new(buf);
+    let value = select(&mut reader, Some(paths.iter().map(Vec::as_slice).collect()), 0).map_err(|e| e.at(reader.position()))?;
+    Ok((value, reader.position()))
+}
+
+/// The states of a child with the given segment, or `None` if it is not selected
+fn descend<'p>(states: &States<'p>, segment: &str) -> Option<States<'p>> {
+    let states = match states {
+        Some(states) => states,
+        None => return Some(None),
+    };
+    let next = states.iter().filter_map(|s| match s {
+        ["**", ..] => Some(*s),
+        [first, rest @ ..] if *first == "*" || *first == segment => Some(rest),
+        _ => None,
+    }).collect::<Vec<_>>();
+    if next.is_empty() { None } else { Some(Some(next)) }
+}
+
+fn select<'a>(reader: &mut Reader<'a>, states: States, depth: usize) -> Result<Value<'a>, DecodeError> {
+    let states = states.and_then(|mut states| {
+        let mut i = 0;
+        while let Some(state) = states.get(i).copied() {
+            if let ["**", rest @ ..] = state {
+                states.push(rest);
+            }
+            i += 1;
+        }
+        if states.iter().any(|s| s.is_empty()) { None } else { Some(states) }
+    });
+    let token = reader.next_token()?;
+    if matches!(token, Token::Arr(_) | Token::Map(_) | Token::Rec(_)) && depth == Decoder::MAX_DEPTH {
+        return Err(DecodeError::Depth(Decoder::MAX_DEPTH));
+    }
+    Ok(match token {
+        Token::Null      => Value::Null,
+        Token::Bool(b)   => Value::Bool(b),
+        Token::F32(v)    => Value::F32(v),
+        Token::F64(v)    => Value::F64(v),
+        Token::Bin(v)    => Value::Bytes(Cow::Borrowed(v)),
+        Token::Int(s, v) => Value::Int(s, v),
+        Token::Str(v)    => Value::Str(Cow::Borrowed(v)),
+        Token::Sym(v)    => Value::Symbol(Cow::Borrowed(v)),
+        Token::Arr(len)  => {
+            let mut elements = allocate(len, reader.remaining())?;
+            for i in 0..len {
+                elements.push(match descend(&states, &i.to_string()) {
+                    Some(next) => select(reader, next, depth + 1)?,
+                    None => { reader.skip()?; Value::Null },
+                });
+            }
+            Value::Array(elements)
+        },
+        Token::Map(len)  => {
+            let mut entries = Vec::new();
+            for _ in 0..len {
+                let key = select(reader, None, depth + 1)?;
+                let segment = match &key {
+                    Value::Str(k) | Value::Symbol(k) => k.as_ref(),
+                    _ => "",
+                };
+                match descend(&states, segment) {
+                    Some(next) => { entries.push((key, select(reader, next, depth + 1)?)); },
+                    None => reader.skip()?,
+                }
+            }
+            Value::Map(entries)
+        },
+        Token::Rec(keys) => {
+            let mut fields = BTreeMap::new();
+            for key in keys.iter() {
+                match descend(&states, key) {
+                    Some(next) => { fields.insert(Cow::Borrowed(*key), select(reader, next, depth + 1)?); },
+                    None => reader.skip()?,
+                }
+            }
+            Value::Record(fields)
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::decode_selected;
+    use crate::error::DecodeError;
+    use crate::header::{Header, Sign};
+    use crate::value::{Decoder, Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn record(fields: Vec<(&'static str, Value<'static>)>) -> Value<'static> {
+        Value::Record(fields.into_iter().map(|(k, v)| (Cow::Borrowed(k), v)).collect::<BTreeMap<_, _>>())
+    }
+
+    fn cat(name: &'static str, lives: u64) -> Value<'static> {
+        record(vec![
+            ("name", Value::Str(Cow::Borrowed(name))),
+            ("lives", Value::Int(Sign::Pos, lives)),
+            ("species", Value::Symbol(Cow::Borrowed("FelisCatus"))),
+        ])
+    }
+
+    fn message() -> Value<'static> {
+        record(vec![
+            ("cats", Value::Array(vec![cat("Jessica", 9), cat("Wantan", 7)])),
+            ("owner", Value::Map(vec![(Value::Str(Cow::Borrowed("name")), Value::Str(Cow::Borrowed("Liv")))])),
+        ])
+    }
+
+    #[test]
+    fn selected() {
+        let mut buf = Vec::new();
+        Encoder::encode(&message(), &mut buf).unwrap();
+        let (value, c) = decode_selected(&buf, &["cats.*.name", "cats.1.species"]).unwrap();
+        assert_eq!(buf.len(), c);
+        let expected = record(vec![("cats", Value::Array(vec![
+            record(vec![("name", Value::Str(Cow::Borrowed("Jessica")))]),
+            // the symbol was defined within the skipped field of the first cat
+            record(vec![("name", Value::Str(Cow::Borrowed("Wantan"))), ("species", Value::Symbol(Cow::Borrowed("FelisCatus")))]),
+        ]))]);
+        assert_eq!(expected, value);
+        let (value, _) = decode_selected(&buf, &["cats.1"]).unwrap();
+        assert_eq!(record(vec![("cats", Value::Array(vec![Value::Null, cat("Wantan", 7)]))]), value);
+        assert_eq!(message(), decode_selected(&buf, &["**"]).unwrap().0);
+        assert_eq!(message(), decode_selected(&buf, &["cats", "owner"]).unwrap().0);
+        let (value, _) = decode_selected(&buf, &["**.name"]).unwrap();
+        assert_eq!(Some(&Value::Str(Cow::Borrowed("Liv"))), value.pointer("/owner/name"));
+        assert_eq!(record(vec![]), decode_selected(&buf, &[]).unwrap().0);
+        assert_eq!(Value::Int(Sign::Pos, 9), decode_selected(&[0x28, 0x09], &["missing"]).unwrap().0);
+    }
+
+    #[test]
+    fn errors() {
+        let mut buf = Vec::new();
+        Encoder::encode(&message(), &mut buf).unwrap();
+        let error = decode_selected(&buf[..buf.len() - 1], &["cats"]).unwrap_err();
+        assert_eq!(DecodeError::Eof, error.into_inner());
+        // skipped values are not subject to the depth limit
+        let mut buf = Vec::new();
+        Header::Arr(2).encode(&mut buf).unwrap();
+        for _ in 0..=Decoder::MAX_DEPTH {
+            Header::Arr(1).encode(&mut buf).unwrap();
+        }
+        Header::Null.encode(&mut buf).unwrap();
+        Header::Null.encode(&mut buf).unwrap();
+        assert_eq!(Value::Array(vec![Value::Null, Value::Null]), decode_selected(&buf, &["1"]).unwrap().0);
+        assert_eq!(DecodeError::Depth(Decoder::MAX_DEPTH), decode_selected(&buf, &["0"]).unwrap_err().into_inner());
+    }
+
+}
@@ -0,0 +1,117 @@
+//! Overwrite a scalar within an encoded message without encoding the whole message again, e.g. to bump a sequence
+//! number in a template before sending it. The new value has to fit into the bytes of the old one. If it is shorter,
+//! its header gets spread over more bytes than necessary, which decoders accept just like the canonical encoding.
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let message = Value::Record(BTreeMap::from([(Cow::Borrowed("seq"), Value::Int(Sign::Pos, 1000))]));
+//! let mut buf = Vec::new();
+//! Encoder::encode(&message, &mut buf).unwrap();
+//! patch(&mut buf, "/seq", &Value::Int(Sign::Pos, 7)).unwrap();
+//! assert_eq!(Some(&Value::Int(Sign::Pos, 7)), Decoder::decode(&buf).unwrap().0.pointer("/seq"));
+//! ```
+//!
+//! Symbols can neither be patched nor patched in since that would change the symbol table, and thereby the meaning of
+//! all references which follow.
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::PatchError;
+use crate::header::Header;
+use crate::spanned::{Node, Spanned};
+use crate::value::Value;
+
+/// Replace the scalar at the given JSON pointer, as understood by `Value::pointer`, with `value`. Both have to be
+/// scalars other than symbols. On error, the buffer is left untouched.
+pub fn patch(buf: &mut [u8], pointer: &str, value: &Value) -> Result<(), PatchError> {
+    let span = {
+        let (spanned, _) = Spanned::decode(&*buf)?;
+        let target = spanned.pointer(pointer).ok_or_else(|| PatchError::Missing(pointer.to_owned()))?;
+        match &target.node {
+            Node::Scalar(Value::Symbol(_)) => return Err(PatchError::Type("symbol")),
+            Node::Scalar(_) => target.span.clone(),
+            Node::Array(_) => return Err(PatchError::Type("array")),
+            Node::Map(_) => return Err(PatchError::Type("map")),
+            Node::Record(_) => return Err(PatchError::Type("record")),
+        }
+    };
+    let (header, payload) = match value {
+        Value::Null        => (Header::Null, Vec::new()),
+        Value::Bool(true)  => (Header::True, Vec::new()),
+        Value::Bool(false) => (Header::False, Vec::new()),
+        Value::F32(v)      => (Header::F32, v.to_be_bytes().to_vec()),
+        Value::F64(v)      => (Header::F64, v.to_be_bytes().to_vec()),
+        Value::Int(s, v)   => (Header::Int(*s, *v), Vec::new()),
+        Value::Bytes(v)    => (Header::Bin(v.len()), v.to_vec()),
+        Value::Str(v)      => (Header::Str(v.len()), v.as_bytes().to_vec()),
+        _                  => return Err(PatchError::Type(value.typename())),
+    };
+    let size = span.len();
+    let len = size.checked_sub(payload.len()).ok_or(PatchError::Size(size))?;
+    let header = header.to_bytes_padded(len).ok_or(PatchError::Size(size))?;
+    let target = buf.get_mut(span).ok_or(PatchError::Size(size))?;
+    let (head, tail) = target.split_at_mut(len);
+    head.copy_from_slice(header.get(..len).unwrap_or_default());
+    tail.copy_from_slice(&payload);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::patch;
+    use crate::error::PatchError;
+    use crate::header::Sign;
+    use crate::value::{Decoder, Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn message() -> Vec<u8> {
+        let value = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("seq"), Value::Int(Sign::Pos, 100_000)),
+            (Cow::Borrowed("from"), Value::Str(Cow::Borrowed("Jessica"))),
+            (Cow::Borrowed("to"), Value::Symbol(Cow::Borrowed("Wantan"))),
+            (Cow::Borrowed("cats"), Value::Array(vec![Value::F64(1.5)])),
+        ]));
+        let mut buf = Vec::new();
+        Encoder::encode(&value, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn scalars() {
+        let mut buf = message();
+        let len = buf.len();
+        for value in [Value::Int(Sign::Pos, 100_001), Value::Int(Sign::Neg, 1), Value::Int(Sign::Pos, 0)] {
+            patch(&mut buf, "/seq", &value).unwrap();
+            assert_eq!(Some(&value), Decoder::decode(&buf).unwrap().0.pointer("/seq"));
+        }
+        let value = Value::Str(Cow::Borrowed("Sphinx"));
+        patch(&mut buf, "/from", &value).unwrap();
+        assert_eq!(Some(&value), Decoder::decode(&buf).unwrap().0.pointer("/from"));
+        patch(&mut buf, "/cats/0", &Value::F64(2.5)).unwrap();
+        patch(&mut buf, "/cats/0", &Value::Int(Sign::Neg, 1 << 40)).unwrap();
+        let decoded = Decoder::decode(&buf).unwrap();
+        assert_eq!(Some(&Value::Int(Sign::Neg, 1 << 40)), decoded.0.pointer("/cats/0"));
+        assert_eq!(len, decoded.1);
+    }
+
+    #[test]
+    fn errors() {
+        let mut buf = message();
+        let original = buf.clone();
+        assert_eq!(Err(PatchError::Size(4)), patch(&mut buf, "/seq", &Value::Int(Sign::Pos, u64::MAX)));
+        assert_eq!(Err(PatchError::Size(4)), patch(&mut buf, "/seq", &Value::F32(1.0)));
+        assert_eq!(Err(PatchError::Size(4)), patch(&mut buf, "/seq", &Value::Null));
+        assert_eq!(Err(PatchError::Size(8)), patch(&mut buf, "/from", &Value::Str(Cow::Borrowed("Chandra!"))));
+        assert_eq!(Err(PatchError::Type("symbol")), patch(&mut buf, "/to", &Value::Null));
+        assert_eq!(Err(PatchError::Type("array")), patch(&mut buf, "/cats", &Value::Null));
+        assert_eq!(Err(PatchError::Type("symbol")), patch(&mut buf, "/seq", &Value::Symbol(Cow::Borrowed("x"))));
+        assert_eq!(Err(PatchError::Missing("/dogs".into())), patch(&mut buf, "/dogs", &Value::Null));
+        assert!(matches!(patch(&mut buf[..3], "/seq", &Value::Null), Err(PatchError::Decode(_))));
+        assert_eq!(original, buf);
+    }
+
+}
@@ -0,0 +1,196 @@
+//! Encrypt selected fields of a value while leaving the rest readable, so that routing layers can still inspect a
+//! message whose sensitive parts are protected. Fields are selected by the same paths as used by `redact`. Each one is
+//! replaced with `Value::Bytes` containing its encrypted canonical encoding.
+//!
+//! `protect` returns the concrete paths of all fields it encrypted as lists of `PathSegment`s, which address record
+//! fields by name and array elements and map entries by position. They need to be passed on to `unprotect`, e.g. in an
+//! envelope next to the message, since an encrypted field is indistinguishable from regular bytes otherwise. Unlike the
+//! patterns, they are resolved literally, so keys containing dots or named like wildcards are no problem.
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! struct Rot1;
+//!
+//! impl Cipher for Rot1 {
+//!     fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+//!         plaintext.iter().map(|b| b.wrapping_add(1)).collect()
+//!     }
+//!     fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+//!         Some(ciphertext.iter().map(|b| b.wrapping_sub(1)).collect())
+//!     }
+//! }
+//!
+//! let original = Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("to"), Value::Symbol(Cow::Borrowed("billing"))),
+//!     (Cow::Borrowed("iban"), Value::Str(Cow::Borrowed("DE02120300000000202051"))),
+//! ]));
+//! let mut value = original.clone();
+//! let protected = protect(&mut value, &["iban"], &Rot1).unwrap();
+//! assert_eq!(vec![vec![PathSegment::Field("iban".to_string())]], protected);
+//! unprotect(&mut value, &protected, &Rot1).unwrap();
+//! assert_eq!(original, value);
+//! ```
+//!
+//! This crate does not ship any cryptography. Bring your own by implementing `Cipher`.
+
+use crate::error::{EncodeError, ProtectError};
+use crate::redact::{select, PathSegment};
+use crate::value::{Decoder, Encoder, Value};
+use std::borrow::Cow;
+
+/// Encrypts and decrypts protected fields.
+pub trait Cipher {
+    /// Encrypt the given plaintext.
+    fn encrypt(&self, plaintext: &[u8]) -> Vec<u8>;
+    /// Decrypt the given ciphertext. Returns `None` if it is invalid, e.g. because authentication failed.
+    fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Encrypt all fields which match any of the paths. Returns the concrete paths of the encrypted fields. Fields within
+/// an encrypted field are not matched any further.
+pub fn protect(value: &mut Value, paths: &[&str], cipher: &dyn Cipher) -> Result<Vec<Vec<PathSegment>>, EncodeError> {
+    let mut protected = Vec::new();
+    select(value, paths, |field, path| -> Result<(), EncodeError> {
+        let mut buf = Vec::new();
+        Encoder::encode(field, &mut buf)?;
+        *field = Value::Bytes(Cow::Owned(cipher.encrypt(&buf)));
+        protected.push(path.to_vec());
+        Ok(())
+    })?;
+    Ok(protected)
+}
+
+/// Decrypt the fields at the given paths as returned by `protect`. Fails if any of them is missing or cannot be
+/// decrypted, in which case the value may have been partially decrypted.
+pub fn unprotect(value: &mut Value, protected: &[Vec<PathSegment>], cipher: &dyn Cipher) -> Result<(), ProtectError> {
+    for path in protected {
+        let display = || path.iter().map(PathSegment::to_string).collect::<Vec<_>>().join(".");
+        let field = resolve(value, path).ok_or_else(|| ProtectError::NotProtected(display()))?;
+        let plaintext = match field {
+            Value::Bytes(ciphertext) => cipher.decrypt(ciphertext).ok_or_else(|| ProtectError::Decrypt(display()))?,
+            _ => return Err(ProtectError::NotProtected(display())),
+        };
+        *field = Decoder::decode(&plaintext)?.0.into_owned();
+    }
+    Ok(())
+}
+
+/// The value at the concrete path
+fn resolve<'v, 'a>(value: &'v mut Value<'a>, path: &[PathSegment]) -> Option<&'v mut Value<'a>> {
+    path.iter().try_fold(value, |value, segment| match (value, segment) {
+        (Value::Record(fields), PathSegment::Field(name)) => fields.get_mut(name.as_str()),
+        (Value::Array(elements), PathSegment::Index(i)) => elements.get_mut(*i),
+        (Value::Map(entries), PathSegment::Index(i)) => entries.get_mut(*i).map(|(_, v)| v),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{protect, unprotect, Cipher};
+    use crate::error::ProtectError;
+    use crate::redact::PathSegment;
+    use crate::header::Sign;
+    use crate::value::Value;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    /// Fails to decrypt if the key is zero
+    struct Xor(u8);
+
+    impl Cipher for Xor {
+        fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+            plaintext.iter().map(|b| b ^ self.0).collect()
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+            match self.0 {
+                0 => None,
+                _ => Some(self.encrypt(ciphertext)),
+            }
+        }
+    }
+
+    fn payment() -> Value<'static> {
+        let account = |owner, iban| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("owner"), Value::Str(Cow::Borrowed(owner))),
+            (Cow::Borrowed("iban"), Value::Str(Cow::Borrowed(iban))),
+        ]));
+        Value::Record(BTreeMap::from([
+            (Cow::Borrowed("amount"), Value::Int(Sign::Pos, 100)),
+            (Cow::Borrowed("accounts"), Value::Array(vec![
+                account("Liv", "DE02120300000000202051"),
+                account("Jessica", "DE02500105170137075030"),
+            ])),
+        ]))
+    }
+
+    fn field(name: &str) -> PathSegment {
+        PathSegment::Field(name.to_string())
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut value = payment();
+        let protected = protect(&mut value, &["accounts.*.iban", "amount"], &Xor(0x55)).unwrap();
+        assert_eq!(3, protected.len());
+        assert!(protected.contains(&vec![field("accounts"), PathSegment::Index(1), field("iban")]));
+        assert!(matches!(&value, Value::Record(r) if matches!(r["amount"], Value::Bytes(_))));
+        assert_ne!(payment(), value);
+        unprotect(&mut value, &protected, &Xor(0x55)).unwrap();
+        assert_eq!(payment(), value);
+    }
+
+    #[test]
+    fn failures() {
+        let mut value = payment();
+        assert_eq!(Err(ProtectError::NotProtected("amount".to_string())), unprotect(&mut value, &[vec![field("amount")]], &Xor(0x55)));
+        assert_eq!(Err(ProtectError::NotProtected("missing".to_string())), unprotect(&mut value, &[vec![field("missing")]], &Xor(0x55)));
+        assert_eq!(Err(ProtectError::NotProtected("accounts.amount".to_string())),
+            unprotect(&mut value, &[vec![field("accounts"), field("amount")]], &Xor(0x55)));
+        let protected = protect(&mut value, &["amount"], &Xor(0)).unwrap();
+        assert_eq!(Err(ProtectError::Decrypt("amount".to_string())), unprotect(&mut value, &protected, &Xor(0)));
+        if let Value::Record(r) = &mut value {
+            r.insert(Cow::Borrowed("amount"), Value::Bytes(Cow::Borrowed(&[])));
+        }
+        assert!(matches!(unprotect(&mut value, &protected, &Xor(0x55)), Err(ProtectError::Decode(_))));
+    }
+
+    #[test]
+    fn literal_paths() {
+        let int = |i| Value::Int(Sign::Pos, i);
+        let original = Value::Array(vec![
+            Value::Record(BTreeMap::from([
+                (Cow::Borrowed("a.b"), int(1)),
+                (Cow::Borrowed("*"), int(2)),
+                (Cow::Borrowed("**"), int(3)),
+            ])),
+            Value::Map(vec![(int(1), int(4)), (Value::Null, int(5)), (int(1), int(6))]),
+        ]);
+        let mut value = original.clone();
+        let protected = protect(&mut value, &["0.*", "1.*"], &Xor(0x55)).unwrap();
+        let index = PathSegment::Index;
+        assert_eq!(vec![
+            vec![index(1), index(2)],
+            vec![index(1), index(1)],
+            vec![index(1), index(0)],
+            vec![index(0), field("a.b")],
+            vec![index(0), field("**")],
+            vec![index(0), field("*")],
+        ], protected);
+        // every path only decrypts its own field
+        for (i, path) in protected.iter().enumerate() {
+            unprotect(&mut value, std::slice::from_ref(path), &Xor(0x55)).unwrap();
+            assert_eq!(Err(ProtectError::NotProtected(path.iter().map(PathSegment::to_string).collect::<Vec<_>>().join("."))),
+                unprotect(&mut value, std::slice::from_ref(path), &Xor(0x55)));
+            if let Some(next) = protected.get(i + 1) {
+                assert!(unprotect(&mut value.clone(), std::slice::from_ref(next), &Xor(0x55)).is_ok());
+            }
+        }
+        assert_eq!(original, value);
+    }
+
+}
@@ -0,0 +1,450 @@
+//! A push-based decoder for integrations with non-blocking IO. Instead of handing over a complete buffer, bytes are fed
+//! as they arrive and the decoder emits an `Event` for every value it was able to decode completely. Incomplete input
+//! is kept until the next call to `PushDecoder::feed`.
+//!
+//! Containers are announced with their length and closed with `Event::End`. Record fields are announced as part of
+//! `Event::Record` and their values follow in the same order. Whenever the outermost value is complete, a message has
+//! been received and the symbol table gets reset, so a stream can consist of any number of independent messages.
+//!
+//! By default, symbols and record keys are emitted as `String`. An `Interner` can map them to application-side atoms
+//! instead. It is only consulted when a symbol gets defined, references to it yield a clone of the atom:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::collections::HashMap;
+//!
+//! #[derive(Default)]
+//! struct Atoms(HashMap<String, u32>);
+//!
+//! impl Interner for Atoms {
+//!     type Atom = u32;
+//!     fn intern(&mut self, symbol: &str) -> u32 {
+//!         let next = self.0.len() as u32;
+//!         *self.0.entry(symbol.to_owned()).or_insert(next)
+//!     }
+//! }
+//!
+//! let mut decoder = PushDecoder::with_interner(Atoms::default());
+//! // a record with the key `a` followed by another record which references its layout
+//! let events = decoder.feed(&[0x82, 0xa1, 0x61, 0x61, 0x01, 0xe1, 0x02]).unwrap();
+//! assert_eq!(Event::Record(vec![0]), events[1]);
+//! assert_eq!(Event::Record(vec![0]), events[4]);
+//! ```
+//!
+//! Like the other decoders, the push decoder never panics, no matter the input, which is enforced by the lints below.
+//! Open containers are tracked on the heap, so nesting does not consume any stack either.
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::{DecodeError, DecoderError};
+use crate::header::{Header, Sign};
+use crate::reader::allocate;
+use std::convert::TryInto;
+use std::str::from_utf8;
+
+/// Maps symbols and record keys to atoms, e.g. indices into an application-wide string table.
+pub trait Interner {
+    type Atom: Clone;
+    /// Return the atom for the given symbol.
+    fn intern(&mut self, symbol: &str) -> Self::Atom;
+}
+
+impl<T: Interner + ?Sized> Interner for &mut T {
+    type Atom = T::Atom;
+
+    fn intern(&mut self, symbol: &str) -> Self::Atom {
+        (**self).intern(symbol)
+    }
+}
+
+/// The default `Interner` which copies every symbol into a `String`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Owned;
+
+impl Interner for Owned {
+    type Atom = String;
+
+    fn intern(&mut self, symbol: &str) -> String {
+        symbol.to_owned()
+    }
+}
+
+/// A token emitted by the `PushDecoder`. Since input may be split across several buffers, all data is owned. Symbols
+/// and record keys are represented by the atoms of the decoder's `Interner`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<A = String> {
+    Null,
+    Bool(bool),
+    F32(f32),
+    F64(f64),
+    Bytes(Vec<u8>),
+    Int(Sign, u64),
+    Str(String),
+    Symbol(A),
+    /// An array with the given number of elements starts
+    Array(usize),
+    /// A map with the given number of entries starts, keys and values alternate
+    Map(usize),
+    /// A record with the given field names starts, the values follow in the same order
+    Record(Vec<A>),
+    /// The innermost open container is complete
+    End,
+}
+
+/// A decoded event and the number of consumed bytes, if the input was sufficient
+type Step<A> = Option<(Event<A>, usize)>;
+
+/// A record key which has been read but not interned yet
+enum Key<'b, A> {
+    /// A symbol defined by the key
+    New(&'b str),
+    /// A reference to a symbol defined before the record
+    Known(A),
+    /// A reference to a symbol defined by an earlier key of the same record
+    Pending(usize),
+}
+
+#[derive(Debug)]
+enum Entry<A> {
+    Sym(A),
+    Rec(Vec<A>),
+}
+
+/// Decodes `nachricht` values from input which arrives in arbitrary chunks.
+#[derive(Debug, Default)]
+pub struct PushDecoder<I: Interner = Owned> {
+    buf: Vec<u8>,
+    /// Number of bytes already removed from `buf`, used to report absolute error positions
+    offset: usize,
+    symbols: Vec<Entry<I::Atom>>,
+    /// Remaining values for each open container
+    stack: Vec<usize>,
+    interner: I,
+}
+
+impl PushDecoder {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+}
+
+impl<I: Interner> PushDecoder<I> {
+
+    /// Create a decoder which emits the atoms of the given interner for symbols and record keys.
+    pub fn with_interner(interner: I) -> Self {
+        Self { buf: Vec::new(), offset: 0, symbols: Vec::new(), stack: Vec::new(), interner }
+    }
+
+    /// Consume the decoder and return its interner.
+    pub fn into_interner(self) -> I {
+        self.interner
+    }
+
+    /// Feed the next chunk of input and return all events which could be decoded. Errors report the position within
+    /// the whole stream. After an error, the state of the decoder is unspecified.
+    pub fn feed(&mut self, input: &[u8]) -> Result<Vec<Event<I::Atom>>, DecoderError> {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.extend_from_slice(input);
+        let mut events = Vec::new();
+        let mut pos = 0;
+        let result = loop {
+            match self.step(buf.get(pos..).unwrap_or_default()) {
+                Ok(Some((event, c))) => {
+                    pos += c;
+                    self.push(event, &mut events);
+                },
+                Ok(None) => break Ok(events),
+                Err(e) => break Err(e.at(self.offset + pos)),
+            }
+        };
+        buf.drain(..pos);
+        self.buf = buf;
+        self.offset += pos;
+        result
+    }
+
+    /// Signal the end of input. Fails if the stream ended within a message.
+    pub fn finish(self) -> Result<(), DecoderError> {
+        if self.buf.is_empty() && self.stack.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::Eof.at(self.offset + self.buf.len()))
+        }
+    }
+
+    /// Returns true if no message is partially decoded.
+    pub fn is_idle(&self) -> bool {
+        self.buf.is_empty() && self.stack.is_empty()
+    }
+
+    fn push(&mut self, event: Event<I::Atom>, events: &mut Vec<Event<I::Atom>>) {
+        let len = match event {
+            Event::Array(v) => Some(v),
+            Event::Map(v) => Some(v.saturating_mul(2)),
+            Event::Record(ref v) => Some(v.len()),
+            _ => None,
+        };
+        events.push(event);
+        if let Some(len) = len {
+            self.stack.push(len);
+        } else {
+            self.complete();
+        }
+        while let Some(0) = self.stack.last() {
+            self.stack.pop();
+            events.push(Event::End);
+            self.complete();
+        }
+    }
+
+    /// Account for a completed value in the enclosing container, if any.
+    fn complete(&mut self) {
+        match self.stack.last_mut() {
+            Some(remaining) => { *remaining -= 1; },
+            None => { self.symbols.clear(); },
+        }
+    }
+
+    /// Try to decode the next event from the buffer. Returns `None` if more input is needed, in which case no state
+    /// has been modified.
+    fn step(&mut self, buf: &[u8]) -> Result<Step<I::Atom>, DecodeError> {
+        let (header, mut c) = match Header::decode(buf) {
+            Ok(h) => h,
+            Err(DecodeError::Eof) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let event = match header {
+            Header::Null      => Event::Null,
+            Header::True      => Event::Bool(true),
+            Header::False     => Event::Bool(false),
+            Header::F32       => match Self::slice(buf, &mut c, 4) {
+                Some(v) => Event::F32(<f32>::from_be_bytes(v.try_into().map_err(|_| DecodeError::Eof)?)),
+                None => return Ok(None),
+            },
+            Header::F64       => match Self::slice(buf, &mut c, 8) {
+                Some(v) => Event::F64(<f64>::from_be_bytes(v.try_into().map_err(|_| DecodeError::Eof)?)),
+                None => return Ok(None),
+            },
+            Header::Bin(v)    => match Self::slice(buf, &mut c, v) {
+                Some(v) => Event::Bytes(v.to_vec()),
+                None => return Ok(None),
+            },
+            Header::Int(s, v) => Event::Int(s, v),
+            Header::Str(v)    => match Self::slice(buf, &mut c, v) {
+                Some(v) => Event::Str(from_utf8(v)?.to_owned()),
+                None => return Ok(None),
+            },
+            Header::Sym(v)    => match Self::slice(buf, &mut c, v) {
+                Some(v) => {
+                    let sym = self.interner.intern(from_utf8(v)?);
+                    self.symbols.push(Entry::Sym(sym.clone()));
+                    Event::Symbol(sym)
+                },
+                None => return Ok(None),
+            },
+            Header::Arr(v)    => Event::Array(v),
+            Header::Map(v)    => Event::Map(v),
+            Header::Rec(v)    => {
+                // All keys need to be available at once, otherwise the symbol table would be modified by an
+                // incomplete record, and the interner would see the same keys again once the rest arrives. Keys are
+                // only ever symbols or references, so they are read right here instead of recursing into `step`,
+                // which would let nested record headers exhaust the stack.
+                let len = self.symbols.len();
+                let mut parsed = allocate(v, buf.get(c..).unwrap_or_default())?;
+                let mut defined = 0;
+                for _ in 0..v {
+                    let (header, d) = match Header::decode(buf.get(c..).unwrap_or_default()) {
+                        Ok(h) => h,
+                        Err(DecodeError::Eof) => return Ok(None),
+                        Err(e) => return Err(e),
+                    };
+                    c += d;
+                    parsed.push(match header {
+                        Header::Sym(v) => match Self::slice(buf, &mut c, v) {
+                            Some(v) => { defined += 1; Key::New(from_utf8(v)?) },
+                            None => return Ok(None),
+                        },
+                        Header::Ref(v) => match self.symbols.get(v) {
+                            Some(Entry::Sym(s)) => Key::Known(s.clone()),
+                            Some(Entry::Rec(_)) => return Err(DecodeError::IllegalKey("record")),
+                            // a key defined earlier within this record
+                            None if v.checked_sub(len).map_or(false, |i| i < defined) => Key::Pending(v),
+                            None => return Err(DecodeError::InvalidRef(v)),
+                        },
+                        header => return Err(DecodeError::IllegalKey(header.kind())),
+                    });
+                }
+                let mut keys = Vec::with_capacity(parsed.len());
+                for key in parsed {
+                    keys.push(match key {
+                        Key::New(symbol) => {
+                            let sym = self.interner.intern(symbol);
+                            self.symbols.push(Entry::Sym(sym.clone()));
+                            sym
+                        },
+                        Key::Known(sym) => sym,
+                        Key::Pending(v) => match self.symbols.get(v) {
+                            Some(Entry::Sym(s)) => s.clone(),
+                            _ => return Err(DecodeError::InvalidRef(v)),
+                        },
+                    });
+                }
+                self.symbols.push(Entry::Rec(keys.clone()));
+                Event::Record(keys)
+            },
+            Header::Ref(v)    => match self.symbols.get(v) {
+                Some(Entry::Sym(s)) => Event::Symbol(s.clone()),
+                Some(Entry::Rec(s)) => Event::Record(s.clone()),
+                None => return Err(DecodeError::InvalidRef(v)),
+            },
+        };
+        Ok(Some((event, c)))
+    }
+
+    fn slice<'b>(buf: &'b [u8], pos: &mut usize, len: usize) -> Option<&'b [u8]> {
+        let slice = buf.get(*pos..)?.get(..len)?;
+        *pos += len;
+        Some(slice)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, Interner, PushDecoder};
+    use crate::{DecodeError, Encoder, Sign, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn cats() -> Value<'static> {
+        Value::Array(vec![
+            Value::Record(BTreeMap::from([
+                (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+                (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("PrionailurusViverrinus"))),
+            ])),
+            Value::Record(BTreeMap::from([
+                (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Wantan"))),
+                (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("PrionailurusViverrinus"))),
+            ])),
+            Value::Array(vec![]),
+        ])
+    }
+
+    fn expected() -> Vec<Event> {
+        let layout = vec!["name".to_string(), "species".to_string()];
+        vec![
+            Event::Array(3),
+            Event::Record(layout.clone()),
+            Event::Str("Jessica".into()),
+            Event::Symbol("PrionailurusViverrinus".into()),
+            Event::End,
+            Event::Record(layout),
+            Event::Str("Wantan".into()),
+            Event::Symbol("PrionailurusViverrinus".into()),
+            Event::End,
+            Event::Array(0),
+            Event::End,
+            Event::End,
+        ]
+    }
+
+    #[test]
+    fn bytewise() {
+        let mut buf = Vec::new();
+        Encoder::encode(&cats(), &mut buf).unwrap();
+        let mut decoder = PushDecoder::new();
+        let mut events = Vec::new();
+        for b in buf.iter() {
+            events.extend(decoder.feed(&[*b]).unwrap());
+        }
+        assert!(decoder.is_idle());
+        decoder.finish().unwrap();
+        assert_eq!(expected(), events);
+    }
+
+    #[test]
+    fn multiple_messages() {
+        let mut buf = Vec::new();
+        Encoder::encode(&cats(), &mut buf).unwrap();
+        Encoder::encode(&Value::Int(Sign::Neg, 3), &mut buf).unwrap();
+        Encoder::encode(&cats(), &mut buf).unwrap();
+        let mut decoder = PushDecoder::new();
+        let events = decoder.feed(&buf).unwrap();
+        let mut expected = expected();
+        expected.push(Event::Int(Sign::Neg, 3));
+        expected.extend(self::expected());
+        assert_eq!(expected, events);
+    }
+
+    /// Numbers symbols in order of appearance
+    #[derive(Default)]
+    struct Counter(Vec<String>);
+
+    impl Interner for Counter {
+        type Atom = usize;
+
+        fn intern(&mut self, symbol: &str) -> usize {
+            self.0.push(symbol.to_owned());
+            self.0.len() - 1
+        }
+    }
+
+    #[test]
+    fn interner() {
+        let mut buf = Vec::new();
+        Encoder::encode(&cats(), &mut buf).unwrap();
+        let mut counter = Counter::default();
+        let events = PushDecoder::with_interner(&mut counter).feed(&buf).unwrap();
+        assert_eq!(vec!["name", "species", "PrionailurusViverrinus"], counter.0);
+        assert_eq!(Event::Record(vec![0, 1]), events[5]);
+        assert_eq!(Event::Symbol(2), events[7]);
+    }
+
+    #[test]
+    fn interner_bytewise() {
+        let mut buf = Vec::new();
+        Encoder::encode(&cats(), &mut buf).unwrap();
+        let mut counter = Counter::default();
+        let mut decoder = PushDecoder::with_interner(&mut counter);
+        let mut events = Vec::new();
+        for b in buf.iter() {
+            events.extend(decoder.feed(&[*b]).unwrap());
+        }
+        decoder.finish().unwrap();
+        // every symbol is interned once, no matter how the input is split
+        assert_eq!(vec!["name", "species", "PrionailurusViverrinus"], counter.0);
+        assert_eq!(Event::Record(vec![0, 1]), events[1]);
+        assert_eq!(Event::Record(vec![0, 1]), events[5]);
+    }
+
+    #[test]
+    fn key_references() {
+        // the second key refers to the first one, which is defined by the same record
+        let events = PushDecoder::new().feed(&[0xa2, 0x61, 0x61, 0xe0, 0x00, 0x01]).unwrap();
+        assert_eq!(Event::Record(vec!["a".to_string(), "a".to_string()]), events[0]);
+        assert!(PushDecoder::new().feed(&[0xa2, 0x61, 0x61, 0xe1, 0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn nested_keys() {
+        // every record header would be the key of the one before it
+        let buf = vec![0xa1; 1 << 20];
+        assert!(matches!(PushDecoder::new().feed(&buf).unwrap_err().into_inner(), DecodeError::IllegalKey("record")));
+        let mut decoder = PushDecoder::new();
+        assert!(decoder.feed(&[0xa2, 0x61]).unwrap().is_empty());
+        assert!(matches!(decoder.feed(&[0x61, 0x21]).unwrap_err().into_inner(), DecodeError::IllegalKey("integer")));
+    }
+
+    #[test]
+    fn errors() {
+        let mut decoder = PushDecoder::new();
+        assert!(decoder.feed(&[0x84, 0x21]).is_ok());
+        assert!(matches!(decoder.feed(&[7 << 5]).unwrap_err().into_inner(), DecodeError::InvalidRef(0)));
+        let mut decoder = PushDecoder::new();
+        decoder.feed(&[0x82, 0x21]).unwrap();
+        assert!(matches!(decoder.finish().unwrap_err().into_inner(), DecodeError::Eof));
+    }
+
+}
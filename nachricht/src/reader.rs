@@ -0,0 +1,499 @@
+//! The shared foundation of all decoders: a cursor over a buffer which yields one `Token` per header. A token carries
+//! the payload of its header and has all references resolved through the `SymbolTable` of the message, so consumers
+//! only need to deal with the structure of the value. Containers are announced with their length and their elements
+//! follow as separate tokens.
+//!
+//! No input makes the reader panic, which is enforced by the lints below. The same holds for everything built on top
+//! of it, see `Decoder::decode_untrusted`.
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::DecodeError;
+use crate::header::{Header, Sign};
+use crate::metrics::Metrics;
+use crate::symbols::{Refable, SymbolTable};
+use crate::value::Value;
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::mem::size_of;
+use std::rc::Rc;
+use std::str::from_utf8;
+
+/// A header together with its payload. References have been resolved into the symbol or record they refer to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<'a> {
+    Null,
+    Bool(bool),
+    F32(f32),
+    F64(f64),
+    Bin(&'a [u8]),
+    Int(Sign, u64),
+    Str(&'a str),
+    Sym(&'a str),
+    /// An array with the given number of elements
+    Arr(usize),
+    /// A map with the given number of entries, keys and values alternate
+    Map(usize),
+    /// A record with the given keys, one value per key follows. The keys are shared with the symbol table, so they are
+    /// only collected where a layout is defined and every reference to it reuses them. Collecting up to 16 keys on the
+    /// stack instead made no difference beyond noise in the `layouts` benchmark of `nachricht-serde`.
+    Rec(Rc<[&'a str]>),
+}
+
+impl<'a> Token<'a> {
+
+    /// Returns the mnemonic of the token. This is useful for error messages.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Token::Null     => "Null",
+            Token::Bool(_)  => "Bool",
+            Token::F32(_)   => "F32",
+            Token::F64(_)   => "F64",
+            Token::Bin(_)   => "Bin",
+            Token::Int(..)  => "Int",
+            Token::Str(_)   => "Str",
+            Token::Sym(_)   => "Sym",
+            Token::Arr(_)   => "Arr",
+            Token::Map(_)   => "Map",
+            Token::Rec(_)   => "Rec",
+        }
+    }
+
+}
+
+/// The estimated memory occupied by one field of a decoded record within its `BTreeMap`
+const FIELD_SIZE: usize = size_of::<(Cow<str>, Value)>();
+
+/// Options which influence which input is accepted by the decoders. The defaults only accept well-formed messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DecoderOptions {
+    /// Accept record keys of type `Str` and treat them like symbols. Encoders in other languages may not distinguish
+    /// between strings and symbols. Such keys do not become part of the symbol table since they have not been defined
+    /// as symbols on the wire.
+    pub str_keys: bool,
+    /// Reject messages whose symbol table grows beyond this many bytes, see `SymbolTable::size`. Since a layout can
+    /// consist of references to long symbols, a small message can otherwise define a lot of data which gets copied
+    /// whenever a value is materialized through a reference. `None` means no limit.
+    pub max_symbol_bytes: Option<usize>,
+    /// Reject messages whose decoded size, as estimated by `Reader::decoded_size`, exceeds this many bytes. `None`
+    /// means no limit.
+    pub max_decoded_bytes: Option<usize>,
+    /// Reject messages whose estimated decoded size exceeds this multiple of the length of the buffer. References allow
+    /// a tiny message to repeat a large layout many times, so that the decoded value gets much larger than the input.
+    /// `None` means no limit.
+    pub max_expansion: Option<usize>,
+    /// Report the size and duration of every decoded value to these hooks.
+    pub metrics: Option<&'static dyn Metrics>,
+}
+
+impl DecoderOptions {
+
+    /// The maximum decoded size for a buffer of the given length
+    fn budget(&self, len: usize) -> usize {
+        let expansion = self.max_expansion.map_or(usize::MAX, |factor| factor.saturating_mul(len));
+        self.max_decoded_bytes.unwrap_or(usize::MAX).min(expansion)
+    }
+
+}
+
+/// The result of `Reader::next_variant`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Variant<'a> {
+    /// A record with exactly one key, which is how enum variants with data are encoded. The value follows.
+    Rec(&'a str),
+    /// Any other token
+    Token(Token<'a>),
+}
+
+/// Allocate a vector for the `len` elements of a container whose encoding starts within `input`. Since every element
+/// occupies at least one byte, the capacity is bounded by the length of `input` so that a forged header can not make
+/// the decoder reserve more memory than the message could ever fill. A failing allocation is reported as
+/// `DecodeError::Allocation` instead of aborting the process.
+pub fn allocate<T>(len: usize, input: &[u8]) -> Result<Vec<T>, DecodeError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(len.min(input.len()))?;
+    Ok(vec)
+}
+
+/// Reads tokens from a buffer, see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    symbols: SymbolTable<'a>,
+    options: DecoderOptions,
+    /// The estimated decoded size so far
+    decoded: usize,
+    /// The maximum for `decoded`
+    budget: usize,
+}
+
+impl<'a> Reader<'a> {
+
+    pub fn new<B: ?Sized + AsRef<[u8]>>(buf: &'a B) -> Self {
+        Self::with_options(buf, DecoderOptions::default())
+    }
+
+    pub fn with_options<B: ?Sized + AsRef<[u8]>>(buf: &'a B, options: DecoderOptions) -> Self {
+        let buf = buf.as_ref();
+        Self { buf, pos: 0, symbols: SymbolTable::new(), options, decoded: 0, budget: options.budget(buf.len()) }
+    }
+
+    /// Continue reading a message at the given position, as if everything before it had been read and had defined the
+    /// given symbols. This allows random access to a value whose symbol table is known, see `Index`.
+    pub fn resume<B: ?Sized + AsRef<[u8]>>(buf: &'a B, position: usize, symbols: SymbolTable<'a>) -> Self {
+        Self { pos: position, symbols, ..Self::new(buf) }
+    }
+
+    /// The options this reader has been created with.
+    pub fn options(&self) -> DecoderOptions {
+        self.options
+    }
+
+    /// The amount of bytes which have been consumed so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// The part of the buffer which has not been consumed yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buf.get(self.pos..).unwrap_or_default()
+    }
+
+    /// An estimate of how much memory the tokens read so far occupy once decoded: every token accounts for the size of
+    /// a `Value` plus the length of its strings, bytes or record keys. Records additionally account for one map entry
+    /// per key since their fields end up in a `BTreeMap`, whose nodes can not be reserved up front.
+    pub fn decoded_size(&self) -> usize {
+        self.decoded
+    }
+
+    /// The symbols and record layouts defined so far.
+    pub fn symbols(&self) -> &SymbolTable<'a> {
+        &self.symbols
+    }
+
+    pub fn into_symbols(self) -> SymbolTable<'a> {
+        self.symbols
+    }
+
+    /// Decode the next header without consuming it.
+    pub fn peek(&self) -> Result<Header, DecodeError> {
+        Header::decode(self.remaining()).map(|(header, _)| header)
+    }
+
+    /// Consume the next header and its payload. The keys of a record are consumed as part of it and have to be
+    /// symbols, or strings if `DecoderOptions::str_keys` is set. After an error, the state of the reader is unspecified.
+    pub fn next_token(&mut self) -> Result<Token<'a>, DecodeError> {
+        let token = self.read_token()?;
+        let size = match &token {
+            Token::Bin(v) => v.len(),
+            Token::Str(v) | Token::Sym(v) => v.len(),
+            Token::Rec(keys) => keys.iter().map(|key| key.len() + FIELD_SIZE).sum(),
+            _ => 0,
+        };
+        self.spend(size)?;
+        Ok(token)
+    }
+
+    fn read_token(&mut self) -> Result<Token<'a>, DecodeError> {
+        Ok(match self.header()? {
+            Header::Null      => Token::Null,
+            Header::True      => Token::Bool(true),
+            Header::False     => Token::Bool(false),
+            Header::F32       => Token::F32(<f32>::from_be_bytes(self.array()?)),
+            Header::F64       => Token::F64(<f64>::from_be_bytes(self.array()?)),
+            Header::Bin(v)    => Token::Bin(self.slice(v)?),
+            Header::Int(s, v) => Token::Int(s, v),
+            Header::Str(v)    => Token::Str(from_utf8(self.slice(v)?)?),
+            Header::Sym(v)    => Token::Sym(self.symbol(v)?),
+            Header::Arr(v)    => Token::Arr(v),
+            Header::Map(v)    => Token::Map(v),
+            Header::Rec(v)    => {
+                let mut keys = allocate(v, self.remaining())?;
+                for _ in 0..v {
+                    keys.push(self.key()?);
+                }
+                let keys = Rc::<[&str]>::from(keys);
+                self.define(Refable::Rec(keys.clone()))?;
+                Token::Rec(keys)
+            },
+            Header::Ref(v)    => match self.symbols.resolve(v)? {
+                Refable::Sym(s) => Token::Sym(s),
+                Refable::Rec(keys) => Token::Rec(keys.clone()),
+            },
+        })
+    }
+
+    /// Like `next_token`, but a record with exactly one key is returned as just that key. Unlike `Token::Rec`, this
+    /// does not allocate if the record refers to a known layout.
+    pub fn next_variant(&mut self) -> Result<Variant<'a>, DecodeError> {
+        let (header, c) = Header::decode(self.remaining())?;
+        match header {
+            Header::Rec(1) => {
+                self.pos += c;
+                let key = self.key()?;
+                self.define(Refable::Rec(Rc::new([key])))?;
+                self.spend(key.len() + FIELD_SIZE)?;
+                Ok(Variant::Rec(key))
+            },
+            Header::Ref(v) => match self.symbols.resolve(v)? {
+                Refable::Rec(keys) => match **keys {
+                    [key] => {
+                        self.pos += c;
+                        self.spend(key.len() + FIELD_SIZE)?;
+                        Ok(Variant::Rec(key))
+                    },
+                    _ => self.next_token().map(Variant::Token),
+                },
+                Refable::Sym(_) => self.next_token().map(Variant::Token),
+            },
+            _ => self.next_token().map(Variant::Token),
+        }
+    }
+
+    /// Consume the next value without decoding it. Symbols and layouts within it are still added to the symbol table
+    /// since later values may refer to them, but strings are not validated and nothing counts towards the decoded size.
+    /// Nested containers are skipped without recursion.
+    pub fn skip(&mut self) -> Result<(), DecodeError> {
+        let mut remaining = 1usize;
+        while remaining > 0 {
+            remaining -= 1;
+            match Header::decode(self.remaining())? {
+                (Header::Bin(v), c) | (Header::Str(v), c) => {
+                    self.pos += c;
+                    self.slice(v)?;
+                },
+                _ => match self.read_token()? {
+                    Token::Arr(v) => { remaining = remaining.saturating_add(v); },
+                    Token::Map(v) => { remaining = remaining.saturating_add(v.saturating_mul(2)); },
+                    Token::Rec(keys) => { remaining = remaining.saturating_add(keys.len()); },
+                    _ => {},
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn key(&mut self) -> Result<&'a str, DecodeError> {
+        match self.header()? {
+            Header::Sym(v) => self.symbol(v),
+            Header::Ref(v) => self.symbols.symbol(v),
+            Header::Str(v) if self.options.str_keys => Ok(from_utf8(self.slice(v)?)?),
+            header => Err(DecodeError::IllegalKey(header.kind())),
+        }
+    }
+
+    fn symbol(&mut self, len: usize) -> Result<&'a str, DecodeError> {
+        let symbol = from_utf8(self.slice(len)?)?;
+        self.define(Refable::Sym(symbol))?;
+        Ok(symbol)
+    }
+
+    /// Account for a decoded value with the given amount of payload.
+    fn spend(&mut self, payload: usize) -> Result<(), DecodeError> {
+        self.decoded = self.decoded.saturating_add(size_of::<Value>()).saturating_add(payload);
+        if self.decoded > self.budget {
+            return Err(DecodeError::OutputLimit(self.budget));
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, entry: Refable<'a>) -> Result<(), DecodeError> {
+        if let Some(max) = self.options.max_symbol_bytes {
+            if self.symbols.size().saturating_add(entry.size()) > max {
+                return Err(DecodeError::SymbolLimit(max));
+            }
+        }
+        self.symbols.push(entry);
+        Ok(())
+    }
+
+    fn header(&mut self) -> Result<Header, DecodeError> {
+        let (header, c) = Header::decode(self.remaining())?;
+        self.pos += c;
+        Ok(header)
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let slice = self.remaining().get(..len).ok_or(DecodeError::Eof)?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        self.slice(N)?.try_into().map_err(|_| DecodeError::Eof)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::{allocate, DecoderOptions, Reader, Token, Variant};
+    use crate::error::DecodeError;
+    use crate::header::{Header, Sign};
+    use crate::value::Value;
+    use std::borrow::Cow;
+    use std::mem::size_of;
+
+    fn encode(headers: &[Header]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for header in headers {
+            header.encode(&mut buf).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn tokens() {
+        let mut buf = encode(&[Header::Arr(3), Header::Rec(1), Header::Sym(1)]);
+        buf.push(b'a');
+        buf.extend(encode(&[Header::Int(Sign::Neg, 1), Header::Ref(1), Header::Null, Header::Ref(0)]));
+        let mut reader = Reader::new(&buf);
+        assert_eq!(Header::Arr(3), reader.peek().unwrap());
+        let mut tokens = Vec::new();
+        while !reader.remaining().is_empty() {
+            tokens.push(reader.next_token().unwrap());
+        }
+        assert_eq!(vec![
+            Token::Arr(3),
+            Token::Rec(vec!["a"].into()),
+            Token::Int(Sign::Neg, 1),
+            Token::Rec(vec!["a"].into()),
+            Token::Null,
+            Token::Sym("a"),
+        ], tokens);
+        assert_eq!(2, reader.symbols().len());
+    }
+
+    #[test]
+    fn variants() {
+        let mut buf = encode(&[Header::Arr(4), Header::Rec(1), Header::Sym(1)]);
+        buf.push(b'a');
+        buf.extend(encode(&[Header::Null, Header::Ref(1), Header::Null, Header::Ref(0)]));
+        let mut reader = Reader::new(&buf);
+        let variants = (0..6).map(|_| reader.next_variant().unwrap()).collect::<Vec<_>>();
+        assert_eq!(vec![
+            Variant::Token(Token::Arr(4)),
+            Variant::Rec("a"),
+            Variant::Token(Token::Null),
+            Variant::Rec("a"),
+            Variant::Token(Token::Null),
+            Variant::Token(Token::Sym("a")),
+        ], variants);
+        assert_eq!(2, reader.symbols().len());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Err(DecodeError::IllegalKey("integer")), Reader::new(&encode(&[Header::Rec(1), Header::Int(Sign::Pos, 1)])).next_token());
+        let buf = encode(&[Header::Rec(0), Header::Rec(1), Header::Ref(0)]);
+        let mut reader = Reader::new(&buf);
+        assert_eq!(Ok(Token::Rec(vec![].into())), reader.next_token());
+        assert_eq!(Err(DecodeError::IllegalKey("record")), reader.next_token());
+        assert_eq!(Err(DecodeError::InvalidRef(0)), Reader::new(&encode(&[Header::Ref(0)])).next_token());
+        assert_eq!(Err(DecodeError::Eof), Reader::new(&encode(&[Header::Str(1)])).next_token());
+    }
+
+    #[test]
+    fn str_keys() {
+        let mut buf = encode(&[Header::Arr(2), Header::Rec(1), Header::Str(1)]);
+        buf.push(b'a');
+        buf.extend(encode(&[Header::Null, Header::Ref(0), Header::Null]));
+        assert_eq!(Err(DecodeError::IllegalKey("string")), Reader::new(&buf[1..]).next_token());
+        let mut reader = Reader::with_options(&buf, DecoderOptions { str_keys: true, ..Default::default() });
+        let tokens = (0..5).map(|_| reader.next_token().unwrap()).collect::<Vec<_>>();
+        assert_eq!(vec![Token::Arr(2), Token::Rec(vec!["a"].into()), Token::Null, Token::Rec(vec!["a"].into()), Token::Null], tokens);
+        // the key is not a symbol on the wire, so only the layout has been defined
+        assert_eq!(1, reader.symbols().len());
+    }
+
+    #[test]
+    fn symbol_limit() {
+        // a symbol of two bytes and a layout which refers to it three times
+        let mut buf = encode(&[Header::Arr(2), Header::Sym(2)]);
+        buf.extend(b"ab");
+        buf.extend(encode(&[Header::Rec(3), Header::Ref(0), Header::Ref(0), Header::Ref(0)]));
+        let options = |max| DecoderOptions { max_symbol_bytes: Some(max), ..Default::default() };
+        let mut reader = Reader::with_options(&buf, options(8));
+        let tokens = (0..3).map(|_| reader.next_token().unwrap()).collect::<Vec<_>>();
+        assert_eq!(vec![Token::Arr(2), Token::Sym("ab"), Token::Rec(vec!["ab", "ab", "ab"].into())], tokens);
+        assert_eq!(8, reader.symbols().size());
+        let mut reader = Reader::with_options(&buf, options(7));
+        assert_eq!(Ok(Token::Arr(2)), reader.next_token());
+        assert_eq!(Ok(Token::Sym("ab")), reader.next_token());
+        assert_eq!(Err(DecodeError::SymbolLimit(7)), reader.next_token());
+    }
+
+    #[test]
+    fn output_limit() {
+        // a layout with a long key which gets repeated by reference
+        let mut buf = encode(&[Header::Arr(100), Header::Rec(1), Header::Sym(100)]);
+        buf.extend([b'a'; 100]);
+        buf.push(Header::Null.to_bytes()[0]);
+        for _ in 1..100 {
+            buf.extend(encode(&[Header::Ref(1), Header::Null]));
+        }
+        let read_all = |options| {
+            let mut reader = Reader::with_options(&buf, options);
+            while !reader.remaining().is_empty() {
+                reader.next_token()?;
+            }
+            Ok(reader.decoded_size())
+        };
+        let decoded = read_all(DecoderOptions::default()).unwrap();
+        assert!(decoded > 100 * 100);
+        assert_eq!(Ok(decoded), read_all(DecoderOptions { max_decoded_bytes: Some(decoded), ..Default::default() }));
+        assert_eq!(Err(DecodeError::OutputLimit(decoded - 1)), read_all(DecoderOptions { max_decoded_bytes: Some(decoded - 1), ..Default::default() }));
+        assert_eq!(Err(DecodeError::OutputLimit(10 * buf.len())), read_all(DecoderOptions { max_expansion: Some(10), ..Default::default() }));
+    }
+
+    #[test]
+    fn record_size() {
+        // every key of a record accounts for a map entry, also if the layout is reused
+        let mut buf = encode(&[Header::Arr(2), Header::Rec(3)]);
+        buf.extend(encode(&[Header::Sym(1)]).iter().chain(b"a").chain(&encode(&[Header::Sym(1)])).chain(b"b"));
+        buf.extend(encode(&[Header::Sym(1)]).iter().chain(b"c"));
+        buf.extend(encode(&[Header::Null, Header::Null, Header::Null, Header::Ref(3), Header::Null, Header::Null, Header::Null]));
+        let mut reader = Reader::new(&buf);
+        let value = size_of::<Value>();
+        let field = size_of::<(Cow<str>, Value)>();
+        reader.next_token().unwrap();
+        assert_eq!(value, reader.decoded_size());
+        reader.next_token().unwrap();
+        assert_eq!(2 * value + 3 * (1 + field), reader.decoded_size());
+        (0..3).for_each(|_| { reader.next_token().unwrap(); });
+        reader.next_token().unwrap();
+        assert_eq!(6 * value + 6 * (1 + field), reader.decoded_size());
+    }
+
+    #[test]
+    fn skip() {
+        let mut buf = encode(&[Header::Arr(3), Header::Rec(1), Header::Sym(1)]);
+        buf.push(b'a');
+        buf.extend(encode(&[Header::Null, Header::Str(2)]));
+        // not valid utf-8, but skipped strings are not validated
+        buf.extend([0xff, 0xfe]);
+        buf.extend(encode(&[Header::Map(1), Header::Int(Sign::Pos, 1), Header::Arr(0), Header::Ref(1), Header::Ref(0)]));
+        let end = buf.len() - 2;
+        let mut reader = Reader::new(&buf);
+        reader.skip().unwrap();
+        assert_eq!(end, reader.position());
+        assert_eq!(0, reader.decoded_size());
+        // the layout and the symbol defined within the skipped value are known
+        assert_eq!(Token::Rec(vec!["a"].into()), reader.next_token().unwrap());
+        assert_eq!(Token::Sym("a"), reader.next_token().unwrap());
+        assert_eq!(Err(DecodeError::Eof), Reader::new(&buf[..end - 1]).skip());
+        let mut deep = encode(&[Header::Arr(1)]).repeat(100_000);
+        deep.extend(encode(&[Header::Null]));
+        Reader::new(&deep).skip().unwrap();
+    }
+
+    #[test]
+    fn allocations() {
+        // the capacity is bounded by the input, not by what the header claims
+        assert_eq!(3, allocate::<u64>(usize::MAX, &[0; 3]).unwrap().capacity());
+        assert_eq!(2, allocate::<u64>(2, &[0; 3]).unwrap().capacity());
+        assert!(allocate::<u8>(0, &[]).unwrap().is_empty());
+    }
+
+}
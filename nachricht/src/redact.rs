@@ -0,0 +1,193 @@
+//! Remove sensitive fields from a value, e.g. before it gets logged or persisted. Fields are selected by paths whose
+//! segments are separated by dots. A segment matches a record field or a map entry whose key is a string or symbol of
+//! the same name, or an array element by its index. Two wildcards are supported: `*` matches any single segment and
+//! `**` matches any number of segments, including none:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let user = |name, password| Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+//!     (Cow::Borrowed("password"), password),
+//! ]));
+//! let mut users = Value::Array(vec![user("Liv", Value::Str(Cow::Borrowed("hunter2")))]);
+//! assert_eq!(1, redact(&mut users, &["**.password"], &Redaction::Null).unwrap());
+//! assert_eq!(Value::Array(vec![user("Liv", Value::Null)]), users);
+//! ```
+//!
+//! Field names which contain dots can only be matched with wildcards.
+
+use crate::error::EncodeError;
+use crate::value::{Encoder, Value};
+use std::borrow::Cow;
+
+/// Produces a digest of a message, e.g. a cryptographic hash.
+pub trait Digest {
+    /// Compute the digest of the given message.
+    fn digest(&self, message: &[u8]) -> Vec<u8>;
+}
+
+/// What to replace a redacted field with.
+pub enum Redaction<'d> {
+    /// Replace the field with `Value::Null`.
+    Null,
+    /// Replace the field with `Value::Bytes` containing the digest of its canonical encoding. Equal values yield equal
+    /// digests, so redacted fields can still be correlated.
+    Hash(&'d dyn Digest),
+}
+
+impl<'d> Redaction<'d> {
+    fn apply(&self, value: &mut Value) -> Result<(), EncodeError> {
+        *value = match self {
+            Redaction::Null => Value::Null,
+            Redaction::Hash(digest) => {
+                let mut buf = Vec::new();
+                Encoder::encode(value, &mut buf)?;
+                Value::Bytes(Cow::Owned(digest.digest(&buf)))
+            },
+        };
+        Ok(())
+    }
+}
+
+/// Replace all fields which match any of the paths as specified by the redaction. Returns the number of replaced
+/// fields. Fields within a replaced field are not matched any further.
+pub fn redact(value: &mut Value, paths: &[&str], redaction: &Redaction) -> Result<usize, EncodeError> {
+    let mut count = 0;
+    select(value, paths, |field, _| { count += 1; redaction.apply(field) })?;
+    Ok(count)
+}
+
+/// A step from a container to one of its values within the concrete path of a field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// A record field by its name
+    Field(String),
+    /// An array element or a map entry by its position
+    Index(usize),
+}
+
+impl std::fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Field(name) => f.write_str(name),
+            PathSegment::Index(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+/// Call `f` with every field which matches any of the paths, together with the concrete path leading to it. Fields
+/// within a matching field are not visited.
+pub(crate) fn select<E>(value: &mut Value, paths: &[&str], mut f: impl FnMut(&mut Value, &[PathSegment]) -> Result<(), E>) -> Result<(), E> {
+    let paths = paths.iter().map(|p| p.split('.').collect::<Vec<_>>()).collect::<Vec<_>>();
+    // each value which needs to be visited together with its path and the remainders of all patterns which reach it
+    let mut stack = vec![(value, Vec::new(), paths.iter().map(Vec::as_slice).collect::<Vec<_>>())];
+    while let Some((value, path, mut states)) = stack.pop() {
+        let mut i = 0;
+        while i < states.len() {
+            if let ["**", rest @ ..] = states[i] {
+                states.push(rest);
+            }
+            i += 1;
+        }
+        if states.iter().any(|s| s.is_empty()) {
+            f(value, &path)?;
+            continue;
+        }
+        // the name which the patterns match and the segment of the concrete path
+        let mut push = |child, name: Option<&str>, segment: PathSegment| {
+            let next = states.iter().filter_map(|s| match s {
+                ["**", ..] => Some(*s),
+                [first, rest @ ..] if *first == "*" || Some(*first) == name => Some(rest),
+                _ => None,
+            }).collect::<Vec<_>>();
+            if !next.is_empty() {
+                let mut path = path.clone();
+                path.push(segment);
+                stack.push((child, path, next));
+            }
+        };
+        match value {
+            Value::Array(elements) => {
+                for (i, element) in elements.iter_mut().enumerate() {
+                    push(element, Some(&i.to_string()), PathSegment::Index(i));
+                }
+            },
+            Value::Record(fields) => {
+                for (key, field) in fields.iter_mut() {
+                    push(field, Some(key), PathSegment::Field(key.to_string()));
+                }
+            },
+            Value::Map(entries) => {
+                for (i, (key, val)) in entries.iter_mut().enumerate() {
+                    match key {
+                        Value::Str(k) | Value::Symbol(k) => push(val, Some(k), PathSegment::Index(i)),
+                        _ => push(val, None, PathSegment::Index(i)),
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{redact, Digest, Redaction};
+    use crate::header::Sign;
+    use crate::value::Value;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn record<'a>(fields: &[(&'a str, Value<'a>)]) -> Value<'a> {
+        Value::Record(fields.iter().cloned().map(|(k, v)| (Cow::Borrowed(k), v)).collect::<BTreeMap<_, _>>())
+    }
+
+    fn string(v: &str) -> Value<'_> {
+        Value::Str(Cow::Borrowed(v))
+    }
+
+    fn message<'a>(email: Value<'a>, first: Value<'a>, second: Value<'a>) -> Value<'a> {
+        record(&[
+            ("user", record(&[("email", email), ("name", string("Liv"))])),
+            ("cards", Value::Array(vec![first, second])),
+            ("headers", Value::Map(vec![(Value::Symbol(Cow::Borrowed("auth")), string("secret"))])),
+        ])
+    }
+
+    #[test]
+    fn paths() {
+        let original = message(string("liv@example.com"), string("1234"), string("5678"));
+        let mut value = original.clone();
+        assert_eq!(2, redact(&mut value, &["user.email", "cards.1"], &Redaction::Null).unwrap());
+        assert_eq!(message(Value::Null, string("1234"), Value::Null), value);
+        let mut value = original.clone();
+        assert_eq!(2, redact(&mut value, &["cards.*"], &Redaction::Null).unwrap());
+        assert_eq!(message(string("liv@example.com"), Value::Null, Value::Null), value);
+        let mut value = original.clone();
+        assert_eq!(1, redact(&mut value, &["**.auth", "auth", "user.*.email"], &Redaction::Null).unwrap());
+        assert!(matches!(&value, Value::Record(r) if r["headers"] == Value::Map(vec![(Value::Symbol(Cow::Borrowed("auth")), Value::Null)])));
+        let mut value = original;
+        assert_eq!(1, redact(&mut value, &["**"], &Redaction::Null).unwrap());
+        assert_eq!(Value::Null, value);
+    }
+
+    struct Length;
+
+    impl Digest for Length {
+        fn digest(&self, message: &[u8]) -> Vec<u8> {
+            vec![message.len() as u8]
+        }
+    }
+
+    #[test]
+    fn hash() {
+        let mut value = message(string("liv@example.com"), Value::Int(Sign::Pos, 1), Value::Null);
+        assert_eq!(1, redact(&mut value, &["user.email"], &Redaction::Hash(&Length)).unwrap());
+        assert_eq!(message(Value::Bytes(Cow::Owned(vec![16])), Value::Int(Sign::Pos, 1), Value::Null), value);
+    }
+
+}
@@ -0,0 +1,89 @@
+//! Extract the searchable tokens of a message without decoding it, e.g. for indexers or scanners which process large
+//! corpora. Only headers are walked, no `Value` is built:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let cat = |name| Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+//!     (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus"))),
+//! ]));
+//! let mut buf = Vec::new();
+//! Encoder::encode(&Value::Array(vec![cat("Jessica"), cat("Wantan"), cat("Jessica")]), &mut buf).unwrap();
+//! let (tokens, _) = scan_symbols(&buf).unwrap();
+//! assert_eq!(vec!["name", "species", "Jessica", "FelisCatus", "Wantan"], tokens);
+//! ```
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::DecoderError;
+use crate::reader::{Reader, Token};
+use std::collections::HashSet;
+
+/// Return every distinct symbol, record key and string of the message at the start of the buffer in order of their
+/// first occurrence, together with the number of consumed bytes. Fails like `Decoder::decode` on malformed input.
+pub fn scan_symbols<B: ?Sized + AsRef<[u8]>>(buf: &B) -> Result<(Vec<&str>, usize), DecoderError> {
+    let mut reader = Reader::new(buf);
+    let mut seen = HashSet::new();
+    let mut tokens = Vec::new();
+    let mut remaining = 1usize;
+    while remaining > 0 {
+        remaining -= 1;
+        match reader.next_token().map_err(|e| e.at(reader.position()))? {
+            Token::Str(v) | Token::Sym(v) if seen.insert(v) => tokens.push(v),
+            Token::Arr(v) => { remaining = remaining.saturating_add(v); },
+            Token::Map(v) => { remaining = remaining.saturating_add(v.saturating_mul(2)); },
+            Token::Rec(keys) => {
+                remaining = remaining.saturating_add(keys.len());
+                tokens.extend(keys.iter().filter(|key| seen.insert(**key)));
+            },
+            _ => {},
+        }
+    }
+    Ok((tokens, reader.position()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::scan_symbols;
+    use crate::error::DecodeError;
+    use crate::header::Header;
+    use crate::value::{Encoder, EncoderOptions, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn tokens() {
+        let value = Value::Array(vec![
+            Value::Map(vec![(Value::Str(Cow::Borrowed("key")), Value::Symbol(Cow::Borrowed("key")))]),
+            Value::Record(BTreeMap::from([(Cow::Borrowed("inner"), Value::Record(BTreeMap::from([(Cow::Borrowed("inner"), Value::Null)])))])),
+            Value::Bytes(Cow::Borrowed(b"bytes")),
+            Value::Str(Cow::Borrowed("")),
+        ]);
+        let mut buf = Vec::new();
+        Encoder::encode(&value, &mut buf).unwrap();
+        buf.push(0xff);
+        assert_eq!((vec!["key", "inner", ""], buf.len() - 1), scan_symbols(&buf).unwrap());
+        // strings which are encoded as symbols are found just the same
+        let mut buf = Vec::new();
+        Encoder::encode_with_options(&value, &mut buf, EncoderOptions { symbol_strings: true, ..Default::default() }).unwrap();
+        assert_eq!(vec!["key", "inner", ""], scan_symbols(&buf).unwrap().0);
+    }
+
+    #[test]
+    fn errors() {
+        let mut buf = Vec::new();
+        Header::Arr(2).encode(&mut buf).unwrap();
+        Header::True.encode(&mut buf).unwrap();
+        assert_eq!(DecodeError::Eof, scan_symbols(&buf).unwrap_err().into_inner());
+        Header::Ref(0).encode(&mut buf).unwrap();
+        assert_eq!(DecodeError::InvalidRef(0), scan_symbols(&buf).unwrap_err().into_inner());
+        let mut buf = Vec::new();
+        Header::Str(1).encode(&mut buf).unwrap();
+        buf.push(0xff);
+        assert!(matches!(scan_symbols(&buf).unwrap_err().into_inner(), DecodeError::Utf8(_)));
+    }
+
+}
@@ -0,0 +1,474 @@
+//! Values which occur several times within a message, e.g. the entries of a string table or the nodes of a directed
+//! acyclic graph, can be encoded once and referenced wherever they occur again. The wire format only references symbols
+//! and record layouts, hence shared values are a convention on top of it: `Value::share` wraps a value into a record
+//! with the single field `@shared` (`SHARED_FIELD`), and `Value::reference` creates a record with the single field
+//! `@ref` (`REFERENCE_FIELD`) which holds the number of the shared value it refers to. Shared values are numbered from
+//! zero in the order in which they occur, with the fields of records ordered by their keys as the `Encoder` writes
+//! them. Since the layouts of both records go into the symbol table, every further definition costs a single byte and
+//! every reference usually two.
+//!
+//! `Value::resolve_shared` replaces the definitions with their values and the references with copies of them. A
+//! reference has to come after the end of the definition it refers to, so a value which contains a reference to itself
+//! is rejected with `SharedError::Cycle`:
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let address = Value::Record(BTreeMap::from([
+//!     (Cow::Borrowed("city"), Value::Str(Cow::Borrowed("Rüsselsheim"))),
+//!     (Cow::Borrowed("street"), Value::Str(Cow::Borrowed("Bahnhofsplatz 1"))),
+//! ]));
+//! let shared = Value::Array(vec![address.clone().share(), Value::reference(0), Value::reference(0)]);
+//! let mut buf = Vec::new();
+//! Encoder::encode(&shared, &mut buf).unwrap();
+//! let mut decoded = Decoder::decode(&buf).unwrap().0;
+//! decoded.resolve_shared(1_000).unwrap();
+//! assert_eq!(Value::Array(vec![address.clone(), address.clone(), address]), decoded);
+//!
+//! let mut cycle = Value::Array(vec![Value::Array(vec![Value::reference(0)]).share()]);
+//! assert_eq!(Err(SharedError::Cycle(0)), cycle.resolve_shared(1_000));
+//! ```
+//!
+//! `Value::deduplicate` does the opposite for values which are not shared yet: it looks for repeated identical
+//! subvalues, e.g. the same address in many records of denormalized data, and replaces all but their first occurrence
+//! with references. Only subvalues whose encoding is longer than a reference are shared, and values with too few
+//! repeats to make up for the two additional layouts are left unchanged.
+//!
+//! Every reference copies the whole shared value, so a few bytes of input can expand to an exponential amount of
+//! values. The `limit` of `resolve_shared` caps the number of values which references may expand to in total, and the
+//! resolved value is never nested deeper than `Decoder::MAX_DEPTH`, so messages from untrusted sources can be resolved
+//! safely after decoding them with `Decoder::decode_untrusted`.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{LengthError, SharedError};
+use crate::header::{Header, Sign};
+use crate::value::{Decoder, Encoder, Value};
+
+/// The single field of the record which defines a shared value
+pub const SHARED_FIELD: &str = "@shared";
+
+/// The single field of the record which refers to a shared value by its number
+pub const REFERENCE_FIELD: &str = "@ref";
+
+/// The encoded size of most references: the layout and the number
+const REFERENCE_SIZE: usize = 2;
+
+/// The encoded size of the layouts of the first definition and the first reference
+const LAYOUT_SIZE: usize = 15;
+
+/// A resolved shared value together with its number of values and its depth
+type Definition<'a> = Option<(Value<'a>, usize, usize)>;
+
+impl<'a> Value<'a> {
+
+    /// Define this value as shared, see the module documentation.
+    pub fn share(self) -> Value<'a> {
+        Value::Record(BTreeMap::from([(Cow::Borrowed(SHARED_FIELD), self)]))
+    }
+
+    /// Refer to the shared value with the given number, see the module documentation.
+    pub fn reference(index: u64) -> Value<'static> {
+        Value::Record(BTreeMap::from([(Cow::Borrowed(REFERENCE_FIELD), Value::Int(Sign::Pos, index))]))
+    }
+
+    /// Share repeated identical subvalues, see the module documentation. Values which already contain records that
+    /// look like definitions or references must not be deduplicated, since they would be resolved as well. Only fails
+    /// if a subvalue cannot be encoded.
+    pub fn deduplicate(&mut self) -> Result<(), LengthError> {
+        let (nodes, sizes) = identify(self)?;
+        let counts = count(&nodes, &sizes);
+        let saved = counts.iter().zip(&sizes)
+            .filter(|(count, _)| **count > 1)
+            .map(|(count, size)| (count - 1) * (size - REFERENCE_SIZE) - 1)
+            .sum::<usize>();
+        if saved > LAYOUT_SIZE {
+            share(self, &nodes, &counts);
+        }
+        Ok(())
+    }
+
+    /// Replace all shared values and references to them with their values, see the module documentation. Fails if
+    /// the references would expand to more than `limit` values in total.
+    pub fn resolve_shared(&mut self, limit: usize) -> Result<(), SharedError> {
+        let mut resolver = Resolver { definitions: Vec::new(), expanded: 0, limit };
+        resolver.resolve(self, 0)
+    }
+
+}
+
+struct Resolver<'a> {
+    /// The shared values by their numbers, `None` while they are being defined
+    definitions: Vec<Definition<'a>>,
+    /// The number of values created by references so far
+    expanded: usize,
+    limit: usize,
+}
+
+impl<'a> Resolver<'a> {
+
+    fn resolve(&mut self, value: &mut Value<'a>, depth: usize) -> Result<(), SharedError> {
+        if depth > Decoder::MAX_DEPTH {
+            return Err(SharedError::Depth(Decoder::MAX_DEPTH));
+        }
+        match value {
+            Value::Record(fields) if fields.len() == 1 && fields.contains_key(SHARED_FIELD) => {
+                let index = self.definitions.len();
+                self.definitions.push(None);
+                if let Some(mut shared) = fields.remove(SHARED_FIELD) {
+                    self.resolve(&mut shared, depth)?;
+                    let (size, height) = measure(&shared);
+                    if let Some(definition) = self.definitions.get_mut(index) {
+                        *definition = Some((shared.clone(), size, height));
+                    }
+                    *value = shared;
+                }
+                Ok(())
+            },
+            Value::Record(fields) if fields.len() == 1 && fields.contains_key(REFERENCE_FIELD) => {
+                let index = match fields.get(REFERENCE_FIELD) {
+                    Some(Value::Int(Sign::Pos, index)) => *index,
+                    _ => return Err(SharedError::Malformed("reference is not a number")),
+                };
+                let (shared, size, height) = match usize::try_from(index).ok().and_then(|i| self.definitions.get(i)) {
+                    Some(Some(definition)) => definition,
+                    Some(None) => return Err(SharedError::Cycle(index)),
+                    None => return Err(SharedError::Undefined(index)),
+                };
+                self.expanded = self.expanded.saturating_add(*size);
+                if self.expanded > self.limit {
+                    return Err(SharedError::Limit(self.limit));
+                }
+                if depth + height > Decoder::MAX_DEPTH {
+                    return Err(SharedError::Depth(Decoder::MAX_DEPTH));
+                }
+                *value = shared.clone();
+                Ok(())
+            },
+            Value::Array(elements) => elements.iter_mut().try_for_each(|e| self.resolve(e, depth + 1)),
+            Value::Map(entries) => entries.iter_mut().try_for_each(|(k, v)| {
+                self.resolve(k, depth + 1)?;
+                self.resolve(v, depth + 1)
+            }),
+            Value::Record(fields) => fields.values_mut().try_for_each(|v| self.resolve(v, depth + 1)),
+            _ => Ok(()),
+        }
+    }
+
+}
+
+/// A subvalue in the order in which `Resolver::resolve` visits them
+struct Node {
+    /// Identical subvalues have the same id
+    id: usize,
+    /// The index of the first node after this subvalue and everything within it
+    end: usize,
+}
+
+/// What makes subvalues identical: the encoding of scalars, or the ids of the children of containers
+#[derive(PartialEq, Eq, Hash)]
+enum Shape<'v> {
+    Scalar(Vec<u8>),
+    Array(Vec<usize>),
+    Map(Vec<usize>),
+    Record(Vec<(&'v str, usize)>),
+}
+
+/// Identify all subvalues of the value and estimate the encoded size of every id. Every subvalue is identified once,
+/// after its children, so the work is linear in the size of the value, and the value is walked with an explicit
+/// stack, so it may be nested arbitrarily deep.
+fn identify(value: &Value) -> Result<(Vec<Node>, Vec<usize>), LengthError> {
+    let mut nodes = Vec::new();
+    let mut sizes = Vec::new();
+    let mut shapes = HashMap::new();
+    // the ids of the identified subvalues whose parents have not been identified yet
+    let mut ids = Vec::new();
+    // a subvalue and the index of its node once its children have been pushed
+    let mut stack = vec![(value, None)];
+    while let Some((value, entered)) = stack.pop() {
+        let index = entered.unwrap_or(nodes.len());
+        if entered.is_none() {
+            nodes.push(Node { id: 0, end: 0 });
+            if let Some(children) = children(value) {
+                stack.push((value, Some(index)));
+                stack.extend(children.rev().map(|child| (child, None)));
+                continue;
+            }
+        }
+        let (shape, size) = match value {
+            Value::Array(elements) => {
+                let children = ids.split_off(ids.len() - elements.len());
+                let size = sized(Header::Arr(elements.len()))? + children.iter().map(|id| sizes[*id]).sum::<usize>();
+                (Shape::Array(children), size)
+            },
+            Value::Map(entries) => {
+                let children = ids.split_off(ids.len() - 2 * entries.len());
+                let size = sized(Header::Map(entries.len()))? + children.iter().map(|id| sizes[*id]).sum::<usize>();
+                (Shape::Map(children), size)
+            },
+            Value::Record(fields) => {
+                let children = ids.split_off(ids.len() - fields.len());
+                let mut size = sized(Header::Rec(fields.len()))? + children.iter().map(|id| sizes[*id]).sum::<usize>();
+                for key in fields.keys() {
+                    size += sized(Header::Sym(key.len()))? + key.len();
+                }
+                (Shape::Record(fields.keys().map(|key| key.as_ref()).zip(children).collect()), size)
+            },
+            _ => {
+                let encoded = encode(value)?;
+                let size = encoded.len();
+                (Shape::Scalar(encoded), size)
+            },
+        };
+        let id = *shapes.entry(shape).or_insert_with(|| {
+            sizes.push(size);
+            sizes.len() - 1
+        });
+        ids.push(id);
+        nodes[index] = Node { id, end: nodes.len() };
+    }
+    Ok((nodes, sizes))
+}
+
+/// The encoded size of the header. The sizes of containers are estimated from the sizes of their children, which
+/// ignores that repeated symbols and layouts within them are encoded as references.
+fn sized(header: Header) -> Result<usize, LengthError> {
+    header.validate()?;
+    Ok(header.encoded_len())
+}
+
+/// Count the occurrences of every subvalue which is worth sharing by its id. Repeats of such subvalues become
+/// references, so their contents are not counted again.
+fn count(nodes: &[Node], sizes: &[usize]) -> Vec<usize> {
+    let mut counts = vec![0; sizes.len()];
+    let mut index = 0;
+    while let Some(node) = nodes.get(index) {
+        index += 1;
+        if sizes[node.id] > REFERENCE_SIZE + 1 {
+            counts[node.id] += 1;
+            if counts[node.id] > 1 {
+                index = node.end;
+            }
+        }
+    }
+    counts
+}
+
+/// Replace the repeats of every subvalue which occurs more than once with references to its first occurrence, which
+/// becomes shared. Visits the subvalues in the same order as `identify`, `count` and `Resolver::resolve`.
+fn share(value: &mut Value, nodes: &[Node], counts: &[usize]) {
+    let mut definitions = HashMap::new();
+    let mut index = 0;
+    // a subvalue and whether it still needs to be visited itself, rather than just its children
+    let mut stack = vec![(value, true)];
+    while let Some((value, visit)) = stack.pop() {
+        if visit {
+            let node = &nodes[index];
+            index += 1;
+            if counts[node.id] > 1 {
+                if let Some(number) = definitions.get(&node.id) {
+                    *value = Value::reference(*number);
+                    index = node.end;
+                    continue;
+                }
+                definitions.insert(node.id, definitions.len() as u64);
+                *value = std::mem::replace(value, Value::Null).share();
+                if let Value::Record(fields) = value {
+                    stack.extend(fields.values_mut().map(|shared| (shared, false)));
+                }
+                continue;
+            }
+        }
+        match value {
+            Value::Array(elements) => stack.extend(elements.iter_mut().rev().map(|e| (e, true))),
+            Value::Map(entries) => stack.extend(entries.iter_mut().rev().flat_map(|(k, v)| [(v, true), (k, true)])),
+            Value::Record(fields) => stack.extend(fields.values_mut().rev().map(|v| (v, true))),
+            _ => {},
+        }
+    }
+}
+
+fn encode(value: &Value) -> Result<Vec<u8>, LengthError> {
+    let mut buf = Vec::new();
+    Encoder::encode_to_vec(value, &mut buf)?;
+    Ok(buf)
+}
+
+/// The number of values within the value, including itself, and the number of nested containers
+fn measure(value: &Value) -> (usize, usize) {
+    match children(value) {
+        Some(children) => children.map(measure).fold((1, 1), |(size, height), (s, h)| (size.saturating_add(s), height.max(h + 1))),
+        None => (1, 0),
+    }
+}
+
+/// The subvalues of a container in the order in which they are encoded, `None` for scalars
+fn children<'v, 'a>(value: &'v Value<'a>) -> Option<Box<dyn DoubleEndedIterator<Item = &'v Value<'a>> + 'v>> {
+    match value {
+        Value::Array(elements) => Some(Box::new(elements.iter())),
+        Value::Map(entries) => Some(Box::new(entries.iter().flat_map(|(k, v)| [k, v]))),
+        Value::Record(fields) => Some(Box::new(fields.values())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::SharedError;
+    use crate::header::Sign;
+    use crate::value::{Decoder, Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn int(i: u64) -> Value<'static> {
+        Value::Int(Sign::Pos, i)
+    }
+
+    fn record(key: &'static str, value: Value<'static>) -> Value<'static> {
+        Value::Record(BTreeMap::from([(Cow::Borrowed(key), value)]))
+    }
+
+    #[test]
+    fn resolve() {
+        // a diamond: both children of the root refer to the same leaf, which is defined within the first one
+        let leaf = Value::Str(Cow::Borrowed("Jessica"));
+        let dag = Value::Array(vec![
+            record("left", Value::Array(vec![leaf.clone().share()])).share(),
+            record("right", Value::reference(1)),
+            Value::reference(0),
+            Value::Map(vec![(Value::reference(1), int(1))]),
+        ]);
+        let mut buf = Vec::new();
+        Encoder::encode(&dag, &mut buf).unwrap();
+        let mut decoded = Decoder::decode(&buf).unwrap().0;
+        decoded.resolve_shared(5).unwrap();
+        let left = record("left", Value::Array(vec![leaf.clone()]));
+        assert_eq!(Value::Array(vec![
+            left.clone(),
+            record("right", leaf.clone()),
+            left,
+            Value::Map(vec![(leaf, int(1))]),
+        ]), decoded);
+        // the references expand to one, three and one value
+        let mut decoded = Decoder::decode(&buf).unwrap().0;
+        assert_eq!(Err(SharedError::Limit(4)), decoded.resolve_shared(4));
+        // other records are left alone
+        let plain = Value::Array(vec![record("@refs", int(0)), Value::Record(BTreeMap::from([
+            (Cow::Borrowed("@shared"), int(0)),
+            (Cow::Borrowed("@ref"), int(0)),
+        ]))]);
+        let mut resolved = plain.clone();
+        resolved.resolve_shared(0).unwrap();
+        assert_eq!(plain, resolved);
+    }
+
+    fn encoded(value: &Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        Encoder::encode(value, &mut buf).unwrap();
+        buf
+    }
+
+    fn address(street: &'static str) -> Value<'static> {
+        Value::Record(BTreeMap::from([
+            (Cow::Borrowed("city"), Value::Str(Cow::Borrowed("Rüsselsheim"))),
+            (Cow::Borrowed("street"), Value::Str(Cow::Borrowed(street))),
+            (Cow::Borrowed("zip"), int(65428)),
+        ]))
+    }
+
+    #[test]
+    fn deduplicate() {
+        let customers = Value::Array((0..10).map(|i| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("billing"), address("Bahnhofsplatz 1")),
+            (Cow::Borrowed("id"), int(i)),
+            (Cow::Borrowed("shipping"), address(if i % 2 == 0 { "Bahnhofsplatz 1" } else { "Mainstraße 2" })),
+        ]))).collect());
+        let mut deduplicated = customers.clone();
+        deduplicated.deduplicate().unwrap();
+        assert!(encoded(&deduplicated).len() * 2 < encoded(&customers).len());
+        match &deduplicated {
+            // the city also occurs in the other address, so it is shared on its own
+            Value::Array(customers) => assert_eq!(Some(&Value::Record(BTreeMap::from([
+                (Cow::Borrowed("city"), Value::Str(Cow::Borrowed("Rüsselsheim")).share()),
+                (Cow::Borrowed("street"), Value::Str(Cow::Borrowed("Bahnhofsplatz 1"))),
+                (Cow::Borrowed("zip"), int(65428)),
+            ])).share()), match &customers[0] {
+                Value::Record(fields) => fields.get("billing"),
+                _ => None,
+            }),
+            _ => panic!("not an array"),
+        }
+        let buf = encoded(&deduplicated);
+        let mut resolved = Decoder::decode(&buf).unwrap().0;
+        resolved.resolve_shared(usize::MAX).unwrap();
+        assert_eq!(encoded(&customers), encoded(&resolved));
+        // the repeats need to make up for the layouts
+        let few = Value::Array(vec![address("Bahnhofsplatz 1"), Value::Str(Cow::Borrowed("Jessica")), Value::Str(Cow::Borrowed("Jessica"))]);
+        let mut unchanged = few.clone();
+        unchanged.deduplicate().unwrap();
+        assert_eq!(few, unchanged);
+        // references need to refer to a definition which comes before them, also within maps
+        let nested = Value::Map(vec![
+            (address("Bahnhofsplatz 1"), Value::Array(vec![address("Bahnhofsplatz 1"); 2])),
+            (Value::Array(vec![address("Bahnhofsplatz 1"); 2]), Value::Null),
+        ]);
+        let mut deduplicated = nested.clone();
+        deduplicated.deduplicate().unwrap();
+        assert_eq!(Value::Map(vec![
+            (address("Bahnhofsplatz 1").share(), Value::Array(vec![Value::reference(0); 2]).share()),
+            (Value::reference(1), Value::Null),
+        ]), deduplicated);
+        deduplicated.resolve_shared(usize::MAX).unwrap();
+        assert_eq!(nested, deduplicated);
+    }
+
+    #[test]
+    fn deduplicate_deep() {
+        // deeply nested values must neither overflow the stack nor be encoded once per level
+        let street = || Value::Str(Cow::Borrowed("Bahnhofsplatz 1"));
+        let mut deep = (0..200_000).fold(Value::Null, |inner, _| Value::Array(vec![street(), inner]));
+        deep.deduplicate().unwrap();
+        // dropping is recursive, so take the value apart by hand
+        let mut definitions = 0;
+        let mut references = 0;
+        while let Value::Array(mut inner) = deep {
+            deep = inner.pop().unwrap();
+            match inner.pop() {
+                Some(v) if v == street().share() => definitions += 1,
+                Some(v) if v == Value::reference(0) => references += 1,
+                _ => {},
+            }
+        }
+        assert_eq!((1, 199_999, Value::Null), (definitions, references, deep));
+    }
+
+    #[test]
+    fn invalid() {
+        let resolved = |mut value: Value<'static>| value.resolve_shared(usize::MAX);
+        assert_eq!(Err(SharedError::Cycle(0)), resolved(Value::Array(vec![Value::reference(0)]).share()));
+        assert_eq!(Err(SharedError::Cycle(0)), resolved(Value::Array(vec![record("x", Value::reference(0)).share()]).share()));
+        assert_eq!(Err(SharedError::Undefined(0)), resolved(Value::Array(vec![Value::reference(0), int(0).share()])));
+        assert_eq!(Err(SharedError::Undefined(u64::MAX)), resolved(Value::reference(u64::MAX)));
+        assert!(matches!(resolved(record("@ref", Value::Str(Cow::Borrowed("0")))), Err(SharedError::Malformed(_))));
+        // every definition doubles the number of values
+        let mut bomb = vec![int(0).share()];
+        for i in 0..64 {
+            bomb.push(Value::Array(vec![Value::reference(i), Value::reference(i)]).share());
+        }
+        assert_eq!(Err(SharedError::Limit(1_000_000)), Value::Array(bomb).resolve_shared(1_000_000));
+        // as does every definition nest the one before it
+        let mut deep = Value::Array(Vec::new());
+        for _ in 0..500 {
+            deep = Value::Array(vec![deep]);
+        }
+        let mut chain = vec![deep.share()];
+        for i in 0..16 {
+            chain.push(Value::Array(vec![Value::reference(i)]).share());
+        }
+        assert_eq!(Err(SharedError::Depth(Decoder::MAX_DEPTH)), resolved(Value::Array(chain)));
+    }
+
+}
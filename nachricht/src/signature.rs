@@ -0,0 +1,199 @@
+//! A detached-signature envelope for authenticated messages. The payload is signed in its canonical encoding, with
+//! the entries of maps sorted by `canonicalize`, so that equal values yield equal payloads no matter the order in
+//! which their entries were given. The envelope itself is a plain `nachricht` record so that it can be inspected with
+//! any tool that understands the format:
+//!
+//! ```text
+//! (
+//!   algorithm: #ed25519,
+//!   payload: '...',
+//!   signature: '...',
+//! )
+//! ```
+//!
+//! This crate does not ship any cryptography. Bring your own by implementing `Signer` and `Verifier`.
+
+use crate::canon::canonicalize;
+use crate::error::{EncodeError, SignatureError};
+use crate::value::{Decoder, Encoder, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+const ALGORITHM: &str = "algorithm";
+const PAYLOAD: &str = "payload";
+const SIGNATURE: &str = "signature";
+
+/// Produces signatures over encoded payloads.
+pub trait Signer {
+    /// The name of the algorithm which gets recorded in the envelope, e.g. `ed25519`.
+    fn algorithm(&self) -> &str;
+    /// Sign the given message.
+    fn sign(&self, message: &[u8]) -> Vec<u8>;
+}
+
+/// Checks signatures produced by a matching `Signer`.
+pub trait Verifier {
+    /// The name of the algorithm this verifier is able to check.
+    fn algorithm(&self) -> &str;
+    /// Returns true if the signature is valid for the given message.
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// An encoded payload together with a detached signature and the algorithm used to produce it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedEnvelope<'a> {
+    pub algorithm: Cow<'a, str>,
+    pub payload: Cow<'a, [u8]>,
+    pub signature: Cow<'a, [u8]>,
+}
+
+impl<'a> SignedEnvelope<'a> {
+
+    /// Encode the value canonically and sign the resulting bytes.
+    pub fn sign<S: Signer>(value: &Value, signer: &S) -> Result<SignedEnvelope<'static>, EncodeError> {
+        let mut canonical = value.clone();
+        canonicalize(&mut canonical)?;
+        let mut payload = Vec::new();
+        Encoder::encode(&canonical, &mut payload)?;
+        let signature = signer.sign(&payload);
+        Ok(SignedEnvelope {
+            algorithm: Cow::Owned(signer.algorithm().to_owned()),
+            payload: Cow::Owned(payload),
+            signature: Cow::Owned(signature),
+        })
+    }
+
+    /// Check the signature and decode the payload if it is valid. The verifier has to match the algorithm recorded
+    /// in the envelope.
+    pub fn verify<V: Verifier>(&self, verifier: &V) -> Result<Value<'_>, SignatureError> {
+        if verifier.algorithm() != self.algorithm {
+            return Err(SignatureError::Algorithm(self.algorithm.to_string()));
+        }
+        if !verifier.verify(&self.payload, &self.signature) {
+            return Err(SignatureError::Invalid);
+        }
+        let (value, c) = Decoder::decode(&self.payload)?;
+        if c != self.payload.len() {
+            return Err(SignatureError::Malformed("trailing bytes after payload"));
+        }
+        Ok(value)
+    }
+
+    /// Convert the envelope into its `Value` representation.
+    pub fn to_value(&self) -> Value<'_> {
+        Value::Record(BTreeMap::from([
+            (Cow::Borrowed(ALGORITHM), Value::Symbol(Cow::Borrowed(&self.algorithm))),
+            (Cow::Borrowed(PAYLOAD), Value::Bytes(Cow::Borrowed(&self.payload))),
+            (Cow::Borrowed(SIGNATURE), Value::Bytes(Cow::Borrowed(&self.signature))),
+        ]))
+    }
+
+    /// Extract an envelope from its `Value` representation. Additional fields are rejected so that nothing can be
+    /// smuggled alongside the signed payload.
+    pub fn from_value(value: Value<'a>) -> Result<Self, SignatureError> {
+        let mut fields = match value {
+            Value::Record(fields) if fields.len() == 3 => fields,
+            Value::Record(_) => return Err(SignatureError::Malformed("envelope needs exactly three fields")),
+            _ => return Err(SignatureError::Malformed("envelope needs to be a record")),
+        };
+        let algorithm = match fields.remove(ALGORITHM) {
+            Some(Value::Symbol(v)) => v,
+            _ => return Err(SignatureError::Malformed("algorithm needs to be a symbol")),
+        };
+        let payload = match fields.remove(PAYLOAD) {
+            Some(Value::Bytes(v)) => v,
+            _ => return Err(SignatureError::Malformed("payload needs to be bytes")),
+        };
+        let signature = match fields.remove(SIGNATURE) {
+            Some(Value::Bytes(v)) => v,
+            _ => return Err(SignatureError::Malformed("signature needs to be bytes")),
+        };
+        Ok(Self { algorithm, payload, signature })
+    }
+
+    /// Encode the envelope to the given writer. The resulting `usize` is the amount of bytes that got written.
+    pub fn encode<W: Write>(&self, writer: &mut W) -> Result<usize, EncodeError> {
+        Encoder::encode(&self.to_value(), writer)
+    }
+
+    /// Decode an envelope from the given buffer. The payload is borrowed from the buffer. Returns the envelope and
+    /// the number of consumed bytes.
+    pub fn decode<B: ?Sized + AsRef<[u8]>>(buf: &'a B) -> Result<(Self, usize), SignatureError> {
+        let (value, c) = Decoder::decode(buf)?;
+        Ok((Self::from_value(value)?, c))
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SignedEnvelope, Signer, Verifier};
+    use crate::{SignatureError, Value, Sign};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    /// Not a cryptographic signature, but good enough to test the plumbing
+    struct Xor(u8);
+
+    impl Signer for Xor {
+        fn algorithm(&self) -> &str { "xor" }
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            vec![message.iter().fold(self.0, |acc, b| acc ^ b)]
+        }
+    }
+
+    impl Verifier for Xor {
+        fn algorithm(&self) -> &str { "xor" }
+        fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+            self.sign(message) == signature
+        }
+    }
+
+    struct Other;
+
+    impl Verifier for Other {
+        fn algorithm(&self) -> &str { "other" }
+        fn verify(&self, _message: &[u8], _signature: &[u8]) -> bool { true }
+    }
+
+    fn value() -> Value<'static> {
+        Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+            (Cow::Borrowed("lives"), Value::Int(Sign::Pos, 9)),
+        ]))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let value = value();
+        let envelope = SignedEnvelope::sign(&value, &Xor(42)).unwrap();
+        let mut buf = Vec::new();
+        let c = envelope.encode(&mut buf).unwrap();
+        let (decoded, d) = SignedEnvelope::decode(&buf).unwrap();
+        assert_eq!(c, d);
+        assert_eq!(envelope, decoded);
+        assert_eq!(value, decoded.verify(&Xor(42)).unwrap());
+    }
+
+    #[test]
+    fn canonical() {
+        let entry = |k: &'static str| (Value::Str(Cow::Borrowed(k)), Value::Null);
+        let sorted = SignedEnvelope::sign(&Value::Map(vec![entry("a"), entry("b")]), &Xor(42)).unwrap();
+        let unsorted = SignedEnvelope::sign(&Value::Map(vec![entry("b"), entry("a")]), &Xor(42)).unwrap();
+        assert_eq!(sorted, unsorted);
+        assert_eq!(Value::Map(vec![entry("a"), entry("b")]), unsorted.verify(&Xor(42)).unwrap());
+    }
+
+    #[test]
+    fn rejections() {
+        let mut envelope = SignedEnvelope::sign(&value(), &Xor(42)).unwrap();
+        assert_eq!(SignatureError::Invalid, envelope.verify(&Xor(7)).unwrap_err());
+        assert_eq!(SignatureError::Algorithm("xor".into()), envelope.verify(&Other).unwrap_err());
+        envelope.payload.to_mut().push(0);
+        envelope.signature = Cow::Owned(Xor(42).sign(&envelope.payload));
+        assert!(matches!(envelope.verify(&Xor(42)).unwrap_err(), SignatureError::Malformed(_)));
+        assert!(matches!(SignedEnvelope::from_value(Value::Null).unwrap_err(), SignatureError::Malformed(_)));
+    }
+
+}
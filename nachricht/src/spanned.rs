@@ -0,0 +1,175 @@
+//! Decode a message into a tree which records where each node is located in the input. Unlike `Value`, the tree keeps
+//! the fields of a record in the order of their layout on the wire. This is meant for tools which need to relate
+//! values to their encoding, e.g. to point at the offending bytes in a diagnostic or to patch a message in place.
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//!
+//! let mut buf = Vec::new();
+//! Encoder::encode(&Value::Array(vec![Value::Null, Value::Str(Cow::Borrowed("cat"))]), &mut buf).unwrap();
+//! let (spanned, _) = Spanned::decode(&buf).unwrap();
+//! assert_eq!(0..6, spanned.span);
+//! assert_eq!(2..6, spanned.pointer("/1").unwrap().span);
+//! ```
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::{DecodeError, DecoderError};
+use crate::reader::{allocate, Reader, Token};
+use crate::value::{Decoder, Value};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// A decoded value together with the range of its encoding within the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<'a> {
+    /// From the start of the header to the end of the payload or the last element. The layout of a record is part of
+    /// it, even if it only consists of a reference.
+    pub span: Range<usize>,
+    pub node: Node<'a>,
+}
+
+/// The contents of a `Spanned`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node<'a> {
+    /// Any value which is not a container
+    Scalar(Value<'a>),
+    Array(Vec<Spanned<'a>>),
+    Map(Vec<(Spanned<'a>, Spanned<'a>)>),
+    /// The fields in the order of the layout on the wire
+    Record(Vec<(&'a str, Spanned<'a>)>),
+}
+
+impl<'a> Spanned<'a> {
+
+    /// Decode a single value like `Decoder::decode` does, returning the tree and the number of consumed bytes. Values
+    /// nested deeper than `Decoder::MAX_DEPTH` are rejected.
+    pub fn decode<B: ?Sized + AsRef<[u8]>>(buf: &'a B) -> Result<(Spanned<'a>, usize), DecoderError> {
+        let mut reader = Reader::new(buf);
+        let spanned = Self::decode_node(&mut reader, 0).map_err(|e| e.at(reader.position()))?;
+        Ok((spanned, reader.position()))
+    }
+
+    fn decode_node(reader: &mut Reader<'a>, depth: usize) -> Result<Spanned<'a>, DecodeError> {
+        let start = reader.position();
+        let token = reader.next_token()?;
+        if matches!(token, Token::Arr(_) | Token::Map(_) | Token::Rec(_)) && depth == Decoder::MAX_DEPTH {
+            return Err(DecodeError::Depth(Decoder::MAX_DEPTH));
+        }
+        let node = match token {
+            Token::Null      => Node::Scalar(Value::Null),
+            Token::Bool(b)   => Node::Scalar(Value::Bool(b)),
+            Token::F32(v)    => Node::Scalar(Value::F32(v)),
+            Token::F64(v)    => Node::Scalar(Value::F64(v)),
+            Token::Bin(v)    => Node::Scalar(Value::Bytes(Cow::Borrowed(v))),
+            Token::Int(s, v) => Node::Scalar(Value::Int(s, v)),
+            Token::Str(v)    => Node::Scalar(Value::Str(Cow::Borrowed(v))),
+            Token::Sym(v)    => Node::Scalar(Value::Symbol(Cow::Borrowed(v))),
+            Token::Arr(len)  => {
+                let mut elements = allocate(len, reader.remaining())?;
+                for _ in 0..len {
+                    elements.push(Self::decode_node(reader, depth + 1)?);
+                }
+                Node::Array(elements)
+            },
+            Token::Map(len)  => {
+                let mut entries = allocate(len, reader.remaining())?;
+                for _ in 0..len {
+                    entries.push((Self::decode_node(reader, depth + 1)?, Self::decode_node(reader, depth + 1)?));
+                }
+                Node::Map(entries)
+            },
+            Token::Rec(keys) => {
+                let mut fields = allocate(keys.len(), reader.remaining())?;
+                for key in keys.iter() {
+                    fields.push((*key, Self::decode_node(reader, depth + 1)?));
+                }
+                Node::Record(fields)
+            },
+        };
+        Ok(Spanned { span: start..reader.position(), node })
+    }
+
+    /// Look up a nested node by a JSON pointer like `Value::pointer` does.
+    pub fn pointer(&self, pointer: &str) -> Option<&Spanned<'a>> {
+        Value::segments(pointer)?.try_fold(self, |spanned, segment| {
+            match &spanned.node {
+                Node::Record(fields) => fields.iter().rev().find(|(key, _)| *key == segment).map(|(_, val)| val),
+                Node::Map(entries) => entries.iter().find_map(|(key, val)| match &key.node {
+                    Node::Scalar(Value::Str(k)) | Node::Scalar(Value::Symbol(k)) if *k == segment => Some(val),
+                    _ => None,
+                }),
+                Node::Array(elements) => elements.get(segment.parse::<usize>().ok()?),
+                Node::Scalar(_) => None,
+            }
+        })
+    }
+
+    /// Discard the spans. This yields the same value as `Decoder::decode`.
+    pub fn into_value(self) -> Value<'a> {
+        match self.node {
+            Node::Scalar(value) => value,
+            Node::Array(elements) => Value::Array(elements.into_iter().map(Spanned::into_value).collect()),
+            Node::Map(entries) => Value::Map(entries.into_iter().map(|(k, v)| (k.into_value(), v.into_value())).collect()),
+            Node::Record(fields) => Value::Record(fields.into_iter().map(|(k, v)| (Cow::Borrowed(k), v.into_value())).collect()),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Node, Spanned};
+    use crate::error::DecodeError;
+    use crate::header::{Header, Sign};
+    use crate::value::{Decoder, Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn spans() {
+        let cat = |name| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+            (Cow::Borrowed("lives"), Value::Int(Sign::Pos, 9)),
+        ]));
+        let value = Value::Map(vec![(Value::Symbol(Cow::Borrowed("cats")), Value::Array(vec![cat("Jessica"), cat("Wantan")]))]);
+        let mut buf = Vec::new();
+        Encoder::encode(&value, &mut buf).unwrap();
+        buf.push(0);
+        let (spanned, c) = Spanned::decode(&buf).unwrap();
+        assert_eq!(buf.len() - 1, c);
+        assert_eq!(0..c, spanned.span);
+        // after the map header and the key "cats"
+        let cats = spanned.pointer("/cats").unwrap();
+        assert_eq!(6..c, cats.span);
+        // the first cat defines its layout of 11 bytes, the second one only references it
+        let first = spanned.pointer("/cats/0").unwrap();
+        assert_eq!(7..29, first.span);
+        assert_eq!(19..21, spanned.pointer("/cats/0/lives").unwrap().span);
+        assert_eq!(b"Jessica", &buf[spanned.pointer("/cats/0/name").unwrap().span.start + 1..first.span.end]);
+        assert_eq!(29..39, spanned.pointer("/cats/1").unwrap().span);
+        match &first.node {
+            Node::Record(fields) => assert_eq!(vec!["lives", "name"], fields.iter().map(|(k, _)| *k).collect::<Vec<_>>()),
+            _ => panic!("not a record"),
+        }
+        assert_eq!(None, spanned.pointer("/cats/2"));
+        assert_eq!(Decoder::decode(&buf).unwrap().0, spanned.into_value());
+    }
+
+    #[test]
+    fn errors() {
+        let mut buf = Vec::new();
+        Header::Arr(2).encode(&mut buf).unwrap();
+        Header::Null.encode(&mut buf).unwrap();
+        let error = Spanned::decode(&buf).unwrap_err();
+        assert_eq!("Unexpected end of buffer while decoding at input position 2", error.to_string());
+        buf.clear();
+        for _ in 0..=Decoder::MAX_DEPTH {
+            Header::Arr(1).encode(&mut buf).unwrap();
+        }
+        Header::Null.encode(&mut buf).unwrap();
+        assert_eq!(DecodeError::Depth(Decoder::MAX_DEPTH), Spanned::decode(&buf).unwrap_err().into_inner());
+    }
+
+}
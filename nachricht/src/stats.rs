@@ -0,0 +1,168 @@
+//! Summary statistics over a corpus of messages, e.g. to find out which fields are actually used before changing a
+//! schema. Values are grouped by their path: record fields are addressed by name, while array elements and map entries
+//! are aggregated under `*` since their indices and keys are data rather than structure. The path of the outermost
+//! value is the empty string.
+//!
+//! ```
+//! use nachricht::*;
+//! use std::borrow::Cow;
+//! use std::collections::BTreeMap;
+//!
+//! let mut stats = Statistics::default();
+//! for name in [Some("Liv"), None] {
+//!     let mut fields = BTreeMap::from([(Cow::Borrowed("age"), Value::Int(Sign::Pos, 33))]);
+//!     if let Some(name) = name {
+//!         fields.insert(Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name)));
+//!     }
+//!     let value = Value::Record(fields);
+//!     let mut buf = Vec::new();
+//!     let size = Encoder::encode(&value, &mut buf).unwrap();
+//!     stats.add(&value, size);
+//! }
+//! assert_eq!(2, stats.messages());
+//! assert_eq!(Some(0.5), stats.frequency("name"));
+//! assert_eq!(Some(&2), stats.path("age").unwrap().types.get("integer"));
+//! ```
+
+use crate::value::Value;
+use std::collections::BTreeMap;
+
+/// Statistics about all values found at a single path.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PathStatistics {
+    /// The number of values found at this path
+    pub count: usize,
+    /// The number of values per type, keyed by `Value::typename`
+    pub types: BTreeMap<&'static str, usize>,
+    /// The sum of the lengths of all strings, symbols, bytes and containers
+    pub total_length: usize,
+}
+
+impl PathStatistics {
+    /// The average length of the values at this path, see `total_length`.
+    pub fn average_length(&self) -> f64 {
+        average(self.total_length, self.count)
+    }
+}
+
+/// Statistics about a corpus of messages, see the module documentation.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Statistics {
+    messages: usize,
+    total_size: usize,
+    paths: BTreeMap<String, PathStatistics>,
+}
+
+impl Statistics {
+
+    /// Add a message and the size of its encoded form.
+    pub fn add(&mut self, value: &Value, size: usize) {
+        self.messages += 1;
+        self.total_size += size;
+        let mut stack = vec![(value, String::new())];
+        while let Some((value, path)) = stack.pop() {
+            let child = |segment: &str| match path.as_str() {
+                "" => segment.to_owned(),
+                parent => format!("{}.{}", parent, segment),
+            };
+            let length = match value {
+                Value::Str(v) | Value::Symbol(v) => v.len(),
+                Value::Bytes(v) => v.len(),
+                Value::Array(v) => {
+                    stack.extend(v.iter().map(|element| (element, child("*"))));
+                    v.len()
+                },
+                Value::Map(v) => {
+                    stack.extend(v.iter().map(|(_, val)| (val, child("*"))));
+                    v.len()
+                },
+                Value::Record(v) => {
+                    stack.extend(v.iter().map(|(key, val)| (val, child(key))));
+                    v.len()
+                },
+                _ => 0,
+            };
+            let stats = self.paths.entry(path).or_default();
+            stats.count += 1;
+            *stats.types.entry(value.typename()).or_default() += 1;
+            stats.total_length += length;
+        }
+    }
+
+    /// The number of messages added so far.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    /// The average encoded size of all messages.
+    pub fn average_size(&self) -> f64 {
+        average(self.total_size, self.messages)
+    }
+
+    /// The statistics for the given path, if any value was found there.
+    pub fn path(&self, path: &str) -> Option<&PathStatistics> {
+        self.paths.get(path)
+    }
+
+    /// The statistics of all paths ordered by path.
+    pub fn paths(&self) -> impl Iterator<Item = (&str, &PathStatistics)> + '_ {
+        self.paths.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// The fraction of values at the parent path which contain a value at the given path. For record fields, this
+    /// is how often the field is present. Returns `None` if the path has never been seen.
+    pub fn frequency(&self, path: &str) -> Option<f64> {
+        let stats = self.paths.get(path)?;
+        let parent = match path.rfind('.') {
+            Some(i) => &path[..i],
+            None if path.is_empty() => return Some(1.0),
+            None => "",
+        };
+        let parent = self.paths.get(parent)?;
+        let containers = parent.types.iter().filter(|(t, _)| ["record", "map", "array"].contains(t)).map(|(_, c)| c).sum();
+        Some(average(stats.count, containers).min(1.0))
+    }
+
+}
+
+fn average(total: usize, count: usize) -> f64 {
+    match count {
+        0 => 0.0,
+        c => total as f64 / c as f64,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Statistics;
+    use crate::header::Sign;
+    use crate::value::Value;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn cat(name: &str, lives: Option<u64>) -> Value<'_> {
+        let mut fields = BTreeMap::from([(Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name)))]);
+        if let Some(lives) = lives {
+            fields.insert(Cow::Borrowed("lives"), Value::Int(Sign::Pos, lives));
+        }
+        Value::Record(fields)
+    }
+
+    #[test]
+    fn statistics() {
+        let mut stats = Statistics::default();
+        stats.add(&Value::Array(vec![cat("Jessica", Some(9)), cat("Wantan", None)]), 30);
+        stats.add(&Value::Array(vec![cat("Sphinx", Some(7)), Value::Null]), 20);
+        assert_eq!(2, stats.messages());
+        assert_eq!(25.0, stats.average_size());
+        let cats = stats.path("*").unwrap();
+        assert_eq!((4, Some(&3), Some(&1)), (cats.count, cats.types.get("record"), cats.types.get("null")));
+        assert_eq!(6.0 + 1.0 / 3.0, stats.path("*.name").unwrap().average_length());
+        assert_eq!(Some(2.0 / 3.0), stats.frequency("*.lives"));
+        assert_eq!(Some(1.0), stats.frequency("*.name"));
+        assert_eq!(Some(1.0), stats.frequency(""));
+        assert_eq!(None, stats.frequency("*.species"));
+        assert_eq!(vec!["", "*", "*.lives", "*.name"], stats.paths().map(|(p, _)| p).collect::<Vec<_>>());
+    }
+
+}
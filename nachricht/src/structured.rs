@@ -0,0 +1,149 @@
+//! Inspection of values through the object-safe `valuable` traits, which is how `tracing` records structured fields.
+//! Records become structs with their fields as named fields, arrays and bytes become lists and maps keep their
+//! arbitrary keys, so a decoded message can be logged as a structured event instead of its textual representation:
+//!
+//! ```ignore
+//! tracing::info!(message = valuable::Valuable::as_value(&value), "received");
+//! ```
+//!
+//! Note that `tracing` only passes these values on to subscribers when built with `--cfg tracing_unstable` and its
+//! `valuable` feature enabled.
+
+use crate::{Sign, Value};
+use valuable::{Fields, Listable, Mappable, NamedField, NamedValues, Slice, StructDef, Structable, Valuable, Visit};
+
+impl<'a> Valuable for Value<'a> {
+
+    fn as_value(&self) -> valuable::Value<'_> {
+        match self {
+            Value::Null                 => valuable::Value::Unit,
+            Value::Bool(v)              => valuable::Value::Bool(*v),
+            Value::F32(v)               => valuable::Value::F32(*v),
+            Value::F64(v)               => valuable::Value::F64(*v),
+            Value::Int(Sign::Pos, v)    => valuable::Value::U64(*v),
+            Value::Int(Sign::Neg, v)    => valuable::Value::I128(-i128::from(*v)),
+            Value::Str(v)               => valuable::Value::String(v),
+            Value::Symbol(v)            => valuable::Value::String(v),
+            Value::Bytes(_)
+            | Value::Array(_)           => valuable::Value::Listable(self),
+            Value::Map(_)               => valuable::Value::Mappable(self),
+            Value::Record(_)            => valuable::Value::Structable(self),
+        }
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        match self {
+            Value::Bytes(v)  => visit.visit_primitive_slice(Slice::U8(v)),
+            Value::Array(v)  => v.iter().for_each(|element| visit.visit_value(element.as_value())),
+            Value::Map(v)    => v.iter().for_each(|(key, val)| visit.visit_entry(key.as_value(), val.as_value())),
+            Value::Record(v) => {
+                let names = v.keys().map(|key| NamedField::new(key)).collect::<Vec<_>>();
+                let values = v.values().map(Valuable::as_value).collect::<Vec<_>>();
+                visit.visit_named_fields(&NamedValues::new(&names, &values));
+            },
+            _                => visit.visit_value(self.as_value()),
+        }
+    }
+
+}
+
+impl<'a> Listable for Value<'a> {
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self {
+            Value::Bytes(v) => v.len(),
+            Value::Array(v) => v.len(),
+            _               => 0,
+        };
+        (len, Some(len))
+    }
+
+}
+
+impl<'a> Mappable for Value<'a> {
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self {
+            Value::Map(v) => v.len(),
+            _             => 0,
+        };
+        (len, Some(len))
+    }
+
+}
+
+impl<'a> Structable for Value<'a> {
+
+    /// The field names of a record are only known at runtime.
+    fn definition(&self) -> StructDef<'_> {
+        StructDef::new_dynamic("record", Fields::Named(&[]))
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Sign, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+    use valuable::{NamedValues, Valuable, Visit};
+
+    /// Flattens the visited values into `path=value` pairs.
+    #[derive(Default)]
+    struct Fields {
+        path: Vec<String>,
+        fields: Vec<String>,
+    }
+
+    impl Visit for Fields {
+
+        fn visit_value(&mut self, value: valuable::Value<'_>) {
+            match value {
+                valuable::Value::Structable(v) => v.visit(self),
+                valuable::Value::Listable(v) => v.visit(self),
+                valuable::Value::Mappable(v) => v.visit(self),
+                _ => self.fields.push(format!("{}={:?}", self.path.join("."), value)),
+            }
+        }
+
+        fn visit_named_fields(&mut self, named_values: &NamedValues<'_>) {
+            for (field, value) in named_values {
+                self.path.push(field.name().to_string());
+                self.visit_value(*value);
+                self.path.pop();
+            }
+        }
+
+        fn visit_entry(&mut self, key: valuable::Value<'_>, value: valuable::Value<'_>) {
+            self.path.push(format!("{:?}", key));
+            self.visit_value(value);
+            self.path.pop();
+        }
+
+    }
+
+    #[test]
+    fn structured() {
+        let value = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+            (Cow::Borrowed("photo"), Value::Bytes(Cow::Borrowed(&[1, 2]))),
+            (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus"))),
+            (Cow::Borrowed("tags"), Value::Array(vec![Value::Str(Cow::Borrowed("cute")), Value::Bool(true)])),
+            (Cow::Borrowed("toys"), Value::Map(vec![(Value::Int(Sign::Neg, 1), Value::Null)])),
+            (Cow::Borrowed("weight"), Value::F32(4.5)),
+        ]));
+        let mut fields = Fields::default();
+        fields.visit_value(value.as_value());
+        assert_eq!(fields.fields, [
+            "name=\"Jessica\"",
+            "photo=1",
+            "photo=2",
+            "species=\"FelisCatus\"",
+            "tags=\"cute\"",
+            "tags=true",
+            "toys.-1=()",
+            "weight=4.5",
+        ]);
+    }
+
+}
@@ -0,0 +1,157 @@
+//! The symbol table which every message builds up while it is decoded. Each `Header::Sym` and each `Header::Rec`
+//! appends an entry in the order in which they appear in the message, where the symbols of a record's keys precede the
+//! record itself. A `Header::Ref` refers to an entry by its index and stands for the referenced symbol or for a record
+//! with the referenced layout, whose values follow the header. The table starts out empty for every message.
+//!
+//! Tools which work at the header level can use `SymbolTable` to resolve references:
+//!
+//! ```
+//! use nachricht::*;
+//!
+//! // a symbol followed by a reference to it
+//! let buf = [0x82, 0x61, 0x61, 0xe0];
+//! let mut table = SymbolTable::new();
+//! let mut pos = 1;
+//! let mut symbols = Vec::new();
+//! while pos < buf.len() {
+//!     let (header, c) = Header::decode(&buf[pos..]).unwrap();
+//!     pos += c;
+//!     let symbol = match header {
+//!         Header::Sym(len) => {
+//!             let symbol = std::str::from_utf8(&buf[pos..pos + len]).unwrap();
+//!             pos += len;
+//!             table.push(Refable::Sym(symbol));
+//!             symbol
+//!         },
+//!         Header::Ref(i) => table.symbol(i).unwrap(),
+//!         _ => unreachable!(),
+//!     };
+//!     symbols.push(symbol);
+//! }
+//! assert_eq!(vec!["a", "a"], symbols);
+//! ```
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::DecodeError;
+use std::rc::Rc;
+
+/// An entry of the symbol table: either a symbol or the keys of a record layout. Layouts are shared, so that every
+/// record which refers to one can hold on to its keys without copying them.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[repr(u8)]
+pub enum Refable<'a> {
+    Sym(&'a str),
+    Rec(Rc<[&'a str]>),
+}
+
+impl<'a> Refable<'a> {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Refable::Sym(_) => "Sym",
+            Refable::Rec(_) => "Rec",
+        }
+    }
+
+    /// The length of the symbol or the total length of the keys, which is what a reference to this entry brings back
+    /// to life.
+    pub fn size(&self) -> usize {
+        match self {
+            Refable::Sym(symbol) => symbol.len(),
+            Refable::Rec(keys) => keys.iter().map(|key| key.len()).sum(),
+        }
+    }
+}
+
+/// The symbols and record layouts defined by a message so far, see the module documentation.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SymbolTable<'a> {
+    entries: Vec<Refable<'a>>,
+    /// The sum of the sizes of all entries
+    size: usize,
+}
+
+impl<'a> SymbolTable<'a> {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an entry and return its index.
+    pub fn push(&mut self, entry: Refable<'a>) -> usize {
+        self.size = self.size.saturating_add(entry.size());
+        self.entries.push(entry);
+        self.entries.len() - 1
+    }
+
+    /// The entry with the given index, if it has been defined.
+    pub fn get(&self, index: usize) -> Option<&Refable<'a>> {
+        self.entries.get(index)
+    }
+
+    /// Resolve a `Header::Ref`. Fails with `DecodeError::InvalidRef` if the entry has not been defined.
+    pub fn resolve(&self, index: usize) -> Result<&Refable<'a>, DecodeError> {
+        self.entries.get(index).ok_or(DecodeError::InvalidRef(index))
+    }
+
+    /// Resolve a `Header::Ref` which has to refer to a symbol, e.g. in key position. Fails with
+    /// `DecodeError::IllegalKey` if it refers to a record layout.
+    pub fn symbol(&self, index: usize) -> Result<&'a str, DecodeError> {
+        match self.resolve(index)? {
+            Refable::Sym(symbol) => Ok(symbol),
+            Refable::Rec(_) => Err(DecodeError::IllegalKey("record")),
+        }
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The total size of all entries in bytes, see `Refable::size`.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Remove all entries, e.g. before the next message gets decoded.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    /// All entries ordered by their index.
+    pub fn as_slice(&self) -> &[Refable<'a>] {
+        &self.entries
+    }
+
+    pub fn into_entries(self) -> Vec<Refable<'a>> {
+        self.entries
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Refable, SymbolTable};
+    use crate::error::DecodeError;
+
+    #[test]
+    fn resolve() {
+        let mut table = SymbolTable::new();
+        assert_eq!(0, table.push(Refable::Sym("name")));
+        assert_eq!(1, table.push(Refable::Rec(vec!["name"].into())));
+        assert_eq!(Ok("name"), table.symbol(0));
+        assert_eq!(Ok(&Refable::Rec(vec!["name"].into())), table.resolve(1));
+        assert_eq!(Err(DecodeError::IllegalKey("record")), table.symbol(1));
+        assert_eq!(Err(DecodeError::InvalidRef(2)), table.resolve(2));
+        assert_eq!(8, table.size());
+        table.clear();
+        assert_eq!(0, table.size());
+        assert!(table.is_empty());
+    }
+
+}
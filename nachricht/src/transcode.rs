@@ -0,0 +1,212 @@
+//! Copy a message header by header without building a `Value`. The output uses a fresh symbol table: every symbol and
+//! record layout is defined on its first occurrence and referenced afterwards, no matter how the input was encoded.
+//! Headers are written in their shortest form. This makes it possible to normalize or re-frame proxied traffic while
+//! only keeping the symbol table in memory.
+//!
+//! Unlike `Encoder`, the transcoder does not sort the fields of records since it never sees a record as a whole. For
+//! input produced by `Encoder`, the output is therefore identical to the canonical encoding.
+
+use crate::error::{EncodeError, TranscodeError};
+use crate::header::Header;
+use crate::reader::{Reader, Token};
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Copies a single message from a buffer to a writer, see the module documentation.
+pub struct Transcoder<'a, 'w, W: Write> {
+    reader: Reader<'a>,
+    writer: &'w mut W,
+    written: usize,
+    /// Map symbol -> entry in the output table
+    symbols: HashMap<&'a str, usize>,
+    /// Map record -> entry in the output table
+    records: HashMap<Rc<[&'a str]>, usize>,
+    /// Next free entry in the output table
+    next_free: usize,
+}
+
+impl<'a, 'w, W: Write> Transcoder<'a, 'w, W> {
+
+    /// Transcode the message at the start of the buffer to the writer. The result contains the number of consumed
+    /// bytes and the number of written bytes. If an error occurs, the writer may contain an incomplete message.
+    pub fn transcode<B: ?Sized + AsRef<[u8]>>(buf: &'a B, writer: &'w mut W) -> Result<(usize, usize), TranscodeError> {
+        let mut transcoder = Self {
+            reader: Reader::new(buf),
+            writer,
+            written: 0,
+            symbols: HashMap::new(),
+            records: HashMap::new(),
+            next_free: 0,
+        };
+        transcoder.transcode_inner()?;
+        Ok((transcoder.reader.position(), transcoder.written))
+    }
+
+    fn transcode_inner(&mut self) -> Result<(), TranscodeError> {
+        // remaining values for each open container
+        let mut stack: Vec<usize> = Vec::new();
+        loop {
+            match self.transcode_token()? {
+                0 => {},
+                len => { stack.push(len); continue; },
+            }
+            loop {
+                match stack.last_mut() {
+                    None => return Ok(()),
+                    Some(1) => { stack.pop(); },
+                    Some(remaining) => { *remaining -= 1; break; },
+                }
+            }
+        }
+    }
+
+    /// Copy the next token. Returns the number of values which belong to it.
+    fn transcode_token(&mut self) -> Result<usize, TranscodeError> {
+        let token = self.reader.next_token().map_err(|e| e.at(self.reader.position()))?;
+        match token {
+            Token::Null => self.write_header(Header::Null)?,
+            Token::Bool(true) => self.write_header(Header::True)?,
+            Token::Bool(false) => self.write_header(Header::False)?,
+            Token::Int(s, v) => self.write_header(Header::Int(s, v))?,
+            Token::F32(v) => {
+                self.write_header(Header::F32)?;
+                self.write_slice(&v.to_be_bytes())?;
+            },
+            Token::F64(v) => {
+                self.write_header(Header::F64)?;
+                self.write_slice(&v.to_be_bytes())?;
+            },
+            Token::Bin(v) => {
+                self.write_header(Header::Bin(v.len()))?;
+                self.write_slice(v)?;
+            },
+            Token::Str(v) => {
+                self.write_header(Header::Str(v.len()))?;
+                self.write_slice(v.as_bytes())?;
+            },
+            Token::Sym(v) => self.encode_symbol(v)?,
+            Token::Rec(keys) => {
+                let len = keys.len();
+                self.encode_layout(keys)?;
+                return Ok(len);
+            },
+            Token::Arr(v) => {
+                self.write_header(Header::Arr(v))?;
+                return Ok(v);
+            },
+            Token::Map(v) => {
+                self.write_header(Header::Map(v))?;
+                return Ok(v.saturating_mul(2));
+            },
+        }
+        Ok(0)
+    }
+
+    fn write_header(&mut self, header: Header) -> Result<(), TranscodeError> {
+        self.written += header.encode(self.writer)?;
+        Ok(())
+    }
+
+    fn write_slice(&mut self, slice: &[u8]) -> Result<(), TranscodeError> {
+        self.writer.write_all(slice).map_err(EncodeError::from)?;
+        self.written += slice.len();
+        Ok(())
+    }
+
+    fn encode_symbol(&mut self, symbol: &'a str) -> Result<(), TranscodeError> {
+        match self.symbols.get(symbol) {
+            Some(i) => self.write_header(Header::Ref(*i)),
+            None => {
+                let index = self.next();
+                self.symbols.insert(symbol, index);
+                self.write_header(Header::Sym(symbol.len()))?;
+                self.write_slice(symbol.as_bytes())
+            }
+        }
+    }
+
+    fn encode_layout(&mut self, keys: Rc<[&'a str]>) -> Result<(), TranscodeError> {
+        match self.records.get(&*keys) {
+            Some(i) => self.write_header(Header::Ref(*i)),
+            None => {
+                self.write_header(Header::Rec(keys.len()))?;
+                for key in keys.iter() {
+                    self.encode_symbol(key)?;
+                }
+                let index = self.next();
+                self.records.insert(keys, index);
+                Ok(())
+            }
+        }
+    }
+
+    fn next(&mut self) -> usize {
+        self.next_free += 1;
+        self.next_free - 1
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use super::Transcoder;
+    use crate::error::{DecodeError, TranscodeError};
+    use crate::header::{Header, Sign};
+    use crate::value::{Decoder, Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn canonical() {
+        let cat = |name, lives| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("lives"), Value::Int(Sign::Pos, lives)),
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+        ]));
+        let value = Value::Map(vec![
+            (Value::Symbol(Cow::Borrowed("Jessica")), cat("Jessica", 9)),
+            (Value::Symbol(Cow::Borrowed("Wantan")), cat("Wantan", 7)),
+            (Value::Bytes(Cow::Borrowed(&[1, 2, 3])), Value::Array(vec![Value::F64(1.5), Value::Null])),
+        ]);
+        let mut canonical = Vec::new();
+        Encoder::encode(&value, &mut canonical).unwrap();
+        let mut buf = Vec::new();
+        assert_eq!((canonical.len(), canonical.len()), Transcoder::transcode(&canonical, &mut buf).unwrap());
+        assert_eq!(canonical, buf);
+    }
+
+    #[test]
+    fn deduplicate() {
+        let mut input = Vec::new();
+        Header::Arr(2).encode(&mut input).unwrap();
+        for lives in [9, 7] {
+            Header::Rec(1).encode(&mut input).unwrap();
+            Header::Sym(5).encode(&mut input).unwrap();
+            input.extend_from_slice(b"lives");
+            Header::Int(Sign::Pos, lives).encode(&mut input).unwrap();
+        }
+        let mut output = Vec::new();
+        let (consumed, written) = Transcoder::transcode(&input, &mut output).unwrap();
+        assert_eq!((input.len(), input.len() - 6), (consumed, written));
+        assert_eq!(Decoder::decode(&input).unwrap().0, Decoder::decode(&output).unwrap().0);
+    }
+
+    #[test]
+    fn invalid() {
+        let encode = |headers: &[Header]| {
+            let mut buf = Vec::new();
+            headers.iter().for_each(|h| { h.encode(&mut buf).unwrap(); });
+            buf
+        };
+        let mut output = Vec::new();
+        match Transcoder::transcode(&encode(&[Header::Rec(1), Header::Int(Sign::Pos, 1)]), &mut output) {
+            Err(TranscodeError::Decode(e)) => assert_eq!(DecodeError::IllegalKey("integer"), e.into_inner()),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match Transcoder::transcode(&encode(&[Header::Arr(2), Header::Null]), &mut output) {
+            Err(TranscodeError::Decode(e)) => assert_eq!(DecodeError::Eof, e.into_inner()),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+}
@@ -4,15 +4,22 @@
 //! so you pay their full bandwidth costs only once. This encoding is transparent, there is no need
 //! to manually define a symbol table within the model.
 
+use crate::canon::map_order;
 use crate::header::{Header, Sign};
-use crate::error::{DecodeError, DecoderError, EncodeError};
-use std::mem::size_of;
-use std::io::Write;
-use std::convert::TryInto;
-use std::str::from_utf8;
+use crate::metrics::{self, Metrics};
+use crate::error::{DecodeError, DecoderError, EncodeError, LengthError};
+use crate::reader::{allocate, DecoderOptions, Reader, Token};
+use crate::symbols::SymbolTable;
+use std::mem::take;
+use std::io::{sink, ErrorKind, Write};
 use std::iter::repeat;
+use std::ops::Range;
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
 
 /// The possible values according to the `nachricht` data model.
 #[derive(Debug, Clone, PartialEq)]
@@ -32,10 +39,51 @@ pub enum Value<'a> {
 
 impl<'a> Value<'a> {
 
-    const PROTECTED_CHARS: &'static str = "\n\\$ ,:\"'()[]{}#";
+    const PROTECTED_CHARS: &'static str = "\\$,:\"'()[]{}#";
 
-    fn b64(input: &[u8]) -> String {
-        const CHAR_SET: &'static [char] = &['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N',
+    /// Whether the character may appear in a symbol or record key without quotes in the text format. This includes
+    /// all letters, digits, emoji and punctuation of any script except for whitespace, control characters, invisible
+    /// formatting characters which could disguise the text in an editor, and the characters `\$,:"'()[]{}#` which are
+    /// part of the syntax.
+    pub fn is_identifier_char(c: char) -> bool {
+        !(c.is_whitespace() || c.is_control() || Self::is_invisible(c) || Self::PROTECTED_CHARS.contains(c))
+    }
+
+    /// Formatting characters without a glyph, like the zero width space or the byte order mark, which editors may
+    /// silently drop or which could disguise the text surrounding them
+    fn is_invisible(c: char) -> bool {
+        matches!(c, '\u{ad}' | '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{2069}' | '\u{feff}')
+    }
+
+    /// Whether a symbol or key has to be quoted in the text format, which is also the case if it contains the start of
+    /// a comment
+    fn needs_quotes(text: &str) -> bool {
+        text.is_empty() || !text.chars().all(Self::is_identifier_char) || text.contains("//") || text.contains("/*")
+    }
+
+    /// Escape a string for the text format. Besides the quote and the backslash, all control characters and invisible
+    /// formatting characters are escaped so that the output cannot mess with a terminal or an editor and is parsed back
+    /// to the same string.
+    fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '"'                 => escaped.push_str("\\\""),
+                '\\'                => escaped.push_str("\\\\"),
+                '\n'                => escaped.push_str("\\n"),
+                '\r'                => escaped.push_str("\\r"),
+                '\t'                => escaped.push_str("\\t"),
+                c if c.is_control() || Self::is_invisible(c)
+                                    => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c                   => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    #[allow(clippy::manual_repeat_n)] // repeat_n is not available on our MSRV
+    pub(crate) fn b64(input: &[u8]) -> String {
+        const CHAR_SET: &[char] = &['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N',
             'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', 'a', 'b', 'c', 'd', 'e', 'f', 'g',
             'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
             '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '+', '/'
@@ -52,7 +100,84 @@ impl<'a> Value<'a> {
         }).collect()
     }
 
-    fn typename(&self) -> &'static str {
+    /// Copy all borrowed data so that the value is no longer tied to the lifetime of its source.
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Self::Null      => Value::Null,
+            Self::Bool(v)   => Value::Bool(v),
+            Self::F32(v)    => Value::F32(v),
+            Self::F64(v)    => Value::F64(v),
+            Self::Bytes(v)  => Value::Bytes(Cow::Owned(v.into_owned())),
+            Self::Int(s, v) => Value::Int(s, v),
+            Self::Str(v)    => Value::Str(Cow::Owned(v.into_owned())),
+            Self::Symbol(v) => Value::Symbol(Cow::Owned(v.into_owned())),
+            Self::Record(v) => Value::Record(v.into_iter().map(|(k, v)| (Cow::Owned(k.into_owned()), v.into_owned())).collect()),
+            Self::Map(v)    => Value::Map(v.into_iter().map(|(k, v)| (k.into_owned(), v.into_owned())).collect()),
+            Self::Array(v)  => Value::Array(v.into_iter().map(Value::into_owned).collect()),
+        }
+    }
+
+    /// The textual representation like `Display` produces it, but deviating from the canonical form as specified by
+    /// the options.
+    pub fn to_text(&self, options: TextOptions) -> String {
+        Text(self, options, &RefCell::default()).to_string()
+    }
+
+    /// The range of the payload of a string, symbol or bytes within the buffer it has been decoded from, e.g. for
+    /// mapping values back to wire offsets in diagnostics or for redacting them in place. Nothing is recorded while
+    /// decoding: the range is computed from the location of the borrowed payload, so it is only available as long as
+    /// the payload is borrowed from `buf`. A symbol which has been decoded from a reference yields the range of its
+    /// definition.
+    pub fn span(&self, buf: &[u8]) -> Option<Range<usize>> {
+        let payload = match self {
+            Self::Str(Cow::Borrowed(v)) | Self::Symbol(Cow::Borrowed(v)) => v.as_bytes(),
+            Self::Bytes(Cow::Borrowed(v)) => v,
+            _ => return None,
+        };
+        let start = (payload.as_ptr() as usize).checked_sub(buf.as_ptr() as usize)?;
+        let end = start.checked_add(payload.len()).filter(|end| *end <= buf.len())?;
+        Some(start..end)
+    }
+
+    /// Look up a nested value by a JSON pointer as specified by RFC 6901, e.g. `/cats/0/name`. Each segment selects a
+    /// record field, a map entry whose key is a string or symbol of the same name, or an array element by its index.
+    /// Within a segment, `~1` stands for `/` and `~0` for `~`. The empty pointer selects the value itself.
+    pub fn pointer(&self, pointer: &str) -> Option<&Value<'a>> {
+        Self::segments(pointer)?.try_fold(self, |value, segment| match value {
+            Self::Record(fields) => fields.get(segment.as_str()),
+            Self::Map(entries) => entries.iter().find_map(|(key, val)| match key {
+                Self::Str(k) | Self::Symbol(k) if *k == segment => Some(val),
+                _ => None,
+            }),
+            Self::Array(elements) => elements.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+
+    /// Like `pointer`, but for modifying the nested value.
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Value<'a>> {
+        Self::segments(pointer)?.try_fold(self, |value, segment| match value {
+            Self::Record(fields) => fields.get_mut(segment.as_str()),
+            Self::Map(entries) => entries.iter_mut().find_map(|(key, val)| match key {
+                Self::Str(k) | Self::Symbol(k) if *k == segment => Some(val),
+                _ => None,
+            }),
+            Self::Array(elements) => elements.get_mut(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+
+    /// The unescaped segments of a JSON pointer, or `None` if it is malformed
+    pub(crate) fn segments(pointer: &str) -> Option<impl Iterator<Item = String> + '_> {
+        let segments = match pointer {
+            "" => None,
+            _ => Some(pointer.strip_prefix('/')?.split('/').map(|s| s.replace("~1", "/").replace("~0", "~"))),
+        };
+        Some(segments.into_iter().flatten())
+    }
+
+    /// The name of the type of this value, e.g. for error messages.
+    pub fn typename(&self) -> &'static str {
         match *self {
             Self::Null      => "null",
             Self::Bool(_)   => "bool",
@@ -68,259 +193,761 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// Render the value as a tree drawn with box-drawing characters, one node per line, for logs and debugging output.
+    /// Unlike the canonical text format, every node is annotated with its type and size, and symbols and record layouts
+    /// with the index the `Encoder` assigns to them in the symbol table.
+    pub fn to_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, "", &mut Interner::default());
+        out
+    }
+
+    fn write_tree<'v>(&'v self, out: &mut String, indent: &str, symbols: &mut Interner<'v>) {
+        let children: Vec<(String, &Value)> = match self {
+            Self::Array(v)  => {
+                out.push_str(&format!("array ({} elements)\n", v.len()));
+                v.iter().map(|e| (String::new(), e)).collect()
+            },
+            Self::Map(v)    => {
+                out.push_str(&format!("map ({} entries)\n", v.len()));
+                v.iter().flat_map(|(k, v)| vec![("key: ".to_string(), k), ("value: ".to_string(), v)]).collect()
+            },
+            Self::Record(v) => {
+                let layout = symbols.layout(v.keys().map(|k| k.as_ref()).collect());
+                out.push_str(&format!("record ({} fields) @{}\n", v.len(), layout));
+                v.iter().map(|(k, v)| (format!("{}: ", Value::Symbol(Cow::Borrowed(k)).to_string().trim_start_matches('#')), v)).collect()
+            },
+            Self::Str(v)    => {
+                out.push_str(&format!("string ({} bytes) {}\n", v.len(), self));
+                Vec::new()
+            },
+            Self::Bytes(v)  => {
+                out.push_str(&format!("bytes ({} bytes) {}\n", v.len(), self));
+                Vec::new()
+            },
+            Self::Symbol(v) => {
+                let index = symbols.symbol(v);
+                out.push_str(&format!("symbol {} @{}\n", self, index));
+                Vec::new()
+            },
+            _ => {
+                out.push_str(&format!("{} {}\n", self.typename(), self));
+                Vec::new()
+            },
+        };
+        let count = children.len();
+        for (i, (label, child)) in children.into_iter().enumerate() {
+            let last = i + 1 == count;
+            out.push_str(indent);
+            out.push_str(if last { "\u{2514}\u{2500} " } else { "\u{251c}\u{2500} " });
+            out.push_str(&label);
+            child.write_tree(out, &format!("{}{}", indent, if last { "   " } else { "\u{2502}  " }), symbols);
+        }
+    }
+
 }
 
 
 
 impl<'a> std::fmt::Display for Value<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        Text(self, TextOptions::default(), &RefCell::default()).fmt(f)
+    }
+}
+
+/// Options for the textual representation produced by `Value::to_text`. The defaults yield the canonical form which
+/// `Display` produces as well. Fields of records are always printed sorted by their keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextOptions {
+    /// Print non-empty byte strings up to this length in hexadecimal like `x'deadbeef'` instead of base64, e.g. to
+    /// compare checksums or identifiers. Longer ones are still printed in base64 which is more compact.
+    pub hex_bytes: usize,
+    /// Print symbols which have been defined before as references like `&3` to the index in the symbol table which the
+    /// `Encoder` assigns to them, which shows how a message is interned on the wire. Parsers resolve them back.
+    pub references: bool,
+    /// The number of spaces per level of nesting, two by default.
+    pub indent: usize,
+    /// Pad the keys of records so that the values of all fields start in the same column.
+    pub align: bool,
+    /// Print the entries of maps in the order which `canonicalize` gives them. Together with `references`, the
+    /// indices are those of the canonicalized value.
+    pub sort_maps: bool,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self { hex_bytes: 0, references: false, indent: 2, align: false, sort_maps: false }
+    }
+}
+
+/// A value with the options to print it with and the symbols defined so far
+struct Text<'t, 'v, 'a>(&'v Value<'a>, TextOptions, &'t RefCell<Interner<'v>>);
+
+impl<'t, 'v, 'a> std::fmt::Display for Text<'t, 'v, 'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Text(value, options, interner) = *self;
+        if options.references {
+            let mut interner = interner.borrow_mut();
+            match value {
+                Value::Symbol(v) => match interner.get(v) {
+                    Some(i) => return write!(f, "&{}", i),
+                    None    => { interner.symbol(v); },
+                },
+                Value::Record(v) => { interner.layout(v.keys().map(|k| k.as_ref()).collect()); },
+                _ => {},
+            }
+        }
+        match value {
             Value::Null         => f.write_str("null"),
             Value::Bool(true)   => f.write_str("true"),
             Value::Bool(false)  => f.write_str("false"),
+            Value::F32(v) if v.is_nan() && v.to_bits() != f32::NAN.to_bits()
+                                => write!(f, "$nan(0x{:08x})", v.to_bits()),
+            Value::F32(v) if v.is_nan()      => f.write_str("$nan"),
+            Value::F32(v) if v.is_infinite() => f.write_str(if *v > 0.0 { "$inf" } else { "-$inf" }),
             Value::F32(v)       => write!(f, "${}", v),
+            Value::F64(v) if v.is_nan() && v.to_bits() != f64::NAN.to_bits()
+                                => write!(f, "$$nan(0x{:016x})", v.to_bits()),
+            Value::F64(v) if v.is_nan()      => f.write_str("$$nan"),
+            Value::F64(v) if v.is_infinite() => f.write_str(if *v > 0.0 { "$$inf" } else { "-$$inf" }),
             Value::F64(v)       => write!(f, "$${}", v),
-            Value::Bytes(v)     => write!(f, "'{}'", Self::b64(v).as_str()),
+            Value::Bytes(v) if !v.is_empty() && v.len() <= options.hex_bytes
+                                => write!(f, "x'{}'", v.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+            Value::Bytes(v)     => write!(f, "'{}'", Value::b64(v).as_str()),
             Value::Int(s, v)    => write!(f, "{}{}", match s { Sign::Pos => "", Sign::Neg => "-" }, v),
-            Value::Str(v)       => write!(f, "\"{}\"", v.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")),
-            Value::Symbol(v) if v.chars().any(|c| Self::PROTECTED_CHARS.contains(c))
-                                => write!(f, "#\"{}\"", v.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")),
+            Value::Str(v)       => write!(f, "\"{}\"", Value::escape(v)),
+            Value::Symbol(v) if Value::needs_quotes(v)
+                                => write!(f, "#\"{}\"", Value::escape(v)),
             Value::Symbol(v)    => write!(f, "#{}", v),
-            Value::Record(v)    => write!(f, "(\n{}\n)", v.iter()
-                .flat_map(|(k, f)| format!("{}: {},", if k.chars().any(|c| Self::PROTECTED_CHARS.contains(c)) {
-                    format!("\"{}\"", k.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n"))
+            Value::Record(v) if v.is_empty() => f.write_str("()"),
+            Value::Map(v) if v.is_empty()    => f.write_str("{}"),
+            Value::Array(v) if v.is_empty()  => f.write_str("[]"),
+            Value::Record(v)    => {
+                let keys = v.keys().map(|k| if Value::needs_quotes(k) { format!("\"{}\":", Value::escape(k)) } else { format!("{}:", k) }).collect::<Vec<_>>();
+                let width = if options.align { keys.iter().map(|k| k.chars().count()).max().unwrap_or_default() } else { 0 };
+                Text::block(f, "(", ")", options, keys.iter().zip(v.values())
+                    .map(|(k, f)| format!("{:width$} {},", k, Text(f, options, interner), width = width)))
+            },
+            Value::Map(v)       => {
+                let order = if options.sort_maps {
+                    map_order(v.iter().map(|(k, _)| k)).map_err(|_| std::fmt::Error)?
                 } else {
-                    format!("{}", k )
-                }, f).lines().map(|line| format!("  {}", line)).collect::<Vec<String>>())
-                .collect::<Vec<String>>().join("\n")),
-            Value::Map(v)       => write!(f, "{{\n{}\n}}", v.iter()
-                .flat_map(|(k, f)| format!("{}: {},", k, f).lines().map(|line| format!("  {}", line)).collect::<Vec<String>>())
-                .collect::<Vec<String>>().join("\n")),
-            Value::Array(v)    => write!(f, "[\n{}\n]", v.iter()
-                .flat_map(|f| format!("{},", f).lines().map(|line| format!("  {}", line)).collect::<Vec<String>>())
-                .collect::<Vec<String>>().join("\n")),
+                    (0..v.len()).collect()
+                };
+                Text::block(f, "{", "}", options, order.into_iter().filter_map(|i| v.get(i))
+                    .map(|(k, f)| format!("{}: {},", Text(k, options, interner), Text(f, options, interner))))
+            },
+            Value::Array(v)    => Text::block(f, "[", "]", options, v.iter().map(|f| format!("{},", Text(f, options, interner)))),
         }
     }
 }
 
-#[derive(PartialEq, Clone)]
-#[repr(u8)]
-pub enum Refable<'a> {
-    Sym(&'a str),
-    Rec(Vec<&'a str>),
+impl<'t, 'v, 'a> Text<'t, 'v, 'a> {
+    /// Write the items of a container on their own lines, indented by one level
+    fn block(f: &mut std::fmt::Formatter<'_>, open: &str, close: &str, options: TextOptions, items: impl Iterator<Item = String>) -> std::fmt::Result {
+        let indent = " ".repeat(options.indent);
+        write!(f, "{}\n{}\n{}", open, items.flat_map(|item| item.lines().map(|line| format!("{}{}", indent, line)).collect::<Vec<_>>())
+            .collect::<Vec<_>>().join("\n"), close)
+    }
 }
 
-impl<'a> Refable<'a> {
-    pub fn name(&self) -> &'static str {
-        match *self {
-            Refable::Sym(_) => "Sym",
-            Refable::Rec(_) => "Rec",
+/// The indices which the `Encoder` assigns to symbols and record layouts, replayed in encoding order by
+/// `Value::to_tree` and the textual representation. The keys of a new layout are defined before the layout itself.
+#[derive(Default)]
+struct Interner<'v> {
+    symbols: HashMap<&'v str, usize>,
+    layouts: HashMap<Vec<&'v str>, usize>,
+    next_free: usize,
+}
+
+impl<'v> Interner<'v> {
+
+    fn get(&self, symbol: &str) -> Option<usize> {
+        self.symbols.get(symbol).copied()
+    }
+
+    fn symbol(&mut self, symbol: &'v str) -> usize {
+        let next_free = &mut self.next_free;
+        *self.symbols.entry(symbol).or_insert_with(|| {
+            *next_free += 1;
+            *next_free - 1
+        })
+    }
+
+    fn layout(&mut self, keys: Vec<&'v str>) -> usize {
+        if let Some(i) = self.layouts.get(&keys) {
+            return *i;
+        }
+        for key in &keys {
+            self.symbol(key);
         }
+        self.next_free += 1;
+        self.layouts.insert(keys, self.next_free - 1);
+        self.next_free - 1
+    }
+
+}
+
+/// Options which influence the wire format produced by the `Encoder`. The defaults yield the canonical encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EncoderOptions {
+    /// Encode map keys of type `Value::Str` as symbols, so that repeated keys only need to be transmitted once. They
+    /// will be decoded as `Value::Symbol`.
+    pub symbol_keys: bool,
+    /// Encode every `Value::Str` as a symbol, so that repeated strings anywhere in the message only need to be
+    /// transmitted once. This changes the decoded type: the strings come back as `Value::Symbol` rather than
+    /// `Value::Str`. Values of any type can be shared without changing it with `Value::share`.
+    pub symbol_strings: bool,
+    /// Report the size and duration of every encoded value to these hooks.
+    pub metrics: Option<&'static dyn Metrics>,
+}
+
+/// A value which still needs to be encoded. Map keys are tracked separately since they may be encoded as symbols.
+enum Pending<'v> {
+    Key(&'v Value<'v>),
+    Value(&'v Value<'v>),
+}
+
+/// How the `Encoder` stores a symbol or key in its tables. Values which outlive the encoder can be borrowed, values
+/// which are produced on the fly need to be copied.
+type Intern<'v, 'w> = fn(&'v str) -> Cow<'w, str>;
+
+fn copy<'w>(symbol: &str) -> Cow<'w, str> {
+    Cow::Owned(symbol.to_owned())
+}
+
+/// Where the `Encoder` puts its output. Writing to a `Vec` cannot fail, so it does not go through `std::io::Write` and
+/// its error type only covers lengths which cannot be represented on the wire.
+trait Sink {
+    type Error: From<LengthError>;
+    fn put(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl Sink for Vec<u8> {
+    type Error = LengthError;
+
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) -> Result<(), LengthError> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+struct IoSink<'w, W: Write>(&'w mut W);
+
+impl<'w, W: Write> Sink for IoSink<'w, W> {
+    type Error = EncodeError;
+
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        Ok(self.0.write_all(bytes)?)
     }
 }
 
+/// The keys of a record layout known to the `Encoder` and its entry in the table
+type Layout<'w> = (Vec<Cow<'w, str>>, usize);
+
 /// Used to encode `nachricht` fields. This uses a symbol table to allow referencing symbols and
 /// record layouts which get repeated.
-pub struct Encoder<'w, W: Write> {
+pub struct Encoder<'w, W> {
     writer: &'w mut W,
+    options: EncoderOptions,
     /// Next free value to insert into the table
     next_free: usize,
     /// Map symbol -> entry in the table
     symbols: HashMap<Cow<'w, str>, usize>,
-    /// Map record -> entry in the table
-    records: HashMap<Vec<Cow<'w, str>>, usize>,
+    /// Map hash of the keys of a record -> layouts with that hash and their entries in the table
+    records: HashMap<u64, Vec<Layout<'w>>>,
+    /// Hashes the keys of records
+    layouts: RandomState,
 }
 
 impl<'w, W: Write> Encoder<'w, W> {
 
     /// Encode a field to the given writer. The resulting `usize` is the amount of bytes that got written.
     pub fn encode(field: &'w Value, writer: &'w mut W) -> Result<usize, EncodeError> {
-        Self { writer, symbols: HashMap::new(), records: HashMap::new(), next_free: 0 }.encode_inner(field)
+        Self::encode_with_options(field, writer, EncoderOptions::default())
+    }
+
+    /// Like `encode` but deviates from the canonical encoding as specified by the options.
+    pub fn encode_with_options(field: &'w Value, writer: &'w mut W, options: EncoderOptions) -> Result<usize, EncodeError> {
+        Encoder::with_sink(&mut IoSink(writer), options).encode_inner(field)
+    }
+
+    /// Encode an array whose elements are produced on the fly, e.g. while reading them from a database, without
+    /// materializing the whole array first. Symbols and layouts are shared between the elements, so the output is the
+    /// same as for a `Value::Array` of these elements. `len` precedes the elements on the wire, if the iterator yields a
+    /// different number of elements, `EncodeError::Count` is returned and the output is incomplete.
+    pub fn encode_array_iter<'v, I: IntoIterator<Item = Value<'v>>>(len: usize, elements: I, writer: &'w mut W) -> Result<usize, EncodeError> {
+        Self::encode_array_iter_with_options(len, elements, writer, EncoderOptions::default())
+    }
+
+    /// Like `encode_array_iter` but deviates from the canonical encoding as specified by the options.
+    pub fn encode_array_iter_with_options<'v, I>(len: usize, elements: I, writer: &'w mut W, options: EncoderOptions) -> Result<usize, EncodeError>
+        where I: IntoIterator<Item = Value<'v>>
+    {
+        Encoder::with_sink(&mut IoSink(writer), options).encode_iter(len, elements, |encoder| encoder.write_header(Header::Arr(len)), |encoder, element| {
+            encoder.encode_pending(Pending::Value(&element), copy)
+        })
+    }
+
+    /// Like `encode_array_iter`, but for a map whose entries are produced on the fly.
+    pub fn encode_map_iter<'v, I: IntoIterator<Item = (Value<'v>, Value<'v>)>>(len: usize, entries: I, writer: &'w mut W) -> Result<usize, EncodeError> {
+        Self::encode_map_iter_with_options(len, entries, writer, EncoderOptions::default())
+    }
+
+    /// Like `encode_map_iter` but deviates from the canonical encoding as specified by the options.
+    pub fn encode_map_iter_with_options<'v, I>(len: usize, entries: I, writer: &'w mut W, options: EncoderOptions) -> Result<usize, EncodeError>
+        where I: IntoIterator<Item = (Value<'v>, Value<'v>)>
+    {
+        Encoder::with_sink(&mut IoSink(writer), options).encode_iter(len, entries, |encoder| encoder.write_header(Header::Map(len)), |encoder, (key, val)| {
+            Ok(encoder.encode_pending(Pending::Key(&key), copy)? + encoder.encode_pending(Pending::Value(&val), copy)?)
+        })
+    }
+
+    /// Like `encode_array_iter`, but for a record whose values are produced on the fly. The keys make up the layout
+    /// which precedes the values, hence they have to be known in advance and the values have to be yielded in the same
+    /// order. A `Value::Record` always has its keys sorted, so the keys need to be sorted as well for the output to
+    /// match and for layouts to be shared with such records.
+    pub fn encode_record_iter<'v, I: IntoIterator<Item = Value<'v>>>(keys: &[&str], values: I, writer: &'w mut W) -> Result<usize, EncodeError> {
+        Self::encode_record_iter_with_options(keys, values, writer, EncoderOptions::default())
+    }
+
+    /// Like `encode_record_iter` but deviates from the canonical encoding as specified by the options.
+    pub fn encode_record_iter_with_options<'v, I>(keys: &[&str], values: I, writer: &'w mut W, options: EncoderOptions) -> Result<usize, EncodeError>
+        where I: IntoIterator<Item = Value<'v>>
+    {
+        Encoder::with_sink(&mut IoSink(writer), options).encode_iter(keys.len(), values, |encoder| encoder.encode_layout(keys.iter().copied(), copy), |encoder, val| {
+            encoder.encode_pending(Pending::Value(&val), copy)
+        })
+    }
+
+}
+
+impl<'w> Encoder<'w, Vec<u8>> {
+
+    /// Append a field to the given `Vec`. Unlike `encode`, this does not go through `std::io::Write`, so the only
+    /// possible error is a length which cannot be represented on the wire. The resulting `usize` is the amount of bytes
+    /// that got appended.
+    pub fn encode_to_vec(field: &'w Value, buf: &'w mut Vec<u8>) -> Result<usize, LengthError> {
+        Self::encode_to_vec_with_options(field, buf, EncoderOptions::default())
+    }
+
+    /// Like `encode_to_vec` but deviates from the canonical encoding as specified by the options.
+    pub fn encode_to_vec_with_options(field: &'w Value, buf: &'w mut Vec<u8>, options: EncoderOptions) -> Result<usize, LengthError> {
+        Self::with_sink(buf, options).encode_inner(field)
+    }
+
+    /// Create an encoder which appends values to the buffer one after another and keeps its symbol table between them,
+    /// see `encode_next`.
+    pub fn new(buf: &'w mut Vec<u8>) -> Self {
+        Self::with_options(buf, EncoderOptions::default())
+    }
+
+    pub fn with_options(buf: &'w mut Vec<u8>, options: EncoderOptions) -> Self {
+        Self::with_sink(buf, options)
+    }
+
+    /// Append a value. Symbols and record layouts of previous values are referenced instead of being repeated, so the
+    /// values have to be decoded one after another by the same `Decoder`, see `Decoder::decode_next`. The resulting
+    /// `usize` is the amount of bytes that got appended.
+    pub fn encode_next(&mut self, field: &Value) -> Result<usize, LengthError> {
+        metrics::measure(self.options.metrics, <dyn Metrics>::encoded, || self.encode_pending(Pending::Value(field), copy).map(|c| (c, c)))
     }
 
-    fn encode_inner(&mut self, field: &'w Value) -> Result<usize, EncodeError> {
+    /// Forget all symbols and record layouts, so that the next value starts a new message with an empty symbol table.
+    pub fn reset(&mut self) {
+        self.symbols.clear();
+        self.records.clear();
+        self.next_free = 0;
+    }
+
+}
+
+impl<'w, S> Encoder<'w, S> {
+
+    fn with_sink(writer: &'w mut S, options: EncoderOptions) -> Self {
+        Self { writer, options, symbols: HashMap::new(), records: HashMap::new(), layouts: RandomState::new(), next_free: 0 }
+    }
+
+    fn encode_inner(&mut self, field: &'w Value) -> Result<usize, S::Error> where S: Sink {
+        metrics::measure(self.options.metrics, <dyn Metrics>::encoded, || self.encode_pending(Pending::Value(field), Cow::Borrowed).map(|c| (c, c)))
+    }
+
+    /// Encode a container given its header and an iterator over its items, each of which is encoded by `item`. The
+    /// number of items has to match `len`.
+    fn encode_iter<T, I, H, F>(&mut self, len: usize, items: I, header: H, mut item: F) -> Result<usize, EncodeError>
+        where S: Sink<Error = EncodeError>, I: IntoIterator<Item = T>, H: FnOnce(&mut Self) -> Result<usize, EncodeError>,
+              F: FnMut(&mut Self, T) -> Result<usize, EncodeError>
+    {
+        metrics::measure(self.options.metrics, <dyn Metrics>::encoded, || {
+            let mut c = header(self)?;
+            let mut items = items.into_iter();
+            for _ in 0..len {
+                c += item(self, items.next().ok_or(EncodeError::Count(len))?)?;
+            }
+            match items.next() {
+                Some(_) => Err(EncodeError::Count(len)),
+                None => Ok((c, c)),
+            }
+        })
+    }
+
+    /// Values still waiting to be encoded are kept on an explicit stack instead of the call stack, so the depth of a
+    /// value is only limited by the available heap.
+    fn encode_pending<'v>(&mut self, pending: Pending<'v>, intern: Intern<'v, 'w>) -> Result<usize, S::Error> where S: Sink {
         let mut c = 0;
-        match &field {
-            Value::Null        => Header::Null.encode(self.writer),
-            Value::Bool(true)  => Header::True.encode(self.writer),
-            Value::Bool(false) => Header::False.encode(self.writer),
-            Value::F32(v)    => {
-                c += Header::F32.encode(self.writer)?;
-                self.writer.write_all(&v.to_be_bytes())?;
-                Ok(c + size_of::<f32>())
-            },
-            Value::F64(v)    => {
-                c += Header::F64.encode(self.writer)?;
-                self.writer.write_all(&v.to_be_bytes())?;
-                Ok(c + size_of::<f64>())
-            },
-            Value::Bytes(v)  => {
-                c += Header::Bin(v.len()).encode(self.writer)?;
-                self.writer.write_all(v)?;
-                Ok(c + v.len())
-            },
-            Value::Int(s, v) => Header::Int(*s, *v).encode(self.writer),
-            Value::Str(v) => {
-                c += Header::Str(v.len()).encode(self.writer)?;
-                self.writer.write_all(v.as_bytes())?;
-                Ok(c + v.len())
-            },
-            Value::Symbol(v) => self.encode_symbol(v),
-            Value::Array(inner) => {
-                c += Header::Arr(inner.len()).encode(self.writer)?;
-                for field in inner.iter() {
-                    c += self.encode_inner(field)?;
-                }
-                Ok(c)
-            },
-            Value::Record(inner) => self.encode_record(inner),
-            Value::Map(inner) => {
-                c += Header::Map(inner.len()).encode(self.writer)?;
-                for (key, val) in inner.iter() {
-                    c += self.encode_inner(key)?;
-                    c += self.encode_inner(val)?;
-                }
-                Ok(c)
-            },
+        let mut stack = vec![pending];
+        while let Some(pending) = stack.pop() {
+            c += match pending {
+                Pending::Key(Value::Str(k)) if self.options.symbol_keys => self.encode_symbol(k, intern)?,
+                Pending::Key(field) | Pending::Value(field) => match field {
+                    Value::Null        => self.write_header(Header::Null)?,
+                    Value::Bool(true)  => self.write_header(Header::True)?,
+                    Value::Bool(false) => self.write_header(Header::False)?,
+                    Value::F32(v)      => self.write_header(Header::F32)? + self.write_slice(&v.to_be_bytes())?,
+                    Value::F64(v)      => self.write_header(Header::F64)? + self.write_slice(&v.to_be_bytes())?,
+                    Value::Bytes(v)    => self.write_header(Header::Bin(v.len()))? + self.write_slice(v)?,
+                    Value::Int(s, v)   => self.write_header(Header::Int(*s, *v))?,
+                    Value::Str(v) if self.options.symbol_strings => self.encode_symbol(v, intern)?,
+                    Value::Str(v)      => self.write_header(Header::Str(v.len()))? + self.write_slice(v.as_bytes())?,
+                    Value::Symbol(v)   => self.encode_symbol(v, intern)?,
+                    Value::Array(inner) => {
+                        stack.extend(inner.iter().rev().map(Pending::Value));
+                        self.write_header(Header::Arr(inner.len()))?
+                    },
+                    Value::Record(inner) => {
+                        stack.extend(inner.values().rev().map(Pending::Value));
+                        self.encode_layout(inner.keys().map(|k| k.as_ref()), intern)?
+                    },
+                    Value::Map(inner) => {
+                        for (key, val) in inner.iter().rev() {
+                            stack.push(Pending::Value(val));
+                            stack.push(Pending::Key(key));
+                        }
+                        self.write_header(Header::Map(inner.len()))?
+                    },
+                },
+            };
         }
+        Ok(c)
     }
 
-    fn encode_record(&mut self, inner: &'w BTreeMap<Cow<'w, str>, Value<'w>>) -> Result<usize, EncodeError> {
-        let mut c = match self.records.get(&inner.keys().map(|i| i.clone()).collect::<Vec<_>>()) {
-            Some(i) => Header::Ref(*i).encode(self.writer)?,
+    /// Encode the header of a record: either a new layout or a reference to a known one. Looking up a known layout
+    /// does not allocate, only new layouts need their keys collected.
+    fn encode_layout<'v, K>(&mut self, keys: K, intern: Intern<'v, 'w>) -> Result<usize, S::Error>
+        where S: Sink, K: ExactSizeIterator<Item = &'v str> + Clone
+    {
+        let mut hasher = self.layouts.build_hasher();
+        keys.clone().for_each(|key| key.hash(&mut hasher));
+        let hash = hasher.finish();
+        let known = self.records.get(&hash)
+            .and_then(|candidates| candidates.iter().find(|(known, _)| known.iter().map(|k| k.as_ref()).eq(keys.clone())))
+            .map(|(_, i)| *i);
+        match known {
+            Some(i) => self.write_header(Header::Ref(i)),
             None    => {
-                let mut x = Header::Rec(inner.len()).encode(self.writer)?;
-                for sym in inner.keys() {
-                    x += self.encode_symbol(sym)?;
+                let mut c = self.write_header(Header::Rec(keys.len()))?;
+                for sym in keys.clone() {
+                    c += self.encode_symbol(sym, intern)?;
                 }
                 let index = self.next();
-                self.records.insert(inner.keys().map(|i| i.clone()).collect(), index);
-                x
+                self.records.entry(hash).or_default().push((keys.map(intern).collect(), index));
+                Ok(c)
             }
-        };
-        for val in inner.values() {
-            c += self.encode_inner(val)?;
         }
-        Ok(c)
     }
 
-    fn encode_symbol(&mut self, symbol: &'w str) -> Result<usize, EncodeError> {
+    fn encode_symbol<'v>(&mut self, symbol: &'v str, intern: Intern<'v, 'w>) -> Result<usize, S::Error> where S: Sink {
         match self.symbols.get(symbol) {
-            Some(i) => Header::Ref(*i).encode(self.writer),
+            Some(i) => self.write_header(Header::Ref(*i)),
             None    => {
                 let index = self.next();
-                self.symbols.insert(symbol.into(), index);
-                let c = Header::Sym(symbol.len()).encode(self.writer)?;
-                self.writer.write_all(symbol.as_bytes())?;
-                Ok(c + symbol.len())
+                self.symbols.insert(intern(symbol), index);
+                Ok(self.write_header(Header::Sym(symbol.len()))? + self.write_slice(symbol.as_bytes())?)
             }
         }
     }
 
+    #[inline]
+    fn write_header(&mut self, header: Header) -> Result<usize, S::Error> where S: Sink {
+        header.validate()?;
+        let len = header.encoded_len();
+        self.writer.put(&header.to_bytes()[..len])?;
+        Ok(len)
+    }
+
+    #[inline]
+    fn write_slice(&mut self, slice: &[u8]) -> Result<usize, S::Error> where S: Sink {
+        self.writer.put(slice)?;
+        Ok(slice.len())
+    }
+
     fn next(&mut self) -> usize {
         self.next_free += 1;
         self.next_free - 1
     }
 
 }
+
+impl<'w, 'b> Encoder<'w, &'b mut [u8]> {
+
+    /// Encode into a preallocated buffer, e.g. for a datagram. The output goes straight into the buffer, which is never
+    /// grown, but the encoder still allocates for its symbol table. If the buffer is too small, the value is encoded a
+    /// second time to report the required size in `EncodeError::BufferTooSmall` and the contents of the buffer are
+    /// unspecified. The resulting `usize` is the amount of bytes that got written.
+    pub fn encode_to_slice(field: &'w Value, buf: &'b mut [u8]) -> Result<usize, EncodeError> {
+        let mut cursor = buf;
+        match Encoder::encode(field, &mut cursor) {
+            Err(EncodeError::Io(e)) if e.kind() == ErrorKind::WriteZero => {
+                Err(EncodeError::BufferTooSmall(Encoder::encode(field, &mut sink())?))
+            },
+            result => result,
+        }
+    }
+
+}
+
+/// The result of decoding a token: either a complete value or a container still waiting for its elements
+enum Item<'a> {
+    Complete(Value<'a>),
+    Open(Partial<'a>),
+}
+
+/// A container whose elements are being decoded
+enum Partial<'a> {
+    /// The elements so far and the number of remaining ones
+    Array(Vec<Value<'a>>, usize),
+    /// The entries so far, the key of the current entry if it has been decoded already and the number of remaining entries
+    Map(Vec<(Value<'a>, Value<'a>)>, Option<Value<'a>>, usize),
+    /// The layout of the record, the fields so far and the index of the next key
+    Record(Rc<[&'a str]>, BTreeMap<Cow<'a, str>, Value<'a>>, usize),
+}
+
 /// Used to decode `nachricht` fields. This uses a symbol table to allow the decoding of encountered references.
 pub struct Decoder<'a> {
-    symbols: Vec<Refable<'a>>,
-    buf: &'a [u8],
-    pos: usize,
+    reader: Reader<'a>,
+    /// Whether running out of input should yield a partial value instead of an error
+    prefix: bool,
+    /// Start of the innermost value that could not be decoded completely
+    truncated: Option<usize>,
+    /// The maximum number of nested containers
+    max_depth: usize,
 }
 
+#[deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable)]
 impl<'a> Decoder<'a> {
 
+    /// Values which are nested deeper than this are rejected by `decode_untrusted`. Every container counts as one
+    /// level.
+    pub const MAX_DEPTH: usize = 512;
+
     /// Decode a single value from the given buffer. All strings, keys, symbols and byte data will be borrowed from the
     /// buffer instead of copied. This means that the decoded field may only live as long as the buffer does. However,
     /// some allocations still occur: containers need their own heap space.
     pub fn decode<B: ?Sized + AsRef<[u8]>>(buf: &'a B) -> Result<(Value<'a>, usize), DecoderError> {
-        let mut decoder = Self { buf: buf.as_ref(), symbols: Vec::new(), pos: 0 };
-        let value = decoder.decode_value().map_err(|e| e.at(decoder.pos))?;
-        Ok((value, decoder.pos))
-    }
-
-    fn decode_value(&mut self) -> Result<Value<'a>, DecodeError> {
-        let header = self.decode_header()?;
-        match header {
-            Header::Null      => Ok(Value::Null),
-            Header::True      => Ok(Value::Bool(true)),
-            Header::False     => Ok(Value::Bool(false)),
-            Header::F32       => Ok(Value::F32(<f32>::from_be_bytes(self.decode_slice(4)?.try_into().unwrap()))),
-            Header::F64       => Ok(Value::F64(<f64>::from_be_bytes(self.decode_slice(8)?.try_into().unwrap()))),
-            Header::Bin(v)    => Ok(Value::Bytes(Cow::Borrowed(self.decode_slice(v)?))),
-            Header::Int(s, v) => Ok(Value::Int(s, v)),
-            Header::Arr(v) => {
-                let mut elements = Vec::with_capacity(0);
-                elements.try_reserve(v)?;
-                for _ in 0..v {
-                    elements.push(self.decode_value()?);
-                }
-                Ok(Value::Array(elements))
-            },
-            Header::Map(v) => {
-                let mut elements = Vec::with_capacity(0);
-                elements.try_reserve(v)?;
-                for _ in 0..v {
-                    let key = self.decode_value()?;
-                    let val = self.decode_value()?;
-                    elements.push((key, val));
+        Self::decode_with_options(buf, DecoderOptions::default())
+    }
+
+    /// Like `decode`, but with options which determine which input is accepted.
+    pub fn decode_with_options<B: ?Sized + AsRef<[u8]>>(buf: &'a B, options: DecoderOptions) -> Result<(Value<'a>, usize), DecoderError> {
+        let mut decoder = Self::with_options(buf, options);
+        let value = decoder.decode_next()?;
+        Ok((value, decoder.position()))
+    }
+
+    /// Decode a message from an untrusted source. No decoding function of this crate panics or exhausts the stack, no
+    /// matter the input: nested containers are either kept on the heap or limited to `MAX_DEPTH` levels. The values
+    /// returned by `decode` may still be nested arbitrarily deep, though, so processing them recursively, which
+    /// includes dropping them, can overflow the stack. Therefore the message has to be nested no deeper than
+    /// `MAX_DEPTH` here, and it has to span the whole buffer.
+    pub fn decode_untrusted<B: ?Sized + AsRef<[u8]>>(buf: &'a B) -> Result<Value<'a>, DecoderError> {
+        let mut decoder = Self { max_depth: Self::MAX_DEPTH, ..Self::new(buf.as_ref()) };
+        let value = decoder.decode_next()?;
+        decoder.finish()?;
+        Ok(value)
+    }
+
+    /// Create a decoder which keeps its state between values. Use this instead of `decode` if you need more than the
+    /// decoded value, e.g. the symbol table.
+    pub fn new<B: ?Sized + AsRef<[u8]>>(buf: &'a B) -> Self {
+        Self::with_options(buf, DecoderOptions::default())
+    }
+
+    pub fn with_options<B: ?Sized + AsRef<[u8]>>(buf: &'a B, options: DecoderOptions) -> Self {
+        Self { reader: Reader::with_options(buf, options), prefix: false, truncated: None, max_depth: usize::MAX }
+    }
+
+    /// Create a decoder which continues a message at the given position, see `Reader::resume`.
+    pub fn resume<B: ?Sized + AsRef<[u8]>>(buf: &'a B, position: usize, symbols: SymbolTable<'a>) -> Self {
+        Self { reader: Reader::resume(buf, position, symbols), ..Self::new(buf) }
+    }
+
+    /// Decode the value at the current position. Symbols and record layouts defined by previous values remain
+    /// referenceable.
+    pub fn decode_next(&mut self) -> Result<Value<'a>, DecoderError> {
+        let start = self.position();
+        metrics::measure(self.reader.options().metrics, <dyn Metrics>::decoded, || {
+            let value = self.decode_element().and_then(|v| v.ok_or(DecodeError::Eof)).map_err(|e| e.at(self.position()))?;
+            Ok((value, self.position() - start))
+        })
+    }
+
+    /// Consume the value at the current position without decoding it, including everything nested within it, and
+    /// return the number of skipped bytes. Symbols and record layouts defined within it remain referenceable. Unlike
+    /// `decode_next`, strings are not validated, see `Reader::skip`.
+    pub fn skip_value(&mut self) -> Result<usize, DecoderError> {
+        let start = self.position();
+        self.reader.skip().map_err(|e| e.at(self.position()))?;
+        Ok(self.position() - start)
+    }
+
+    /// The amount of bytes which have been consumed so far.
+    pub fn position(&self) -> usize {
+        self.reader.position()
+    }
+
+    /// The part of the buffer which has not been decoded yet, e.g. the next frame if messages are concatenated.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.reader.remaining()
+    }
+
+    /// Check that the whole buffer has been decoded, otherwise report the amount of trailing bytes as
+    /// `DecodeError::Trailing`.
+    pub fn finish(&self) -> Result<(), DecoderError> {
+        match self.remaining().len() {
+            0 => Ok(()),
+            n => Err(DecodeError::Trailing(n).at(self.position())),
+        }
+    }
+
+    /// The symbols and record layouts defined so far. The index of each entry is the one used by `Header::Ref`.
+    pub fn symbols(&self) -> &SymbolTable<'a> {
+        self.reader.symbols()
+    }
+
+    /// Consume the decoder and return its symbol table, see `symbols`.
+    pub fn into_symbols(self) -> SymbolTable<'a> {
+        self.reader.into_symbols()
+    }
+
+    /// Decode as much of a possibly truncated buffer as possible. This is useful for analyzing crash dumps or network
+    /// captures where the tail of a message is missing. Containers which are cut short contain all elements that could
+    /// be decoded completely, scalars and records whose keys are cut short are omitted. The first part of the result is
+    /// `None` if not even the outermost header could be decoded, the second part is the position of the innermost value
+    /// that was incomplete or `None` if the buffer contained a complete value. Errors other than running out of input
+    /// are reported as usual.
+    pub fn decode_prefix<B: ?Sized + AsRef<[u8]>>(buf: &'a B) -> Result<(Option<Value<'a>>, Option<usize>), DecoderError> {
+        let mut decoder = Self { prefix: true, ..Self::new(buf.as_ref()) };
+        let value = decoder.decode_element().map_err(|e| e.at(decoder.position()))?;
+        Ok((value, decoder.truncated))
+    }
+
+    /// Decode a complete value. Returns `None` if the input ended prematurely while decoding a prefix. Containers which
+    /// got cut short will be returned but also mark the decoder as truncated. Nested containers are kept on an explicit
+    /// stack instead of the call stack, so the depth of a value is only limited by the available heap.
+    fn decode_element(&mut self) -> Result<Option<Value<'a>>, DecodeError> {
+        let mut stack = Vec::new();
+        loop {
+            let start = self.position();
+            let mut value = match self.decode_value() {
+                Ok(Item::Complete(value)) => Some(value),
+                Ok(Item::Open(_)) if stack.len() == self.max_depth => return Err(DecodeError::Depth(self.max_depth)),
+                Ok(Item::Open(partial)) => { stack.push(partial); continue; },
+                Err(DecodeError::Eof) if self.prefix => {
+                    self.truncated.get_or_insert(start);
+                    None
+                },
+                Err(DecodeError::Eof) => return Err(Self::truncated_field(&stack).map_or(DecodeError::Eof, |field| DecodeError::TruncatedRecord(field.to_owned()))),
+                Err(e) => return Err(e),
+            };
+            // hand the finished value to its container, which in turn may be finished now
+            loop {
+                let partial = match stack.last_mut() {
+                    Some(partial) => partial,
+                    None => return Ok(value),
+                };
+                match self.accept(partial, value) {
+                    Some(complete) => { stack.pop(); value = Some(complete); },
+                    None => break,
                 }
-                Ok(Value::Map(elements))
             }
-            Header::Str(v) => Ok(Value::Str(Cow::Borrowed(from_utf8(&self.decode_slice(v)?)?))),
-            Header::Sym(v) => {
-                let sym = from_utf8(&self.decode_slice(v)?)?;
-                self.symbols.push(Refable::Sym(sym));
-                Ok(Value::Symbol(Cow::Borrowed(sym)))
+        }
+    }
+
+    /// The field of the innermost record whose value is being decoded, if any
+    fn truncated_field<'s>(stack: &'s [Partial<'a>]) -> Option<&'a str> {
+        stack.iter().rev().find_map(|partial| match partial {
+            Partial::Record(keys, _, next) => keys.get(*next).copied(),
+            _ => None,
+        })
+    }
+
+    /// Decode a scalar or the header of a container. Containers with at least one element have to be completed by
+    /// passing their elements to `accept`.
+    fn decode_value(&mut self) -> Result<Item<'a>, DecodeError> {
+        Ok(Item::Complete(match self.reader.next_token()? {
+            Token::Null      => Value::Null,
+            Token::Bool(b)   => Value::Bool(b),
+            Token::F32(v)    => Value::F32(v),
+            Token::F64(v)    => Value::F64(v),
+            Token::Bin(v)    => Value::Bytes(Cow::Borrowed(v)),
+            Token::Int(s, v) => Value::Int(s, v),
+            Token::Str(v)    => Value::Str(Cow::Borrowed(v)),
+            Token::Sym(v)    => Value::Symbol(Cow::Borrowed(v)),
+            Token::Arr(0)    => Value::Array(Vec::new()),
+            Token::Arr(v)    => {
+                let elements = allocate(v, self.reader.remaining())?;
+                return Ok(Item::Open(Partial::Array(elements, v)));
             },
-            Header::Rec(v) => {
-                let mut fields = BTreeMap::new();
-                let mut keys = Vec::with_capacity(0);
-                keys.try_reserve(v)?;
-                for _ in 0..v {
-                    match self.decode_value()? {
-                        Value::Symbol(Cow::Borrowed(sym)) => { keys.push(sym); },
-                        x => { return Err(DecodeError::IllegalKey(x.typename())); }
-                    }
-                }
-                self.symbols.push(Refable::Rec(keys.clone()));
-                for key in keys {
-                    let val = self.decode_value()?;
-                    fields.insert(Cow::Borrowed(key), val);
+            Token::Map(0)    => Value::Map(Vec::new()),
+            Token::Map(v)    => {
+                let elements = allocate(v, self.reader.remaining())?;
+                return Ok(Item::Open(Partial::Map(elements, None, v)));
+            },
+            Token::Rec(keys) if keys.is_empty() => Value::Record(BTreeMap::new()),
+            // a `BTreeMap` can not reserve memory up front, but its fields are bounded by the keys which the reader
+            // has allocated already
+            Token::Rec(keys) => return Ok(Item::Open(Partial::Record(keys, BTreeMap::new(), 0))),
+        }))
+    }
+
+    /// Add the next element to a container. `None` is passed if the element was cut short. Returns the container if it
+    /// is complete, either because all of its elements have been decoded or because the input got truncated.
+    fn accept(&mut self, partial: &mut Partial<'a>, element: Option<Value<'a>>) -> Option<Value<'a>> {
+        let truncated = self.truncated.is_some();
+        match partial {
+            Partial::Array(elements, remaining) => {
+                elements.extend(element);
+                *remaining -= 1;
+                if truncated || *remaining == 0 {
+                    return Some(Value::Array(take(elements)));
                 }
-                Ok(Value::Record(fields))
             },
-            Header::Ref(v) => {
-                match self.symbols.get(v) {
-                    Some(Refable::Sym(s)) => Ok(Value::Symbol(Cow::Borrowed(s))),
-                    Some(Refable::Rec(ref s)) => {
-                        let mut fields = BTreeMap::<Cow<'a, str>, Value<'a>>::new();
-                        for key in s.clone() {
-                            fields.insert(Cow::Borrowed(key), self.decode_value()?);
-                        }
-                        Ok(Value::Record(fields))
+            Partial::Map(elements, key, remaining) => match key.take() {
+                None => match element {
+                    Some(element) if !truncated => { *key = Some(element); },
+                    _ => return Some(Value::Map(take(elements))),
+                },
+                Some(key) => {
+                    elements.extend(element.map(|val| (key, val)));
+                    *remaining -= 1;
+                    if truncated || *remaining == 0 {
+                        return Some(Value::Map(take(elements)));
                     }
-                    None => Err(DecodeError::InvalidRef(v))
+                },
+            },
+            Partial::Record(keys, fields, next) => {
+                if let (Some(val), Some(key)) = (element, keys.get(*next)) {
+                    fields.insert(Cow::Borrowed(*key), val);
+                }
+                *next += 1;
+                if truncated || *next == keys.len() {
+                    return Some(Value::Record(take(fields)));
                 }
             },
         }
-    }
-
-    fn decode_header(&mut self) -> Result<Header, DecodeError> {
-        let (header, c) = Header::decode(&self.buf[self.pos..])?;
-        self.pos += c;
-        Ok(header)
-    }
-
-    fn decode_slice(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
-        if self.buf[self.pos..].len() < len {
-            Err(DecodeError::Eof)
-        } else {
-            self.pos += len;
-            Ok(&self.buf[self.pos - len .. self.pos])
-        }
+        None
     }
 
 }
@@ -328,7 +955,9 @@ impl<'a> Decoder<'a> {
 
 #[cfg(test)]
 mod test {
-    use super::{Value, Sign, Encoder, Decoder, DecodeError};
+    use super::{Value, Sign, TextOptions, Encoder, EncoderOptions, Decoder, DecoderOptions, DecodeError, EncodeError};
+    use crate::symbols::Refable;
+    use crate::header::Header;
     use std::borrow::Cow;
     use std::collections::BTreeMap;
 
@@ -372,6 +1001,118 @@ mod test {
         ]), &mut buf);
     }
 
+    #[test]
+    fn text() {
+        let value = Value::Array(vec![Value::Bytes(Cow::Borrowed(&[0xde, 0xad, 0xbe, 0xef])), Value::Bytes(Cow::Borrowed(&[1; 5])), Value::Bytes(Cow::Borrowed(&[]))]);
+        assert_eq!("[\n  '3q2+7w==',\n  'AQEBAQE=',\n  '',\n]", value.to_string());
+        assert_eq!("[\n  x'deadbeef',\n  'AQEBAQE=',\n  '',\n]", value.to_text(TextOptions { hex_bytes: 4, ..Default::default() }));
+        let value = Value::Array(vec![Value::Array(vec![]), Value::Str(Cow::Borrowed("a\u{200b}b\u{feff}"))]);
+        assert_eq!("[\n  [],\n  \"a\\u{200b}b\\u{feff}\",\n]", value.to_string());
+    }
+
+    #[test]
+    fn references() {
+        let cat = |species| Value::Record(BTreeMap::from([(Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed(species)))]));
+        let value = Value::Array(vec![cat("FelisCatus"), cat("LynxLynx"), cat("FelisCatus"), Value::Symbol(Cow::Borrowed("species"))]);
+        let options = TextOptions { references: true, ..Default::default() };
+        assert_eq!("[\n  (\n    species: #FelisCatus,\n  ),\n  (\n    species: #LynxLynx,\n  ),\n  (\n    species: &2,\n  ),\n  &0,\n]",
+            value.to_text(options));
+    }
+
+    #[test]
+    fn layout() {
+        let value = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+            (Cow::Borrowed("owner name"), Value::Map(vec![(Value::Int(Sign::Pos, 2), Value::Null), (Value::Int(Sign::Pos, 1), Value::Array(vec![Value::Null]))])),
+        ]));
+        assert_eq!("(\n  name: \"Jessica\",\n  \"owner name\": {\n    2: null,\n    1: [\n      null,\n    ],\n  },\n)", value.to_string());
+        let options = TextOptions { indent: 4, align: true, sort_maps: true, ..Default::default() };
+        assert_eq!("(\n    name:         \"Jessica\",\n    \"owner name\": {\n        1: [\n            null,\n        ],\n        2: null,\n    },\n)",
+            value.to_text(options));
+        let options = TextOptions { indent: 0, ..Default::default() };
+        assert_eq!("[\n[\nnull,\n],\n]", Value::Array(vec![Value::Array(vec![Value::Null])]).to_text(options));
+    }
+
+    #[test]
+    fn special_floats() {
+        let value = Value::Array(vec![Value::F32(f32::NAN), Value::F32(f32::INFINITY), Value::F64(f64::NEG_INFINITY), Value::F64(-0.0)]);
+        assert_eq!("[\n  $nan,\n  $inf,\n  -$$inf,\n  $$-0,\n]", value.to_string());
+    }
+
+    #[test]
+    fn span() {
+        let value = Value::Array(vec![
+            Value::Symbol(Cow::Borrowed("cat")),
+            Value::Bytes(Cow::Borrowed(&[1, 2, 3])),
+            Value::Symbol(Cow::Borrowed("cat")),
+            Value::Str(Cow::Borrowed("")),
+            Value::Int(Sign::Pos, 1),
+        ]);
+        let mut buf = Vec::new();
+        Encoder::encode(&value, &mut buf).unwrap();
+        let decoded = Decoder::decode(&buf).unwrap().0;
+        let spans = match &decoded {
+            Value::Array(elements) => elements.iter().map(|e| e.span(&buf)).collect::<Vec<_>>(),
+            _ => panic!("not an array"),
+        };
+        assert_eq!(vec![Some(2..5), Some(6..9), Some(2..5), Some(11..11), None], spans);
+        assert_eq!(b"cat", &buf[2..5]);
+        assert_eq!(None, Value::Str(Cow::Borrowed("cat")).span(&buf));
+        assert_eq!(None, decoded.into_owned().pointer("/0").unwrap().span(&buf));
+    }
+
+    #[test]
+    fn pointer() {
+        let cat = Value::Record(BTreeMap::from([(Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica")))]));
+        let value = Value::Map(vec![
+            (Value::Symbol(Cow::Borrowed("cats")), Value::Array(vec![cat.clone()])),
+            (Value::Str(Cow::Borrowed("a/b~c")), Value::Null),
+            (Value::Int(Sign::Pos, 1), Value::Bool(true)),
+        ]);
+        assert_eq!(Some(&value), value.pointer(""));
+        assert_eq!(Some(&cat), value.pointer("/cats/0"));
+        assert_eq!(Some(&Value::Str(Cow::Borrowed("Jessica"))), value.pointer("/cats/0/name"));
+        assert_eq!(Some(&Value::Null), value.pointer("/a~1b~0c"));
+        assert_eq!(None, value.pointer("/cats/1"));
+        assert_eq!(None, value.pointer("/cats/first"));
+        assert_eq!(None, value.pointer("/1"));
+        assert_eq!(None, value.pointer("cats"));
+        assert_eq!(None, value.pointer("/cats/0/name/0"));
+        let mut value = value;
+        *value.pointer_mut("/cats/0/name").unwrap() = Value::Str(Cow::Borrowed("Wantan"));
+        assert_eq!(Some(&Value::Str(Cow::Borrowed("Wantan"))), value.pointer("/cats/0/name"));
+        assert_eq!(None, value.pointer_mut("/dogs"));
+    }
+
+    #[test]
+    fn tree() {
+        let cat = |name, species| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+            (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed(species))),
+        ]));
+        let value = Value::Record(BTreeMap::from([
+            (Cow::Borrowed("cats"), Value::Array(vec![cat("Jessica", "FelisCatus"), cat("Wantan", "FelisCatus")])),
+            (Cow::Borrowed("owner"), Value::Map(vec![(Value::Int(Sign::Neg, 1), Value::Bytes(Cow::Borrowed(&[1, 2])))])),
+        ]));
+        assert_eq!(value.to_tree(), "\
+record (2 fields) @2
+\u{251c}\u{2500} cats: array (2 elements)
+\u{2502}  \u{251c}\u{2500} record (2 fields) @5
+\u{2502}  \u{2502}  \u{251c}\u{2500} name: string (7 bytes) \"Jessica\"
+\u{2502}  \u{2502}  \u{2514}\u{2500} species: symbol #FelisCatus @6
+\u{2502}  \u{2514}\u{2500} record (2 fields) @5
+\u{2502}     \u{251c}\u{2500} name: string (6 bytes) \"Wantan\"
+\u{2502}     \u{2514}\u{2500} species: symbol #FelisCatus @6
+\u{2514}\u{2500} owner: map (1 entries)
+   \u{251c}\u{2500} key: integer -1
+   \u{2514}\u{2500} value: bytes (2 bytes) 'AQI='
+");
+        // the annotated indices are the ones used on the wire
+        let mut buf = Vec::new();
+        Encoder::encode_to_vec(&value, &mut buf).unwrap();
+        assert!(buf.windows(2).any(|w| w == [Header::Ref(5).to_bytes()[0], Header::Str(6).to_bytes()[0]]));
+    }
+
     #[test]
     fn bytes() {
         let mut buf = Vec::new();
@@ -408,6 +1149,89 @@ mod test {
         ]), &mut buf);
     }
 
+    #[test]
+    fn symbol_keys() {
+        let entry = |k, v| (Value::Str(Cow::Borrowed(k)), Value::Int(Sign::Pos, v));
+        let value = Value::Array(vec![
+            Value::Map(vec![entry("first", 1), entry("second", 2)]),
+            Value::Map(vec![entry("first", 3), entry("second", 4)]),
+        ]);
+        let mut plain = Vec::new();
+        let mut interned = Vec::new();
+        Encoder::encode(&value, &mut plain).unwrap();
+        Encoder::encode_with_options(&value, &mut interned, EncoderOptions { symbol_keys: true, ..Default::default() }).unwrap();
+        assert_eq!(plain.len() - interned.len(), 11);
+        let symbol = |k, v| (Value::Symbol(Cow::Borrowed(k)), Value::Int(Sign::Pos, v));
+        assert_eq!(Value::Array(vec![
+            Value::Map(vec![symbol("first", 1), symbol("second", 2)]),
+            Value::Map(vec![symbol("first", 3), symbol("second", 4)]),
+        ]), Decoder::decode(&interned).unwrap().0);
+    }
+
+    #[test]
+    fn symbol_strings() {
+        let value = Value::Array(vec![Value::Str(Cow::Borrowed("Jessica")); 3]);
+        let mut plain = Vec::new();
+        let mut interned = Vec::new();
+        Encoder::encode(&value, &mut plain).unwrap();
+        Encoder::encode_with_options(&value, &mut interned, EncoderOptions { symbol_strings: true, ..Default::default() }).unwrap();
+        assert_eq!(plain.len() - interned.len(), 14);
+        assert_eq!(Value::Array(vec![Value::Symbol(Cow::Borrowed("Jessica")); 3]), Decoder::decode(&interned).unwrap().0);
+    }
+
+    #[test]
+    fn slice() {
+        let value = Value::Array(vec![Value::Str(Cow::Borrowed("Jessica")), Value::Int(Sign::Neg, 1337)]);
+        let mut buf = Vec::new();
+        let c = Encoder::encode(&value, &mut buf).unwrap();
+        let mut exact = vec![0; c];
+        assert_eq!(c, Encoder::encode_to_slice(&value, &mut exact).unwrap());
+        assert_eq!(buf, exact);
+        let mut short = vec![0; c - 1];
+        assert!(matches!(Encoder::encode_to_slice(&value, &mut short), Err(EncodeError::BufferTooSmall(r)) if r == c));
+    }
+
+    #[test]
+    fn vec() {
+        let value = Value::Array(vec![Value::Symbol(Cow::Borrowed("Jessica")), Value::Symbol(Cow::Borrowed("Jessica")), Value::F64(1.5)]);
+        let mut written = Vec::new();
+        let c = Encoder::encode(&value, &mut written).unwrap();
+        let mut buf = vec![0x00];
+        assert_eq!(c, Encoder::encode_to_vec(&value, &mut buf).unwrap());
+        assert_eq!(written, buf[1..]);
+        assert_eq!(value, Decoder::decode(&buf[1..]).unwrap().0);
+    }
+
+    #[test]
+    fn iter() {
+        let cat = |name: String, species: &str| Value::Record(BTreeMap::from([
+                (Cow::Borrowed("name"), Value::Str(Cow::Owned(name))),
+                (Cow::Borrowed("species"), Value::Symbol(Cow::Owned(species.to_owned()))),
+        ]));
+        let cats = || (0..3).map(|i| cat(format!("Jessica {}", i), if i == 1 { "LynxLynx" } else { "FelisCatus" }));
+        let (mut expected, mut buf) = (Vec::new(), Vec::new());
+        let c = Encoder::encode(&Value::Array(cats().collect()), &mut expected).unwrap();
+        assert_eq!(c, Encoder::encode_array_iter(3, cats(), &mut buf).unwrap());
+        assert_eq!(expected, buf);
+        // map keys are symbols just like with `encode`
+        let entries = || cats().map(|cat| (Value::Str(Cow::Borrowed("cat")), cat));
+        let options = EncoderOptions { symbol_keys: true, ..Default::default() };
+        expected.clear();
+        buf.clear();
+        Encoder::encode_with_options(&Value::Map(entries().collect()), &mut expected, options).unwrap();
+        Encoder::encode_map_iter_with_options(3, entries(), &mut buf, options).unwrap();
+        assert_eq!(expected, buf);
+        expected.clear();
+        buf.clear();
+        let value = cat("Wantan".into(), "LynxLynx");
+        Encoder::encode(&value, &mut expected).unwrap();
+        Encoder::encode_record_iter(&["name", "species"], [Value::Str(Cow::Borrowed("Wantan")), Value::Symbol(Cow::Borrowed("LynxLynx"))], &mut buf).unwrap();
+        assert_eq!(expected, buf);
+        assert!(matches!(Encoder::encode_array_iter(4, cats(), &mut Vec::new()), Err(EncodeError::Count(4))));
+        assert!(matches!(Encoder::encode_array_iter(2, cats(), &mut Vec::new()), Err(EncodeError::Count(2))));
+        assert!(matches!(Encoder::encode_record_iter(&["name"], cats(), &mut Vec::new()), Err(EncodeError::Count(1))));
+    }
+
     #[test]
     fn record() {
         let mut buf = Vec::new();
@@ -429,12 +1253,46 @@ mod test {
         assert!(matches!(Decoder::decode(&buf).unwrap_err().into_inner(), DecodeError::Eof));
         let buf = [2 << 5 | 2, 0xc3, 0x28];
         assert!(matches!(Decoder::decode(&buf).unwrap_err().into_inner(), DecodeError::Utf8(_)));
-        let buf = [7 << 5 | 0];
+        let buf = [7 << 5];
         assert!(matches!(Decoder::decode(&buf).unwrap_err().into_inner(), DecodeError::InvalidRef(0)));
         let buf = [5 << 5 | 1, 5 << 5];
         assert!(matches!(dbg!(Decoder::decode(&buf)).unwrap_err().into_inner(), DecodeError::IllegalKey("record")));
     }
 
+    #[test]
+    fn ref_keys() {
+        let value = Value::Array(vec![
+            Value::Symbol(Cow::Borrowed("name")),
+            Value::Record(BTreeMap::from([(Cow::Borrowed("name"), Value::Null)])),
+        ]);
+        let mut buf = Vec::new();
+        Encoder::encode(&value, &mut buf).unwrap();
+        let mut expected = Vec::new();
+        for header in [Header::Arr(2), Header::Sym(4)] {
+            header.encode(&mut expected).unwrap();
+        }
+        expected.extend_from_slice(b"name");
+        for header in [Header::Rec(1), Header::Ref(0), Header::Null] {
+            header.encode(&mut expected).unwrap();
+        }
+        assert_eq!(expected, buf);
+        let mut decoder = Decoder::new(&buf);
+        assert_eq!(value, decoder.decode_next().unwrap());
+        assert_eq!(vec![Refable::Sym("name"), Refable::Rec(vec!["name"].into())], decoder.into_symbols().into_entries());
+    }
+
+    #[test]
+    fn str_keys() {
+        let mut buf = Vec::new();
+        Header::Rec(1).encode(&mut buf).unwrap();
+        Header::Str(4).encode(&mut buf).unwrap();
+        buf.extend_from_slice(b"name");
+        Header::Null.encode(&mut buf).unwrap();
+        assert!(matches!(Decoder::decode(&buf).unwrap_err().into_inner(), DecodeError::IllegalKey("string")));
+        let value = Value::Record(BTreeMap::from([(Cow::Borrowed("name"), Value::Null)]));
+        assert_eq!((value, buf.len()), Decoder::decode_with_options(&buf, DecoderOptions { str_keys: true, ..Default::default() }).unwrap());
+    }
+
     #[test]
     fn too_big_allocations() {
         let mut buf = [0u8; 9];
@@ -446,6 +1304,199 @@ mod test {
         }
     }
 
+    #[test]
+    fn truncated_record() {
+        let mut buf = Vec::new();
+        Header::Rec(2).encode(&mut buf).unwrap();
+        Header::Sym(1).encode(&mut buf).unwrap();
+        buf.push(b'a');
+        Header::Sym(1).encode(&mut buf).unwrap();
+        buf.push(b'b');
+        Header::Null.encode(&mut buf).unwrap();
+        assert_eq!(DecodeError::TruncatedRecord("b".into()), Decoder::decode(&buf).unwrap_err().into_inner());
+        // the innermost record is reported, even if the input ends within a nested container
+        Header::Arr(2).encode(&mut buf).unwrap();
+        Header::Null.encode(&mut buf).unwrap();
+        assert_eq!(DecodeError::TruncatedRecord("b".into()), Decoder::decode(&buf).unwrap_err().into_inner());
+        // the keys themselves are not part of the values
+        assert_eq!(DecodeError::Eof, Decoder::decode(&buf[..3]).unwrap_err().into_inner());
+    }
+
+    #[test]
+    fn remaining() {
+        let mut buf = Vec::new();
+        Header::True.encode(&mut buf).unwrap();
+        Header::Arr(1).encode(&mut buf).unwrap();
+        Header::Null.encode(&mut buf).unwrap();
+        let mut decoder = Decoder::new(&buf);
+        assert_eq!(&buf[..], decoder.remaining());
+        assert_eq!(Value::Bool(true), decoder.decode_next().unwrap());
+        assert_eq!((1, &buf[1..]), (decoder.position(), decoder.remaining()));
+        assert_eq!("2 bytes of trailing input after the end of the message at input position 1", decoder.finish().unwrap_err().to_string());
+        assert_eq!(Value::Array(vec![Value::Null]), decoder.decode_next().unwrap());
+        assert!(decoder.remaining().is_empty());
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn skip_value() {
+        let cat = |name| Value::Record(BTreeMap::from([(Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name)))]));
+        let mut buf = Vec::new();
+        let mut encoder = Encoder::new(&mut buf);
+        let first = encoder.encode_next(&Value::Array(vec![cat("Jessica"), Value::Symbol(Cow::Borrowed("FelisCatus"))])).unwrap();
+        encoder.encode_next(&Value::Array(vec![cat("Wantan"), Value::Symbol(Cow::Borrowed("FelisCatus"))])).unwrap();
+        let mut decoder = Decoder::new(&buf);
+        assert_eq!(first, decoder.skip_value().unwrap());
+        // the layout and the symbol have been defined by the skipped value
+        assert_eq!(Value::Array(vec![cat("Wantan"), Value::Symbol(Cow::Borrowed("FelisCatus"))]), decoder.decode_next().unwrap());
+        assert_eq!(DecodeError::Eof, decoder.skip_value().unwrap_err().into_inner());
+        let mut decoder = Decoder::new(&buf[..first - 1]);
+        let error = decoder.skip_value().unwrap_err();
+        assert_eq!(DecodeError::Eof, error.into_inner());
+    }
+
+    #[test]
+    fn untrusted() {
+        let mut buf = Vec::new();
+        for _ in 0..Decoder::MAX_DEPTH {
+            Header::Arr(1).encode(&mut buf).unwrap();
+        }
+        Header::Null.encode(&mut buf).unwrap();
+        assert!(Decoder::decode_untrusted(&buf).is_ok());
+        Header::Null.encode(&mut buf).unwrap();
+        assert_eq!(DecodeError::Trailing(1), Decoder::decode_untrusted(&buf).unwrap_err().into_inner());
+        buf.pop();
+        buf.insert(0, Header::Arr(1).to_bytes()[0]);
+        assert_eq!(DecodeError::Depth(Decoder::MAX_DEPTH), Decoder::decode_untrusted(&buf).unwrap_err().into_inner());
+    }
+
+    #[test]
+    fn fuzz() {
+        // garbage and mutations of a valid message must never cause a panic in any of the decoders
+        let value = Value::Array(vec![
+            Value::Record(BTreeMap::from([(Cow::Borrowed("a"), Value::F64(1.0)), (Cow::Borrowed("b"), Value::Symbol(Cow::Borrowed("c")))])),
+            Value::Record(BTreeMap::from([(Cow::Borrowed("a"), Value::Int(Sign::Neg, 300)), (Cow::Borrowed("b"), Value::Symbol(Cow::Borrowed("c")))])),
+            Value::Map(vec![(Value::Str(Cow::Borrowed("d")), Value::Bytes(Cow::Borrowed(&[1, 2, 3])))]),
+        ]);
+        let mut valid = Vec::new();
+        Encoder::encode(&value, &mut valid).unwrap();
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || { state ^= state << 13; state ^= state >> 7; state ^= state << 17; state };
+        for i in 0..20_000 {
+            let buf = if i % 2 == 0 {
+                let len = next() % 32;
+                (0..len).map(|_| next() as u8).collect::<Vec<_>>()
+            } else {
+                let mut buf = valid.clone();
+                let pos = next() as usize % buf.len();
+                buf[pos] = next() as u8;
+                buf
+            };
+            let _ = Decoder::decode_untrusted(&buf);
+            let _ = Decoder::decode_prefix(&buf);
+            let _ = crate::verify(&buf);
+            let _ = crate::PushDecoder::new().feed(&buf);
+            let mut reader = crate::Reader::new(&buf);
+            while reader.next_variant().is_ok() && !reader.remaining().is_empty() {}
+        }
+    }
+
+    #[test]
+    fn hostile() {
+        // deeply nested input must neither overflow the stack of any decoder nor yield values which would overflow it
+        let nested = |header: Header| vec![header.to_bytes()[0]; 1 << 20];
+        for buf in [nested(Header::Rec(1)), nested(Header::Arr(1)), nested(Header::Map(1))] {
+            assert!(Decoder::decode_untrusted(&buf).is_err());
+            assert!(Decoder::new(&buf).skip_value().is_err());
+            assert!(crate::verify(&buf).is_err());
+            assert!(crate::scan_symbols(&buf).is_err());
+            assert!(crate::Transcoder::transcode(&buf, &mut Vec::new()).is_err());
+            assert!(crate::decode_selected(&buf, &["0"]).is_err());
+            assert!(crate::Index::build(&buf, usize::MAX).is_err());
+            assert!(crate::Spanned::decode(&buf).is_err());
+            assert!(crate::patch(&mut buf.clone(), "/0", &Value::Null).is_err());
+            let mut decoder = crate::PushDecoder::new();
+            assert!(decoder.feed(&buf).map_or(true, |_| decoder.finish().is_err()));
+            let mut reader = crate::Reader::new(&buf);
+            while reader.next_variant().is_ok() && !reader.remaining().is_empty() {}
+        }
+        let records = nested(Header::Rec(1));
+        assert_eq!(DecodeError::IllegalKey("record"), Decoder::decode(&records).unwrap_err().into_inner());
+    }
+
+    #[test]
+    fn deep() {
+        let mut buf = Vec::new();
+        for _ in 0..1_000_000 {
+            Header::Arr(1).encode(&mut buf).unwrap();
+        }
+        Header::Null.encode(&mut buf).unwrap();
+        let (mut value, c) = Decoder::decode(&buf).unwrap();
+        assert_eq!(buf.len(), c);
+        // dropping is recursive, so take the value apart by hand
+        let mut depth = 0;
+        while let Value::Array(mut inner) = value {
+            value = inner.pop().unwrap();
+            depth += 1;
+        }
+        assert_eq!((1_000_000, Value::Null), (depth, value));
+    }
+
+    #[test]
+    fn deep_encode() {
+        let mut value = (0..1_000_000).fold(Value::Null, |inner, _| Value::Record(BTreeMap::from([(Cow::Borrowed("inner"), inner)])));
+        let mut buf = Vec::new();
+        let c = Encoder::encode(&value, &mut buf).unwrap();
+        assert_eq!((1_000_000 + 7, 1_000_000 + 7), (c, buf.len()));
+        // dropping is recursive, so take the value apart by hand
+        while let Value::Record(mut inner) = value {
+            value = inner.remove("inner").unwrap();
+        }
+    }
+
+    #[test]
+    fn symbol_table() {
+        let cat = |name| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+            (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus"))),
+        ]));
+        let mut buf = Vec::new();
+        Encoder::encode(&Value::Array(vec![cat("Jessica"), cat("Wantan")]), &mut buf).unwrap();
+        let mut decoder = Decoder::new(&buf);
+        decoder.decode_next().unwrap();
+        assert_eq!(buf.len(), decoder.position());
+        assert_eq!(vec![
+            Refable::Sym("name"),
+            Refable::Sym("species"),
+            Refable::Rec(vec!["name", "species"].into()),
+            Refable::Sym("FelisCatus"),
+        ], decoder.into_symbols().into_entries());
+    }
+
+    #[test]
+    fn prefix() {
+        let value = Value::Array(vec![
+            Value::Int(Sign::Pos, 1),
+            Value::Record(BTreeMap::from([
+                (Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica"))),
+                (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("PrionailurusViverrinus"))),
+            ])),
+        ]);
+        let mut buf = Vec::new();
+        Encoder::encode(&value, &mut buf).unwrap();
+        assert_eq!((Some(value.clone()), None), Decoder::decode_prefix(&buf).unwrap());
+        assert_eq!((None, Some(0)), Decoder::decode_prefix(&buf[..0]).unwrap());
+        assert_eq!((Some(Value::Array(vec![])), Some(1)), Decoder::decode_prefix(&buf[..1]).unwrap());
+        // cut off within the species symbol
+        let (partial, at) = Decoder::decode_prefix(&buf[..buf.len() - 1]).unwrap();
+        assert_eq!(Some(Value::Array(vec![
+            Value::Int(Sign::Pos, 1),
+            Value::Record(BTreeMap::from([(Cow::Borrowed("name"), Value::Str(Cow::Borrowed("Jessica")))])),
+        ])), partial);
+        assert_eq!(Some(buf.len() - 23), at);
+        assert!(matches!(Decoder::decode_prefix(&[2 << 5 | 2, 0xc3, 0x28]).unwrap_err().into_inner(), DecodeError::Utf8(_)));
+    }
+
     #[test]
     fn display_record_key() {
         let value = Value::Record(BTreeMap::from([(Cow::Borrowed("true or false"), Value::Bool(false))]));
@@ -0,0 +1,134 @@
+//! Check a message for structural validity without decoding it into a `Value`. This is meant as a gatekeeper before
+//! accepting untrusted messages and as a debugging aid for other implementations. A message is valid if
+//!
+//! * every header and its payload lies within the buffer and every container is complete,
+//! * strings, symbols and keys are valid UTF-8,
+//! * every reference resolves to an entry of the symbol table which has been defined before it, where entries are
+//!   numbered as described in the documentation of `SymbolTable`,
+//! * every record key is a symbol or a reference to one and
+//! * the buffer ends with the message.
+//!
+//! Valid messages may still be encoded wastefully, which `Report::redefinitions` gives a hint of.
+
+#![cfg_attr(not(test), deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::panic, clippy::unreachable))]
+
+use crate::error::{DecodeError, DecoderError};
+use crate::reader::{Reader, Token};
+use crate::symbols::Refable;
+use std::collections::HashSet;
+
+/// What `verify` found out about a valid message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// Size of the message in bytes
+    pub size: usize,
+    /// Number of values, counting containers as well as their elements but not record keys
+    pub values: usize,
+    /// Maximum nesting of containers, a scalar has depth zero
+    pub depth: usize,
+    /// Number of symbols in the symbol table
+    pub symbols: usize,
+    /// Number of record layouts in the symbol table
+    pub records: usize,
+    /// Number of entries in the symbol table which repeat an earlier entry instead of referencing it
+    pub redefinitions: usize,
+}
+
+/// Verify that the buffer contains exactly one valid message, see the module documentation.
+pub fn verify<B: ?Sized + AsRef<[u8]>>(buf: &B) -> Result<Report, DecoderError> {
+    let mut reader = Reader::new(buf);
+    let mut report = Report::default();
+    // remaining values for each open container
+    let mut stack: Vec<usize> = Vec::new();
+    loop {
+        let token = reader.next_token().map_err(|e| e.at(reader.position()))?;
+        report.values += 1;
+        let len = match token {
+            Token::Arr(v) => v,
+            Token::Map(v) => v.saturating_mul(2),
+            Token::Rec(keys) => keys.len(),
+            _ => 0,
+        };
+        if len > 0 {
+            stack.push(len);
+            report.depth = report.depth.max(stack.len());
+            continue;
+        }
+        loop {
+            match stack.last_mut() {
+                None => {
+                    let trailing = reader.remaining().len();
+                    if trailing > 0 {
+                        return Err(DecodeError::Trailing(trailing).at(reader.position()));
+                    }
+                    report.size = reader.position();
+                    let mut seen = HashSet::new();
+                    for entry in reader.into_symbols().into_entries() {
+                        match entry {
+                            Refable::Sym(_) => report.symbols += 1,
+                            Refable::Rec(_) => report.records += 1,
+                        }
+                        if !seen.insert(entry) {
+                            report.redefinitions += 1;
+                        }
+                    }
+                    return Ok(report);
+                },
+                Some(1) => { stack.pop(); },
+                Some(remaining) => { *remaining -= 1; break; },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{verify, Report};
+    use crate::error::DecodeError;
+    use crate::header::{Header, Sign};
+    use crate::value::{Encoder, Value};
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+
+    fn encode(headers: &[Header]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for header in headers {
+            header.encode(&mut buf).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn valid() {
+        let cat = |name| Value::Record(BTreeMap::from([
+            (Cow::Borrowed("name"), Value::Str(Cow::Borrowed(name))),
+            (Cow::Borrowed("species"), Value::Symbol(Cow::Borrowed("FelisCatus"))),
+        ]));
+        let mut buf = Vec::new();
+        Encoder::encode(&Value::Array(vec![cat("Jessica"), cat("Wantan")]), &mut buf).unwrap();
+        assert_eq!(Report { size: buf.len(), values: 7, depth: 2, symbols: 3, records: 1, redefinitions: 0 }, verify(&buf).unwrap());
+        assert_eq!(Report { size: 1, values: 1, ..Default::default() }, verify(&encode(&[Header::Null])).unwrap());
+    }
+
+    #[test]
+    fn redefinitions() {
+        let mut buf = encode(&[Header::Arr(2), Header::Sym(1)]);
+        buf.push(b'a');
+        buf.extend(encode(&[Header::Sym(1)]));
+        buf.push(b'a');
+        assert_eq!(1, verify(&buf).unwrap().redefinitions);
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |headers: &[Header]| verify(&encode(headers)).unwrap_err().into_inner();
+        assert_eq!(DecodeError::Eof, error(&[Header::Arr(2), Header::Null]));
+        assert_eq!(DecodeError::Eof, error(&[Header::Bin(4)]));
+        assert_eq!(DecodeError::InvalidRef(0), error(&[Header::Ref(0)]));
+        assert_eq!(DecodeError::InvalidRef(1), error(&[Header::Arr(2), Header::Rec(0), Header::Ref(1)]));
+        assert_eq!(DecodeError::IllegalKey("record"), error(&[Header::Arr(2), Header::Rec(0), Header::Rec(1), Header::Ref(0)]));
+        assert_eq!(DecodeError::IllegalKey("integer"), error(&[Header::Rec(1), Header::Int(Sign::Pos, 1)]));
+        assert_eq!(DecodeError::Trailing(1), error(&[Header::Null, Header::Null]));
+    }
+
+}